@@ -5,6 +5,8 @@ fn main() {
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=shaders");
     println!("cargo:rerun-if-changed=shaders/chunk");
+    println!("cargo:rerun-if-changed=shaders/sky");
+    println!("cargo:rerun-if-changed=shaders/ui");
 
     // Note that there are a number of downsides to this approach, the comments
     // below detail how to improve the portability of these commands.
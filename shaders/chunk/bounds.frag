@@ -0,0 +1,7 @@
+#version 460
+
+layout(location = 0) out vec4 outFragColor;
+
+void main() {
+  outFragColor = vec4(1.0, 0.1, 0.1, 1.0);
+}
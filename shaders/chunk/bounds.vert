@@ -0,0 +1,53 @@
+#version 460
+#include "../bindless.glsl"
+
+// edges of the chunks occlusion culling rejected, one instance per chunk and two vertices per edge
+
+struct CameraData{
+    mat4 viewproj;
+    vec3 pos;
+};
+
+struct DrawCommand{
+  uint vertex_count;
+  uint instance_count;
+  uint first_vertex;
+  uint first_instance;
+};
+
+struct Bounds{
+  vec4 min;
+  vec4 max;
+};
+
+layout(push_constant) uniform constants {
+  uint cam_index;
+  uint culled_index;
+} push;
+
+layout(set = 0, binding = 3) uniform Camera{
+    CameraData camera;
+}cam[];
+
+// written by cull.comp
+layout(std430, set = 0, binding = 2) readonly buffer Culled{
+    DrawCommand command;
+    Bounds bounds[];
+} culled_buffer[];
+
+// same as voxel_scale in chunk.vert
+const float voxel_scale = 0.1;
+
+// corners as bits of x, y and z, 4 edges along each axis
+const uint edges[24] = uint[24](
+  0, 1, 2, 3, 4, 5, 6, 7,
+  0, 2, 1, 3, 4, 6, 5, 7,
+  0, 4, 1, 5, 2, 6, 3, 7
+);
+
+void main() {
+  Bounds bounds = culled_buffer[push.culled_index].bounds[gl_InstanceIndex];
+  uint corner = edges[gl_VertexIndex];
+  vec3 position = mix(bounds.min.xyz, bounds.max.xyz, bvec3((corner & 1) != 0, (corner & 2) != 0, (corner & 4) != 0));
+  gl_Position = cam[push.cam_index].camera.viewproj * vec4(position * voxel_scale, 1.0);
+}
@@ -1,23 +1,201 @@
 #version 450
 
 #include "../bindless.glsl"
+#include "../sky/atmosphere.glsl"
+#include "../sky/sky.glsl"
 
+// same binding as globalSamples, texture arrays are bound there too
+layout(set = BindlessDescriptorSet, binding = BindlessCombinedImage) uniform sampler2DArray globalSampleArrays[];
+// the shadow cascades with a compare sampler
+layout(set = BindlessDescriptorSet, binding = BindlessCombinedImage) uniform sampler2DArrayShadow globalShadowArrays[];
 
+layout(push_constant) uniform constants {
+  uint cam_index;
+  uint quad_index;
+  uint texture_index;
+  uint material_index;
+  uint draw_index;
+  uint shadow_index;
+  uint flags;
+  uint sky_index;
+  uint atmosphere_index;
+  uint fog_index;
+} push;
 
+const uint SHADOWS = 1;
+const uint DAYLIGHT = 2;
+const uint ATMOSPHERE = 4;
+const uint FOG = 8;
+
+struct CameraData{
+    mat4 viewproj;
+    vec4 pos;
+};
+
+layout(set = 0, binding = 3) uniform Camera{
+    CameraData camera;
+}cam[];
+
+// cascades fitted by render::shadow::ShadowData::fit
+struct ShadowData {
+  mat4 cascades[4];
+  vec4 splits;
+  vec4 texel_sizes;
+  vec4 sun;
+  vec4 forward;
+  uint map_index;
+  uint cascade_count;
+  uint pcf_radius;
+  uint pad;
+};
+
+layout(set = 0, binding = 3) uniform Shadow{
+    ShadowData shadow;
+}shadows[];
+
+// written by render::fog::Fog
+struct FogData {
+  vec4 tint;
+  float density;
+  float height_falloff;
+  float base_height;
+  float start;
+  float end;
+  float seconds;
+  float fade_seconds;
+  float pad;
+};
+
+layout(set = 0, binding = 3) uniform Fog{
+    FogData fog;
+}fogs[];
+
+// texture array layers of a block
+struct Material {
+  uint top;
+  uint side;
+  uint bottom;
+  uint pad;
+};
+
+layout(std430, set = 0, binding = 2) readonly buffer Materials{
+    Material materials[];
+} material_buffer[];
+
+layout(location = 0) in vec2 uv;
 layout(location = 1) in flat uint face;
+layout(location = 2) in flat uint material;
+layout(location = 3) in vec3 world_position;
+layout(location = 4) in flat float loaded_at;
 
 layout(location = 0) out vec4 finalColor;
 
+// light per face so edges stay visible without lighting, x sides, z sides, bottom, top
+const float face_shade[6] = float[6](0.8, 0.8, 0.65, 0.65, 0.5, 1.0);
 
-const vec3 colors[6] = vec3[6](
-    vec3(1, 0, 0),
-    vec3(1, 0, 0),
-    vec3(0, 1, 0),
-    vec3(0, 1, 0),
-    vec3(0, 0, 1),
-    vec3(0, 0, 1)
-);
+const vec3 face_normal[6] = vec3[6](vec3(-1, 0, 0), vec3(1, 0, 0), vec3(0, 0, -1), vec3(0, 0, 1), vec3(0, -1, 0), vec3(0, 1, 0));
+// light left in full shadow
+const float shadow_ambient = 0.55;
+
+// 1 when the sun reaches the fragment, averaged over a square of cascade texels
+float sun_visibility(ShadowData shadow, vec3 normal) {
+  if (dot(normal, shadow.sun.xyz) <= 0) {
+    return 0;
+  }
+  float depth = dot(world_position - cam[push.cam_index].camera.pos.xyz, shadow.forward.xyz);
+  uint cascade = 0;
+  while (cascade + 1 < shadow.cascade_count && depth > shadow.splits[cascade]) {
+    cascade++;
+  }
+  if (depth > shadow.splits[shadow.cascade_count - 1]) {
+    return 1;
+  }
+
+  // moving along the normal by a texel keeps flat faces from shadowing themselves
+  vec3 position = world_position + normal * shadow.texel_sizes[cascade] * 1.5;
+  vec4 clip = shadow.cascades[cascade] * vec4(position, 1.0);
+  vec2 map_uv = clip.xy * 0.5 + 0.5;
+  vec2 texel = 1.0 / vec2(textureSize(globalShadowArrays[shadow.map_index], 0).xy);
+  int radius = int(shadow.pcf_radius);
+  float lit = 0;
+  for (int y = -radius; y <= radius; y++) {
+    for (int x = -radius; x <= radius; x++) {
+      lit += texture(globalShadowArrays[shadow.map_index], vec4(map_uv + vec2(x, y) * texel, cascade, clip.z));
+    }
+  }
+  return lit / float((2 * radius + 1) * (2 * radius + 1));
+}
+
+// light scattered in and out between the camera and the fragment, assuming the air at the camera height all the way
+vec3 aerial_perspective(AtmosphereData a, vec3 color) {
+  vec3 to_fragment = world_position - cam[push.cam_index].camera.pos.xyz;
+  float kilometers = length(to_fragment) * a.radii.w;
+  vec3 fragment_transmittance = exp(-medium_at(a, camera_radius(a)).extinction * kilometers);
+  // below the horizon the LUT only has the short way to the ground, the haze in front of terrain is the horizon sky
+  vec3 dir = normalize(vec3(to_fragment.x, max(to_fragment.y, 0.0) + 1e-4, to_fragment.z));
+  vec3 haze = tonemap(a, sky_luminance(a, dir));
+  return color * fragment_transmittance + haze * (1.0 - fragment_transmittance);
+}
+
+// what the sky shows behind the fragment, clamped to the horizon like the haze
+vec3 sky_behind(vec3 dir) {
+  SkyData sky = skies[push.sky_index].sky;
+  vec3 horizontal = normalize(vec3(dir.x, max(dir.y, 0.0) + 1e-4, dir.z));
+  if ((push.flags & ATMOSPHERE) != 0) {
+    AtmosphereData a = atmospheres[push.atmosphere_index].atmosphere;
+    return tonemap(a, sky_luminance(a, horizontal)) + gradient(sky, horizontal) * sky.stars.x;
+  }
+  return gradient(sky, horizontal);
+}
+
+// exponential height fog integrated along the view ray, then full fog at the render distance so chunks don't pop in
+vec3 apply_fog(FogData fog, vec3 color) {
+  vec3 camera = cam[push.cam_index].camera.pos.xyz;
+  vec3 to_fragment = world_position - camera;
+  float distance_to = length(to_fragment);
+  float camera_density = fog.density * exp(-fog.height_falloff * (camera.y - fog.base_height));
+  float rise = fog.height_falloff * to_fragment.y;
+  // integral of the density along the ray, the limit for a flat ray is the plain distance
+  float along = abs(rise) > 1e-4 ? (1.0 - exp(-rise)) / rise : 1.0;
+  float height_fog = 1.0 - exp(-camera_density * distance_to * along);
+  float edge_fog = smoothstep(fog.start, fog.end, distance_to);
+
+  vec3 sky = sky_behind(to_fragment / max(distance_to, 1e-6));
+  color = mix(color, sky * fog.tint.rgb, clamp(height_fog, 0.0, 1.0));
+  return mix(color, sky, edge_fog);
+}
+
+// 4x4 ordered dither thresholds
+const float bayer[16] = float[16](0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5);
 
 void main() {
-    finalColor = vec4(colors[face].rgb, 1.0);
-}
\ No newline at end of file
+    if ((push.flags & FOG) != 0) {
+        FogData fog = fogs[push.fog_index].fog;
+        float fade = fog.fade_seconds > 0.0 ? (fog.seconds - loaded_at) / fog.fade_seconds : 1.0;
+        ivec2 pixel = ivec2(gl_FragCoord.xy) % 4;
+        if (fade < (bayer[pixel.x + pixel.y * 4] + 0.5) / 16.0) {
+            discard;
+        }
+    }
+
+    Material mat = material_buffer[push.material_index].materials[material];
+    uint layer = face == 5 ? mat.top : (face == 4 ? mat.bottom : mat.side);
+    vec4 color = texture(globalSampleArrays[push.texture_index], vec3(uv, layer));
+    vec3 normal = face_normal[face];
+    vec3 light = vec3(face_shade[face]);
+    if ((push.flags & DAYLIGHT) != 0) {
+        SkyData sky = skies[push.sky_index].sky;
+        float lit = (push.flags & SHADOWS) != 0 ? sun_visibility(shadows[push.shadow_index].shadow, normal) : float(dot(normal, sky.light.xyz) > 0);
+        light *= sky.ambient.rgb + sky.light_color.rgb * lit;
+    } else if ((push.flags & SHADOWS) != 0) {
+        light *= mix(shadow_ambient, 1.0, sun_visibility(shadows[push.shadow_index].shadow, normal));
+    }
+    vec3 shaded = color.rgb * light;
+    if ((push.flags & ATMOSPHERE) != 0) {
+        shaded = aerial_perspective(atmospheres[push.atmosphere_index].atmosphere, shaded);
+    }
+    if ((push.flags & FOG) != 0) {
+        shaded = apply_fog(fogs[push.fog_index].fog, shaded);
+    }
+    finalColor = vec4(shaded, 1.0);
+}
@@ -4,11 +4,6 @@
 
 #extension GL_ARB_gpu_shader_int64 : enable
 
-struct ChunkConstant {
-    uint cam_index;
-    vec3 pos;
-};
-
 struct CameraData{
     mat4 viewproj;
     vec3 pos;
@@ -18,12 +13,24 @@ struct Quad{
   int64_t quad;
 };
 
+// one per indirect draw, read with gl_DrawID
+struct ChunkDraw{
+  vec4 offset;
+};
+
 // Variables
 
 layout(push_constant) uniform constants {
   uint cam_index;
   uint quad_index;
-  vec3 chunk_offset;
+  uint texture_index;
+  uint material_index;
+  uint draw_index;
+  uint shadow_index;
+  uint flags;
+  uint sky_index;
+  uint atmosphere_index;
+  uint fog_index;
 } push;
 
 
@@ -35,6 +42,10 @@ layout(std430, set = 0, binding = 2) readonly buffer Quads{
     Quad quads[];
 } quad_buffer[];
 
+layout(std430, set = 0, binding = 2) readonly buffer Draws{
+    ChunkDraw draws[];
+} draw_buffer[];
+
 
 
 const vec3 normalLookup[6] = {
@@ -76,16 +87,23 @@ const uvec2 vertice_orders[18] = uvec2[18](
     uvec2(1, 1)
 );
 
+layout(location = 0) out vec2 uv;
 layout(location = 1) out uint face_num;
+layout(location = 2) flat out uint material;
+layout(location = 3) out vec3 world_position;
+// when the chunk was loaded, for the fade in
+layout(location = 4) flat out float loaded_at;
 
 const float voxel_scale = 0.1;
 
 void main(){
+// the first instance of each draw is the first quad of its chunk in the shared buffer
   int64_t quad = quad_buffer[push.quad_index].quads[gl_InstanceIndex].quad;
   CameraData camera = cam[push.cam_index].camera;
   int64_t mask = (1 << 7) - 1;
 
-  uint face = uint((quad >> 35) & mask);
+  uint face = uint((quad >> 35) & 7);
+  material = uint((quad >> 38) & 255);
 
   face_num = face;
   uint axis = face/2;
@@ -124,5 +142,18 @@ void main(){
 
 
   vec3 normal = normalLookup[face / 2];
+
+// in blocks so textures repeat once per block across merged quads, v points down on the sides
+  vec3 block_position = final_position.xyz / voxel_scale;
+  uv = vec2(block_position[w_dir], block_position[h_dir]);
+  if (axis != 2) {
+    uv.y = -uv.y;
+  }
+
+// the chunk offset is in blocks
+  vec4 offset = draw_buffer[push.draw_index].draws[gl_DrawID].offset;
+  final_position.xyz += offset.xyz * voxel_scale;
+  world_position = final_position.xyz;
+  loaded_at = offset.w;
   gl_Position = camera.viewproj * final_position;
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+// Rayleigh, Mie and ozone atmosphere shared by the LUT passes, the sky and aerial perspective.
+// Distances are in kilometers from the planet center, see render::atmosphere::AtmosphereData.
+// Needs bindless.glsl first.
+
+struct AtmosphereData {
+  // scattering per km at the ground, w is the scale height
+  vec4 rayleigh;
+  // scattering, extinction, anisotropy and scale height
+  vec4 mie;
+  // absorption per km at the peak of the ozone layer
+  vec4 ozone;
+  // bottom and top radius, camera height above the ground and km per world unit including the aerial scale
+  vec4 radii;
+  // towards the sun and its illuminance
+  vec4 sun;
+  // sampled transmittance and sky view LUTs, their storage images
+  uvec4 luts;
+  // exposure before the tonemap, rest unused
+  vec4 exposure;
+};
+
+layout(set = 0, binding = 3) uniform Atmosphere{
+    AtmosphereData atmosphere;
+}atmospheres[];
+
+// same binding as globalImages, the LUTs are half floats
+layout(set = BindlessDescriptorSet, binding = BindlessStorageImage, rgba16f) uniform image2D lutImages[];
+
+const float PI = 3.14159265;
+const float OZONE_CENTER = 25.0;
+const float OZONE_HALF_WIDTH = 15.0;
+
+// distance to the far intersection with a sphere around the planet center, -1 when missed
+float ray_sphere_far(float height, float cos_zenith, float radius) {
+  float discriminant = height * height * (cos_zenith * cos_zenith - 1.0) + radius * radius;
+  return discriminant < 0.0 ? -1.0 : -height * cos_zenith + sqrt(discriminant);
+}
+
+// distance to the near intersection, -1 when missed or behind
+float ray_sphere_near(float height, float cos_zenith, float radius) {
+  float discriminant = height * height * (cos_zenith * cos_zenith - 1.0) + radius * radius;
+  if (discriminant < 0.0) {
+    return -1.0;
+  }
+  float t = -height * cos_zenith - sqrt(discriminant);
+  return t < 0.0 ? -1.0 : t;
+}
+
+struct Medium {
+  vec3 scattering_rayleigh;
+  float scattering_mie;
+  vec3 extinction;
+};
+
+Medium medium_at(AtmosphereData a, float radius) {
+  float height = max(radius - a.radii.x, 0.0);
+  float rayleigh_density = exp(-height / a.rayleigh.w);
+  float mie_density = exp(-height / a.mie.w);
+  float ozone_density = max(0.0, 1.0 - abs(height - OZONE_CENTER) / OZONE_HALF_WIDTH);
+  Medium m;
+  m.scattering_rayleigh = a.rayleigh.rgb * rayleigh_density;
+  m.scattering_mie = a.mie.x * mie_density;
+  m.extinction = m.scattering_rayleigh + a.mie.y * mie_density + a.ozone.rgb * ozone_density;
+  return m;
+}
+
+float rayleigh_phase(float cos_theta) {
+  return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+// Cornette-Shanks
+float mie_phase(float g, float cos_theta) {
+  float g2 = g * g;
+  return 3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + cos_theta * cos_theta) / ((2.0 + g2) * pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5));
+}
+
+// Bruneton's parametrization, x covers the distance to the top of the atmosphere, y the height
+vec2 transmittance_uv(AtmosphereData a, float radius, float cos_zenith) {
+  float bottom = a.radii.x;
+  float top = a.radii.y;
+  float horizon = sqrt(top * top - bottom * bottom);
+  float rho = sqrt(max(radius * radius - bottom * bottom, 0.0));
+  float d = max(ray_sphere_far(radius, cos_zenith, top), 0.0);
+  float d_min = top - radius;
+  float d_max = rho + horizon;
+  return vec2((d - d_min) / (d_max - d_min), rho / horizon);
+}
+
+void transmittance_from_uv(AtmosphereData a, vec2 uv, out float radius, out float cos_zenith) {
+  float bottom = a.radii.x;
+  float top = a.radii.y;
+  float horizon = sqrt(top * top - bottom * bottom);
+  float rho = horizon * uv.y;
+  radius = sqrt(rho * rho + bottom * bottom);
+  float d_min = top - radius;
+  float d_max = rho + horizon;
+  float d = d_min + uv.x * (d_max - d_min);
+  cos_zenith = d == 0.0 ? 1.0 : clamp((horizon * horizon - rho * rho - d * d) / (2.0 * radius * d), -1.0, 1.0);
+}
+
+// towards the top of the atmosphere, 0 through the ground
+vec3 transmittance(AtmosphereData a, float radius, float cos_zenith) {
+  if (ray_sphere_near(radius, cos_zenith, a.radii.x) > 0.0) {
+    return vec3(0.0);
+  }
+  return texture(globalSamples[a.luts.x], transmittance_uv(a, radius, cos_zenith)).rgb;
+}
+
+// Hillaire's sky view parametrization: y is the zenith angle with more texels near the horizon,
+// x the azimuth from the sun, squared so the texels bunch up around it
+vec2 sky_view_uv(AtmosphereData a, float radius, float cos_zenith, float cos_azimuth) {
+  float horizon = sqrt(max(radius * radius - a.radii.x * a.radii.x, 0.0));
+  float beta = acos(clamp(horizon / radius, -1.0, 1.0));
+  float zenith_horizon = PI - beta;
+  float zenith = acos(clamp(cos_zenith, -1.0, 1.0));
+  float v;
+  if (zenith < zenith_horizon) {
+    v = (1.0 - sqrt(1.0 - zenith / zenith_horizon)) * 0.5;
+  } else {
+    v = sqrt((zenith - zenith_horizon) / beta) * 0.5 + 0.5;
+  }
+  return vec2(sqrt(-cos_azimuth * 0.5 + 0.5), v);
+}
+
+void sky_view_from_uv(AtmosphereData a, float radius, vec2 uv, out float cos_zenith, out float cos_azimuth) {
+  float horizon = sqrt(max(radius * radius - a.radii.x * a.radii.x, 0.0));
+  float beta = acos(clamp(horizon / radius, -1.0, 1.0));
+  float zenith_horizon = PI - beta;
+  if (uv.y < 0.5) {
+    float coord = 1.0 - uv.y * 2.0;
+    cos_zenith = cos(zenith_horizon * (1.0 - coord * coord));
+  } else {
+    float coord = uv.y * 2.0 - 1.0;
+    cos_zenith = cos(zenith_horizon + beta * coord * coord);
+  }
+  cos_azimuth = -(uv.x * uv.x * 2.0 - 1.0);
+}
+
+// cosine of the azimuth between a direction and the sun, the sky is symmetric around the sun
+float azimuth_to_sun(vec3 dir, vec3 sun) {
+  vec2 flat_dir = dir.xz;
+  vec2 flat_sun = sun.xz;
+  if (dot(flat_dir, flat_dir) < 1e-8 || dot(flat_sun, flat_sun) < 1e-8) {
+    return 1.0;
+  }
+  return dot(normalize(flat_dir), normalize(flat_sun));
+}
+
+float camera_radius(AtmosphereData a) {
+  return a.radii.x + a.radii.z;
+}
+
+// light scattered towards the camera along `dir`, from the sky view LUT
+vec3 sky_luminance(AtmosphereData a, vec3 dir) {
+  vec2 uv = sky_view_uv(a, camera_radius(a), dir.y, azimuth_to_sun(dir, a.sun.xyz));
+  return texture(globalSamples[a.luts.y], uv).rgb;
+}
+
+vec3 tonemap(AtmosphereData a, vec3 luminance) {
+  return 1.0 - exp(-luminance * a.exposure.x);
+}
@@ -0,0 +1,27 @@
+// the time of day, written by render::sky::SkyPass. Needs bindless.glsl first.
+
+struct SkyData {
+  mat4 inverse_viewproj;
+  vec4 camera;
+  vec4 sun;
+  vec4 moon;
+  vec4 zenith;
+  vec4 horizon;
+  vec4 light;
+  vec4 light_color;
+  vec4 ambient;
+  // visibility and rotation
+  vec4 stars;
+};
+
+layout(set = 0, binding = 3) uniform Sky{
+    SkyData sky;
+}skies[];
+
+// horizon to zenith, the sky without the atmosphere and the night sky with it
+vec3 gradient(SkyData sky, vec3 dir) {
+  float height = dir.y;
+  vec3 color = mix(sky.horizon.rgb, sky.zenith.rgb, sqrt(clamp(height, 0.0, 1.0)));
+  // below the horizon fades to a darker horizon
+  return height < 0.0 ? sky.horizon.rgb * mix(1.0, 0.4, clamp(-height * 4.0, 0.0, 1.0)) : color;
+}
@@ -0,0 +1,21 @@
+#version 460
+#include "../bindless.glsl"
+
+layout(push_constant) uniform constants {
+  vec2 scale;
+  vec2 translate;
+  uint vertex_index;
+  uint index_index;
+  uint texture_index;
+  uint first_index;
+  uint vertex_offset;
+} push;
+
+layout(location = 0) in vec2 uv;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 out_color;
+
+void main(){
+  out_color = color * texture(globalSamples[push.texture_index], uv);
+}
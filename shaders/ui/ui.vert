@@ -0,0 +1,38 @@
+#version 460
+#include "../bindless.glsl"
+
+// imgui vertices and indices pulled from storage buffers, see `render::ui::Gui`
+// an ImDrawVert is 5 words: pos, uv and the color as rgba8
+
+layout(push_constant) uniform constants {
+  vec2 scale;
+  vec2 translate;
+  uint vertex_index;
+  uint index_index;
+  uint texture_index;
+  uint first_index;
+  uint vertex_offset;
+} push;
+
+layout(std430, set = 0, binding = 2) readonly buffer UiVertices{
+  uint words[];
+} vertex_buffer[];
+
+// 16 bit indices, two per word
+layout(std430, set = 0, binding = 2) readonly buffer UiIndices{
+  uint words[];
+} index_buffer[];
+
+layout(location = 0) out vec2 uv;
+layout(location = 1) out vec4 color;
+
+void main(){
+  uint index_position = push.first_index + gl_VertexIndex;
+  uint index = (index_buffer[push.index_index].words[index_position / 2] >> (16 * (index_position & 1))) & 0xffff;
+  uint base = (push.vertex_offset + index) * 5;
+
+  vec2 position = vec2(uintBitsToFloat(vertex_buffer[push.vertex_index].words[base]), uintBitsToFloat(vertex_buffer[push.vertex_index].words[base + 1]));
+  uv = vec2(uintBitsToFloat(vertex_buffer[push.vertex_index].words[base + 2]), uintBitsToFloat(vertex_buffer[push.vertex_index].words[base + 3]));
+  color = unpackUnorm4x8(vertex_buffer[push.vertex_index].words[base + 4]);
+  gl_Position = vec4(position * push.scale + push.translate, 0, 1);
+}
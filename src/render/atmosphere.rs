@@ -0,0 +1,208 @@
+use ash::vk;
+use voxelengine::vulkan::util::slice_as_u8;
+
+use super::{
+    gpu::{image_barrier, update_buffer, Buffer, Gpu, Image, BINDLESS_SAMPLED_IMAGE, BINDLESS_STORAGE_IMAGE, BINDLESS_UNIFORM},
+    CameraData, RENDER_VOXEL_SCALE,
+};
+
+const TRANSMITTANCE_SHADER: &str = "shaders/spv/transmittance.comp.spv";
+const SKY_VIEW_SHADER: &str = "shaders/spv/skyview.comp.spv";
+/// `local_size_x` and `local_size_y` of both LUT shaders
+const LUT_GROUP_SIZE: u32 = 8;
+const LUT_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const TRANSMITTANCE_EXTENT: vk::Extent2D = vk::Extent2D { width: 256, height: 64 };
+const SKY_VIEW_EXTENT: vk::Extent2D = vk::Extent2D { width: 192, height: 108 };
+
+/// Earth like atmosphere from Hillaire's "A Scalable and Production Ready Sky and Atmosphere Rendering Technique", in kilometers
+const BOTTOM_RADIUS: f32 = 6360.0;
+const TOP_RADIUS: f32 = 6460.0;
+const RAYLEIGH_SCATTERING: [f32; 3] = [5.802e-3, 13.558e-3, 33.1e-3];
+const RAYLEIGH_SCALE_HEIGHT: f32 = 8.0;
+const MIE_SCATTERING: f32 = 3.996e-3;
+const MIE_EXTINCTION: f32 = 4.44e-3;
+const MIE_SCALE_HEIGHT: f32 = 1.2;
+const OZONE_ABSORPTION: [f32; 3] = [0.65e-3, 1.881e-3, 0.085e-3];
+/// Tent shaped ozone layer, matches `OZONE_CENTER` and `OZONE_HALF_WIDTH` in `shaders/sky/atmosphere.glsl`
+const OZONE_CENTER: f32 = 25.0;
+const OZONE_HALF_WIDTH: f32 = 15.0;
+/// Steps of `transmittance`, the same as `shaders/sky/transmittance.comp`
+const TRANSMITTANCE_STEPS: usize = 40;
+
+/// What the settings UI changes, everything takes effect the next frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtmosphereSettings {
+    pub enabled: bool,
+    /// Multipliers of the Rayleigh and Mie densities, more Mie gives a hazier sky
+    pub rayleigh_density: f32,
+    pub mie_density: f32,
+    /// How much Mie scattering goes forward, the glow around the sun
+    pub mie_anisotropy: f32,
+    pub sun_illuminance: f32,
+    /// Luminance multiplier before the tonemap
+    pub exposure: f32,
+    /// Distances to terrain are stretched by this for aerial perspective, the render distance is only a few hundred blocks
+    pub aerial_scale: f32,
+}
+
+impl Default for AtmosphereSettings {
+    fn default() -> Self {
+        Self { enabled: true, rayleigh_density: 1.0, mie_density: 1.0, mie_anisotropy: 0.8, sun_illuminance: 1.0, exposure: 10.0, aerial_scale: 10.0 }
+    }
+}
+
+impl AtmosphereSettings {
+    /// Keeps edited values in a range the LUTs can handle
+    pub fn clamp(&mut self) {
+        self.rayleigh_density = self.rayleigh_density.clamp(0.0, 10.0);
+        self.mie_density = self.mie_density.clamp(0.0, 50.0);
+        self.mie_anisotropy = self.mie_anisotropy.clamp(0.0, 0.99);
+        self.sun_illuminance = self.sun_illuminance.max(0.0);
+        self.exposure = self.exposure.max(0.0);
+        self.aerial_scale = self.aerial_scale.max(0.0);
+    }
+
+    fn rayleigh(&self) -> glm::Vec3 {
+        glm::Vec3::from(RAYLEIGH_SCATTERING) * self.rayleigh_density
+    }
+
+    /// Extinction per km at `height` km above the ground
+    fn extinction(&self, height: f32) -> glm::Vec3 {
+        let ozone = (1.0 - (height - OZONE_CENTER).abs() / OZONE_HALF_WIDTH).max(0.0);
+        self.rayleigh() * (-height / RAYLEIGH_SCALE_HEIGHT).exp() + glm::Vec3::broadcast(MIE_EXTINCTION * self.mie_density * (-height / MIE_SCALE_HEIGHT).exp()) + glm::Vec3::from(OZONE_ABSORPTION) * ozone
+    }
+
+    /// Fraction of the light reaching `height` km above the ground from a direction `cos_zenith` away from straight up,
+    /// what `shaders/sky/transmittance.comp` stores. Colors the sunlight on the cpu.
+    pub fn transmittance(&self, height: f32, cos_zenith: f32) -> glm::Vec3 {
+        let radius = BOTTOM_RADIUS + height.max(0.0);
+        let discriminant = radius * radius * (cos_zenith * cos_zenith - 1.0) + BOTTOM_RADIUS * BOTTOM_RADIUS;
+        if discriminant >= 0.0 && -radius * cos_zenith - discriminant.sqrt() > 0.0 {
+            return glm::Vec3::zero();
+        }
+        let length = (-radius * cos_zenith + (radius * radius * (cos_zenith * cos_zenith - 1.0) + TOP_RADIUS * TOP_RADIUS).sqrt()).max(0.0);
+        let step = length / TRANSMITTANCE_STEPS as f32;
+        let mut depth = glm::Vec3::zero();
+        for index in 0..TRANSMITTANCE_STEPS {
+            let t = (index as f32 + 0.5) * step;
+            let r = (radius * radius + t * t + 2.0 * radius * cos_zenith * t).sqrt();
+            depth += self.extinction(r - BOTTOM_RADIUS) * step;
+        }
+        depth.map(|v| (-v).exp())
+    }
+}
+
+/// Matches `AtmosphereData` in `shaders/sky/atmosphere.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct AtmosphereData {
+    /// Scattering per km at the ground, w is the scale height
+    pub rayleigh: glm::Vec4,
+    /// Scattering, extinction, anisotropy and scale height
+    pub mie: glm::Vec4,
+    pub ozone: glm::Vec4,
+    /// Bottom and top radius, camera height and km per world unit for aerial perspective
+    pub radii: glm::Vec4,
+    /// Towards the sun, w is the illuminance
+    pub sun: glm::Vec4,
+    /// Sampled transmittance and sky view LUTs, then their storage images
+    pub luts: [u32; 4],
+    /// x is the exposure, the rest is unused
+    pub exposure: glm::Vec4,
+}
+
+/// Rayleigh and Mie single scattering with ozone absorption. Every frame a compute pass fills a transmittance LUT
+/// and a sky view LUT for the camera height and sun, the sky and the aerial perspective of chunks sample them.
+pub struct Atmosphere {
+    transmittance: Image,
+    sky_view: Image,
+    sampler: vk::Sampler,
+    transmittance_pipeline: vk::Pipeline,
+    sky_view_pipeline: vk::Pipeline,
+    luts: [u32; 4],
+    data: Buffer,
+    /// Uniform index of the `AtmosphereData`
+    pub data_index: u32,
+    pub settings: AtmosphereSettings,
+}
+
+impl Atmosphere {
+    pub fn new(gpu: &mut Gpu, settings: AtmosphereSettings) -> Result<Self, String> {
+        let mut settings = settings;
+        settings.clamp();
+        let transmittance_pipeline = gpu.compute_pipeline(TRANSMITTANCE_SHADER)?;
+        let sky_view_pipeline = gpu.compute_pipeline(SKY_VIEW_SHADER)?;
+        let usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED;
+        let transmittance = gpu.create_image(TRANSMITTANCE_EXTENT, LUT_FORMAT, usage, vk::ImageViewType::TYPE_2D, 1, 1);
+        let sky_view = gpu.create_image(SKY_VIEW_EXTENT, LUT_FORMAT, usage, vk::ImageViewType::TYPE_2D, 1, 1);
+
+        // the sky view only holds the half of the sky on one side of the sun, clamping is right in both directions
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { gpu.device.create_sampler(&sampler_info, None).unwrap() };
+
+        let luts = [
+            gpu.bindless.add_image(&gpu.device, transmittance.view, sampler, BINDLESS_SAMPLED_IMAGE),
+            gpu.bindless.add_image(&gpu.device, sky_view.view, sampler, BINDLESS_SAMPLED_IMAGE),
+            gpu.bindless.add_image(&gpu.device, transmittance.view, vk::Sampler::null(), BINDLESS_STORAGE_IMAGE),
+            gpu.bindless.add_image(&gpu.device, sky_view.view, vk::Sampler::null(), BINDLESS_STORAGE_IMAGE),
+        ];
+        let data = gpu.create_buffer(size_of::<AtmosphereData>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, false);
+        let data_index = gpu.bindless.add_buffer(&gpu.device, &data, BINDLESS_UNIFORM);
+        Ok(Self { transmittance, sky_view, sampler, transmittance_pipeline, sky_view_pipeline, luts, data, data_index, settings })
+    }
+
+    pub fn data(&self, sun: glm::Vec3, camera: &CameraData) -> AtmosphereData {
+        let settings = &self.settings;
+        // a block is a meter, the world starts at sea level
+        let camera_height = (camera.pos.y / RENDER_VOXEL_SCALE / 1000.0).max(0.0);
+        let km_per_unit = settings.aerial_scale / RENDER_VOXEL_SCALE / 1000.0;
+        AtmosphereData {
+            rayleigh: settings.rayleigh().into_homogeneous_vector() + glm::Vec4::new(0.0, 0.0, 0.0, RAYLEIGH_SCALE_HEIGHT),
+            mie: glm::Vec4::new(MIE_SCATTERING * settings.mie_density, MIE_EXTINCTION * settings.mie_density, settings.mie_anisotropy, MIE_SCALE_HEIGHT),
+            ozone: glm::Vec3::from(OZONE_ABSORPTION).into_homogeneous_vector(),
+            radii: glm::Vec4::new(BOTTOM_RADIUS, TOP_RADIUS, camera_height, km_per_unit),
+            sun: glm::Vec4::new(sun.x, sun.y, sun.z, settings.sun_illuminance),
+            luts: self.luts,
+            exposure: glm::Vec4::new(settings.exposure, 0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Record before `record` and everything sampling the LUTs, outside of rendering
+    pub fn update(&self, gpu: &Gpu, cmd: vk::CommandBuffer, sun: glm::Vec3, camera: &CameraData) {
+        update_buffer(&gpu.device, cmd, &self.data, slice_as_u8(&[self.data(sun, camera)]));
+    }
+
+    /// Fills both LUTs and leaves them ready for sampling in compute and fragment shaders
+    pub fn record(&self, gpu: &Gpu, cmd: vk::CommandBuffer) {
+        let device = &gpu.device;
+        let sampled = (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags2::COMPUTE_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ);
+        let storage = (vk::ImageLayout::GENERAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE);
+        // the sky view samples the transmittance, so they run in this order
+        for (image, pipeline) in [(&self.transmittance, self.transmittance_pipeline), (&self.sky_view, self.sky_view_pipeline)] {
+            // last frame's contents aren't needed, waiting for the last readers is enough
+            image_barrier(device, cmd, image, (vk::ImageLayout::UNDEFINED, sampled.1, vk::AccessFlags2::NONE), storage);
+            unsafe {
+                device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, pipeline);
+                gpu.bind_bindless(cmd, vk::PipelineBindPoint::COMPUTE);
+                gpu.push_constants(cmd, &self.data_index);
+                device.cmd_dispatch(cmd, image.extent.width.div_ceil(LUT_GROUP_SIZE), image.extent.height.div_ceil(LUT_GROUP_SIZE), 1);
+            }
+            image_barrier(device, cmd, image, storage, sampled);
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.transmittance_pipeline, None);
+            device.destroy_pipeline(self.sky_view_pipeline, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+        self.transmittance.destroy(device);
+        self.sky_view.destroy(device);
+        self.data.destroy(device);
+    }
+}
@@ -0,0 +1,236 @@
+use ash::vk;
+use voxelengine::vulkan::util::slice_as_u8;
+
+use super::{
+    gpu::{Buffer, Gpu, BINDLESS_STORAGE_BUFFER},
+    ChunkPushConstant,
+};
+use crate::world_test::mesh::GPUQuad;
+
+/// Face directions of `GPUQuad::face`, quads of a chunk are sorted into one group per direction
+pub const FACE_GROUPS: usize = 6;
+
+/// Per draw data, the vertex shader reads it with `gl_DrawID`. Matches `ChunkDraw` in `shaders/chunk/chunk.vert`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChunkDraw {
+    /// World position of the chunk in blocks, w is when it was loaded in seconds on the clock of `fog::FogData::seconds`
+    pub offset: [f32; 4],
+}
+
+/// What culling needs to know about a chunk. Matches `ChunkRecord` in `shaders/chunk/cull.comp`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkRecord {
+    /// World space bounds of the quads in blocks, w is unused
+    pub min: [f32; 4],
+    pub max: [f32; 4],
+    pub draw: ChunkDraw,
+    /// First quad and quad count per face direction
+    pub groups: [[u32; 2]; FACE_GROUPS],
+}
+
+/// Vertices of the line list drawing the 12 edges of a box
+pub const CULLED_BOX_VERTICES: u32 = 24;
+
+/// Bounds of a chunk rejected by occlusion culling. Matches `Bounds` in `shaders/chunk/cull.comp`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CulledBounds {
+    /// In blocks like `ChunkRecord`, w is unused
+    pub min: [f32; 4],
+    pub max: [f32; 4],
+}
+
+/// Matches the push constants of `shaders/chunk/bounds.vert`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct BoundsPushConstant {
+    cam_index: u32,
+    culled_index: u32,
+}
+
+/// Quads of many chunks packed into one buffer with one indirect draw per chunk
+#[derive(Default)]
+pub struct ChunkBatch {
+    pub quads: Vec<GPUQuad>,
+    pub chunks: Vec<ChunkRecord>,
+    pub draws: Vec<ChunkDraw>,
+    pub commands: Vec<vk::DrawIndirectCommand>,
+}
+
+impl ChunkBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `position` in blocks, chunks without quads get no draw
+    pub fn add(&mut self, position: [i32; 3], quads: Vec<GPUQuad>) {
+        self.add_loaded_at(position, quads, 0.0);
+    }
+
+    /// Like `add`, the chunk dithers in over `fog::FogSettings::fade_seconds` from `loaded_at`
+    pub fn add_loaded_at(&mut self, position: [i32; 3], mut quads: Vec<GPUQuad>, loaded_at: f32) {
+        if quads.is_empty() {
+            return;
+        }
+        quads.sort_by_key(GPUQuad::face);
+        let first = self.quads.len() as u32;
+        let offset = [position[0] as f32, position[1] as f32, position[2] as f32, loaded_at];
+
+        let mut record = ChunkRecord { min: [f32::MAX; 4], max: [f32::MIN; 4], draw: ChunkDraw { offset }, groups: [[0; 2]; FACE_GROUPS] };
+        for (index, quad) in quads.iter().enumerate() {
+            let group = &mut record.groups[quad.face()];
+            if group[1] == 0 {
+                group[0] = first + index as u32;
+            }
+            group[1] += 1;
+
+            let (min, max) = quad.bounds();
+            for axis in 0..3 {
+                record.min[axis] = record.min[axis].min(offset[axis] + min[axis] as f32);
+                record.max[axis] = record.max[axis].max(offset[axis] + max[axis] as f32);
+            }
+        }
+        record.min[3] = 0.0;
+        record.max[3] = 0.0;
+
+        self.commands.push(vk::DrawIndirectCommand { vertex_count: 6, instance_count: quads.len() as u32, first_vertex: 0, first_instance: first });
+        self.draws.push(record.draw);
+        self.chunks.push(record);
+        self.quads.extend(quads);
+    }
+
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// Most draws culling can output, one per face group
+    pub fn max_draws(&self) -> usize {
+        self.chunks.len() * FACE_GROUPS
+    }
+}
+
+/// A `ChunkBatch` on the gpu, drawn with a single `cmd_draw_indirect_count`.
+/// The indirect, draw and count buffers start out drawing every chunk, culling rewrites them.
+pub struct GpuChunks {
+    pub quads: Buffer,
+    pub chunks: Buffer,
+    pub draws: Buffer,
+    pub indirect: Buffer,
+    /// Number of draws in `indirect`, one u32
+    pub count: Buffer,
+    /// Every chunk with all its quads whatever the culling kept, for passes that don't see through the camera
+    pub all_draws: Buffer,
+    pub all_indirect: Buffer,
+    /// Line draw of the chunks occlusion culling rejected, an indirect command followed by `CulledBounds`
+    pub culled: Buffer,
+    pub quad_index: u32,
+    pub chunk_index: u32,
+    pub draw_index: u32,
+    pub all_draw_index: u32,
+    pub indirect_index: u32,
+    pub count_index: u32,
+    pub culled_index: u32,
+    pub chunk_count: u32,
+    pub max_draws: u32,
+}
+
+impl GpuChunks {
+    pub fn upload(gpu: &mut Gpu, batch: &ChunkBatch) -> Self {
+        let max_draws = batch.max_draws();
+        let quads = gpu.buffer_with_data(vk::BufferUsageFlags::STORAGE_BUFFER, slice_as_u8(&batch.quads));
+        let chunks = gpu.buffer_with_data(vk::BufferUsageFlags::STORAGE_BUFFER, slice_as_u8(&batch.chunks));
+        let draws = gpu.create_buffer((max_draws * size_of::<ChunkDraw>()) as u64, vk::BufferUsageFlags::STORAGE_BUFFER, true);
+        draws.write(0, slice_as_u8(&batch.draws));
+        let indirect = gpu.create_buffer((max_draws * size_of::<vk::DrawIndirectCommand>()) as u64, vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER, true);
+        indirect.write(0, slice_as_u8(&batch.commands));
+        let count = gpu.buffer_with_data(vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, &(batch.len() as u32).to_le_bytes());
+        let all_draws = gpu.buffer_with_data(vk::BufferUsageFlags::STORAGE_BUFFER, slice_as_u8(&batch.draws));
+        let all_indirect = gpu.buffer_with_data(vk::BufferUsageFlags::INDIRECT_BUFFER, slice_as_u8(&batch.commands));
+        let culled = gpu.create_buffer((size_of::<vk::DrawIndirectCommand>() + batch.chunks.len().max(1) * size_of::<CulledBounds>()) as u64, vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, true);
+        culled.write(0, slice_as_u8(&[vk::DrawIndirectCommand { vertex_count: CULLED_BOX_VERTICES, instance_count: 0, first_vertex: 0, first_instance: 0 }]));
+
+        Self {
+            quad_index: gpu.bindless.add_buffer(&gpu.device, &quads, BINDLESS_STORAGE_BUFFER),
+            chunk_index: gpu.bindless.add_buffer(&gpu.device, &chunks, BINDLESS_STORAGE_BUFFER),
+            draw_index: gpu.bindless.add_buffer(&gpu.device, &draws, BINDLESS_STORAGE_BUFFER),
+            all_draw_index: gpu.bindless.add_buffer(&gpu.device, &all_draws, BINDLESS_STORAGE_BUFFER),
+            indirect_index: gpu.bindless.add_buffer(&gpu.device, &indirect, BINDLESS_STORAGE_BUFFER),
+            count_index: gpu.bindless.add_buffer(&gpu.device, &count, BINDLESS_STORAGE_BUFFER),
+            culled_index: gpu.bindless.add_buffer(&gpu.device, &culled, BINDLESS_STORAGE_BUFFER),
+            quads,
+            chunks,
+            draws,
+            indirect,
+            count,
+            all_draws,
+            all_indirect,
+            culled,
+            chunk_count: batch.chunks.len() as u32,
+            max_draws: max_draws as u32,
+        }
+    }
+
+    /// Every chunk in one call, `push` only needs the camera and texture indices
+    pub fn draw(&self, gpu: &Gpu, cmd: vk::CommandBuffer, push: ChunkPushConstant) {
+        gpu.push_constants(cmd, &ChunkPushConstant { quad_index: self.quad_index, draw_index: self.draw_index, ..push });
+        unsafe { gpu.device.cmd_draw_indirect_count(cmd, self.indirect.buffer, 0, self.count.buffer, 0, self.max_draws, size_of::<vk::DrawIndirectCommand>() as u32) };
+    }
+
+    /// Every chunk ignoring the cull pass, used by the shadow cascades
+    pub fn draw_all(&self, gpu: &Gpu, cmd: vk::CommandBuffer, push: ChunkPushConstant) {
+        gpu.push_constants(cmd, &ChunkPushConstant { quad_index: self.quad_index, draw_index: self.all_draw_index, ..push });
+        unsafe { gpu.device.cmd_draw_indirect(cmd, self.all_indirect.buffer, 0, self.chunk_count, size_of::<vk::DrawIndirectCommand>() as u32) };
+    }
+
+    /// Boxes around the chunks occlusion culling rejected, for a line list pipeline with `shaders/chunk/bounds.vert`
+    pub fn draw_culled(&self, gpu: &Gpu, cmd: vk::CommandBuffer, cam_index: u32) {
+        gpu.push_constants(cmd, &BoundsPushConstant { cam_index, culled_index: self.culled_index });
+        unsafe { gpu.device.cmd_draw_indirect(cmd, self.culled.buffer, 0, 1, size_of::<vk::DrawIndirectCommand>() as u32) };
+    }
+
+    /// Commands and draws currently in the buffers, only valid once the gpu is done with them
+    pub fn read_draws(&self) -> Vec<(vk::DrawIndirectCommand, ChunkDraw)> {
+        let count = u32::from_le_bytes(self.count.read()[..4].try_into().unwrap()) as usize;
+        let commands = self.indirect.read();
+        let draws = self.draws.read();
+        (0..count.min(self.max_draws as usize))
+            .map(|index| {
+                let command = &commands[index * size_of::<vk::DrawIndirectCommand>()..];
+                let word = |i: usize| u32::from_le_bytes(command[i * 4..i * 4 + 4].try_into().unwrap());
+                let draw = &draws[index * size_of::<ChunkDraw>()..];
+                let float = |i: usize| f32::from_le_bytes(draw[i * 4..i * 4 + 4].try_into().unwrap());
+                (vk::DrawIndirectCommand { vertex_count: word(0), instance_count: word(1), first_vertex: word(2), first_instance: word(3) }, ChunkDraw { offset: [float(0), float(1), float(2), float(3)] })
+            })
+            .collect()
+    }
+
+    /// Bounds of the chunks the last occlusion cull rejected, only valid once the gpu is done with them
+    pub fn read_culled(&self) -> Vec<CulledBounds> {
+        let culled = self.culled.read();
+        let count = u32::from_le_bytes(culled[4..8].try_into().unwrap()) as usize;
+        let float = |i: usize| f32::from_le_bytes(culled[i * 4..i * 4 + 4].try_into().unwrap());
+        let header = size_of::<vk::DrawIndirectCommand>() / 4;
+        (0..count.min(self.chunk_count as usize))
+            .map(|index| {
+                let base = header + index * 8;
+                CulledBounds { min: std::array::from_fn(|i| float(base + i)), max: std::array::from_fn(|i| float(base + 4 + i)) }
+            })
+            .collect()
+    }
+
+    /// Like `destroy`, and hands the bindless indices out again. Retire it with `FrameScheduler::retire` while frames read it.
+    pub fn free(&self, gpu: &mut Gpu) {
+        for index in [self.quad_index, self.chunk_index, self.draw_index, self.all_draw_index, self.indirect_index, self.count_index, self.culled_index] {
+            gpu.bindless.free(BINDLESS_STORAGE_BUFFER, index);
+        }
+        self.destroy(&gpu.device);
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        for buffer in [&self.quads, &self.chunks, &self.draws, &self.indirect, &self.count, &self.all_draws, &self.all_indirect, &self.culled] {
+            buffer.destroy(device);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use ash::vk;
+
+use super::{
+    chunks::{ChunkBatch, ChunkDraw, GpuChunks, FACE_GROUPS},
+    gpu::{buffer_barrier, Gpu},
+    hiz::{CpuHiZ, HiZ},
+    CameraData, RENDER_VOXEL_SCALE,
+};
+
+const CULL_SHADER: &str = "shaders/spv/cull.comp.spv";
+/// `local_size_x` of `cull.comp`
+const CULL_GROUP_SIZE: u32 = 64;
+
+/// Planes of a view projection matrix, normals point inside
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    /// Left, right, bottom, top, near and far of the vulkan clip space with depth from 0 to 1
+    pub fn from_viewproj(viewproj: &glm::Mat4) -> Self {
+        let rows: [glm::Vec4; 4] = std::array::from_fn(|row| glm::Vec4::new(viewproj.cols[0][row], viewproj.cols[1][row], viewproj.cols[2][row], viewproj.cols[3][row]));
+        Self { planes: [rows[3] + rows[0], rows[3] - rows[0], rows[3] + rows[1], rows[3] - rows[1], rows[2], rows[3] - rows[2]] }
+    }
+
+    /// False only if the box is completely outside one plane, boxes near a corner can pass
+    pub fn intersects_aabb(&self, min: glm::Vec3, max: glm::Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            let corner = glm::Vec3::new(if plane.x > 0.0 { max.x } else { min.x }, if plane.y > 0.0 { max.y } else { min.y }, if plane.z > 0.0 { max.z } else { min.z });
+            plane.xyz().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+/// Faces of a group can only be seen from the side they point to, see `GPUQuad::face`
+fn group_faces_camera(group: usize, min: &[f32; 4], max: &[f32; 4], cam_pos: glm::Vec3) -> bool {
+    let axis = [0, 2, 1][group / 2];
+    let cam = [cam_pos.x, cam_pos.y, cam_pos.z][axis];
+    if group.is_multiple_of(2) {
+        cam < max[axis]
+    } else {
+        cam > min[axis]
+    }
+}
+
+/// What `occluded` in `cull.comp` decides, true when the whole box is behind the depth in `hiz`
+pub fn occluded_cpu(viewproj: &glm::Mat4, min: glm::Vec3, max: glm::Vec3, hiz: &CpuHiZ) -> bool {
+    let mut screen_min = glm::Vec2::broadcast(1.0);
+    let mut screen_max = glm::Vec2::broadcast(0.0);
+    let mut nearest = 1.0f32;
+    for i in 0..8 {
+        let corner = glm::Vec3::new(if i & 1 != 0 { max.x } else { min.x }, if i & 2 != 0 { max.y } else { min.y }, if i & 4 != 0 { max.z } else { min.z });
+        let clip = *viewproj * corner.into_homogeneous_point();
+        // reaches behind the camera, the projection isn't usable
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz() / clip.w;
+        let screen = ndc.xy() * 0.5 + glm::Vec2::broadcast(0.5);
+        screen_min = screen_min.min_by_component(screen);
+        screen_max = screen_max.max_by_component(screen);
+        nearest = nearest.min(ndc.z);
+    }
+
+    // in pixels of level 0, a rect no larger than a texel of the level touches at most 2x2 texels
+    let size = glm::Vec2::new(hiz.extent.width as f32, hiz.extent.height as f32);
+    let pixel_min = screen_min.clamped(glm::Vec2::zero(), glm::Vec2::one()) * size;
+    let pixel_max = screen_max.clamped(glm::Vec2::zero(), glm::Vec2::one()) * size;
+    let extent = pixel_max - pixel_min;
+    let level = (extent.x.max(extent.y).max(1.0).log2().ceil() as i32).clamp(0, hiz.levels.len() as i32 - 1) as u32;
+
+    let level_size = hiz.level_size(level);
+    let lo = [((pixel_min.x as u32) >> level).min(level_size[0] - 1), ((pixel_min.y as u32) >> level).min(level_size[1] - 1)];
+    let hi = [((pixel_max.x as u32) >> level).min(level_size[0] - 1), ((pixel_max.y as u32) >> level).min(level_size[1] - 1)];
+    let furthest = [lo, [hi[0], lo[1]], [lo[0], hi[1]], hi].map(|texel| hiz.texel(level, texel)).into_iter().fold(0.0, f32::max);
+    nearest > furthest
+}
+
+/// What `cull.comp` outputs in the order the chunks come in, the gpu appends in any order.
+/// With `hiz` chunks behind its depth are dropped like with occlusion culling.
+pub fn cull_cpu(batch: &ChunkBatch, camera: &CameraData, face_culling: bool, hiz: Option<&CpuHiZ>) -> Vec<(vk::DrawIndirectCommand, ChunkDraw)> {
+    let frustum = Frustum::from_viewproj(&camera.viewproj);
+    let cam_pos = camera.pos.xyz() / RENDER_VOXEL_SCALE;
+    let mut out = vec![];
+    for chunk in &batch.chunks {
+        let min = glm::Vec3::new(chunk.min[0], chunk.min[1], chunk.min[2]) * RENDER_VOXEL_SCALE;
+        let max = glm::Vec3::new(chunk.max[0], chunk.max[1], chunk.max[2]) * RENDER_VOXEL_SCALE;
+        if !frustum.intersects_aabb(min, max) || hiz.is_some_and(|hiz| occluded_cpu(&camera.viewproj, min, max, hiz)) {
+            continue;
+        }
+        for group in 0..FACE_GROUPS {
+            let [first, count] = chunk.groups[group];
+            if count == 0 || (face_culling && !group_faces_camera(group, &chunk.min, &chunk.max, cam_pos)) {
+                continue;
+            }
+            out.push((vk::DrawIndirectCommand { vertex_count: 6, instance_count: count, first_vertex: 0, first_instance: first }, chunk.draw));
+        }
+    }
+    out
+}
+
+/// Matches the push constants of `shaders/chunk/cull.comp`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct CullPushConstant {
+    cam_index: u32,
+    chunk_index: u32,
+    draw_index: u32,
+    indirect_index: u32,
+    count_index: u32,
+    chunk_count: u32,
+    flags: u32,
+    hiz_index: u32,
+    hiz_size: [u32; 2],
+    hiz_levels: u32,
+    culled_index: u32,
+}
+
+/// Bits of `flags` in `cull.comp`
+const FACE_CULLING: u32 = 1;
+const OCCLUSION_CULLING: u32 = 2;
+
+/// Frustum and occlusion culling in a compute pass, visible chunks are compacted into the indirect buffer of `GpuChunks`
+pub struct CullPass {
+    pipeline: vk::Pipeline,
+    pub face_culling: bool,
+}
+
+impl CullPass {
+    pub fn new(gpu: &Gpu) -> Result<Self, String> {
+        Ok(Self { pipeline: gpu.compute_pipeline(CULL_SHADER)?, face_culling: true })
+    }
+
+    /// Record before the render pass that draws `chunks`, the barrier at the end makes the output visible to the draw.
+    /// With `hiz` chunks behind its depth are dropped too and their bounds end up in `GpuChunks::culled`.
+    pub fn record(&self, gpu: &Gpu, cmd: vk::CommandBuffer, chunks: &GpuChunks, cam_index: u32, hiz: Option<&HiZ>) {
+        let mut push = CullPushConstant {
+            cam_index,
+            chunk_index: chunks.chunk_index,
+            draw_index: chunks.draw_index,
+            indirect_index: chunks.indirect_index,
+            count_index: chunks.count_index,
+            chunk_count: chunks.chunk_count,
+            flags: if self.face_culling { FACE_CULLING } else { 0 },
+            culled_index: chunks.culled_index,
+            ..Default::default()
+        };
+        if let Some(hiz) = hiz {
+            push.flags |= OCCLUSION_CULLING;
+            push.hiz_index = hiz.sampled_index;
+            push.hiz_size = [hiz.image.extent.width, hiz.image.extent.height];
+            push.hiz_levels = hiz.levels();
+        }
+        let device = &gpu.device;
+        let readers = (
+            vk::PipelineStageFlags2::DRAW_INDIRECT | vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::HOST,
+            vk::AccessFlags2::INDIRECT_COMMAND_READ | vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::HOST_READ,
+        );
+        let transfer = (vk::PipelineStageFlags2::ALL_TRANSFER, vk::AccessFlags2::TRANSFER_WRITE);
+        let compute = (vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE);
+        // the previous frame's draws still read the output, the counters are reset first
+        for buffer in [&chunks.draws, &chunks.indirect] {
+            buffer_barrier(device, cmd, buffer, readers, compute);
+        }
+        for buffer in [&chunks.count, &chunks.culled] {
+            buffer_barrier(device, cmd, buffer, readers, transfer);
+        }
+        unsafe {
+            device.cmd_fill_buffer(cmd, chunks.count.buffer, 0, 4, 0);
+            // instance count of the culled bounds draw
+            device.cmd_fill_buffer(cmd, chunks.culled.buffer, 4, 4, 0);
+        }
+        for buffer in [&chunks.count, &chunks.culled] {
+            buffer_barrier(device, cmd, buffer, transfer, compute);
+        }
+
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            gpu.bind_bindless(cmd, vk::PipelineBindPoint::COMPUTE);
+            gpu.push_constants(cmd, &push);
+            device.cmd_dispatch(cmd, chunks.chunk_count.div_ceil(CULL_GROUP_SIZE), 1, 1);
+        }
+        for buffer in [&chunks.draws, &chunks.indirect, &chunks.count, &chunks.culled] {
+            buffer_barrier(device, cmd, buffer, (vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE), readers);
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_pipeline(self.pipeline, None) };
+    }
+}
+
+/// Draws as a sorted list so gpu and cpu output can be compared
+pub fn sorted_draws(draws: Vec<(vk::DrawIndirectCommand, ChunkDraw)>) -> Vec<([u32; 4], [u32; 4])> {
+    let mut keys: Vec<([u32; 4], [u32; 4])> = draws.into_iter().map(|(command, draw)| ([command.first_instance, command.instance_count, command.vertex_count, command.first_vertex], draw.offset.map(f32::to_bits))).collect();
+    keys.sort();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_test::{block, mesh};
+
+    fn camera() -> CameraData {
+        CameraData::look_at(glm::Vec3::new(0.4, 0.4, -1.0), glm::Vec3::new(0.4, 0.4, 1.0), 60.0, 1.0)
+    }
+
+    /// Quads of a solid 8³ chunk, one per face
+    fn cube() -> Vec<mesh::GPUQuad> {
+        mesh::mesh_materials(&[block::STONE; 8 * 8 * 8])
+    }
+
+    fn depth_at(camera: &CameraData, point: glm::Vec3) -> f32 {
+        let clip = camera.viewproj * point.into_homogeneous_point();
+        clip.z / clip.w
+    }
+
+    fn flat_hiz(depth: f32) -> CpuHiZ {
+        let extent = vk::Extent2D { width: 64, height: 64 };
+        CpuHiZ::from_depth(extent, vec![depth; 64 * 64])
+    }
+
+    #[test]
+    fn frustum_keeps_boxes_in_view() {
+        let frustum = Frustum::from_viewproj(&camera().viewproj);
+        let aabb = |min: [f32; 3], max: [f32; 3]| frustum.intersects_aabb(min.into(), max.into());
+        assert!(aabb([0.0; 3], [0.8; 3]));
+        // around the camera, crossing the near plane
+        assert!(aabb([0.0, 0.0, -1.5], [0.8, 0.8, -0.5]));
+        assert!(!aabb([0.0, 0.0, -3.0], [0.8, 0.8, -2.0]));
+        assert!(!aabb([50.0, 0.0, 1.0], [51.0, 0.8, 2.0]));
+        assert!(!aabb([0.0, 0.0, 200.0], [0.8, 0.8, 201.0]));
+    }
+
+    #[test]
+    fn cpu_cull_drops_chunks_outside_the_view() {
+        let mut batch = ChunkBatch::new();
+        batch.add([0, 0, 0], cube());
+        batch.add([0, 0, -40], cube());
+        let draws = cull_cpu(&batch, &camera(), false, None);
+        assert_eq!(draws.len(), FACE_GROUPS);
+        assert!(draws.iter().all(|(_, draw)| draw.offset[2] == 0.0));
+    }
+
+    #[test]
+    fn face_culling_drops_groups_facing_away() {
+        let mut batch = ChunkBatch::new();
+        batch.add([0, 0, 0], cube());
+        let draws = cull_cpu(&batch, &camera(), true, None);
+        // the camera is inside the chunk's x and y range, only the +z faces point away from it
+        assert_eq!(draws.len(), FACE_GROUPS - 1);
+        let back = batch.chunks[0].groups[3];
+        assert!(draws.iter().all(|(command, _)| command.first_instance != back[0]));
+    }
+
+    #[test]
+    fn occlusion_drops_chunks_behind_the_depth() {
+        let camera = camera();
+        let wall = flat_hiz(depth_at(&camera, glm::Vec3::new(0.4, 0.4, 1.5)));
+        let box_at = |z: f32| (glm::Vec3::new(0.0, 0.0, z), glm::Vec3::new(0.8, 0.8, z + 0.8));
+
+        let (min, max) = box_at(2.0);
+        assert!(occluded_cpu(&camera.viewproj, min, max, &wall));
+        // partly in front of the wall
+        let (min, max) = box_at(1.0);
+        assert!(!occluded_cpu(&camera.viewproj, min, max, &wall));
+        // nothing drawn yet
+        let (min, max) = box_at(2.0);
+        assert!(!occluded_cpu(&camera.viewproj, min, max, &flat_hiz(1.0)));
+        // reaching behind the camera
+        assert!(!occluded_cpu(&camera.viewproj, glm::Vec3::new(0.0, 0.0, -2.0), glm::Vec3::new(0.8, 0.8, 3.0), &wall));
+
+        let mut batch = ChunkBatch::new();
+        batch.add([0, 0, 0], cube());
+        batch.add([0, 0, 20], cube());
+        let draws = cull_cpu(&batch, &camera, false, Some(&wall));
+        assert_eq!(draws.len(), FACE_GROUPS);
+        assert!(draws.iter().all(|(_, draw)| draw.offset[2] == 0.0));
+    }
+
+    #[test]
+    fn pyramid_keeps_the_furthest_depth() {
+        let mut depth = vec![0.1; 16];
+        depth[3] = 0.7;
+        let hiz = CpuHiZ::from_depth(vk::Extent2D { width: 4, height: 4 }, depth);
+        assert_eq!(hiz.levels.len(), 3);
+        assert_eq!(hiz.levels[1], vec![0.1, 0.7, 0.1, 0.1]);
+        assert_eq!(hiz.levels[2], vec![0.7]);
+
+        // the last texel of an odd level covers the leftover row and column
+        let mut depth = vec![0.1; 9];
+        depth[8] = 0.9;
+        let hiz = CpuHiZ::from_depth(vk::Extent2D { width: 3, height: 3 }, depth);
+        assert_eq!(hiz.levels[1], vec![0.9]);
+    }
+}
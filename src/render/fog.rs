@@ -0,0 +1,157 @@
+use ash::vk;
+use voxelengine::vulkan::util::slice_as_u8;
+
+use super::{
+    gpu::{update_buffer, Buffer, Gpu, BINDLESS_UNIFORM},
+    RENDER_VOXEL_SCALE,
+};
+use crate::world_test::{biome::Biome, CHUNK_RESOLUTION};
+
+/// What the settings UI changes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FogSettings {
+    pub enabled: bool,
+    /// Chunk columns loaded around the camera, the radius of `ChunkManager::load_around`. Chunks are fully fogged there.
+    pub render_distance: u32,
+    /// Where the fog towards the render distance starts, as a fraction of it
+    pub start: f32,
+    /// Multiplier of the biome fog density
+    pub density: f32,
+    /// Seconds newly loaded chunks take to dither in, 0 shows them at once
+    pub fade_seconds: f32,
+    /// Seconds to blend into the fog of a new biome
+    pub blend_seconds: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self { enabled: true, render_distance: 4, start: 0.6, density: 1.0, fade_seconds: 0.75, blend_seconds: 3.0 }
+    }
+}
+
+impl FogSettings {
+    pub fn clamp(&mut self) {
+        self.render_distance = self.render_distance.clamp(1, 32);
+        self.start = self.start.clamp(0.0, 0.99);
+        self.density = self.density.max(0.0);
+        self.fade_seconds = self.fade_seconds.max(0.0);
+        self.blend_seconds = self.blend_seconds.max(0.0);
+    }
+
+    /// `render_distance` in world units
+    pub fn render_distance_units(&self) -> f32 {
+        (self.render_distance as usize * CHUNK_RESOLUTION) as f32 * RENDER_VOXEL_SCALE
+    }
+}
+
+/// Height fog of a biome, thickest at `base_height` and thinning out above it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiomeFog {
+    /// Per world unit at `base_height`
+    pub density: f32,
+    /// How fast the density drops per world unit of height
+    pub height_falloff: f32,
+    /// In blocks
+    pub base_height: f32,
+    /// Multiplies the sky color the fog takes
+    pub tint: [f32; 3],
+}
+
+impl BiomeFog {
+    pub fn of(biome: Biome) -> Self {
+        let (density, height_falloff, base_height, tint) = match biome {
+            Biome::Plains => (0.01, 0.15, 10.0, [1.0, 1.0, 1.0]),
+            Biome::Forest => (0.025, 0.25, 12.0, [0.9, 1.0, 0.92]),
+            Biome::Desert => (0.015, 0.1, 10.0, [1.1, 0.98, 0.85]),
+            Biome::Mountains => (0.006, 0.08, 30.0, [0.95, 0.97, 1.0]),
+            Biome::Tundra => (0.03, 0.2, 10.0, [1.05, 1.05, 1.1]),
+            Biome::Ocean => (0.02, 0.2, 6.0, [0.85, 0.92, 1.0]),
+            Biome::Beach => (0.015, 0.2, 6.0, [1.0, 1.0, 0.97]),
+        };
+        Self { density, height_falloff, base_height, tint }
+    }
+
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        Self {
+            density: mix(self.density, other.density),
+            height_falloff: mix(self.height_falloff, other.height_falloff),
+            base_height: mix(self.base_height, other.base_height),
+            tint: [0, 1, 2].map(|i| mix(self.tint[i], other.tint[i])),
+        }
+    }
+}
+
+/// Matches `FogData` in `shaders/chunk/chunk.frag`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FogData {
+    /// w is unused
+    pub tint: glm::Vec4,
+    pub density: f32,
+    pub height_falloff: f32,
+    /// In world units
+    pub base_height: f32,
+    /// Distance fog towards the render distance goes from `start` to `end`, in world units
+    pub start: f32,
+    pub end: f32,
+    /// Seconds since the app started, `ChunkDraw::offset` w is when a chunk was loaded on the same clock
+    pub seconds: f32,
+    pub fade_seconds: f32,
+    pub _pad: f32,
+}
+
+impl FogData {
+    pub fn new(settings: &FogSettings, fog: &BiomeFog, seconds: f32) -> Self {
+        let end = settings.render_distance_units();
+        Self {
+            tint: glm::Vec3::from(fog.tint).into_homogeneous_vector(),
+            density: fog.density * settings.density,
+            height_falloff: fog.height_falloff,
+            base_height: fog.base_height * RENDER_VOXEL_SCALE,
+            start: end * settings.start,
+            end,
+            seconds,
+            fade_seconds: settings.fade_seconds,
+            _pad: 0.0,
+        }
+    }
+}
+
+/// Distance and height fog in the color of the sky behind it, the fog of the biome at the camera blends in over time
+pub struct Fog {
+    data: Buffer,
+    /// Uniform index of the `FogData`
+    pub data_index: u32,
+    pub settings: FogSettings,
+    /// What the fog is at now, moves towards the camera's biome
+    current: BiomeFog,
+}
+
+impl Fog {
+    pub fn new(gpu: &mut Gpu, settings: FogSettings, biome: Biome) -> Self {
+        let mut settings = settings;
+        settings.clamp();
+        let data = gpu.create_buffer(size_of::<FogData>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, false);
+        let data_index = gpu.bindless.add_buffer(&gpu.device, &data, BINDLESS_UNIFORM);
+        Self { data, data_index, settings, current: BiomeFog::of(biome) }
+    }
+
+    /// Blends towards the fog of `biome`, `None` while the camera's column isn't loaded keeps the current fog
+    pub fn blend(&mut self, biome: Option<Biome>, delta_seconds: f32) {
+        let Some(biome) = biome else {
+            return;
+        };
+        let t = if self.settings.blend_seconds > 0.0 { (delta_seconds / self.settings.blend_seconds).min(1.0) } else { 1.0 };
+        self.current = self.current.lerp(&BiomeFog::of(biome), t);
+    }
+
+    /// Record before the chunks of the frame, outside of rendering
+    pub fn update(&self, gpu: &Gpu, cmd: vk::CommandBuffer, seconds: f32) {
+        update_buffer(&gpu.device, cmd, &self.data, slice_as_u8(&[FogData::new(&self.settings, &self.current, seconds)]));
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.data.destroy(device);
+    }
+}
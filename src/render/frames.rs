@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+
+use ash::vk;
+
+use super::gpu::Gpu;
+
+/// Command buffer of one frame slot and the timeline value its last submit signals
+struct Frame {
+    pool: vk::CommandPool,
+    cmd: vk::CommandBuffer,
+    value: u64,
+}
+
+/// Destroy callbacks with the timeline value that has to be reached first, they get the whole `Gpu` to free bindless indices
+type Retired = VecDeque<(u64, Box<dyn FnOnce(&mut Gpu)>)>;
+
+/// Frames in flight on one timeline semaphore. Every submit signals the next value, a frame slot is reused once
+/// the value of its last submit is reached, and resources retired while recording a frame are destroyed once
+/// that frame's value is reached. Nothing waits for the whole device.
+pub struct FrameScheduler {
+    timeline: vk::Semaphore,
+    frames: Vec<Frame>,
+    /// Value the frame being recorded signals
+    next_value: u64,
+    recording: Option<usize>,
+    retired: Retired,
+}
+
+impl FrameScheduler {
+    pub fn new(gpu: &Gpu, frames_in_flight: usize) -> Self {
+        let device = &gpu.device;
+        let mut timeline_info = vk::SemaphoreTypeCreateInfo::default().semaphore_type(vk::SemaphoreType::TIMELINE).initial_value(0);
+        let timeline = unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default().push_next(&mut timeline_info), None).unwrap() };
+        let frames = (0..frames_in_flight.max(1))
+            .map(|_| unsafe {
+                // transient pools are reset as a whole when the slot comes around again
+                let pool = device.create_command_pool(&vk::CommandPoolCreateInfo::default().queue_family_index(gpu.queue_family).flags(vk::CommandPoolCreateFlags::TRANSIENT), None).unwrap();
+                let cmd = device.allocate_command_buffers(&vk::CommandBufferAllocateInfo::default().command_pool(pool).command_buffer_count(1)).unwrap()[0];
+                Frame { pool, cmd, value: 0 }
+            })
+            .collect();
+        Self { timeline, frames, next_value: 1, recording: None, retired: VecDeque::new() }
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Slot of the frame being recorded, index per frame resources like uniform buffers with it
+    pub fn frame_index(&self) -> usize {
+        (self.next_value as usize - 1) % self.frames.len()
+    }
+
+    /// Highest value the gpu has signaled, every frame up to it is done
+    pub fn completed(&self, gpu: &Gpu) -> u64 {
+        unsafe { gpu.device.get_semaphore_counter_value(self.timeline).unwrap() }
+    }
+
+    fn wait(&self, gpu: &Gpu, value: u64) {
+        let semaphores = [self.timeline];
+        let values = [value];
+        unsafe { gpu.device.wait_semaphores(&vk::SemaphoreWaitInfo::default().semaphores(&semaphores).values(&values), u64::MAX).unwrap() };
+    }
+
+    /// Waits until the slot's previous frame is done, destroys what is no longer used and starts recording.
+    /// There is no barrier against earlier frames, every pass barriers the resources it shares with them,
+    /// e.g. `CullPass::record` waits for the last draw of the chunk buffers and `gpu::update_buffer` for the last uniform reads.
+    pub fn begin(&mut self, gpu: &mut Gpu) -> vk::CommandBuffer {
+        assert!(self.recording.is_none(), "begin called twice without submit");
+        let index = self.frame_index();
+        self.wait(gpu, self.frames[index].value);
+        self.collect(gpu);
+        let frame = &self.frames[index];
+        unsafe {
+            gpu.device.reset_command_pool(frame.pool, vk::CommandPoolResetFlags::empty()).unwrap();
+            gpu.device.begin_command_buffer(frame.cmd, &vk::CommandBufferBeginInfo::default().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)).unwrap();
+        }
+        self.recording = Some(index);
+        frame.cmd
+    }
+
+    /// Ends and submits the frame, signaling its timeline value after all commands. `waits` and `signals` are
+    /// extra semaphores like the swapchain acquire and present ones. Returns the value the frame signals.
+    pub fn submit(&mut self, gpu: &Gpu, waits: &[vk::SemaphoreSubmitInfo], signals: &[vk::SemaphoreSubmitInfo]) -> u64 {
+        let index = self.recording.take().expect("submit called without begin");
+        let value = self.next_value;
+        let frame = &mut self.frames[index];
+        let mut signal_infos = signals.to_vec();
+        signal_infos.push(vk::SemaphoreSubmitInfo::default().semaphore(self.timeline).value(value).stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS));
+        let cmd_info = [vk::CommandBufferSubmitInfo::default().command_buffer(frame.cmd)];
+        let submit = vk::SubmitInfo2::default().wait_semaphore_infos(waits).command_buffer_infos(&cmd_info).signal_semaphore_infos(&signal_infos);
+        unsafe {
+            gpu.device.end_command_buffer(frame.cmd).unwrap();
+            gpu.device.queue_submit2(gpu.queue, &[submit], vk::Fence::null()).unwrap();
+        }
+        frame.value = value;
+        self.next_value += 1;
+        value
+    }
+
+    /// Destroys with `destroy` once the frame being recorded, or the last submitted one outside of a frame, is done
+    pub fn retire(&mut self, destroy: impl FnOnce(&mut Gpu) + 'static) {
+        let value = if self.recording.is_some() { self.next_value } else { self.next_value - 1 };
+        self.retired.push_back((value, Box::new(destroy)));
+    }
+
+    /// Destroys every retired resource the gpu is done with, in the order they were retired
+    pub fn collect(&mut self, gpu: &mut Gpu) {
+        let completed = self.completed(gpu);
+        while self.retired.front().is_some_and(|(value, _)| *value <= completed) {
+            let (_, destroy) = self.retired.pop_front().unwrap();
+            destroy(gpu);
+        }
+    }
+
+    /// Waits for the last submitted frame, for resizes and shutdown
+    pub fn wait_idle(&mut self, gpu: &mut Gpu) {
+        self.wait(gpu, self.next_value - 1);
+        self.collect(gpu);
+    }
+
+    pub fn destroy(&mut self, gpu: &mut Gpu) {
+        self.wait_idle(gpu);
+        unsafe {
+            for frame in &self.frames {
+                gpu.device.destroy_command_pool(frame.pool, None);
+            }
+            gpu.device.destroy_semaphore(self.timeline, None);
+        }
+    }
+}
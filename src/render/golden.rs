@@ -0,0 +1,433 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use ash::vk;
+use image::{Rgba, RgbaImage};
+use voxelengine::vulkan::util::slice_as_u8;
+
+use super::{
+    atmosphere::{Atmosphere, AtmosphereSettings},
+    chunks::{ChunkBatch, CulledBounds, GpuChunks},
+    culling::{cull_cpu, sorted_draws, CullPass},
+    fog::{Fog, FogSettings},
+    frames::FrameScheduler,
+    gpu::{update_buffer, Gpu, OffscreenTarget, PipelineDesc, BINDLESS_STORAGE_IMAGE, BINDLESS_UNIFORM},
+    hiz::HiZ,
+    shadow::{ShadowMap, ShadowSettings},
+    sky::{SkyData, SkyPass, SkyState},
+    textures::{GpuTextures, TextureSet, TEXTURE_DIR},
+    CameraData, ChunkPushConstant, CHUNK_ATMOSPHERE, CHUNK_DAYLIGHT, CHUNK_FOG, CHUNK_SHADOWS,
+};
+use crate::world_test::{
+    biome::Biome,
+    block::{self, BlockId},
+    mesh,
+    time::{WorldTime, TICKS_PER_DAY},
+};
+
+pub const GOLDEN_RENDER_DIR: &str = "golden/render";
+const USAGE: &str = "usage: render <check|refresh|debug|frames> [--gpu <name>] [--scene <name>] [--out <dir>]";
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 256, height: 256 };
+/// Channels closer than this count as equal, drivers differ slightly in rasterization and blending
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels allowed to differ, edges of triangles land on different pixels across drivers
+const MAX_DIFF_FRACTION: f64 = 0.001;
+/// Frames `render frames` records per scene
+const FRAME_COUNT: u32 = 240;
+const FRAMES_IN_FLIGHT: usize = 2;
+/// `render frames` uploads the scene again this often and retires the old chunks, like streaming does
+const RESTREAM_FRAMES: u32 = 60;
+/// Size of the grid the mesher works on
+const GRID: usize = 8;
+/// Mid morning, the sun is high enough to light everything and low enough for visible shadows
+const MORNING: u64 = TICKS_PER_DAY / 6;
+
+/// A fixed block pattern seen from a fixed camera
+struct Scene {
+    name: &'static str,
+    /// Chunks in x, y and z starting at the origin, all of them are drawn with one indirect call
+    chunks: [usize; 3],
+    /// Block at a world position in blocks
+    block: fn(i32, i32, i32) -> BlockId,
+    eye: [f32; 3],
+    target: [f32; 3],
+    /// World time in ticks, sets the sky and where the shadows fall
+    time: u64,
+}
+
+fn terraces(x: i32, y: i32, z: i32) -> BlockId {
+    let height = 1 + (x / 4 + z / 4).rem_euclid(4) + (x / 8 + z / 8).rem_euclid(3);
+    match y {
+        _ if y >= height => if y < 3 { block::WATER } else { block::AIR },
+        _ if y + 1 == height => if height < 4 { block::SAND } else { block::GRASS },
+        _ if y + 3 >= height => block::DIRT,
+        _ => block::STONE,
+    }
+}
+
+const SCENES: [Scene; 6] = [
+    Scene { name: "steps", chunks: [1, 1, 1], block: |x, y, _| [block::AIR, block::GRASS, block::DIRT, block::STONE][(y <= x) as usize + (y < x) as usize + (y + 2 < x) as usize], eye: [1.6, 1.3, 1.9], target: [0.4, 0.3, 0.4], time: MORNING },
+    Scene { name: "pillars", chunks: [1, 1, 1], block: |x, y, z| if x % 3 == 0 && z % 3 == 0 && y <= (x + z) / 2 { block::LOG } else if y == 0 { block::SAND } else { block::AIR }, eye: [-0.5, 1.2, 1.6], target: [0.4, 0.2, 0.4], time: MORNING },
+    Scene {
+        name: "shell",
+        chunks: [1, 1, 1],
+        block: |x, y, z| if !(y > 2 && x > 2 && x < 6) && [x, y, z].iter().any(|v| *v == 0 || *v == GRID as i32 - 1) { [block::COBBLESTONE, block::PLANKS][(x + y + z).rem_euclid(2) as usize] } else if y < 2 { block::WATER } else { block::AIR },
+        eye: [0.4, 0.5, 2.0],
+        target: [0.4, 0.4, 0.4],
+        time: MORNING,
+    },
+    Scene {
+        name: "terraces",
+        chunks: [4, 1, 4],
+        block: terraces,
+        eye: [4.2, 2.4, 4.2],
+        target: [1.4, 0.0, 1.4],
+        time: MORNING,
+    },
+    // low sun behind the camera, long shadows and the twilight horizon
+    Scene { name: "dusk", chunks: [4, 1, 4], block: terraces, eye: [-0.6, 1.0, 1.6], target: [1.6, 0.2, 1.6], time: TICKS_PER_DAY / 2 - 400 },
+    // the wall hides the chunks behind it from the depth pyramid
+    Scene {
+        name: "ridge",
+        chunks: [4, 2, 4],
+        block: |x, y, z| match (y, z) {
+            (0, _) => block::GRASS,
+            (_, 8..=9) => block::STONE,
+            (_, 16..) if x % 4 == 1 && z % 4 == 1 && y < 12 => block::LOG,
+            _ => block::AIR,
+        },
+        eye: [1.6, 0.6, -0.8],
+        target: [1.6, 0.6, 1.6],
+        time: MORNING,
+    },
+];
+
+/// Every chunk of the scene meshed, positions in blocks
+fn scene_batch(scene: &Scene) -> ChunkBatch {
+    let mut batch = ChunkBatch::new();
+    for chunk_y in 0..scene.chunks[1] {
+        for chunk_z in 0..scene.chunks[2] {
+            for chunk_x in 0..scene.chunks[0] {
+                let origin = [chunk_x, chunk_y, chunk_z].map(|v| (v * GRID) as i32);
+                // the grid `mesh::mesh_materials` expects, indexed `x + z * 8 + y * 64`
+                let mut blocks = vec![block::AIR; GRID * GRID * GRID];
+                for y in 0..GRID {
+                    for z in 0..GRID {
+                        for x in 0..GRID {
+                            blocks[x + z * GRID + y * GRID * GRID] = (scene.block)(origin[0] + x as i32, origin[1] + y as i32, origin[2] + z as i32);
+                        }
+                    }
+                }
+                batch.add(origin, mesh::mesh_materials(&blocks));
+            }
+        }
+    }
+    batch
+}
+
+struct Rendered {
+    image: RgbaImage,
+    /// Set when the draws of the cull pass don't match `cull_cpu` or occlusion culling added draws
+    cull_mismatch: Option<String>,
+    /// Chunks the depth pyramid rejected
+    occluded: Vec<CulledBounds>,
+    chunk_count: usize,
+}
+
+/// Renders every scene with the chunk shaders and the block textures after culling it on the gpu.
+/// A depth prepass of the frustum culled chunks feeds the depth pyramid the second cull tests against,
+/// the app uses the last frame's depth instead. The sky of the scene's time is the background, seen through the atmosphere.
+/// With `debug` the boxes of occluded chunks are drawn on top.
+fn render_scenes(gpu: &mut Gpu, scenes: &[&Scene], debug: bool) -> Result<Vec<Rendered>, String> {
+    let pipeline = gpu.graphics_pipeline(&PipelineDesc::new("shaders/spv/chunk.vert.spv", Some("shaders/spv/chunk.frag.spv")))?;
+    let depth_pipeline = gpu.graphics_pipeline(&PipelineDesc { color_format: None, ..PipelineDesc::new("shaders/spv/chunk.vert.spv", None) })?;
+    let bounds_pipeline = gpu.graphics_pipeline(&PipelineDesc {
+        cull_mode: vk::CullModeFlags::NONE,
+        depth_compare: vk::CompareOp::ALWAYS,
+        depth_write: false,
+        topology: vk::PrimitiveTopology::LINE_LIST,
+        ..PipelineDesc::new("shaders/spv/bounds.vert.spv", Some("shaders/spv/bounds.frag.spv"))
+    })?;
+    let cull = CullPass::new(gpu)?;
+    let target = OffscreenTarget::new(gpu, EXTENT);
+    let hiz = HiZ::new(gpu, &target.depth)?;
+    let shadows = ShadowMap::new(gpu, ShadowSettings::default())?;
+    let sky = SkyPass::new(gpu)?;
+    let atmosphere = Atmosphere::new(gpu, AtmosphereSettings::default())?;
+    let fog = Fog::new(gpu, FogSettings::default(), Biome::Plains);
+    // every chunk was loaded at 0 and has faded in by now
+    gpu.submit_and_wait(|_, cmd| fog.update(gpu, cmd, fog.settings.fade_seconds));
+    let color_index = gpu.bindless.add_image(&gpu.device, target.color.view, vk::Sampler::null(), BINDLESS_STORAGE_IMAGE);
+    let textures = GpuTextures::upload(gpu, &TextureSet::load(Path::new(TEXTURE_DIR)));
+
+    let mut images = vec![];
+    for scene in scenes {
+        let batch = scene_batch(scene);
+        let chunks = GpuChunks::upload(gpu, &batch);
+        let camera = CameraData::look_at(scene.eye.into(), scene.target.into(), 60.0, EXTENT.width as f32 / EXTENT.height as f32);
+        let camera_buffer = gpu.buffer_with_data(vk::BufferUsageFlags::UNIFORM_BUFFER, slice_as_u8(&[camera]));
+        let mut state = SkyState::at(&WorldTime::new(scene.time));
+        state.apply_atmosphere(&atmosphere.settings);
+        let push = ChunkPushConstant {
+            cam_index: gpu.bindless.add_buffer(&gpu.device, &camera_buffer, BINDLESS_UNIFORM),
+            texture_index: textures.texture_index,
+            material_index: textures.material_index,
+            shadow_index: shadows.data_index,
+            sky_index: sky.data_index,
+            atmosphere_index: atmosphere.data_index,
+            fog_index: fog.data_index,
+            flags: CHUNK_DAYLIGHT | CHUNK_ATMOSPHERE | CHUNK_FOG | if shadows.settings.enabled { CHUNK_SHADOWS } else { 0 },
+            ..Default::default()
+        };
+
+        gpu.submit_and_wait(|_, cmd| cull.record(gpu, cmd, &chunks, push.cam_index, None));
+        let gpu_draws = sorted_draws(chunks.read_draws());
+        let cpu_draws = sorted_draws(cull_cpu(&batch, &camera, cull.face_culling, None));
+        let mut cull_mismatch = (gpu_draws != cpu_draws).then(|| format!("{}: the cull pass kept {} of {} draws, the cpu {}", scene.name, gpu_draws.len(), batch.max_draws(), cpu_draws.len()));
+
+        target.render_depth(gpu, |device, cmd| unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, depth_pipeline);
+            gpu.bind_bindless(cmd, vk::PipelineBindPoint::GRAPHICS);
+            chunks.draw(gpu, cmd, push);
+        });
+        gpu.submit_and_wait(|_, cmd| {
+            hiz.build(gpu, cmd, &target.depth);
+            cull.record(gpu, cmd, &chunks, push.cam_index, Some(&hiz));
+        });
+        let occluded_draws = sorted_draws(chunks.read_draws());
+        if cull_mismatch.is_none() && !occluded_draws.iter().all(|draw| gpu_draws.binary_search(draw).is_ok()) {
+            cull_mismatch = Some(format!("{}: occlusion culling added draws the frustum culling dropped", scene.name));
+        }
+
+        gpu.submit_and_wait(|_, cmd| {
+            shadows.record(gpu, cmd, &chunks, &camera, state.light);
+        });
+        let background = |_: &ash::Device, cmd| {
+            sky.update(gpu, cmd, &SkyData::new(&state, &camera));
+            atmosphere.update(gpu, cmd, state.sun, &camera);
+            atmosphere.record(gpu, cmd);
+            sky.record(gpu, cmd, color_index, target.extent(), Some(&atmosphere));
+        };
+        let image = target.render_over(gpu, background, |device, cmd| unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            gpu.bind_bindless(cmd, vk::PipelineBindPoint::GRAPHICS);
+            chunks.draw(gpu, cmd, push);
+            if debug {
+                device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, bounds_pipeline);
+                chunks.draw_culled(gpu, cmd, push.cam_index);
+            }
+        });
+        images.push(Rendered { image, cull_mismatch, occluded: chunks.read_culled(), chunk_count: batch.chunks.len() });
+        chunks.destroy(&gpu.device);
+        camera_buffer.destroy(&gpu.device);
+    }
+
+    hiz.destroy(&gpu.device);
+    shadows.destroy(&gpu.device);
+    sky.destroy(&gpu.device);
+    atmosphere.destroy(&gpu.device);
+    fog.destroy(&gpu.device);
+    target.destroy(&gpu.device);
+    textures.destroy(&gpu.device);
+    cull.destroy(&gpu.device);
+    unsafe {
+        for pipeline in [pipeline, depth_pipeline, bounds_pipeline] {
+            gpu.device.destroy_pipeline(pipeline, None);
+        }
+    }
+    Ok(images)
+}
+
+fn reference_path(scene: &Scene, suffix: &str) -> PathBuf {
+    Path::new(GOLDEN_RENDER_DIR).join(format!("{}{}.png", scene.name, suffix))
+}
+
+/// Differing pixels and an image marking them red over the dimmed reference
+fn compare(expected: &RgbaImage, actual: &RgbaImage) -> (usize, RgbaImage) {
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut differing = 0;
+    for ((expected, actual), out) in expected.pixels().zip(actual.pixels()).zip(diff.pixels_mut()) {
+        if expected.0.iter().zip(actual.0.iter()).any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE) {
+            differing += 1;
+            *out = Rgba([255, 0, 0, 255]);
+        } else {
+            let gray = (expected.0[0] as u16 + expected.0[1] as u16 + expected.0[2] as u16) / 12;
+            *out = Rgba([gray as u8, gray as u8, gray as u8, 255]);
+        }
+    }
+    (differing, diff)
+}
+
+/// Compares the renders against the references and the gpu culling against the cpu, returns the failing scenes.
+/// Failing scenes leave `<scene>.actual.png` and `<scene>.diff.png` next to the reference.
+fn check(gpu: &mut Gpu, scenes: &[&Scene]) -> Result<Vec<String>, String> {
+    let rendered = render_scenes(gpu, scenes, false)?;
+    let mut failures = vec![];
+    for (scene, Rendered { image: actual, cull_mismatch, .. }) in scenes.iter().zip(rendered) {
+        failures.extend(cull_mismatch);
+        let path = reference_path(scene, "");
+        let expected = image::open(&path).map_err(|e| format!("{}: {}, run `render refresh` first", path.display(), e))?.to_rgba8();
+        if expected.dimensions() != actual.dimensions() {
+            failures.push(format!("{}: expected {:?}, rendered {:?}", scene.name, expected.dimensions(), actual.dimensions()));
+            continue;
+        }
+
+        let (differing, diff) = compare(&expected, &actual);
+        let fraction = differing as f64 / (actual.width() * actual.height()) as f64;
+        if fraction > MAX_DIFF_FRACTION {
+            actual.save(reference_path(scene, ".actual")).map_err(|e| e.to_string())?;
+            diff.save(reference_path(scene, ".diff")).map_err(|e| e.to_string())?;
+            failures.push(format!("{}: {} pixels differ ({:.3}%), see {}", scene.name, differing, fraction * 100.0, reference_path(scene, ".diff").display()));
+        } else {
+            let _ = std::fs::remove_file(reference_path(scene, ".actual"));
+            let _ = std::fs::remove_file(reference_path(scene, ".diff"));
+        }
+    }
+    Ok(failures)
+}
+
+fn refresh(gpu: &mut Gpu, scenes: &[&Scene]) -> Result<(), String> {
+    let rendered = render_scenes(gpu, scenes, false)?;
+    if let Some(mismatch) = rendered.iter().find_map(|rendered| rendered.cull_mismatch.as_ref()) {
+        return Err(format!("{}, not writing references while culling is broken", mismatch));
+    }
+    std::fs::create_dir_all(GOLDEN_RENDER_DIR).map_err(|e| format!("{}: {}", GOLDEN_RENDER_DIR, e))?;
+    for (scene, Rendered { image, .. }) in scenes.iter().zip(rendered) {
+        image.save(reference_path(scene, "")).map_err(|e| format!("{}: {}", reference_path(scene, "").display(), e))?;
+    }
+    Ok(())
+}
+
+/// Renders with the occluded chunks outlined into `<out>/<scene>.debug.png`
+fn debug(gpu: &mut Gpu, scenes: &[&Scene], out: &Path) -> Result<(), String> {
+    let rendered = render_scenes(gpu, scenes, true)?;
+    std::fs::create_dir_all(out).map_err(|e| format!("{}: {}", out.display(), e))?;
+    for (scene, Rendered { image, cull_mismatch, occluded, chunk_count }) in scenes.iter().zip(rendered) {
+        if let Some(mismatch) = cull_mismatch {
+            eprintln!("{}", mismatch);
+        }
+        let path = out.join(format!("{}.debug.png", scene.name));
+        image.save(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("{}: {} of {} chunks occluded, see {}", scene.name, occluded.len(), chunk_count, path.display());
+    }
+    Ok(())
+}
+
+/// Culls every scene from a camera orbiting its target with frames in flight on a timeline semaphore
+/// and prints the frame time. Nothing waits for the device until the last frame.
+fn frames(gpu: &mut Gpu, scenes: &[&Scene]) -> Result<(), String> {
+    let cull = CullPass::new(gpu)?;
+    let mut scheduler = FrameScheduler::new(gpu, FRAMES_IN_FLIGHT);
+    // written from the command buffer, so frames in flight can share it
+    let camera_buffer = gpu.create_buffer(size_of::<CameraData>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, false);
+    let cam_index = gpu.bindless.add_buffer(&gpu.device, &camera_buffer, BINDLESS_UNIFORM);
+
+    for scene in scenes {
+        let batch = scene_batch(scene);
+        let mut chunks = GpuChunks::upload(gpu, &batch);
+        let start = Instant::now();
+        for frame in 0..FRAME_COUNT {
+            if frame > 0 && frame % RESTREAM_FRAMES == 0 {
+                let old = std::mem::replace(&mut chunks, GpuChunks::upload(gpu, &batch));
+                scheduler.retire(move |gpu| old.free(gpu));
+            }
+            let cmd = scheduler.begin(gpu);
+            let angle = frame as f32 / FRAME_COUNT as f32 * std::f32::consts::TAU;
+            let target = glm::Vec3::from(scene.target);
+            let offset = glm::Vec3::from(scene.eye) - target;
+            let eye = target + glm::Vec3::new(offset.x * angle.cos() - offset.z * angle.sin(), offset.y, offset.x * angle.sin() + offset.z * angle.cos());
+            let camera = CameraData::look_at(eye, target, 60.0, EXTENT.width as f32 / EXTENT.height as f32);
+            update_buffer(&gpu.device, cmd, &camera_buffer, slice_as_u8(&[camera]));
+            cull.record(gpu, cmd, &chunks, cam_index, None);
+            scheduler.submit(gpu, &[], &[]);
+        }
+        scheduler.wait_idle(gpu);
+        let elapsed = start.elapsed();
+        println!("{}: {} frames, {:.3} ms per frame with {} in flight", scene.name, FRAME_COUNT, elapsed.as_secs_f64() * 1000.0 / FRAME_COUNT as f64, scheduler.frames_in_flight());
+        chunks.free(gpu);
+    }
+
+    scheduler.destroy(gpu);
+    camera_buffer.destroy(&gpu.device);
+    cull.destroy(&gpu.device);
+    Ok(())
+}
+
+/// Entry point for `vulkanrust render ...`, exits with 1 when a render changed
+pub fn run(args: &[String]) {
+    let mut gpu_name = None;
+    let mut scene_name = None;
+    let mut out = PathBuf::from(GOLDEN_RENDER_DIR);
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--gpu" => gpu_name = rest.next().cloned(),
+            "--scene" => scene_name = rest.next().cloned(),
+            "--out" => out = rest.next().map(PathBuf::from).unwrap_or(out),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+    let scenes: Vec<&Scene> = SCENES.iter().filter(|scene| scene_name.as_ref().is_none_or(|name| name == scene.name)).collect();
+    if scenes.is_empty() {
+        eprintln!("no scene named {}, there are {}", scene_name.unwrap_or_default(), SCENES.iter().map(|scene| scene.name).collect::<Vec<_>>().join(", "));
+        std::process::exit(1);
+    }
+
+    let result = Gpu::headless(gpu_name.as_deref()).and_then(|mut gpu| {
+        println!("rendering on {}", gpu.name);
+        match args.first().map(String::as_str) {
+            Some("check") => check(&mut gpu, &scenes),
+            Some("refresh") => refresh(&mut gpu, &scenes).map(|()| {
+                println!("wrote {} images to {}", scenes.len(), GOLDEN_RENDER_DIR);
+                vec![]
+            }),
+            Some("debug") => debug(&mut gpu, &scenes, &out).map(|()| vec![]),
+            Some("frames") => frames(&mut gpu, &scenes).map(|()| vec![]),
+            _ => Err(USAGE.to_owned()),
+        }
+    });
+    match result {
+        Ok(failures) if failures.is_empty() => {
+            if args.first().is_some_and(|command| command == "check") {
+                println!("renders match {}", GOLDEN_RENDER_DIR);
+            }
+        }
+        Ok(failures) => {
+            for failure in &failures {
+                eprintln!("{}", failure);
+            }
+            eprintln!("{} scenes changed, run `render refresh` if that is intended", failures.len());
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `render check` as a test, skipped where there is no vulkan device
+    #[test]
+    fn renders_match_golden() {
+        let mut gpu = match Gpu::headless(None) {
+            Ok(gpu) => gpu,
+            Err(e) => {
+                eprintln!("skipping the golden renders: {}", e);
+                return;
+            }
+        };
+        let scenes: Vec<&Scene> = SCENES.iter().collect();
+        let failures = check(&mut gpu, &scenes).unwrap();
+        assert!(failures.is_empty(), "{} scenes changed, run `render refresh` if that is intended:\n{}", failures.len(), failures.join("\n"));
+    }
+}
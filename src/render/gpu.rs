@@ -0,0 +1,698 @@
+use std::{ffi::CStr, path::Path};
+
+use ash::vk;
+use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+
+/// Bindings of the bindless set, see `shaders/bindless.glsl`
+pub const BINDLESS_SAMPLED_IMAGE: u32 = 0;
+pub const BINDLESS_STORAGE_IMAGE: u32 = 1;
+pub const BINDLESS_STORAGE_BUFFER: u32 = 2;
+pub const BINDLESS_UNIFORM: u32 = 3;
+const BINDLESS_COUNTS: [(vk::DescriptorType, u32); 4] = [
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 256),
+    (vk::DescriptorType::STORAGE_IMAGE, 64),
+    (vk::DescriptorType::STORAGE_BUFFER, 256),
+    (vk::DescriptorType::UNIFORM_BUFFER, 64),
+];
+/// Same size and stages as the push constant range of `VulkanContext`
+pub const PUSH_CONSTANT_SIZE: u32 = 128;
+pub const PUSH_CONSTANT_STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_raw(vk::ShaderStageFlags::VERTEX.as_raw() | vk::ShaderStageFlags::FRAGMENT.as_raw() | vk::ShaderStageFlags::COMPUTE.as_raw());
+
+/// Every stage a bindless resource can be read in, see `PUSH_CONSTANT_STAGES`
+const SHADER_STAGES: vk::PipelineStageFlags2 = vk::PipelineStageFlags2::from_raw(vk::PipelineStageFlags2::VERTEX_SHADER.as_raw() | vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw() | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw());
+
+pub const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Host visible and coherent when `mapped` is set, otherwise device local
+pub struct Buffer {
+    pub buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    pub size: u64,
+    mapped: Option<*mut u8>,
+}
+
+impl Buffer {
+    /// Panics if the buffer isn't host visible or `data` doesn't fit
+    pub fn write(&self, offset: u64, data: &[u8]) {
+        assert!(offset + data.len() as u64 <= self.size, "write past the end of the buffer");
+        let mapped = self.mapped.expect("buffer isn't host visible");
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), mapped.add(offset as usize), data.len()) };
+    }
+
+    pub fn read(&self) -> Vec<u8> {
+        let mapped = self.mapped.expect("buffer isn't host visible");
+        unsafe { std::slice::from_raw_parts(mapped, self.size as usize).to_vec() }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_buffer(self.buffer, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+pub struct Image {
+    pub image: vk::Image,
+    memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub layers: u32,
+    pub mip_levels: u32,
+    pub aspect: vk::ImageAspectFlags,
+}
+
+impl Image {
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange { aspect_mask: self.aspect, base_mip_level: 0, level_count: self.mip_levels, base_array_layer: 0, layer_count: self.layers }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// Layout transition of every mip and layer with a synchronization2 barrier
+pub fn image_barrier(device: &ash::Device, cmd: vk::CommandBuffer, image: &Image, from: (vk::ImageLayout, vk::PipelineStageFlags2, vk::AccessFlags2), to: (vk::ImageLayout, vk::PipelineStageFlags2, vk::AccessFlags2)) {
+    let barrier = vk::ImageMemoryBarrier2::default()
+        .image(image.image)
+        .subresource_range(image.subresource_range())
+        .old_layout(from.0)
+        .src_stage_mask(from.1)
+        .src_access_mask(from.2)
+        .new_layout(to.0)
+        .dst_stage_mask(to.1)
+        .dst_access_mask(to.2);
+    unsafe { device.cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::default().image_memory_barriers(&[barrier])) };
+}
+
+/// Execution and memory dependency on the whole buffer
+pub fn buffer_barrier(device: &ash::Device, cmd: vk::CommandBuffer, buffer: &Buffer, from: (vk::PipelineStageFlags2, vk::AccessFlags2), to: (vk::PipelineStageFlags2, vk::AccessFlags2)) {
+    let barrier = vk::BufferMemoryBarrier2::default().buffer(buffer.buffer).offset(0).size(vk::WHOLE_SIZE).src_stage_mask(from.0).src_access_mask(from.1).dst_stage_mask(to.0).dst_access_mask(to.1);
+    unsafe { device.cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::default().buffer_memory_barriers(&[barrier])) };
+}
+
+/// Writes `data` with `cmd_update_buffer`, ordered after the shader reads recorded before and before the ones after.
+/// Outside of rendering, the buffer needs `TRANSFER_DST` and `data` at most 64 KiB.
+pub fn update_buffer(device: &ash::Device, cmd: vk::CommandBuffer, buffer: &Buffer, data: &[u8]) {
+    let shaders = (SHADER_STAGES, vk::AccessFlags2::SHADER_READ);
+    let transfer = (vk::PipelineStageFlags2::ALL_TRANSFER, vk::AccessFlags2::TRANSFER_WRITE);
+    buffer_barrier(device, cmd, buffer, shaders, transfer);
+    unsafe { device.cmd_update_buffer(cmd, buffer.buffer, 0, data) };
+    buffer_barrier(device, cmd, buffer, transfer, shaders);
+}
+
+/// Execution and memory dependency without a resource, used between passes over the same buffers
+pub fn memory_barrier(device: &ash::Device, cmd: vk::CommandBuffer, from: (vk::PipelineStageFlags2, vk::AccessFlags2), to: (vk::PipelineStageFlags2, vk::AccessFlags2)) {
+    let barrier = vk::MemoryBarrier2::default().src_stage_mask(from.0).src_access_mask(from.1).dst_stage_mask(to.0).dst_access_mask(to.1);
+    unsafe { device.cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::default().memory_barriers(&[barrier])) };
+}
+
+/// The bindless descriptor set every shader includes through `bindless.glsl`, freed indices are handed out first
+pub struct Bindless {
+    pub layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    pub set: vk::DescriptorSet,
+    pub pipeline_layout: vk::PipelineLayout,
+    next: [u32; 4],
+    free: [Vec<u32>; 4],
+}
+
+impl Bindless {
+    fn new(device: &ash::Device) -> Self {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = BINDLESS_COUNTS.iter().enumerate().map(|(binding, (ty, count))| vk::DescriptorSetLayoutBinding::default().binding(binding as u32).descriptor_type(*ty).descriptor_count(*count).stage_flags(vk::ShaderStageFlags::ALL)).collect();
+        // slots a pending frame doesn't read can be rewritten, freed indices are reused while other frames are in flight
+        let flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING; 4];
+        let mut binding_flags = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&flags);
+        let sizes: Vec<vk::DescriptorPoolSize> = BINDLESS_COUNTS.iter().map(|(ty, count)| vk::DescriptorPoolSize { ty: *ty, descriptor_count: *count }).collect();
+        let push_constant = vk::PushConstantRange { stage_flags: PUSH_CONSTANT_STAGES, offset: 0, size: PUSH_CONSTANT_SIZE };
+
+        unsafe {
+            let layout = device
+                .create_descriptor_set_layout(&vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings).flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL).push_next(&mut binding_flags), None)
+                .unwrap();
+            let pool = device.create_descriptor_pool(&vk::DescriptorPoolCreateInfo::default().flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND).max_sets(1).pool_sizes(&sizes), None).unwrap();
+            let set = device.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::default().descriptor_pool(pool).set_layouts(&[layout])).unwrap()[0];
+            let pipeline_layout = device.create_pipeline_layout(&vk::PipelineLayoutCreateInfo::default().set_layouts(&[layout]).push_constant_ranges(&[push_constant]), None).unwrap();
+            Self { layout, pool, set, pipeline_layout, next: [0; 4], free: Default::default() }
+        }
+    }
+
+    fn next_index(&mut self, binding: u32) -> u32 {
+        if let Some(index) = self.free[binding as usize].pop() {
+            return index;
+        }
+        let index = self.next[binding as usize];
+        assert!(index < BINDLESS_COUNTS[binding as usize].1, "out of bindless slots for binding {}", binding);
+        self.next[binding as usize] += 1;
+        index
+    }
+
+    /// Storage or uniform buffer, returns the index shaders use
+    pub fn add_buffer(&mut self, device: &ash::Device, buffer: &Buffer, binding: u32) -> u32 {
+        let index = self.next_index(binding);
+        let info = [vk::DescriptorBufferInfo { buffer: buffer.buffer, offset: 0, range: vk::WHOLE_SIZE }];
+        let write = vk::WriteDescriptorSet::default().dst_set(self.set).dst_binding(binding).dst_array_element(index).descriptor_type(BINDLESS_COUNTS[binding as usize].0).buffer_info(&info);
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+        index
+    }
+
+    /// Sampled in `SHADER_READ_ONLY_OPTIMAL`, storage images in `GENERAL`
+    pub fn add_image(&mut self, device: &ash::Device, view: vk::ImageView, sampler: vk::Sampler, binding: u32) -> u32 {
+        let index = self.next_index(binding);
+        let layout = if binding == BINDLESS_STORAGE_IMAGE { vk::ImageLayout::GENERAL } else { vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL };
+        let info = [vk::DescriptorImageInfo { sampler, image_view: view, image_layout: layout }];
+        let write = vk::WriteDescriptorSet::default().dst_set(self.set).dst_binding(binding).dst_array_element(index).descriptor_type(BINDLESS_COUNTS[binding as usize].0).image_info(&info);
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+        index
+    }
+
+    /// Hands `index` out again, only once no submitted frame reads it, see `FrameScheduler::retire`
+    pub fn free(&mut self, binding: u32, index: u32) {
+        debug_assert!(index < self.next[binding as usize] && !self.free[binding as usize].contains(&index), "bindless index {} of binding {} isn't in use", index, binding);
+        self.free[binding as usize].push(index);
+    }
+
+    fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_pool(self.pool, None);
+            device.destroy_descriptor_set_layout(self.layout, None);
+        }
+    }
+}
+
+/// Fixed function state of a graphics pipeline, everything else is shared by all pipelines
+pub struct PipelineDesc<'a> {
+    pub vertex: &'a str,
+    /// Depth only without one
+    pub fragment: Option<&'a str>,
+    pub color_format: Option<vk::Format>,
+    pub depth_format: vk::Format,
+    pub cull_mode: vk::CullModeFlags,
+    pub depth_compare: vk::CompareOp,
+    pub depth_write: bool,
+    pub polygon_mode: vk::PolygonMode,
+    pub topology: vk::PrimitiveTopology,
+    /// Constant and slope factor, both zero disables the bias
+    pub depth_bias: [f32; 2],
+    /// Without it `depth_format` can be `UNDEFINED`
+    pub depth_test: bool,
+    /// Alpha blending of the color attachment, for the ui
+    pub blend: bool,
+}
+
+impl<'a> PipelineDesc<'a> {
+    /// Back face culled, clockwise and `LESS_OR_EQUAL` like the chunk pipeline of the app
+    pub fn new(vertex: &'a str, fragment: Option<&'a str>) -> Self {
+        Self {
+            vertex,
+            fragment,
+            color_format: Some(COLOR_FORMAT),
+            depth_format: DEPTH_FORMAT,
+            cull_mode: vk::CullModeFlags::BACK,
+            depth_compare: vk::CompareOp::LESS_OR_EQUAL,
+            depth_write: true,
+            polygon_mode: vk::PolygonMode::FILL,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            depth_bias: [0.0; 2],
+            depth_test: true,
+            blend: false,
+        }
+    }
+}
+
+/// Vulkan 1.3 device, headless for the golden-image checks or with a window surface for the app. Picks hardware over
+/// software drivers unless `preferred` names one, so the headless checks run on GPU-less machines with lavapipe installed.
+pub struct Gpu {
+    pub(super) entry: ash::Entry,
+    pub instance: ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: ash::Device,
+    pub queue: vk::Queue,
+    pub queue_family: u32,
+    pub name: String,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    command_pool: vk::CommandPool,
+    pub cmd: vk::CommandBuffer,
+    fence: vk::Fence,
+    pub bindless: Bindless,
+}
+
+fn device_rank(properties: &vk::PhysicalDeviceProperties) -> u32 {
+    match properties.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 0,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 3,
+        _ => 4,
+    }
+}
+
+impl Gpu {
+    /// `preferred` is matched against the device names, case insensitive
+    pub fn headless(preferred: Option<&str>) -> Result<Self, String> {
+        Self::new(preferred, None).map(|(gpu, _)| gpu)
+    }
+
+    /// Device whose queue can present to `window`, with the surface for `swapchain::Swapchain`
+    pub fn windowed(window: &winit::window::Window) -> Result<(Self, vk::SurfaceKHR), String> {
+        Self::new(None, Some(window)).map(|(gpu, surface)| (gpu, surface.unwrap()))
+    }
+
+    fn new(preferred: Option<&str>, window: Option<&winit::window::Window>) -> Result<(Self, Option<vk::SurfaceKHR>), String> {
+        unsafe {
+            let entry = ash::Entry::load().map_err(|e| format!("no vulkan loader: {}", e))?;
+            let handles = match window {
+                Some(window) => {
+                    let display = window.display_handle().map_err(|e| e.to_string())?.as_raw();
+                    Some((display, window.window_handle().map_err(|e| e.to_string())?.as_raw()))
+                }
+                None => None,
+            };
+            let extensions = match handles {
+                Some((display, _)) => ash_window::enumerate_required_extensions(display).map_err(|e| format!("window extensions: {}", e))?,
+                None => &[],
+            };
+            let app_info = vk::ApplicationInfo::default().application_name(c"vulkanrust").api_version(vk::API_VERSION_1_3);
+            let instance_info = vk::InstanceCreateInfo::default().application_info(&app_info).enabled_extension_names(extensions);
+            let instance = entry.create_instance(&instance_info, None).map_err(|e| format!("creating the vulkan instance: {}", e))?;
+
+            let surface = match handles {
+                Some((display, window)) => match ash_window::create_surface(&entry, &instance, display, window, None) {
+                    Ok(surface) => Some(surface),
+                    Err(e) => {
+                        instance.destroy_instance(None);
+                        return Err(format!("creating the window surface: {}", e));
+                    }
+                },
+                None => None,
+            };
+            let surface_loader = ash::khr::surface::Instance::new(&entry, &instance);
+
+            let mut candidates = vec![];
+            for physical_device in instance.enumerate_physical_devices().map_err(|e| e.to_string())? {
+                let properties = instance.get_physical_device_properties(physical_device);
+                let name = properties.device_name_as_c_str().map(CStr::to_string_lossy).unwrap_or_default().into_owned();
+                let presents = |family: usize| surface.is_none_or(|surface| surface_loader.get_physical_device_surface_support(physical_device, family as u32, surface).unwrap_or(false));
+                let family = instance
+                    .get_physical_device_queue_family_properties(physical_device)
+                    .iter()
+                    .enumerate()
+                    .position(|(index, family)| family.queue_flags.contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE) && presents(index));
+                match family {
+                    Some(family) if properties.api_version >= vk::API_VERSION_1_3 => candidates.push((physical_device, family as u32, name, device_rank(&properties))),
+                    _ => log::info!("skipping {}, it needs vulkan 1.3 and a graphics queue{}", name, if surface.is_some() { " that presents" } else { "" }),
+                }
+            }
+            if let Some(preferred) = preferred {
+                candidates.retain(|(_, _, name, _)| name.to_lowercase().contains(&preferred.to_lowercase()));
+            }
+            candidates.sort_by_key(|candidate| candidate.3);
+            let Some((physical_device, queue_family, name, _)) = candidates.into_iter().next() else {
+                if let Some(surface) = surface {
+                    surface_loader.destroy_surface(surface, None);
+                }
+                instance.destroy_instance(None);
+                return Err(format!("no vulkan 1.3 device{}", preferred.map(|name| format!(" matching '{}'", name)).unwrap_or_default()));
+            };
+
+            let device_extensions = if surface.is_some() { vec![ash::khr::swapchain::NAME.as_ptr()] } else { vec![] };
+            let device = Self::create_device(&instance, physical_device, queue_family, &device_extensions).map_err(|e| format!("{}: {}", name, e))?;
+            log::info!("rendering {}on {}", if surface.is_some() { "" } else { "headless " }, name);
+
+            let queue = device.get_device_queue(queue_family, 0);
+            let command_pool = device.create_command_pool(&vk::CommandPoolCreateInfo::default().queue_family_index(queue_family).flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER), None).unwrap();
+            let cmd = device.allocate_command_buffers(&vk::CommandBufferAllocateInfo::default().command_pool(command_pool).command_buffer_count(1)).unwrap()[0];
+            let fence = device.create_fence(&vk::FenceCreateInfo::default(), None).unwrap();
+            let bindless = Bindless::new(&device);
+            let memory_properties = instance.get_physical_device_memory_properties(physical_device);
+
+            Ok((Self { entry, instance, physical_device, device, queue, queue_family, name, memory_properties, command_pool, cmd, fence, bindless }, surface))
+        }
+    }
+
+    unsafe fn create_device(instance: &ash::Instance, physical_device: vk::PhysicalDevice, queue_family: u32, extensions: &[*const std::ffi::c_char]) -> Result<ash::Device, String> {
+        let mut features11 = vk::PhysicalDeviceVulkan11Features::default();
+        let mut features12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut features13 = vk::PhysicalDeviceVulkan13Features::default();
+        let mut supported = vk::PhysicalDeviceFeatures2::default().push_next(&mut features11).push_next(&mut features12).push_next(&mut features13);
+        instance.get_physical_device_features2(physical_device, &mut supported);
+        let core = supported.features;
+
+        let required = [
+            ("shaderInt64", core.shader_int64),
+            ("multiDrawIndirect", core.multi_draw_indirect),
+            ("drawIndirectFirstInstance", core.draw_indirect_first_instance),
+            ("fillModeNonSolid", core.fill_mode_non_solid),
+            ("shaderDrawParameters", features11.shader_draw_parameters),
+            ("drawIndirectCount", features12.draw_indirect_count),
+            ("dynamicRendering", features13.dynamic_rendering),
+            ("synchronization2", features13.synchronization2),
+            ("timelineSemaphore", features12.timeline_semaphore),
+            ("runtimeDescriptorArray", features12.runtime_descriptor_array),
+            ("descriptorBindingPartiallyBound", features12.descriptor_binding_partially_bound),
+            ("descriptorBindingStorageBufferUpdateAfterBind", features12.descriptor_binding_storage_buffer_update_after_bind),
+            ("descriptorBindingUniformBufferUpdateAfterBind", features12.descriptor_binding_uniform_buffer_update_after_bind),
+            ("descriptorBindingSampledImageUpdateAfterBind", features12.descriptor_binding_sampled_image_update_after_bind),
+            ("descriptorBindingStorageImageUpdateAfterBind", features12.descriptor_binding_storage_image_update_after_bind),
+            ("descriptorBindingUpdateUnusedWhilePending", features12.descriptor_binding_update_unused_while_pending),
+            ("shaderStorageBufferArrayNonUniformIndexing", features12.shader_storage_buffer_array_non_uniform_indexing),
+            ("shaderSampledImageArrayNonUniformIndexing", features12.shader_sampled_image_array_non_uniform_indexing),
+            ("bufferDeviceAddress", features12.buffer_device_address),
+        ];
+        let missing: Vec<&str> = required.iter().filter(|(_, supported)| *supported == vk::FALSE).map(|(name, _)| *name).collect();
+        if !missing.is_empty() {
+            return Err(format!("missing features {}", missing.join(", ")));
+        }
+
+        let features = vk::PhysicalDeviceFeatures::default().shader_int64(true).multi_draw_indirect(true).draw_indirect_first_instance(true).fill_mode_non_solid(true);
+        let mut enable11 = vk::PhysicalDeviceVulkan11Features::default().shader_draw_parameters(true);
+        let mut enable12 = vk::PhysicalDeviceVulkan12Features::default()
+            .runtime_descriptor_array(true)
+            .descriptor_binding_partially_bound(true)
+            .descriptor_binding_storage_buffer_update_after_bind(true)
+            .descriptor_binding_uniform_buffer_update_after_bind(true)
+            .descriptor_binding_sampled_image_update_after_bind(true)
+            .descriptor_binding_storage_image_update_after_bind(true)
+            .descriptor_binding_update_unused_while_pending(true)
+            .shader_storage_buffer_array_non_uniform_indexing(true)
+            .shader_sampled_image_array_non_uniform_indexing(true)
+            .buffer_device_address(true)
+            .draw_indirect_count(true)
+            .timeline_semaphore(true);
+        let mut enable13 = vk::PhysicalDeviceVulkan13Features::default().dynamic_rendering(true).synchronization2(true);
+        let mut enable = vk::PhysicalDeviceFeatures2::default().features(features).push_next(&mut enable11).push_next(&mut enable12).push_next(&mut enable13);
+
+        let priorities = [1.0];
+        let queue_info = vk::DeviceQueueCreateInfo::default().queue_family_index(queue_family).queue_priorities(&priorities);
+        let info = vk::DeviceCreateInfo::default().queue_create_infos(std::slice::from_ref(&queue_info)).enabled_extension_names(extensions).push_next(&mut enable);
+        instance.create_device(physical_device, &info, None).map_err(|e| format!("creating the device: {}", e))
+    }
+
+    fn memory_type(&self, type_bits: u32, flags: vk::MemoryPropertyFlags) -> u32 {
+        (0..self.memory_properties.memory_type_count)
+            .find(|index| type_bits & (1 << index) != 0 && self.memory_properties.memory_types[*index as usize].property_flags.contains(flags))
+            .expect("no matching memory type")
+    }
+
+    pub fn create_buffer(&self, size: u64, usage: vk::BufferUsageFlags, host_visible: bool) -> Buffer {
+        let flags = if host_visible { vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT } else { vk::MemoryPropertyFlags::DEVICE_LOCAL };
+        unsafe {
+            let buffer = self.device.create_buffer(&vk::BufferCreateInfo::default().size(size.max(4)).usage(usage).sharing_mode(vk::SharingMode::EXCLUSIVE), None).unwrap();
+            let requirements = self.device.get_buffer_memory_requirements(buffer);
+            let info = vk::MemoryAllocateInfo::default().allocation_size(requirements.size).memory_type_index(self.memory_type(requirements.memory_type_bits, flags));
+            let memory = self.device.allocate_memory(&info, None).unwrap();
+            self.device.bind_buffer_memory(buffer, memory, 0).unwrap();
+            let mapped = host_visible.then(|| self.device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()).unwrap() as *mut u8);
+            Buffer { buffer, memory, size: size.max(4), mapped }
+        }
+    }
+
+    /// Host visible buffer holding `data`
+    pub fn buffer_with_data(&self, usage: vk::BufferUsageFlags, data: &[u8]) -> Buffer {
+        let buffer = self.create_buffer(data.len() as u64, usage, true);
+        buffer.write(0, data);
+        buffer
+    }
+
+    /// `view_type` has to be an array type for array images, a single layer can still be viewed as an array
+    pub fn create_image(&self, extent: vk::Extent2D, format: vk::Format, usage: vk::ImageUsageFlags, view_type: vk::ImageViewType, layers: u32, mip_levels: u32) -> Image {
+        let aspect = match format {
+            vk::Format::D32_SFLOAT | vk::Format::D16_UNORM => vk::ImageAspectFlags::DEPTH,
+            _ => vk::ImageAspectFlags::COLOR,
+        };
+        unsafe {
+            let info = vk::ImageCreateInfo::default()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(format)
+                .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+                .mip_levels(mip_levels)
+                .array_layers(layers)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(usage)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+            let image = self.device.create_image(&info, None).unwrap();
+            let requirements = self.device.get_image_memory_requirements(image);
+            let allocate = vk::MemoryAllocateInfo::default().allocation_size(requirements.size).memory_type_index(self.memory_type(requirements.memory_type_bits, vk::MemoryPropertyFlags::DEVICE_LOCAL));
+            let memory = self.device.allocate_memory(&allocate, None).unwrap();
+            self.device.bind_image_memory(image, memory, 0).unwrap();
+
+            let range = vk::ImageSubresourceRange { aspect_mask: aspect, base_mip_level: 0, level_count: mip_levels, base_array_layer: 0, layer_count: layers };
+            let view = self.device.create_image_view(&vk::ImageViewCreateInfo::default().image(image).view_type(view_type).format(format).subresource_range(range), None).unwrap();
+            Image { image, memory, view, format, extent, layers, mip_levels, aspect }
+        }
+    }
+
+    /// Records with `record` and waits for the queue to finish it
+    pub fn submit_and_wait(&self, record: impl FnOnce(&ash::Device, vk::CommandBuffer)) {
+        unsafe {
+            self.device.begin_command_buffer(self.cmd, &vk::CommandBufferBeginInfo::default().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)).unwrap();
+            record(&self.device, self.cmd);
+            self.device.end_command_buffer(self.cmd).unwrap();
+
+            let cmd_info = [vk::CommandBufferSubmitInfo::default().command_buffer(self.cmd)];
+            self.device.queue_submit2(self.queue, &[vk::SubmitInfo2::default().command_buffer_infos(&cmd_info)], self.fence).unwrap();
+            self.device.wait_for_fences(&[self.fence], true, u64::MAX).unwrap();
+            self.device.reset_fences(&[self.fence]).unwrap();
+        }
+    }
+
+    /// SPIR-V compiled by `build.rs`, e.g. `shaders/spv/chunk.vert.spv`
+    pub fn shader(&self, path: &str) -> Result<vk::ShaderModule, String> {
+        let mut file = std::fs::File::open(Path::new(path)).map_err(|e| format!("{}: {}", path, e))?;
+        let code = ash::util::read_spv(&mut file).map_err(|e| format!("{}: {}", path, e))?;
+        unsafe { self.device.create_shader_module(&vk::ShaderModuleCreateInfo::default().code(&code), None).map_err(|e| format!("{}: {}", path, e)) }
+    }
+
+    /// Dynamic rendering pipeline on the bindless layout, viewport and scissor are dynamic
+    pub fn graphics_pipeline(&self, desc: &PipelineDesc) -> Result<vk::Pipeline, String> {
+        let vertex = self.shader(desc.vertex)?;
+        let fragment = desc.fragment.map(|path| self.shader(path)).transpose()?;
+        let mut stages = vec![vk::PipelineShaderStageCreateInfo::default().stage(vk::ShaderStageFlags::VERTEX).module(vertex).name(c"main")];
+        if let Some(fragment) = fragment {
+            stages.push(vk::PipelineShaderStageCreateInfo::default().stage(vk::ShaderStageFlags::FRAGMENT).module(fragment).name(c"main"));
+        }
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default().topology(desc.topology);
+        let viewport = vk::PipelineViewportStateCreateInfo::default().viewport_count(1).scissor_count(1);
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default().polygon_mode(desc.polygon_mode).cull_mode(desc.cull_mode).front_face(vk::FrontFace::CLOCKWISE).line_width(1.0);
+        let rasterization = rasterization.depth_bias_enable(desc.depth_bias != [0.0; 2]).depth_bias_constant_factor(desc.depth_bias[0]).depth_bias_slope_factor(desc.depth_bias[1]);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default().rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let depth = vk::PipelineDepthStencilStateCreateInfo::default().depth_test_enable(desc.depth_test).depth_write_enable(desc.depth_write).depth_compare_op(desc.depth_compare);
+        let attachments = [vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(desc.blend)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)];
+        let blend = vk::PipelineColorBlendStateCreateInfo::default().attachments(if desc.color_format.is_some() { &attachments } else { &[] });
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic = vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+        let color_formats: Vec<vk::Format> = desc.color_format.into_iter().collect();
+        let mut rendering = vk::PipelineRenderingCreateInfo::default().color_attachment_formats(&color_formats).depth_attachment_format(desc.depth_format);
+
+        let info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth)
+            .color_blend_state(&blend)
+            .dynamic_state(&dynamic)
+            .layout(self.bindless.pipeline_layout)
+            .push_next(&mut rendering);
+        let pipeline = unsafe { self.device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None) };
+        unsafe {
+            self.device.destroy_shader_module(vertex, None);
+            if let Some(fragment) = fragment {
+                self.device.destroy_shader_module(fragment, None);
+            }
+        }
+        pipeline.map(|pipelines| pipelines[0]).map_err(|(_, e)| format!("{}: {}", desc.vertex, e))
+    }
+
+    pub fn compute_pipeline(&self, path: &str) -> Result<vk::Pipeline, String> {
+        let module = self.shader(path)?;
+        let stage = vk::PipelineShaderStageCreateInfo::default().stage(vk::ShaderStageFlags::COMPUTE).module(module).name(c"main");
+        let info = vk::ComputePipelineCreateInfo::default().stage(stage).layout(self.bindless.pipeline_layout);
+        let pipeline = unsafe { self.device.create_compute_pipelines(vk::PipelineCache::null(), &[info], None) };
+        unsafe { self.device.destroy_shader_module(module, None) };
+        pipeline.map(|pipelines| pipelines[0]).map_err(|(_, e)| format!("{}: {}", path, e))
+    }
+
+    pub fn push_constants<T>(&self, cmd: vk::CommandBuffer, constants: &T) {
+        let bytes = unsafe { std::slice::from_raw_parts(constants as *const T as *const u8, size_of::<T>()) };
+        unsafe { self.device.cmd_push_constants(cmd, self.bindless.pipeline_layout, PUSH_CONSTANT_STAGES, 0, bytes) };
+    }
+
+    pub fn bind_bindless(&self, cmd: vk::CommandBuffer, bind_point: vk::PipelineBindPoint) {
+        unsafe { self.device.cmd_bind_descriptor_sets(cmd, bind_point, self.bindless.pipeline_layout, 0, &[self.bindless.set], &[]) };
+    }
+}
+
+impl Drop for Gpu {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+            self.bindless.destroy(&self.device);
+            self.device.destroy_fence(self.fence, None);
+            self.device.destroy_command_pool(self.command_pool, None);
+            self.device.destroy_device(None);
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+/// Color and depth image rendered into instead of a swapchain image
+pub struct OffscreenTarget {
+    pub color: Image,
+    pub depth: Image,
+    readback: Buffer,
+}
+
+impl OffscreenTarget {
+    pub fn new(gpu: &Gpu, extent: vk::Extent2D) -> Self {
+        let color = gpu.create_image(extent, COLOR_FORMAT, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::STORAGE, vk::ImageViewType::TYPE_2D, 1, 1);
+        let depth = gpu.create_image(extent, DEPTH_FORMAT, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED, vk::ImageViewType::TYPE_2D, 1, 1);
+        let readback = gpu.create_buffer(extent.width as u64 * extent.height as u64 * 4, vk::BufferUsageFlags::TRANSFER_DST, true);
+        Self { color, depth, readback }
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.color.extent
+    }
+
+    /// Clears the target, runs `draw` inside dynamic rendering with the viewport set and reads the color back
+    pub fn render(&self, gpu: &Gpu, clear: [f32; 4], draw: impl FnOnce(&ash::Device, vk::CommandBuffer)) -> image::RgbaImage {
+        gpu.submit_and_wait(|device, cmd| unsafe {
+            image_barrier(
+                device,
+                cmd,
+                &self.color,
+                (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::TOP_OF_PIPE, vk::AccessFlags2::NONE),
+                (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE),
+            );
+            self.record(device, cmd, Some(clear), draw);
+        });
+        self.read_color()
+    }
+
+    /// Like `render`, but `background` writes the color image as a storage image in `GENERAL` layout from a compute pass
+    /// first and `draw` draws over it. Used for the sky.
+    pub fn render_over(&self, gpu: &Gpu, background: impl FnOnce(&ash::Device, vk::CommandBuffer), draw: impl FnOnce(&ash::Device, vk::CommandBuffer)) -> image::RgbaImage {
+        gpu.submit_and_wait(|device, cmd| unsafe {
+            image_barrier(
+                device,
+                cmd,
+                &self.color,
+                (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::TOP_OF_PIPE, vk::AccessFlags2::NONE),
+                (vk::ImageLayout::GENERAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE),
+            );
+            background(device, cmd);
+            image_barrier(
+                device,
+                cmd,
+                &self.color,
+                (vk::ImageLayout::GENERAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE),
+                (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE),
+            );
+            self.record(device, cmd, None, draw);
+        });
+        self.read_color()
+    }
+
+    /// Renders into the color image in `COLOR_ATTACHMENT_OPTIMAL`, loading it without `clear`, and copies it to the readback buffer
+    unsafe fn record(&self, device: &ash::Device, cmd: vk::CommandBuffer, clear: Option<[f32; 4]>, draw: impl FnOnce(&ash::Device, vk::CommandBuffer)) {
+        let extent = self.extent();
+        image_barrier(
+            device,
+            cmd,
+            &self.depth,
+            (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::TOP_OF_PIPE, vk::AccessFlags2::NONE),
+            (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS, vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        );
+
+        let color = vk::RenderingAttachmentInfo::default()
+            .image_view(self.color.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(if clear.is_some() { vk::AttachmentLoadOp::CLEAR } else { vk::AttachmentLoadOp::LOAD })
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue { color: vk::ClearColorValue { float32: clear.unwrap_or_default() } });
+        let depth = vk::RenderingAttachmentInfo::default()
+            .image_view(self.depth.view)
+            .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } });
+        let area = vk::Rect2D { offset: vk::Offset2D::default(), extent };
+        device.cmd_begin_rendering(cmd, &vk::RenderingInfo::default().render_area(area).layer_count(1).color_attachments(std::slice::from_ref(&color)).depth_attachment(&depth));
+        device.cmd_set_viewport(cmd, 0, &[vk::Viewport { x: 0.0, y: 0.0, width: extent.width as f32, height: extent.height as f32, min_depth: 0.0, max_depth: 1.0 }]);
+        device.cmd_set_scissor(cmd, 0, &[area]);
+        draw(device, cmd);
+        device.cmd_end_rendering(cmd);
+
+        image_barrier(
+            device,
+            cmd,
+            &self.color,
+            (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE),
+            (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_READ),
+        );
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers { aspect_mask: vk::ImageAspectFlags::COLOR, mip_level: 0, base_array_layer: 0, layer_count: 1 })
+            .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 });
+        device.cmd_copy_image_to_buffer(cmd, self.color.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.readback.buffer, &[region]);
+        memory_barrier(device, cmd, (vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_WRITE), (vk::PipelineStageFlags2::HOST, vk::AccessFlags2::HOST_READ));
+    }
+
+    fn read_color(&self) -> image::RgbaImage {
+        let extent = self.extent();
+        image::RgbaImage::from_raw(extent.width, extent.height, self.readback.read()).unwrap()
+    }
+
+    /// Clears the depth and runs `draw` without a color attachment, the depth stays in `DEPTH_ATTACHMENT_OPTIMAL`
+    pub fn render_depth(&self, gpu: &Gpu, draw: impl FnOnce(&ash::Device, vk::CommandBuffer)) {
+        let extent = self.extent();
+        gpu.submit_and_wait(|device, cmd| unsafe {
+            image_barrier(
+                device,
+                cmd,
+                &self.depth,
+                (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::TOP_OF_PIPE, vk::AccessFlags2::NONE),
+                (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS, vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            );
+            let depth = vk::RenderingAttachmentInfo::default()
+                .image_view(self.depth.view)
+                .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } });
+            let area = vk::Rect2D { offset: vk::Offset2D::default(), extent };
+            device.cmd_begin_rendering(cmd, &vk::RenderingInfo::default().render_area(area).layer_count(1).depth_attachment(&depth));
+            device.cmd_set_viewport(cmd, 0, &[vk::Viewport { x: 0.0, y: 0.0, width: extent.width as f32, height: extent.height as f32, min_depth: 0.0, max_depth: 1.0 }]);
+            device.cmd_set_scissor(cmd, 0, &[area]);
+            draw(device, cmd);
+            device.cmd_end_rendering(cmd);
+        });
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.color.destroy(device);
+        self.depth.destroy(device);
+        self.readback.destroy(device);
+    }
+}
@@ -0,0 +1,185 @@
+use ash::vk;
+
+use super::gpu::{image_barrier, memory_barrier, Gpu, Image, BINDLESS_SAMPLED_IMAGE, BINDLESS_STORAGE_IMAGE};
+
+const HIZ_SHADER: &str = "shaders/spv/hiz.comp.spv";
+/// `local_size_x` and `local_size_y` of `hiz.comp`
+const HIZ_GROUP_SIZE: u32 = 8;
+const HIZ_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+
+/// Matches the push constants of `shaders/chunk/hiz.comp`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct HiZPushConstant {
+    src_index: u32,
+    dst_index: u32,
+    from_depth: u32,
+    _pad: u32,
+    src_size: [u32; 2],
+    dst_size: [u32; 2],
+}
+
+/// Depth pyramid of a depth buffer, each level halves the size and keeps the furthest depth.
+/// Level 0 has the size of the depth buffer, the last texel of odd sized levels covers the leftover row or column.
+pub struct HiZ {
+    pub image: Image,
+    level_views: Vec<vk::ImageView>,
+    /// Storage image index of every level
+    level_indices: Vec<u32>,
+    /// All levels as one sampled image, what the cull pass reads
+    pub sampled_index: u32,
+    depth_index: u32,
+    sampler: vk::Sampler,
+    pipeline: vk::Pipeline,
+}
+
+fn level_size(extent: vk::Extent2D, level: u32) -> [u32; 2] {
+    [(extent.width >> level).max(1), (extent.height >> level).max(1)]
+}
+
+impl HiZ {
+    /// `depth` has to be sampled, it is read in `SHADER_READ_ONLY_OPTIMAL`
+    pub fn new(gpu: &mut Gpu, depth: &Image) -> Result<Self, String> {
+        let pipeline = gpu.compute_pipeline(HIZ_SHADER)?;
+        let levels = depth.extent.width.max(depth.extent.height).ilog2() + 1;
+        let image = gpu.create_image(depth.extent, HIZ_FORMAT, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED, vk::ImageViewType::TYPE_2D, 1, levels);
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(levels as f32);
+        let sampler = unsafe { gpu.device.create_sampler(&sampler_info, None).unwrap() };
+
+        let mut level_views = vec![];
+        let mut level_indices = vec![];
+        for level in 0..levels {
+            let range = vk::ImageSubresourceRange { aspect_mask: vk::ImageAspectFlags::COLOR, base_mip_level: level, level_count: 1, base_array_layer: 0, layer_count: 1 };
+            let info = vk::ImageViewCreateInfo::default().image(image.image).view_type(vk::ImageViewType::TYPE_2D).format(HIZ_FORMAT).subresource_range(range);
+            let view = unsafe { gpu.device.create_image_view(&info, None).unwrap() };
+            level_indices.push(gpu.bindless.add_image(&gpu.device, view, vk::Sampler::null(), BINDLESS_STORAGE_IMAGE));
+            level_views.push(view);
+        }
+        let sampled_index = gpu.bindless.add_image(&gpu.device, image.view, sampler, BINDLESS_SAMPLED_IMAGE);
+        let depth_index = gpu.bindless.add_image(&gpu.device, depth.view, sampler, BINDLESS_SAMPLED_IMAGE);
+        Ok(Self { image, level_views, level_indices, sampled_index, depth_index, sampler, pipeline })
+    }
+
+    pub fn levels(&self) -> u32 {
+        self.image.mip_levels
+    }
+
+    /// Records the build from `depth` straight after the depth was written, leaves the pyramid ready for sampling in compute
+    pub fn build(&self, gpu: &Gpu, cmd: vk::CommandBuffer, depth: &Image) {
+        let device = &gpu.device;
+        image_barrier(
+            device,
+            cmd,
+            depth,
+            (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS, vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ),
+        );
+        // the last frame's pyramid isn't needed anymore
+        image_barrier(
+            device,
+            cmd,
+            &self.image,
+            (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ),
+            (vk::ImageLayout::GENERAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE),
+        );
+
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            gpu.bind_bindless(cmd, vk::PipelineBindPoint::COMPUTE);
+        }
+        for level in 0..self.levels() {
+            let dst_size = level_size(self.image.extent, level);
+            let push = if level == 0 {
+                HiZPushConstant { src_index: self.depth_index, dst_index: self.level_indices[0], from_depth: 1, src_size: dst_size, dst_size, ..Default::default() }
+            } else {
+                HiZPushConstant { src_index: self.level_indices[level as usize - 1], dst_index: self.level_indices[level as usize], from_depth: 0, src_size: level_size(self.image.extent, level - 1), dst_size, ..Default::default() }
+            };
+            gpu.push_constants(cmd, &push);
+            unsafe { device.cmd_dispatch(cmd, dst_size[0].div_ceil(HIZ_GROUP_SIZE), dst_size[1].div_ceil(HIZ_GROUP_SIZE), 1) };
+            memory_barrier(device, cmd, (vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE), (vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_READ));
+        }
+
+        image_barrier(
+            device,
+            cmd,
+            &self.image,
+            (vk::ImageLayout::GENERAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE),
+            (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ),
+        );
+    }
+
+    /// Like `destroy`, and hands the bindless indices out again. Retire it with `FrameScheduler::retire` while frames read it.
+    pub fn free(&self, gpu: &mut Gpu) {
+        for index in &self.level_indices {
+            gpu.bindless.free(BINDLESS_STORAGE_IMAGE, *index);
+        }
+        for index in [self.sampled_index, self.depth_index] {
+            gpu.bindless.free(BINDLESS_SAMPLED_IMAGE, index);
+        }
+        self.destroy(&gpu.device);
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            for view in &self.level_views {
+                device.destroy_image_view(*view, None);
+            }
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_pipeline(self.pipeline, None);
+        }
+        self.image.destroy(device);
+    }
+}
+
+/// The pyramid `HiZ::build` makes, built on the cpu from a read back depth buffer for `culling::occluded_cpu`
+pub struct CpuHiZ {
+    pub extent: vk::Extent2D,
+    /// Texels of every level row by row
+    pub levels: Vec<Vec<f32>>,
+}
+
+impl CpuHiZ {
+    /// `depth` holds `extent.width * extent.height` depths row by row
+    pub fn from_depth(extent: vk::Extent2D, depth: Vec<f32>) -> Self {
+        let level_count = extent.width.max(extent.height).ilog2() + 1;
+        let mut levels = vec![depth];
+        for level in 1..level_count {
+            let [src_width, src_height] = level_size(extent, level - 1);
+            let [width, height] = level_size(extent, level);
+            let src = levels.last().unwrap();
+            let mut texels = Vec::with_capacity((width * height) as usize);
+            for y in 0..height {
+                for x in 0..width {
+                    // the last texel of an odd sized level also covers the row or column left over
+                    let extent_x = 2 + if x == width - 1 { src_width & 1 } else { 0 };
+                    let extent_y = 2 + if y == height - 1 { src_height & 1 } else { 0 };
+                    let mut depth = 0.0f32;
+                    for sy in 0..extent_y {
+                        for sx in 0..extent_x {
+                            let [src_x, src_y] = [(x * 2 + sx).min(src_width - 1), (y * 2 + sy).min(src_height - 1)];
+                            depth = depth.max(src[(src_y * src_width + src_x) as usize]);
+                        }
+                    }
+                    texels.push(depth);
+                }
+            }
+            levels.push(texels);
+        }
+        Self { extent, levels }
+    }
+
+    pub fn level_size(&self, level: u32) -> [u32; 2] {
+        level_size(self.extent, level)
+    }
+
+    pub fn texel(&self, level: u32, [x, y]: [u32; 2]) -> f32 {
+        self.levels[level as usize][(y * self.level_size(level)[0] + x) as usize]
+    }
+}
@@ -0,0 +1,69 @@
+//! Rendering on the bindless `gpu::Gpu`, headless for the golden-image checks or into a window for the app
+
+pub mod atmosphere;
+pub mod chunks;
+pub mod culling;
+pub mod fog;
+pub mod frames;
+pub mod golden;
+pub mod gpu;
+pub mod hiz;
+pub mod shadow;
+pub mod sky;
+pub mod swapchain;
+pub mod textures;
+pub mod ui;
+
+/// World units per block, `voxel_scale` in the chunk shaders
+pub const RENDER_VOXEL_SCALE: f32 = 0.1;
+/// Clip planes of `CameraData::look_at`, in world units
+pub const CAMERA_NEAR: f32 = 0.01;
+pub const CAMERA_FAR: f32 = 100.0;
+
+/// Matches `CameraData` in `shaders/chunk/chunk.vert`, `pos.w` is unused
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraData {
+    pub viewproj: glm::Mat4,
+    pub pos: glm::Vec4,
+}
+
+impl CameraData {
+    /// Perspective camera with the vulkan clip space, y up in the world
+    pub fn look_at(eye: glm::Vec3, target: glm::Vec3, fov_degrees: f32, aspect: f32) -> Self {
+        let view = glm::Mat4::look_at(eye, target, glm::Vec3::unit_y());
+        let projection = glm::projection::rh_yup::perspective_vk(fov_degrees.to_radians(), aspect, CAMERA_NEAR, CAMERA_FAR);
+        Self { viewproj: projection * view, pos: eye.into_homogeneous_point() }
+    }
+}
+
+/// Matches the push constants of `shaders/chunk/chunk.vert`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkPushConstant {
+    pub cam_index: u32,
+    pub quad_index: u32,
+    /// Bindless indices of the block texture array and the materials buffer, see `textures::GpuTextures`
+    pub texture_index: u32,
+    pub material_index: u32,
+    /// Bindless index of the `ChunkDraw` buffer, see `chunks::GpuChunks`
+    pub draw_index: u32,
+    /// Uniform index of the `shadow::ShadowData`, only read with `CHUNK_SHADOWS`
+    pub shadow_index: u32,
+    pub flags: u32,
+    /// Uniform index of the `sky::SkyData`, only read with `CHUNK_DAYLIGHT`
+    pub sky_index: u32,
+    /// Uniform index of the `atmosphere::AtmosphereData`, only read with `CHUNK_ATMOSPHERE`
+    pub atmosphere_index: u32,
+    /// Uniform index of the `fog::FogData`, only read with `CHUNK_FOG`
+    pub fog_index: u32,
+}
+
+/// Bits of `ChunkPushConstant::flags`
+pub const CHUNK_SHADOWS: u32 = 1;
+/// Light with the sun or moon and the ambient of the time of day instead of fixed face shading alone
+pub const CHUNK_DAYLIGHT: u32 = 2;
+/// Aerial perspective, distant chunks fade into the scattered light of the atmosphere
+pub const CHUNK_ATMOSPHERE: u32 = 4;
+/// Fog in the sky color and the fade in of new chunks, takes the sky from `sky_index` so it needs `CHUNK_DAYLIGHT`
+pub const CHUNK_FOG: u32 = 8;
@@ -0,0 +1,274 @@
+use ash::vk;
+use voxelengine::vulkan::util::slice_as_u8;
+
+use super::{
+    chunks::GpuChunks,
+    gpu::{image_barrier, update_buffer, Buffer, Gpu, Image, PipelineDesc, BINDLESS_SAMPLED_IMAGE, BINDLESS_UNIFORM, DEPTH_FORMAT},
+    CameraData, ChunkPushConstant, CAMERA_FAR, CAMERA_NEAR,
+};
+
+/// Array length of `ShadowData::cascades`, `ShadowSettings::cascades` can't go higher
+pub const MAX_CASCADES: usize = 4;
+/// How far behind a cascade casters are still rendered, in world units
+const CASTER_DISTANCE: f32 = 10.0;
+/// Bounding sphere radii are rounded up to this so they don't change size while the camera turns
+const RADIUS_STEP: f32 = 1.0 / 16.0;
+
+/// What the settings UI changes, the shadow map is recreated when `cascades` or `resolution` change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// 1 to `MAX_CASCADES`
+    pub cascades: u32,
+    /// Size of every cascade in texels
+    pub resolution: u32,
+    /// Shadows end here, in world units from the camera
+    pub distance: f32,
+    /// Blend between uniform (0) and logarithmic (1) cascade splits
+    pub split_lambda: f32,
+    /// Texels in every direction averaged by the PCF filter
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self { enabled: true, cascades: 4, resolution: 2048, distance: 16.0, split_lambda: 0.75, pcf_radius: 1 }
+    }
+}
+
+impl ShadowSettings {
+    /// Keeps edited values in a range the shadow map can handle
+    pub fn clamp(&mut self) {
+        self.cascades = self.cascades.clamp(1, MAX_CASCADES as u32);
+        self.resolution = self.resolution.clamp(256, 8192).next_power_of_two();
+        self.distance = self.distance.max(CAMERA_NEAR * 2.0);
+        self.split_lambda = self.split_lambda.clamp(0.0, 1.0);
+        self.pcf_radius = self.pcf_radius.min(3);
+    }
+}
+
+/// Where cascade `index` ends in view depth, the practical split scheme between uniform and logarithmic splits
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|index| {
+            let fraction = index as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Matches `ShadowData` in `shaders/chunk/chunk.frag`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowData {
+    /// World to shadow map clip space per cascade
+    pub cascades: [glm::Mat4; MAX_CASCADES],
+    /// View depth where each cascade ends
+    pub splits: [f32; MAX_CASCADES],
+    /// World size of a texel per cascade, fragments are offset along their normal by it
+    pub texel_sizes: [f32; MAX_CASCADES],
+    /// Towards the light casting the shadows, w is unused
+    pub sun: glm::Vec4,
+    /// Camera forward for the view depth, w is unused
+    pub forward: glm::Vec4,
+    pub map_index: u32,
+    pub cascade_count: u32,
+    pub pcf_radius: u32,
+    pub _pad: u32,
+}
+
+impl ShadowData {
+    /// Fits every cascade around a bounding sphere of its slice of the camera frustum. The light view never rotates
+    /// and the sphere center is snapped to whole texels, so shadow edges don't shimmer while the camera moves or turns.
+    /// `sun` points towards the light, `sky::SkyState::light` follows the time of day.
+    pub fn fit(camera: &CameraData, settings: &ShadowSettings, sun: glm::Vec3) -> Self {
+        let inverse = camera.viewproj.inversed();
+        let unproject = |x: f32, y: f32, z: f32| {
+            let point = inverse * glm::Vec4::new(x, y, z, 1.0);
+            point.xyz() / point.w
+        };
+        // near and far corners of the camera frustum, depth is linear in view depth along each edge
+        let corners: Vec<(glm::Vec3, glm::Vec3)> = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].iter().map(|(x, y)| (unproject(*x, *y, 0.0), unproject(*x, *y, 1.0))).collect();
+        let forward = ((corners.iter().map(|(_, far)| *far).sum::<glm::Vec3>() - corners.iter().map(|(near, _)| *near).sum::<glm::Vec3>()) / 4.0).normalized();
+
+        let up = if sun.y.abs() > 0.99 { glm::Vec3::unit_z() } else { glm::Vec3::unit_y() };
+        let view = glm::Mat4::look_at(glm::Vec3::zero(), -sun, up);
+        let count = settings.cascades.clamp(1, MAX_CASCADES as u32) as usize;
+        let far = settings.distance.min(CAMERA_FAR);
+        let splits = cascade_splits(CAMERA_NEAR, far, count, settings.split_lambda);
+
+        let mut data = Self {
+            cascades: [glm::Mat4::identity(); MAX_CASCADES],
+            splits: [far; MAX_CASCADES],
+            texel_sizes: [0.0; MAX_CASCADES],
+            sun: sun.into_homogeneous_vector(),
+            forward: forward.into_homogeneous_vector(),
+            map_index: 0,
+            cascade_count: count as u32,
+            pcf_radius: settings.pcf_radius,
+            _pad: 0,
+        };
+        let mut start = CAMERA_NEAR;
+        for (index, end) in splits.into_iter().enumerate() {
+            let slice: Vec<glm::Vec3> = corners
+                .iter()
+                .flat_map(|(near, far)| {
+                    let along = |depth: f32| *near + (*far - *near) * ((depth - CAMERA_NEAR) / (CAMERA_FAR - CAMERA_NEAR));
+                    [along(start), along(end)]
+                })
+                .collect();
+            let center = slice.iter().copied().sum::<glm::Vec3>() / slice.len() as f32;
+            let radius = (slice.iter().map(|corner| (*corner - center).mag()).fold(0.0, f32::max) / RADIUS_STEP).ceil() * RADIUS_STEP;
+
+            let texel = radius * 2.0 / settings.resolution as f32;
+            let light_center = view.transform_point3(center);
+            let [x, y] = [(light_center.x / texel).floor() * texel, (light_center.y / texel).floor() * texel];
+            // the light looks down -z, casters up to `CASTER_DISTANCE` in front of the sphere still land in the map.
+            // `orthographic_vk` only gets y right for symmetric bounds, the snapped center is moved to the origin instead
+            let projection = glm::projection::rh_yup::orthographic_vk(-radius, radius, -radius, radius, -light_center.z - radius - CASTER_DISTANCE, -light_center.z + radius);
+            data.cascades[index] = projection * glm::Mat4::from_translation(glm::Vec3::new(-x, -y, 0.0)) * view;
+            data.splits[index] = end;
+            data.texel_sizes[index] = texel;
+            start = end;
+        }
+        data
+    }
+}
+
+/// Depth array with one layer per cascade, rendered from every chunk with the chunk vertex shader
+pub struct ShadowMap {
+    pub image: Image,
+    layer_views: Vec<vk::ImageView>,
+    sampler: vk::Sampler,
+    pipeline: vk::Pipeline,
+    /// The whole array with a compare sampler, see `ShadowData::map_index`
+    pub sampled_index: u32,
+    /// `ShadowData` and one `CameraData` per cascade, rewritten by `record`
+    data: Buffer,
+    cameras: Vec<Buffer>,
+    pub data_index: u32,
+    camera_indices: Vec<u32>,
+    pub settings: ShadowSettings,
+}
+
+impl ShadowMap {
+    pub fn new(gpu: &mut Gpu, settings: ShadowSettings) -> Result<Self, String> {
+        let mut settings = settings;
+        settings.clamp();
+        let pipeline = gpu.graphics_pipeline(&PipelineDesc {
+            color_format: None,
+            // both sides cast, thin walls would leak light with back faces culled
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_bias: [1.25, 1.75],
+            ..PipelineDesc::new("shaders/spv/chunk.vert.spv", None)
+        })?;
+        let extent = vk::Extent2D { width: settings.resolution, height: settings.resolution };
+        let layers = settings.cascades;
+        let image = gpu.create_image(extent, DEPTH_FORMAT, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED, vk::ImageViewType::TYPE_2D_ARRAY, layers, 1);
+
+        // linear filtering of a compare sampler averages 2x2 comparisons for free
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL);
+        let sampler = unsafe { gpu.device.create_sampler(&sampler_info, None).unwrap() };
+
+        let layer_views = (0..layers)
+            .map(|layer| {
+                let range = vk::ImageSubresourceRange { aspect_mask: vk::ImageAspectFlags::DEPTH, base_mip_level: 0, level_count: 1, base_array_layer: layer, layer_count: 1 };
+                let info = vk::ImageViewCreateInfo::default().image(image.image).view_type(vk::ImageViewType::TYPE_2D).format(DEPTH_FORMAT).subresource_range(range);
+                unsafe { gpu.device.create_image_view(&info, None).unwrap() }
+            })
+            .collect();
+        let sampled_index = gpu.bindless.add_image(&gpu.device, image.view, sampler, BINDLESS_SAMPLED_IMAGE);
+
+        let usage = vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST;
+        let data = gpu.create_buffer(size_of::<ShadowData>() as u64, usage, false);
+        let data_index = gpu.bindless.add_buffer(&gpu.device, &data, BINDLESS_UNIFORM);
+        let cameras: Vec<Buffer> = (0..layers).map(|_| gpu.create_buffer(size_of::<CameraData>() as u64, usage, false)).collect();
+        let camera_indices = cameras.iter().map(|camera| gpu.bindless.add_buffer(&gpu.device, camera, BINDLESS_UNIFORM)).collect();
+        Ok(Self { image, layer_views, sampler, pipeline, sampled_index, data, cameras, data_index, camera_indices, settings })
+    }
+
+    /// Whether `settings` can be used without creating the map again
+    pub fn fits(&self, settings: &ShadowSettings) -> bool {
+        let mut settings = *settings;
+        settings.clamp();
+        settings.cascades == self.settings.cascades && settings.resolution == self.settings.resolution
+    }
+
+    /// Fits the cascades to `camera`, updates the uniforms and renders every cascade outside of rendering.
+    /// Leaves the map ready for sampling in fragment shaders.
+    pub fn record(&self, gpu: &Gpu, cmd: vk::CommandBuffer, chunks: &GpuChunks, camera: &CameraData, sun: glm::Vec3) -> ShadowData {
+        let data = ShadowData { map_index: self.sampled_index, ..ShadowData::fit(camera, &self.settings, sun) };
+        let device = &gpu.device;
+        update_buffer(device, cmd, &self.data, slice_as_u8(&[data]));
+        for (layer, camera_buffer) in self.cameras.iter().enumerate() {
+            update_buffer(device, cmd, camera_buffer, slice_as_u8(&[CameraData { viewproj: data.cascades[layer], pos: camera.pos }]));
+        }
+
+        let extent = self.image.extent;
+        image_barrier(
+            device,
+            cmd,
+            &self.image,
+            (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ),
+            (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS, vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        );
+        for (layer, view) in self.layer_views.iter().enumerate() {
+            let depth = vk::RenderingAttachmentInfo::default()
+                .image_view(*view)
+                .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } });
+            let area = vk::Rect2D { offset: vk::Offset2D::default(), extent };
+            unsafe {
+                device.cmd_begin_rendering(cmd, &vk::RenderingInfo::default().render_area(area).layer_count(1).depth_attachment(&depth));
+                device.cmd_set_viewport(cmd, 0, &[vk::Viewport { x: 0.0, y: 0.0, width: extent.width as f32, height: extent.height as f32, min_depth: 0.0, max_depth: 1.0 }]);
+                device.cmd_set_scissor(cmd, 0, &[area]);
+                device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+                gpu.bind_bindless(cmd, vk::PipelineBindPoint::GRAPHICS);
+                chunks.draw_all(gpu, cmd, ChunkPushConstant { cam_index: self.camera_indices[layer], ..Default::default() });
+                device.cmd_end_rendering(cmd);
+            }
+        }
+        image_barrier(
+            device,
+            cmd,
+            &self.image,
+            (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS, vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ),
+        );
+        data
+    }
+
+    /// Like `destroy`, and hands the bindless indices out again. Retire it with `FrameScheduler::retire` while frames read it.
+    pub fn free(&self, gpu: &mut Gpu) {
+        gpu.bindless.free(BINDLESS_SAMPLED_IMAGE, self.sampled_index);
+        for index in self.camera_indices.iter().chain([&self.data_index]) {
+            gpu.bindless.free(BINDLESS_UNIFORM, *index);
+        }
+        self.destroy(&gpu.device);
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            for view in &self.layer_views {
+                device.destroy_image_view(*view, None);
+            }
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_pipeline(self.pipeline, None);
+        }
+        for buffer in self.cameras.iter().chain([&self.data]) {
+            buffer.destroy(device);
+        }
+        self.image.destroy(device);
+    }
+}
@@ -0,0 +1,189 @@
+use ash::vk;
+use voxelengine::vulkan::util::slice_as_u8;
+
+use super::{
+    atmosphere::{Atmosphere, AtmosphereSettings},
+    gpu::{update_buffer, Buffer, Gpu, BINDLESS_UNIFORM},
+    CameraData,
+};
+use crate::world_test::time::WorldTime;
+
+const SKY_SHADER: &str = "shaders/spv/skybox.comp.spv";
+/// `local_size_x` and `local_size_y` of `skybox.comp`
+const SKY_GROUP_SIZE: u32 = 16;
+/// How far the sun path leans towards -z, 0 would cross the zenith at noon
+const SUN_TILT_DEGREES: f32 = 25.0;
+
+const DAY_ZENITH: [f32; 3] = [0.22, 0.45, 0.85];
+const DAY_HORIZON: [f32; 3] = [0.65, 0.78, 0.92];
+const NIGHT_ZENITH: [f32; 3] = [0.005, 0.008, 0.03];
+const NIGHT_HORIZON: [f32; 3] = [0.02, 0.03, 0.07];
+/// Added to the horizon around sunrise and sunset
+const TWILIGHT_HORIZON: [f32; 3] = [0.55, 0.25, 0.08];
+/// Light and ambient add up to a bit over 1 at noon, the face shading darkens the sides
+const DAY_LIGHT: [f32; 3] = [0.72, 0.7, 0.65];
+const LOW_SUN_LIGHT: [f32; 3] = [0.75, 0.45, 0.25];
+const MOON_LIGHT: [f32; 3] = [0.1, 0.12, 0.18];
+const DAY_AMBIENT: [f32; 3] = [0.38, 0.4, 0.45];
+const NIGHT_AMBIENT: [f32; 3] = [0.08, 0.09, 0.13];
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> glm::Vec3 {
+    glm::Vec3::from(a) * (1.0 - t) + glm::Vec3::from(b) * t
+}
+
+/// Everything the sky and lighting derive from the time of day
+#[derive(Clone, Copy, Debug)]
+pub struct SkyState {
+    /// Towards the sun and moon, the moon is always opposite
+    pub sun: glm::Vec3,
+    pub moon: glm::Vec3,
+    pub zenith: glm::Vec3,
+    pub horizon: glm::Vec3,
+    /// 0 by day, 1 in the middle of the night
+    pub stars: f32,
+    /// Direction and color of whichever of sun and moon is up, shadows are cast from it
+    pub light: glm::Vec3,
+    pub light_color: glm::Vec3,
+    pub ambient: glm::Vec3,
+    /// Angle the stars turned around the sun path axis
+    pub star_rotation: f32,
+}
+
+impl SkyState {
+    pub fn at(time: &WorldTime) -> Self {
+        let angle = time.time_of_day() * std::f32::consts::TAU;
+        let tilt = SUN_TILT_DEGREES.to_radians();
+        // rises in +x, sets in -x
+        let sun = glm::Vec3::new(angle.cos(), angle.sin() * tilt.cos(), -angle.sin() * tilt.sin());
+        let moon = -sun;
+        let day = smoothstep(-0.1, 0.2, sun.y);
+        let twilight = (-(sun.y / 0.15).powi(2)).exp();
+
+        let (light, light_color) = if sun.y > -0.05 {
+            (sun, mix(LOW_SUN_LIGHT, DAY_LIGHT, smoothstep(0.0, 0.35, sun.y)) * smoothstep(-0.05, 0.05, sun.y))
+        } else {
+            (moon, mix([0.0; 3], MOON_LIGHT, smoothstep(0.0, 0.1, moon.y)))
+        };
+        Self {
+            sun,
+            moon,
+            zenith: mix(NIGHT_ZENITH, DAY_ZENITH, day),
+            horizon: mix(NIGHT_HORIZON, DAY_HORIZON, day) + glm::Vec3::from(TWILIGHT_HORIZON) * twilight,
+            stars: smoothstep(0.05, -0.25, sun.y),
+            light,
+            light_color,
+            ambient: mix(NIGHT_AMBIENT, DAY_AMBIENT, day),
+            star_rotation: angle,
+        }
+    }
+
+    /// Colors the sunlight by what the atmosphere lets through, reddening it towards sunset.
+    /// Scaled so the sun at the zenith keeps `DAY_LIGHT`, the moonlight is left alone.
+    pub fn apply_atmosphere(&mut self, settings: &AtmosphereSettings) {
+        if self.light != self.sun {
+            return;
+        }
+        let zenith = settings.transmittance(0.0, 1.0);
+        let transmittance = settings.transmittance(0.0, self.sun.y.max(0.0));
+        let filtered = glm::Vec3::from(DAY_LIGHT) * transmittance / zenith.max_by_component(glm::Vec3::broadcast(1e-6));
+        self.light_color = filtered * smoothstep(-0.05, 0.05, self.sun.y);
+    }
+}
+
+/// Matches `SkyData` in `shaders/skybox.comp` and `shaders/chunk/chunk.frag`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SkyData {
+    /// Clip space to world space of the camera, for view rays
+    pub inverse_viewproj: glm::Mat4,
+    pub camera: glm::Vec4,
+    pub sun: glm::Vec4,
+    pub moon: glm::Vec4,
+    pub zenith: glm::Vec4,
+    pub horizon: glm::Vec4,
+    pub light: glm::Vec4,
+    pub light_color: glm::Vec4,
+    pub ambient: glm::Vec4,
+    /// Star visibility and rotation, z and w are unused
+    pub stars: glm::Vec4,
+}
+
+impl SkyData {
+    pub fn new(state: &SkyState, camera: &CameraData) -> Self {
+        Self {
+            inverse_viewproj: camera.viewproj.inversed(),
+            camera: camera.pos,
+            sun: state.sun.into_homogeneous_vector(),
+            moon: state.moon.into_homogeneous_vector(),
+            zenith: state.zenith.into_homogeneous_vector(),
+            horizon: state.horizon.into_homogeneous_vector(),
+            light: state.light.into_homogeneous_vector(),
+            light_color: state.light_color.into_homogeneous_vector(),
+            ambient: state.ambient.into_homogeneous_vector(),
+            stars: glm::Vec4::new(state.stars, state.star_rotation, 0.0, 0.0),
+        }
+    }
+}
+
+/// Matches the push constants of `shaders/skybox.comp`, replaces the hand tuned `SkyBoxPushConstant` of the engine
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkyPushConstant {
+    pub sky_index: u32,
+    pub image_index: u32,
+    /// Uniform index of the `atmosphere::AtmosphereData`, only read with `SKY_ATMOSPHERE`
+    pub atmosphere_index: u32,
+    pub flags: u32,
+}
+
+/// Bits of `SkyPushConstant::flags`
+/// Scattered sunlight from the atmosphere LUTs instead of the gradient, the night sky is still the gradient
+pub const SKY_ATMOSPHERE: u32 = 1;
+
+/// Fills a storage image with the sky of a `SkyData`, chunks are drawn over it afterwards
+pub struct SkyPass {
+    pipeline: vk::Pipeline,
+    data: Buffer,
+    /// Uniform index of the `SkyData`, the chunk shaders light with it too
+    pub data_index: u32,
+}
+
+impl SkyPass {
+    pub fn new(gpu: &mut Gpu) -> Result<Self, String> {
+        let pipeline = gpu.compute_pipeline(SKY_SHADER)?;
+        let data = gpu.create_buffer(size_of::<SkyData>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, false);
+        let data_index = gpu.bindless.add_buffer(&gpu.device, &data, BINDLESS_UNIFORM);
+        Ok(Self { pipeline, data, data_index })
+    }
+
+    /// Record before the sky and the chunks of the frame, outside of rendering
+    pub fn update(&self, gpu: &Gpu, cmd: vk::CommandBuffer, data: &SkyData) {
+        update_buffer(&gpu.device, cmd, &self.data, slice_as_u8(&[*data]));
+    }
+
+    /// `image_index` is a storage image in `GENERAL` layout, `atmosphere` has to be recorded before
+    pub fn record(&self, gpu: &Gpu, cmd: vk::CommandBuffer, image_index: u32, extent: vk::Extent2D, atmosphere: Option<&Atmosphere>) {
+        let push = SkyPushConstant {
+            sky_index: self.data_index,
+            image_index,
+            atmosphere_index: atmosphere.map_or(0, |atmosphere| atmosphere.data_index),
+            flags: if atmosphere.is_some() { SKY_ATMOSPHERE } else { 0 },
+        };
+        unsafe {
+            gpu.device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            gpu.bind_bindless(cmd, vk::PipelineBindPoint::COMPUTE);
+            gpu.push_constants(cmd, &push);
+            gpu.device.cmd_dispatch(cmd, extent.width.div_ceil(SKY_GROUP_SIZE), extent.height.div_ceil(SKY_GROUP_SIZE), 1);
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_pipeline(self.pipeline, None) };
+        self.data.destroy(device);
+    }
+}
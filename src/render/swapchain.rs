@@ -0,0 +1,179 @@
+use ash::{khr, vk};
+
+use super::{
+    frames::FrameScheduler,
+    gpu::{Gpu, Image},
+};
+
+/// Formats the frame can be blitted to, the first one the surface supports is used
+const SURFACE_FORMATS: [vk::Format; 2] = [vk::Format::B8G8R8A8_UNORM, vk::Format::R8G8B8A8_UNORM];
+
+/// Image acquired for a frame with the semaphores its submit waits on and signals
+pub struct SwapchainImage {
+    pub index: u32,
+    pub wait: vk::SemaphoreSubmitInfo<'static>,
+    pub signal: vk::SemaphoreSubmitInfo<'static>,
+}
+
+/// Swapchain of the window surface. Frames render into their own color image and are blitted to the swapchain image,
+/// so the images only need `TRANSFER_DST`.
+pub struct Swapchain {
+    surface_loader: khr::surface::Instance,
+    surface: vk::SurfaceKHR,
+    loader: khr::swapchain::Device,
+    swapchain: vk::SwapchainKHR,
+    images: Vec<vk::Image>,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    /// One per frame slot, signaled by the acquire
+    acquired: Vec<vk::Semaphore>,
+    /// One per image, signaled by the frame and waited on by the present
+    rendered: Vec<vk::Semaphore>,
+}
+
+fn create_semaphores(device: &ash::Device, count: usize) -> Vec<vk::Semaphore> {
+    (0..count).map(|_| unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None).unwrap() }).collect()
+}
+
+impl Swapchain {
+    /// Takes ownership of `surface`, `extent` is used when the surface doesn't dictate one
+    pub fn new(gpu: &Gpu, surface: vk::SurfaceKHR, extent: vk::Extent2D, frames_in_flight: usize) -> Self {
+        let surface_loader = khr::surface::Instance::new(&gpu.entry, &gpu.instance);
+        let loader = khr::swapchain::Device::new(&gpu.instance, &gpu.device);
+        let mut swapchain = Self {
+            surface_loader,
+            surface,
+            loader,
+            swapchain: vk::SwapchainKHR::null(),
+            images: vec![],
+            format: vk::Format::UNDEFINED,
+            extent,
+            acquired: create_semaphores(&gpu.device, frames_in_flight),
+            rendered: vec![],
+        };
+        swapchain.create(gpu, extent);
+        swapchain
+    }
+
+    /// Creates the swapchain and its semaphores, replacing `self.swapchain` if there is one
+    fn create(&mut self, gpu: &Gpu, extent: vk::Extent2D) {
+        unsafe {
+            let capabilities = self.surface_loader.get_physical_device_surface_capabilities(gpu.physical_device, self.surface).unwrap();
+            let formats = self.surface_loader.get_physical_device_surface_formats(gpu.physical_device, self.surface).unwrap();
+            let format = SURFACE_FORMATS.iter().find_map(|wanted| formats.iter().find(|format| format.format == *wanted)).copied().unwrap_or(formats[0]);
+            self.extent = if capabilities.current_extent.width != u32::MAX {
+                capabilities.current_extent
+            } else {
+                vk::Extent2D {
+                    width: extent.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+                    height: extent.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height),
+                }
+            };
+            let mut image_count = capabilities.min_image_count + 1;
+            if capabilities.max_image_count > 0 {
+                image_count = image_count.min(capabilities.max_image_count);
+            }
+
+            let info = vk::SwapchainCreateInfoKHR::default()
+                .surface(self.surface)
+                .min_image_count(image_count)
+                .image_format(format.format)
+                .image_color_space(format.color_space)
+                .image_extent(self.extent)
+                .image_array_layers(1)
+                .image_usage(vk::ImageUsageFlags::TRANSFER_DST)
+                .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .pre_transform(capabilities.current_transform)
+                .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+                .present_mode(vk::PresentModeKHR::FIFO)
+                .clipped(true)
+                .old_swapchain(self.swapchain);
+            self.swapchain = self.loader.create_swapchain(&info, None).unwrap();
+            self.images = self.loader.get_swapchain_images(self.swapchain).unwrap();
+            self.format = format.format;
+            self.rendered = create_semaphores(&gpu.device, self.images.len());
+        }
+    }
+
+    /// Builds a swapchain of `extent` from the old one. Call it while recording a frame, the old swapchain is retired
+    /// with that frame so the presents queued before it are done too.
+    pub fn recreate(&mut self, gpu: &Gpu, scheduler: &mut FrameScheduler, extent: vk::Extent2D) {
+        let old = self.swapchain;
+        let old_rendered = std::mem::take(&mut self.rendered);
+        self.create(gpu, extent);
+
+        let loader = self.loader.clone();
+        scheduler.retire(move |gpu| unsafe {
+            loader.destroy_swapchain(old, None);
+            for semaphore in old_rendered {
+                gpu.device.destroy_semaphore(semaphore, None);
+            }
+        });
+        log::info!("recreated the swapchain at {}x{}", self.extent.width, self.extent.height);
+    }
+
+    /// Next image for the frame in `slot` of the scheduler, `None` when the swapchain is out of date
+    pub fn acquire(&self, slot: usize) -> Option<SwapchainImage> {
+        let acquired = self.acquired[slot];
+        match unsafe { self.loader.acquire_next_image(self.swapchain, u64::MAX, acquired, vk::Fence::null()) } {
+            Ok((index, _)) => Some(SwapchainImage {
+                index,
+                wait: vk::SemaphoreSubmitInfo::default().semaphore(acquired).stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER),
+                signal: vk::SemaphoreSubmitInfo::default().semaphore(self.rendered[index as usize]).stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+            }),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => None,
+            Err(e) => panic!("acquiring a swapchain image: {}", e),
+        }
+    }
+
+    /// Copies `source` in `TRANSFER_SRC_OPTIMAL` to the image, scaled if the sizes differ, and leaves it ready to present
+    pub fn record_blit(&self, gpu: &Gpu, cmd: vk::CommandBuffer, image: &SwapchainImage, source: &Image) {
+        let target = self.images[image.index as usize];
+        let barrier = |from: (vk::ImageLayout, vk::PipelineStageFlags2, vk::AccessFlags2), to: (vk::ImageLayout, vk::PipelineStageFlags2, vk::AccessFlags2)| {
+            let range = vk::ImageSubresourceRange { aspect_mask: vk::ImageAspectFlags::COLOR, base_mip_level: 0, level_count: 1, base_array_layer: 0, layer_count: 1 };
+            let barrier = vk::ImageMemoryBarrier2::default()
+                .image(target)
+                .subresource_range(range)
+                .old_layout(from.0)
+                .src_stage_mask(from.1)
+                .src_access_mask(from.2)
+                .new_layout(to.0)
+                .dst_stage_mask(to.1)
+                .dst_access_mask(to.2);
+            unsafe { gpu.device.cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::default().image_memory_barriers(&[barrier])) };
+        };
+        let transfer = (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags2::BLIT, vk::AccessFlags2::TRANSFER_WRITE);
+
+        // the acquire semaphore is waited on at the transfer stage
+        barrier((vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::BLIT, vk::AccessFlags2::NONE), transfer);
+        let layers = vk::ImageSubresourceLayers { aspect_mask: vk::ImageAspectFlags::COLOR, mip_level: 0, base_array_layer: 0, layer_count: 1 };
+        let corner = |extent: vk::Extent2D| vk::Offset3D { x: extent.width as i32, y: extent.height as i32, z: 1 };
+        let region = vk::ImageBlit::default().src_subresource(layers).src_offsets([vk::Offset3D::default(), corner(source.extent)]).dst_subresource(layers).dst_offsets([vk::Offset3D::default(), corner(self.extent)]);
+        unsafe { gpu.device.cmd_blit_image(cmd, source.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, target, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region], vk::Filter::LINEAR) };
+        barrier(transfer, (vk::ImageLayout::PRESENT_SRC_KHR, vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE));
+    }
+
+    /// Presents once the frame signaled `image.signal`, returns whether the swapchain has to be recreated
+    pub fn present(&self, gpu: &Gpu, image: &SwapchainImage) -> bool {
+        let waits = [self.rendered[image.index as usize]];
+        let swapchains = [self.swapchain];
+        let indices = [image.index];
+        let info = vk::PresentInfoKHR::default().wait_semaphores(&waits).swapchains(&swapchains).image_indices(&indices);
+        match unsafe { self.loader.queue_present(gpu.queue, &info) } {
+            Ok(suboptimal) => suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => true,
+            Err(e) => panic!("presenting: {}", e),
+        }
+    }
+
+    /// Also destroys the surface, the device has to be idle
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            for semaphore in self.acquired.iter().chain(&self.rendered) {
+                device.destroy_semaphore(*semaphore, None);
+            }
+            self.loader.destroy_swapchain(self.swapchain, None);
+            self.surface_loader.destroy_surface(self.surface, None);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::path::Path;
+
+use ash::vk;
+use image::{imageops::FilterType, Rgba, RgbaImage};
+use voxelengine::vulkan::util::slice_as_u8;
+
+use super::gpu::{image_barrier, Buffer, Gpu, Image, BINDLESS_SAMPLED_IMAGE, BINDLESS_STORAGE_BUFFER};
+use crate::world_test::block::{self, BlockId, BLOCKS};
+
+/// `<block name>.png` for every face, `<block name>_top.png`, `_side.png` and `_bottom.png` override single faces
+pub const TEXTURE_DIR: &str = "assets/textures";
+/// Textures of another size are scaled to it, has to be a power of two for the mip chain
+pub const TILE_SIZE: u32 = 16;
+pub const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Texture array layers of one block, indexed by `BlockId` in the materials buffer.
+/// Matches `Material` in `shaders/chunk/chunk.frag`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GPUMaterial {
+    pub top: u32,
+    pub side: u32,
+    pub bottom: u32,
+    pub _pad: u32,
+}
+
+/// Layers and materials on the cpu, every layer is its own image so mipmapping never mixes two textures
+pub struct TextureSet {
+    pub layers: Vec<RgbaImage>,
+    pub materials: Vec<GPUMaterial>,
+}
+
+/// Block color with a little per pixel noise, used when a block has no texture
+fn color_tile(block: BlockId) -> RgbaImage {
+    let color = block::info(block).color;
+    RgbaImage::from_fn(TILE_SIZE, TILE_SIZE, |x, y| {
+        let hash = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663) ^ (block as u32).wrapping_mul(83_492_791)) % 17;
+        let shade = |channel: u8| (channel as i32 + hash as i32 - 8).clamp(0, 255) as u8;
+        Rgba([shade(color[0]), shade(color[1]), shade(color[2]), 255])
+    })
+}
+
+fn load_tile(path: &Path) -> Option<RgbaImage> {
+    if !path.exists() {
+        return None;
+    }
+    match image::open(path) {
+        Ok(image) => {
+            let mut tile = image.to_rgba8();
+            if tile.dimensions() != (TILE_SIZE, TILE_SIZE) {
+                log::warn!("{}: {:?} isn't {}x{}, scaling it", path.display(), tile.dimensions(), TILE_SIZE, TILE_SIZE);
+                tile = image::imageops::resize(&tile, TILE_SIZE, TILE_SIZE, FilterType::Nearest);
+            }
+            Some(tile)
+        }
+        Err(e) => {
+            log::warn!("{}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn to_srgb(linear: f32) -> u8 {
+    let c = if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Every level down to 1x1, each texel averages 2x2 texels of the level above in linear space
+pub fn mip_chain(tile: &RgbaImage) -> Vec<RgbaImage> {
+    let mut levels = vec![tile.clone()];
+    while levels.last().unwrap().width() > 1 {
+        let above = levels.last().unwrap();
+        let level = RgbaImage::from_fn(above.width() / 2, above.height() / 2, |x, y| {
+            let texels = [above.get_pixel(x * 2, y * 2), above.get_pixel(x * 2 + 1, y * 2), above.get_pixel(x * 2, y * 2 + 1), above.get_pixel(x * 2 + 1, y * 2 + 1)];
+            let mut out = [0; 4];
+            for (channel, value) in out.iter_mut().enumerate().take(3) {
+                *value = to_srgb(texels.iter().map(|texel| to_linear(texel.0[channel])).sum::<f32>() / 4.0);
+            }
+            out[3] = (texels.iter().map(|texel| texel.0[3] as u32).sum::<u32>() / 4) as u8;
+            Rgba(out)
+        });
+        levels.push(level);
+    }
+    levels
+}
+
+impl TextureSet {
+    /// Layers for every block in `BLOCKS`, faces sharing a file share a layer
+    pub fn load(dir: &Path) -> Self {
+        let mut layers = vec![];
+        let mut files: Vec<String> = vec![];
+        let mut materials = vec![GPUMaterial::default(); BLOCKS.len()];
+
+        for (id, info) in BLOCKS.iter().enumerate().skip(1) {
+            let mut layer = |suffix: &str| -> u32 {
+                let name = [format!("{}_{}.png", info.name, suffix), format!("{}.png", info.name)].into_iter().find(|name| dir.join(name).exists());
+                let name = name.unwrap_or_else(|| format!("<{}>", info.name));
+                if let Some(index) = files.iter().position(|file| *file == name) {
+                    return index as u32;
+                }
+                let tile = load_tile(&dir.join(&name)).unwrap_or_else(|| color_tile(id as BlockId));
+                files.push(name);
+                layers.push(tile);
+                layers.len() as u32 - 1
+            };
+            materials[id] = GPUMaterial { top: layer("top"), side: layer("side"), bottom: layer("bottom"), _pad: 0 };
+        }
+        let generated = files.iter().filter(|file| file.starts_with('<')).count();
+        if generated > 0 {
+            log::info!("{} blocks have no texture in {}, using their colors", generated, dir.display());
+        }
+        Self { layers, materials }
+    }
+}
+
+/// The texture array and materials buffer registered in the bindless set
+pub struct GpuTextures {
+    pub image: Image,
+    pub sampler: vk::Sampler,
+    pub materials: Buffer,
+    pub texture_index: u32,
+    pub material_index: u32,
+}
+
+impl GpuTextures {
+    pub fn upload(gpu: &mut Gpu, set: &TextureSet) -> Self {
+        let mip_levels = TILE_SIZE.ilog2() + 1;
+        let extent = vk::Extent2D { width: TILE_SIZE, height: TILE_SIZE };
+        let image = gpu.create_image(extent, TEXTURE_FORMAT, vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, vk::ImageViewType::TYPE_2D_ARRAY, set.layers.len() as u32, mip_levels);
+
+        let mut data = vec![];
+        let mut regions = vec![];
+        for (layer, tile) in set.layers.iter().enumerate() {
+            for (level, mip) in mip_chain(tile).iter().enumerate() {
+                regions.push(
+                    vk::BufferImageCopy::default()
+                        .buffer_offset(data.len() as u64)
+                        .image_subresource(vk::ImageSubresourceLayers { aspect_mask: vk::ImageAspectFlags::COLOR, mip_level: level as u32, base_array_layer: layer as u32, layer_count: 1 })
+                        .image_extent(vk::Extent3D { width: mip.width(), height: mip.height(), depth: 1 }),
+                );
+                data.extend_from_slice(mip.as_raw());
+            }
+        }
+        let staging = gpu.buffer_with_data(vk::BufferUsageFlags::TRANSFER_SRC, &data);
+        gpu.submit_and_wait(|device, cmd| unsafe {
+            image_barrier(device, cmd, &image, (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::TOP_OF_PIPE, vk::AccessFlags2::NONE), (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_WRITE));
+            device.cmd_copy_buffer_to_image(cmd, staging.buffer, image.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+            image_barrier(
+                device,
+                cmd,
+                &image,
+                (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_WRITE),
+                (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ),
+            );
+        });
+        staging.destroy(&gpu.device);
+
+        // repeat so one texture tiles across greedy merged quads, nearest up close keeps the pixel look
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .max_lod(mip_levels as f32);
+        let sampler = unsafe { gpu.device.create_sampler(&sampler_info, None).unwrap() };
+        let materials = gpu.buffer_with_data(vk::BufferUsageFlags::STORAGE_BUFFER, slice_as_u8(&set.materials));
+
+        let texture_index = gpu.bindless.add_image(&gpu.device, image.view, sampler, BINDLESS_SAMPLED_IMAGE);
+        let material_index = gpu.bindless.add_buffer(&gpu.device, &materials, BINDLESS_STORAGE_BUFFER);
+        Self { image, sampler, materials, texture_index, material_index }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.image.destroy(device);
+        self.materials.destroy(device);
+        unsafe { device.destroy_sampler(self.sampler, None) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_block_has_a_texture() {
+        let set = TextureSet::load(Path::new(TEXTURE_DIR));
+        for (id, material) in set.materials.iter().enumerate().skip(1) {
+            for layer in [material.top, material.side, material.bottom] {
+                assert_ne!(set.layers[layer as usize], color_tile(id as BlockId), "{} has no texture in {}", BLOCKS[id].name, TEXTURE_DIR);
+            }
+        }
+        // grass and log have their own top and bottom
+        let grass = set.materials[block::GRASS as usize];
+        assert!(grass.top != grass.side && grass.side != grass.bottom);
+        let log = set.materials[block::LOG as usize];
+        assert!(log.top != log.side && log.top != log.bottom);
+    }
+
+    #[test]
+    fn mip_chain_ends_at_one_texel() {
+        let levels = mip_chain(&color_tile(block::STONE));
+        assert_eq!(levels.len() as u32, TILE_SIZE.ilog2() + 1);
+        assert_eq!(levels.last().unwrap().dimensions(), (1, 1));
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use ash::vk;
+use imgui_winit_support::{HiDpiMode, WinitPlatform};
+use voxelengine::vulkan::util::slice_as_u8;
+use winit::{event::Event, window::Window};
+
+use super::gpu::{image_barrier, Buffer, Gpu, Image, PipelineDesc, BINDLESS_SAMPLED_IMAGE, BINDLESS_STORAGE_BUFFER};
+
+/// Matches the push constants of `shaders/ui/ui.vert`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct UiPushConstant {
+    scale: [f32; 2],
+    translate: [f32; 2],
+    vertex_index: u32,
+    index_index: u32,
+    texture_index: u32,
+    /// Into the whole index buffer of the frame, the draw lists are packed after each other
+    first_index: u32,
+    vertex_offset: u32,
+}
+
+/// Host visible vertex and index buffers of one frame slot, grown when the ui doesn't fit
+struct UiBuffers {
+    vertices: Buffer,
+    indices: Buffer,
+    vertex_index: u32,
+    index_index: u32,
+}
+
+impl UiBuffers {
+    fn new(gpu: &mut Gpu, vertex_size: u64, index_size: u64) -> Self {
+        let vertices = gpu.create_buffer(vertex_size, vk::BufferUsageFlags::STORAGE_BUFFER, true);
+        let indices = gpu.create_buffer(index_size, vk::BufferUsageFlags::STORAGE_BUFFER, true);
+        let vertex_index = gpu.bindless.add_buffer(&gpu.device, &vertices, BINDLESS_STORAGE_BUFFER);
+        let index_index = gpu.bindless.add_buffer(&gpu.device, &indices, BINDLESS_STORAGE_BUFFER);
+        Self { vertices, indices, vertex_index, index_index }
+    }
+
+    fn free(&self, gpu: &mut Gpu) {
+        gpu.bindless.free(BINDLESS_STORAGE_BUFFER, self.vertex_index);
+        gpu.bindless.free(BINDLESS_STORAGE_BUFFER, self.index_index);
+        self.vertices.destroy(&gpu.device);
+        self.indices.destroy(&gpu.device);
+    }
+}
+
+/// Imgui on the bindless layout, built and drawn over the frame's color image once per frame
+pub struct Gui {
+    pub context: imgui::Context,
+    platform: WinitPlatform,
+    pipeline: vk::Pipeline,
+    font: Image,
+    sampler: vk::Sampler,
+    font_index: u32,
+    frames: Vec<Option<UiBuffers>>,
+}
+
+impl Gui {
+    pub fn new(gpu: &mut Gpu, window: &Window, frames_in_flight: usize) -> Result<Self, String> {
+        let mut context = imgui::Context::create();
+        context.set_ini_filename(None);
+        let mut platform = WinitPlatform::new(&mut context);
+        platform.attach_window(context.io_mut(), window, HiDpiMode::Default);
+
+        let pipeline = gpu.graphics_pipeline(&PipelineDesc {
+            depth_format: vk::Format::UNDEFINED,
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_test: false,
+            depth_write: false,
+            blend: true,
+            ..PipelineDesc::new("shaders/spv/ui.vert.spv", Some("shaders/spv/ui.frag.spv"))
+        })?;
+
+        let fonts = context.fonts();
+        let texture = fonts.build_rgba32_texture();
+        let extent = vk::Extent2D { width: texture.width, height: texture.height };
+        let font = gpu.create_image(extent, vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, vk::ImageViewType::TYPE_2D, 1, 1);
+        let staging = gpu.buffer_with_data(vk::BufferUsageFlags::TRANSFER_SRC, texture.data);
+        gpu.submit_and_wait(|device, cmd| unsafe {
+            image_barrier(device, cmd, &font, (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::TOP_OF_PIPE, vk::AccessFlags2::NONE), (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_WRITE));
+            let region = vk::BufferImageCopy::default()
+                .image_subresource(vk::ImageSubresourceLayers { aspect_mask: vk::ImageAspectFlags::COLOR, mip_level: 0, base_array_layer: 0, layer_count: 1 })
+                .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 });
+            device.cmd_copy_buffer_to_image(cmd, staging.buffer, font.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region]);
+            image_barrier(
+                device,
+                cmd,
+                &font,
+                (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_WRITE),
+                (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ),
+            );
+        });
+        staging.destroy(&gpu.device);
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { gpu.device.create_sampler(&sampler_info, None).unwrap() };
+        let font_index = gpu.bindless.add_image(&gpu.device, font.view, sampler, BINDLESS_SAMPLED_IMAGE);
+        // texture ids are bindless indices
+        fonts.tex_id = imgui::TextureId::new(font_index as usize);
+
+        Ok(Self { context, platform, pipeline, font, sampler, font_index, frames: (0..frames_in_flight).map(|_| None).collect() })
+    }
+
+    pub fn handle_event(&mut self, window: &Window, event: &Event<()>) {
+        self.platform.handle_event(self.context.io_mut(), window, event);
+    }
+
+    pub fn update_delta_time(&mut self, delta: Duration) {
+        self.context.io_mut().update_delta_time(delta);
+    }
+
+    /// Builds the ui with `build` and draws it over `target` in `COLOR_ATTACHMENT_OPTIMAL`. `slot` is the frame slot
+    /// of the scheduler, its buffers are free to rewrite once the slot's frame began.
+    pub fn record(&mut self, gpu: &mut Gpu, cmd: vk::CommandBuffer, slot: usize, window: &Window, target: &Image, build: impl FnOnce(&imgui::Ui)) {
+        self.platform.prepare_frame(self.context.io_mut(), window).unwrap();
+        let ui = self.context.new_frame();
+        build(ui);
+        self.platform.prepare_render(ui, window);
+        let draw_data = self.context.render();
+        if draw_data.total_idx_count == 0 {
+            return;
+        }
+
+        let vertex_size = (draw_data.total_vtx_count as usize * size_of::<imgui::DrawVert>()) as u64;
+        // rounded up so every list starts on a whole word
+        let index_size = draw_data.draw_lists().map(|list| list.idx_buffer().len().next_multiple_of(2) * size_of::<imgui::DrawIdx>()).sum::<usize>() as u64;
+        let buffers = &mut self.frames[slot];
+        if buffers.as_ref().is_none_or(|buffers| buffers.vertices.size < vertex_size || buffers.indices.size < index_size) {
+            if let Some(old) = buffers.take() {
+                old.free(gpu);
+            }
+            *buffers = Some(UiBuffers::new(gpu, vertex_size.next_power_of_two(), index_size.next_power_of_two()));
+        }
+        let buffers = buffers.as_ref().unwrap();
+
+        let [width, height] = draw_data.display_size;
+        let scale = [2.0 / width, 2.0 / height];
+        let mut push = UiPushConstant {
+            scale,
+            translate: [-1.0 - draw_data.display_pos[0] * scale[0], -1.0 - draw_data.display_pos[1] * scale[1]],
+            vertex_index: buffers.vertex_index,
+            index_index: buffers.index_index,
+            ..Default::default()
+        };
+        let framebuffer = [width * draw_data.framebuffer_scale[0], height * draw_data.framebuffer_scale[1]];
+
+        let attachment = vk::RenderingAttachmentInfo::default().image_view(target.view).image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL).load_op(vk::AttachmentLoadOp::LOAD).store_op(vk::AttachmentStoreOp::STORE);
+        let area = vk::Rect2D { offset: vk::Offset2D::default(), extent: target.extent };
+        unsafe {
+            gpu.device.cmd_begin_rendering(cmd, &vk::RenderingInfo::default().render_area(area).layer_count(1).color_attachments(std::slice::from_ref(&attachment)));
+            gpu.device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            gpu.bind_bindless(cmd, vk::PipelineBindPoint::GRAPHICS);
+            gpu.device.cmd_set_viewport(cmd, 0, &[vk::Viewport { x: 0.0, y: 0.0, width: framebuffer[0], height: framebuffer[1], min_depth: 0.0, max_depth: 1.0 }]);
+        }
+
+        let (mut vertex_base, mut index_base) = (0, 0);
+        for list in draw_data.draw_lists() {
+            buffers.vertices.write(vertex_base as u64 * size_of::<imgui::DrawVert>() as u64, slice_as_u8(list.vtx_buffer()));
+            buffers.indices.write(index_base as u64 * size_of::<imgui::DrawIdx>() as u64, slice_as_u8(list.idx_buffer()));
+            for command in list.commands() {
+                if let imgui::DrawCmd::Elements { count, cmd_params } = command {
+                    let clip = cmd_params.clip_rect;
+                    let to_framebuffer = |value: f32, axis: usize| ((value - draw_data.display_pos[axis]) * draw_data.framebuffer_scale[axis]).clamp(0.0, framebuffer[axis]);
+                    let (min, max) = ([to_framebuffer(clip[0], 0), to_framebuffer(clip[1], 1)], [to_framebuffer(clip[2], 0), to_framebuffer(clip[3], 1)]);
+                    if max[0] <= min[0] || max[1] <= min[1] {
+                        continue;
+                    }
+                    let scissor = vk::Rect2D { offset: vk::Offset2D { x: min[0] as i32, y: min[1] as i32 }, extent: vk::Extent2D { width: (max[0] - min[0]) as u32, height: (max[1] - min[1]) as u32 } };
+
+                    push.texture_index = cmd_params.texture_id.id() as u32;
+                    push.first_index = (index_base + cmd_params.idx_offset) as u32;
+                    push.vertex_offset = (vertex_base + cmd_params.vtx_offset) as u32;
+                    gpu.push_constants(cmd, &push);
+                    unsafe {
+                        gpu.device.cmd_set_scissor(cmd, 0, &[scissor]);
+                        gpu.device.cmd_draw(cmd, count as u32, 1, 0, 0);
+                    }
+                }
+            }
+            vertex_base += list.vtx_buffer().len();
+            index_base += list.idx_buffer().len().next_multiple_of(2);
+        }
+        unsafe { gpu.device.cmd_end_rendering(cmd) };
+    }
+
+    /// The device has to be idle
+    pub fn destroy(&mut self, gpu: &mut Gpu) {
+        for buffers in self.frames.iter_mut().filter_map(Option::take) {
+            buffers.free(gpu);
+        }
+        gpu.bindless.free(BINDLESS_SAMPLED_IMAGE, self.font_index);
+        self.font.destroy(&gpu.device);
+        unsafe {
+            gpu.device.destroy_sampler(self.sampler, None);
+            gpu.device.destroy_pipeline(self.pipeline, None);
+        }
+    }
+}
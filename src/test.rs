@@ -1,367 +1,641 @@
-#![feature(inherent_associated_types)]
-
 use std::{
-    mem::transmute,
+    collections::{HashMap, HashSet},
+    path::Path,
     time::{Duration, Instant},
 };
 
-use ash::vk::{self, FrontFace};
+use ash::vk;
 use env_logger::Builder;
-use voxelengine::{
-    app::ApplicationTrait,
-    core::camera::{Camera, Controls, GPUCamera},
-    terrain::{block::GPUBlock, World},
-    vulkan::{
-        builder::{self},
-        mesh::{EmptyVertex, Vertex, VertexBlock},
-        resource::{BufferBuilder, BufferIndex, BufferType, Memory},
-        util, VulkanContext,
-    },
-};
+use voxelengine::vulkan::util::slice_as_u8;
 use winit::{
-    event::{ElementState, Event, RawKeyEvent},
-    event_loop::EventLoop,
-    keyboard::KeyCode,
-    window::CursorGrabMode,
+    dpi::PhysicalSize,
+    event::{DeviceEvent, ElementState, Event, KeyEvent, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{CursorGrabMode, Window, WindowBuilder},
 };
 
-use crate::world_test::chunk::ChunkMesh;
+use crate::{
+    render::{
+        atmosphere::{Atmosphere, AtmosphereSettings},
+        chunks::{ChunkBatch, GpuChunks},
+        culling::{CullPass, Frustum},
+        fog::{Fog, FogSettings},
+        frames::FrameScheduler,
+        hiz::HiZ,
+        gpu::{image_barrier, update_buffer, Buffer, Gpu, Image, PipelineDesc, BINDLESS_STORAGE_IMAGE, BINDLESS_UNIFORM, COLOR_FORMAT, DEPTH_FORMAT},
+        shadow::{ShadowMap, ShadowSettings, MAX_CASCADES},
+        sky::{SkyData, SkyPass, SkyState},
+        swapchain::Swapchain,
+        textures::{GpuTextures, TextureSet, TEXTURE_DIR},
+        ui::Gui,
+        CameraData, ChunkPushConstant, CHUNK_ATMOSPHERE, CHUNK_DAYLIGHT, CHUNK_FOG, CHUNK_SHADOWS, RENDER_VOXEL_SCALE,
+    },
+    world_test::{
+        generation::{NoiseParameters, TerrainSource},
+        manager::ChunkManager,
+        mesh::GPUQuad,
+        time::WorldTime,
+        CHUNK_RESOLUTION,
+    },
+};
 
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
-
-#[repr(C, align(16))]
-struct NodeVertex {
+const WINDOW_SIZE: PhysicalSize<u32> = PhysicalSize::new(1280, 720);
+const FOV: f32 = 70.0;
+/// World units per second
+const CAMERA_SPEED: f32 = 2.0;
+/// Radians per pixel of mouse motion
+const MOUSE_SENSITIVITY: f32 = 0.002;
+/// Blocks the camera starts above the terrain
+const SPAWN_HEIGHT: u32 = 8;
+
+/// Free flying camera, looks around with the mouse while the cursor is grabbed
+struct FlyCamera {
     position: glm::Vec3,
+    yaw: f32,
+    pitch: f32,
 }
 
-impl Vertex for NodeVertex {
-    fn get_vertex_attribute_desc() -> Vec<vk::VertexInputAttributeDescription> {
-        [vk::VertexInputAttributeDescription::default().binding(0).location(0).format(vk::Format::R32G32B32_SFLOAT).offset(0)].to_vec()
+impl FlyCamera {
+    fn forward(&self) -> glm::Vec3 {
+        glm::Vec3::new(self.yaw.cos() * self.pitch.cos(), self.pitch.sin(), self.yaw.sin() * self.pitch.cos())
     }
-}
 
-impl NodeVertex {
-    pub const fn new(position: glm::Vec3) -> Self {
-        Self { position }
+    /// Block the camera is in
+    fn block(&self) -> [i32; 3] {
+        let position = self.position / RENDER_VOXEL_SCALE;
+        [position.x.floor() as i32, position.y.floor() as i32, position.z.floor() as i32]
     }
-}
-
-const Vertices: [NodeVertex; 36] = [
-    // right
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, 0.5)),
-    // Left face
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, 0.5)),
-    // Top face
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, -0.5)),
-    // Bottom face
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, 0.5)),
-    // Front face
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, 0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, 0.5)),
-    // Back face
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, -0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(0.5, 0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, 0.5, -0.5)),
-    NodeVertex::new(glm::Vec3::new(-0.5, -0.5, -0.5)),
-];
-
-/// There should only be application relevant information in these functions
-pub struct TestApplication {
-    vulkan: VulkanContext,
-
-    last_frame: Instant,
-
-    pipeline: Vec<vk::Pipeline>,
 
-    cam: Camera,
-    cam_buffers: Vec<BufferIndex>,
-
-    controls: Controls,
-
-    focus: bool,
+    fn data(&self, extent: vk::Extent2D) -> CameraData {
+        CameraData::look_at(self.position, self.position + self.forward(), FOV, extent.width as f32 / extent.height.max(1) as f32)
+    }
 
-    resize: bool,
+    fn rotate(&mut self, (x, y): (f64, f64)) {
+        self.yaw += x as f32 * MOUSE_SENSITIVITY;
+        self.pitch = (self.pitch - y as f32 * MOUSE_SENSITIVITY).clamp(-1.55, 1.55);
+    }
 
-    world: World,
-    is_frustum: bool,
+    /// WASD in the view direction, space and shift up and down
+    fn fly(&mut self, keys: &HashSet<KeyCode>, seconds: f32) {
+        let forward = self.forward();
+        let right = forward.cross(glm::Vec3::unit_y()).normalized();
+        let axis = |positive: KeyCode, negative: KeyCode| keys.contains(&positive) as i32 as f32 - keys.contains(&negative) as i32 as f32;
+        let direction = forward * axis(KeyCode::KeyW, KeyCode::KeyS) + right * axis(KeyCode::KeyD, KeyCode::KeyA) + glm::Vec3::unit_y() * axis(KeyCode::Space, KeyCode::ShiftLeft);
+        self.position += direction * CAMERA_SPEED * seconds;
+    }
+}
 
+/// Everything the settings window edits
+struct Settings {
+    /// 0 fills the chunks, 1 draws their wireframe
     pipeline_index: i32,
-
-    chunk_mesh: ChunkMesh,
+    shadows: ShadowSettings,
+    /// Drives the sky and the sun, sped up or frozen in the time section
+    world_time: WorldTime,
+    atmosphere: AtmosphereSettings,
+    fog: FogSettings,
+    /// Only the sections `ChunkManager::visible_sections` reaches are uploaded and all of them are drawn,
+    /// otherwise every loaded section is uploaded and the cull pass picks the draws
+    cpu_culling: bool,
+    face_culling: bool,
+    /// Drops chunks behind the last frame's depth
+    occlusion_culling: bool,
+    /// Draws boxes around the chunks occlusion culling dropped
+    show_culled: bool,
 }
 
-impl ApplicationTrait for TestApplication {
-    fn on_new(event_loop: &EventLoop<()>) -> Self {
-        Builder::new().filter_level(log::LevelFilter::Info).init();
-
-        let mut vulkan = VulkanContext::new(&event_loop, MAX_FRAMES_IN_FLIGHT, true);
-        //  Octree::new(&mut vulkan.resources.get_buffer_storage(), Vec3::zero());
-
-        let cam = Camera::new(vulkan.window_extent);
-        let world = World::new(cam.get_pos(), 4);
-
-        //let objects = world.get_culled();
-
-        let cmd = vulkan.cmds[0];
-        let mut buffer_builder = BufferBuilder::new();
-        util::begin_cmd(&vulkan.device, vulkan.cmds[0]);
-
-        /*Create Vulkan Buffers*/
-        let res = vulkan.resources.get_buffer_storage();
-
-        let cam_buffers = buffer_builder
-            .set_frames(MAX_FRAMES_IN_FLIGHT as u32)
-            .set_size(size_of::<GPUCamera>() as u64)
-            .set_memory(Memory::Host)
-            .set_type(BufferType::Uniform)
-            .set_is_descriptor(true)
-            .set_name("camera-buffer")
-            .set_data(&[])
-            .build_resource(res, cmd);
-        let chunk_mesh = ChunkMesh::new_test(res, vulkan.graphic, vulkan.cmds[0]);
-
-        util::end_cmd_and_submit(&vulkan.device, vulkan.cmds[0], vulkan.graphic, vec![], vec![], vk::Fence::null());
-        unsafe { vulkan.device.device_wait_idle().unwrap() };
-
-        /*Create Vulkan Pipeline */
-        let vertex = util::create_shader(&vulkan.device, "shaders/spv/chunk.vert.spv".to_owned());
-        let frag = util::create_shader(&vulkan.device, "shaders/spv/chunk.frag.spv".to_owned());
-
-        let pipelines = builder::PipelineBuilder::new()
-            .add_layout(vulkan.pipeline_layout)
-            .add_color_format(vulkan.get_swapchain_format())
-            .add_depth(vulkan.get_depth_format(), true, true, vk::CompareOp::LESS_OR_EQUAL)
-            .cull_mode(vk::CullModeFlags::BACK, FrontFace::CLOCKWISE)
-            .add_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .add_wire()
-            .build::<EmptyVertex>(&vulkan.device, vertex, frag);
-
-        vulkan.window.set_cursor_grab(CursorGrabMode::None).unwrap();
-        vulkan.window.set_cursor_visible(true);
-        vulkan.window.focus_window();
-
-        vulkan.resources.set_frame(0);
-        Self {
-            cam,
-            vulkan,
-            last_frame: Instant::now(),
-            pipeline: pipelines,
-            controls: Controls::new(),
-            focus: false,
-            resize: false,
-            world,
-            is_frustum: false,
-            pipeline_index: 0,
-            cam_buffers,
-            chunk_mesh,
+impl Settings {
+    fn build(&mut self, ui: &imgui::Ui) {
+        ui.slider("pipeline index", 0, 1, &mut self.pipeline_index);
+
+        if ui.collapsing_header("Shadows", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+            let settings = &mut self.shadows;
+            ui.checkbox("enabled", &mut settings.enabled);
+            ui.slider("cascades", 1, MAX_CASCADES as u32, &mut settings.cascades);
+            ui.slider("resolution", 256, 8192, &mut settings.resolution);
+            ui.slider("distance", 1.0, 100.0, &mut settings.distance);
+            ui.slider("split lambda", 0.0, 1.0, &mut settings.split_lambda);
+            ui.slider("pcf radius", 0, 3, &mut settings.pcf_radius);
+            settings.clamp();
         }
-    }
 
-    fn resize_event(&mut self) {
-        self.resize = true;
-    }
-
-    fn on_draw(&mut self) {
-        unsafe {
-            self.vulkan.prepare_frame(&mut self.resize);
-
-            if self.resize == true {
-                self.recreate_swapchain();
-                self.vulkan.prepare_frame(&mut self.resize);
+        if ui.collapsing_header("Time", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+            let (hours, minutes) = self.world_time.clock();
+            ui.text(format!("day {} {:02}:{:02}", self.world_time.day(), hours, minutes));
+            ui.checkbox("frozen", &mut self.world_time.frozen);
+            ui.slider("speed", 0.0, 500.0, &mut self.world_time.speed);
+            let mut time_of_day = self.world_time.time_of_day();
+            if ui.slider("time of day", 0.0, 1.0, &mut time_of_day) {
+                self.world_time.set_time_of_day(time_of_day);
             }
+        }
 
-            let device = &self.vulkan.device;
-            let frame_index = self.vulkan.current_frame;
-            let swapchain_index = self.vulkan.swapchain.image_index;
-            let cmd = self.vulkan.cmds[frame_index];
-
-            // let data = &mut self.frame_data[frame_index];
-
-            // device.cmd_bind_descriptor_sets(
-            //     cmd,
-            //     vk::PipelineBindPoint::COMPUTE,
-            //     self.vulkan.pipeline_layout,
-            //     0,
-            //     &[self.vulkan.resources.set],
-            //     &vec![],
-            // );
-
-            util::transition_image_color(&device, cmd, self.vulkan.swapchain.images[swapchain_index as usize].image);
-
-            let gpu_cam = vec![self.cam.get_gpu_camera()];
-            self.vulkan.resources.get_buffer_storage().write_to_buffer_host(self.cam_buffers[frame_index], util::slice_as_u8(&gpu_cam));
-
-            self.vulkan.begin_rendering(vk::AttachmentLoadOp::CLEAR);
-
-            let pipeline = self.pipeline[self.pipeline_index as usize];
-
-            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
-
-            // device.cmd_bind_vertex_buffers(cmd, 0, &[self.vertex_buffer.buffer], &vec![0]);
-
-            device.cmd_bind_descriptor_sets(
-                cmd,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.vulkan.pipeline_layout,
-                0,
-                &vec![self.vulkan.resources.set],
-                &vec![],
-            );
-
-            let cam_index = self.vulkan.resources.get_buffer_storage().get_buffer_ref(self.cam_buffers[frame_index]).index;
-
-            self.chunk_mesh.draw(
-                &self.vulkan.device,
-                self.vulkan.resources.get_buffer_storage(),
-                cmd,
-                self.vulkan.pipeline_layout,
-                cam_index as u32,
-            );
+        if ui.collapsing_header("Atmosphere", imgui::TreeNodeFlags::empty()) {
+            let settings = &mut self.atmosphere;
+            // labels are ids in imgui, the shadows section already has "enabled"
+            ui.checkbox("scattering", &mut settings.enabled);
+            ui.slider("rayleigh density", 0.0, 10.0, &mut settings.rayleigh_density);
+            ui.slider("mie density", 0.0, 50.0, &mut settings.mie_density);
+            ui.slider("mie anisotropy", 0.0, 0.99, &mut settings.mie_anisotropy);
+            ui.slider("sun illuminance", 0.0, 10.0, &mut settings.sun_illuminance);
+            ui.slider("exposure", 0.0, 50.0, &mut settings.exposure);
+            ui.slider("aerial scale", 0.0, 100.0, &mut settings.aerial_scale);
+            settings.clamp();
+        }
 
-            self.vulkan.end_rendering();
+        if ui.collapsing_header("Fog", imgui::TreeNodeFlags::empty()) {
+            let settings = &mut self.fog;
+            ui.checkbox("fog", &mut settings.enabled);
+            ui.slider("render distance", 1, 32, &mut settings.render_distance);
+            ui.slider("fog start", 0.0, 0.99, &mut settings.start);
+            ui.slider("fog density", 0.0, 10.0, &mut settings.density);
+            ui.slider("fade in seconds", 0.0, 5.0, &mut settings.fade_seconds);
+            ui.slider("biome blend seconds", 0.0, 10.0, &mut settings.blend_seconds);
+            settings.clamp();
+        }
 
-            let imgui = self.vulkan.imgui.as_mut().unwrap();
+        if ui.collapsing_header("Culling", imgui::TreeNodeFlags::empty()) {
+            ui.checkbox("cave culling on the cpu", &mut self.cpu_culling);
+            ui.disabled(self.cpu_culling, || {
+                ui.checkbox("face culling", &mut self.face_culling);
+                ui.checkbox("occlusion culling", &mut self.occlusion_culling);
+                ui.checkbox("show culled bounds", &mut self.show_culled);
+            });
+        }
+    }
+}
 
-            let ui = imgui.get_draw_instance(&self.vulkan.window);
+/// Color and depth the frame renders into before the color is blitted to the swapchain, sized like the window
+struct FrameTargets {
+    color: Image,
+    depth: Image,
+    /// The color as a storage image, the sky pass writes it first
+    color_index: u32,
+    /// Pyramid of the depth, `None` if it couldn't be created and occlusion culling is off
+    hiz: Option<HiZ>,
+    /// The last frame built `hiz`, the next one can cull against it
+    hiz_built: bool,
+}
 
-            // ui.slider("pipeline index", 0, 1, &mut self.pipeline_index);
-            let set = self.vulkan.resources.set;
+impl FrameTargets {
+    fn new(gpu: &mut Gpu, extent: vk::Extent2D) -> Self {
+        let color = gpu.create_image(extent, COLOR_FORMAT, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::STORAGE, vk::ImageViewType::TYPE_2D, 1, 1);
+        let depth = gpu.create_image(extent, DEPTH_FORMAT, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED, vk::ImageViewType::TYPE_2D, 1, 1);
+        let color_index = gpu.bindless.add_image(&gpu.device, color.view, vk::Sampler::null(), BINDLESS_STORAGE_IMAGE);
+        let hiz = HiZ::new(gpu, &depth).map_err(|e| log::warn!("{}, occlusion culling is off", e)).ok();
+        Self { color, depth, color_index, hiz, hiz_built: false }
+    }
 
-            ui.slider("pipeline index", 0, 1, &mut self.pipeline_index);
+    fn free(&self, gpu: &mut Gpu) {
+        gpu.bindless.free(BINDLESS_STORAGE_IMAGE, self.color_index);
+        if let Some(hiz) = &self.hiz {
+            hiz.free(gpu);
+        }
+        self.color.destroy(&gpu.device);
+        self.depth.destroy(&gpu.device);
+    }
+}
 
-            imgui.render(
-                self.vulkan.window_extent,
-                &self.vulkan.swapchain.images[self.vulkan.swapchain.image_index as usize],
-                self.vulkan.current_frame,
-                &mut self.vulkan.resources.get_buffer_storage(),
-                cmd,
-                set,
-            );
+/// Quads of one tile of a section with its offset in blocks
+type Tile = ([i32; 3], Vec<GPUQuad>);
 
-            if self.vulkan.end_frame_and_submit() {
-                self.resize = true;
-            }
+/// Tiles of a meshed section and when it was first meshed, remeshing it doesn't fade it in again
+struct SectionMesh {
+    tiles: Vec<Tile>,
+    /// Seconds on the app's clock, see `fog::FogData::seconds`
+    loaded_at: f32,
+}
 
-            let now = Instant::now();
+/// The windowed app, renders with the same passes as `render::golden` on the `Gpu` context
+pub struct TestApplication {
+    window: Window,
+    gpu: Gpu,
+    swapchain: Swapchain,
+    scheduler: FrameScheduler,
+    gui: Gui,
+    targets: FrameTargets,
+    /// Fill and wireframe
+    pipelines: [vk::Pipeline; 2],
+    /// Line list over the chunks occlusion culling dropped
+    bounds_pipeline: vk::Pipeline,
+    textures: GpuTextures,
+    camera: FlyCamera,
+    /// Written from the command buffer, so frames in flight share it
+    camera_buffer: Buffer,
+    cam_index: u32,
+    /// Uploaded again through `FrameScheduler::retire` whenever a mesh changes, or the visible sections with cpu culling
+    chunks: GpuChunks,
+    /// Picks the draws of `chunks` every frame unless culling runs on the cpu
+    cull: CullPass,
+    /// Generates the columns around the camera, nothing is saved
+    world: ChunkManager,
+    /// Tiles of every meshed section, the batch of `chunks` is built from them
+    meshes: HashMap<[i32; 3], SectionMesh>,
+    /// Sections `ChunkManager::visible_sections` reached last frame with cpu culling, only their meshes are in `chunks`.
+    /// `None` when `chunks` holds every mesh.
+    visible: Option<Vec<[i32; 3]>>,
+    /// Created again through `FrameScheduler::retire` when the cascades or the resolution change
+    shadows: ShadowMap,
+    /// Background of the frame, its `SkyData` lights the chunks
+    sky: SkyPass,
+    /// Takes the atmosphere section's settings every frame, none of them change the LUTs' sizes
+    atmosphere: Atmosphere,
+    /// Blends towards the biome under the camera, its render distance is the load radius
+    fog: Fog,
+
+    keys: HashSet<KeyCode>,
+    /// Start of the clock chunks fade in on
+    started: Instant,
+    last_frame: Instant,
+    focus: bool,
+    resize: bool,
+    settings: Settings,
+}
 
-            let diff = now - self.last_frame;
+impl TestApplication {
+    pub fn new(event_loop: &EventLoop<()>) -> Result<Self, String> {
+        let window = WindowBuilder::new().with_title("vulkanrust").with_inner_size(WINDOW_SIZE).build(event_loop).map_err(|e| e.to_string())?;
+        let (mut gpu, surface) = Gpu::windowed(&window)?;
+        let size = window.inner_size();
+        let extent = vk::Extent2D { width: size.width, height: size.height };
+        let swapchain = Swapchain::new(&gpu, surface, extent, MAX_FRAMES_IN_FLIGHT);
+        let scheduler = FrameScheduler::new(&gpu, MAX_FRAMES_IN_FLIGHT);
+        let gui = Gui::new(&mut gpu, &window, MAX_FRAMES_IN_FLIGHT)?;
+        let targets = FrameTargets::new(&mut gpu, swapchain.extent);
+
+        let desc = PipelineDesc::new("shaders/spv/chunk.vert.spv", Some("shaders/spv/chunk.frag.spv"));
+        let pipelines = [gpu.graphics_pipeline(&desc)?, gpu.graphics_pipeline(&PipelineDesc { polygon_mode: vk::PolygonMode::LINE, cull_mode: vk::CullModeFlags::NONE, ..desc })?];
+        let bounds_pipeline = gpu.graphics_pipeline(&PipelineDesc {
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_compare: vk::CompareOp::ALWAYS,
+            depth_write: false,
+            topology: vk::PrimitiveTopology::LINE_LIST,
+            ..PipelineDesc::new("shaders/spv/bounds.vert.spv", Some("shaders/spv/bounds.frag.spv"))
+        })?;
+        let textures = GpuTextures::upload(&mut gpu, &TextureSet::load(Path::new(TEXTURE_DIR)));
+        let camera_buffer = gpu.create_buffer(size_of::<CameraData>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, false);
+        let cam_index = gpu.bindless.add_buffer(&gpu.device, &camera_buffer, BINDLESS_UNIFORM);
+        // the first frame loads the columns around the camera
+        let chunks = GpuChunks::upload(&mut gpu, &ChunkBatch::new());
+        let cull = CullPass::new(&gpu)?;
+        let world = ChunkManager::new(TerrainSource::load(NoiseParameters::default()));
+        let shadow_settings = ShadowSettings::default();
+        let shadows = ShadowMap::new(&mut gpu, shadow_settings)?;
+        let sky = SkyPass::new(&mut gpu)?;
+        let atmosphere = Atmosphere::new(&mut gpu, AtmosphereSettings::default())?;
+
+        let spawn = world.terrain.finished_column(0, 0);
+        let fog = Fog::new(&mut gpu, FogSettings::default(), spawn.biome);
+        let fog_settings = fog.settings;
+        let height = (spawn.height.max(spawn.water_level) + SPAWN_HEIGHT) as f32;
+        let camera = FlyCamera { position: glm::Vec3::new(0.5, height, 0.5) * RENDER_VOXEL_SCALE, yaw: std::f32::consts::FRAC_PI_4, pitch: -0.4 };
+
+        window.set_cursor_visible(true);
+        window.focus_window();
+        Ok(Self {
+            window,
+            gpu,
+            swapchain,
+            scheduler,
+            gui,
+            targets,
+            pipelines,
+            bounds_pipeline,
+            textures,
+            camera,
+            camera_buffer,
+            cam_index,
+            chunks,
+            cull,
+            world,
+            meshes: HashMap::new(),
+            visible: None,
+            shadows,
+            sky,
+            atmosphere,
+            fog,
+            keys: HashSet::new(),
+            started: Instant::now(),
+            last_frame: Instant::now(),
+            focus: false,
+            resize: false,
+            settings: Settings { pipeline_index: 0, shadows: shadow_settings, world_time: WorldTime::new(0), atmosphere: AtmosphereSettings::default(), fog: fog_settings, cpu_culling: false, face_culling: true, occlusion_culling: true, show_culled: false },
+        })
+    }
 
-            let hz_diff = Self::HZ_MAX - diff.as_millis() as i64;
+    fn handle_event(&mut self, event: Event<()>, target: &EventLoopWindowTarget<()>) {
+        self.gui.handle_event(&self.window, &event);
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => target.exit(),
+                WindowEvent::Resized(_) => self.resize = true,
+                WindowEvent::KeyboardInput { event: KeyEvent { physical_key: PhysicalKey::Code(key), state, repeat, .. }, .. } => self.key(key, state, repeat),
+                WindowEvent::RedrawRequested => self.frame(),
+                _ => {}
+            },
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } if self.focus => self.camera.rotate(delta),
+            Event::AboutToWait => self.window.request_redraw(),
+            Event::LoopExiting => self.destroy(),
+            _ => {}
+        }
+    }
 
-            if hz_diff > 0 {
-                std::thread::sleep(Duration::from_millis(hz_diff as u64));
+    fn key(&mut self, key: KeyCode, state: ElementState, repeat: bool) {
+        match state {
+            ElementState::Pressed => self.keys.insert(key),
+            ElementState::Released => self.keys.remove(&key),
+        };
+        if state == ElementState::Pressed && !repeat && key == KeyCode::Escape {
+            self.focus = !self.focus;
+            let grab = if self.focus { CursorGrabMode::Confined } else { CursorGrabMode::None };
+            if let Err(e) = self.window.set_cursor_grab(grab).or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Locked)) {
+                log::warn!("grabbing the cursor: {}", e);
             }
-
-            self.vulkan.window.request_redraw();
+            self.window.set_cursor_visible(!self.focus);
         }
     }
 
-    fn on_new_frame(&mut self, event: &Event<()>) {
-        let now = Instant::now();
-        let delta_time = now.duration_since(self.last_frame);
+    /// Rebuilds the swapchain and the window sized targets, the old ones are retired with the frame being recorded
+    fn recreate_swapchain(&mut self) {
+        let size = self.window.inner_size();
+        self.swapchain.recreate(&self.gpu, &mut self.scheduler, vk::Extent2D { width: size.width, height: size.height });
+        let old = std::mem::replace(&mut self.targets, FrameTargets::new(&mut self.gpu, self.swapchain.extent));
+        self.scheduler.retire(move |gpu| old.free(gpu));
+        self.resize = false;
+    }
 
-        self.vulkan.imgui.as_mut().unwrap().update_delta_time(delta_time);
+    fn frame(&mut self) {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_frame);
         self.last_frame = now;
+        self.update(delta);
 
-        self.vulkan.imgui.as_mut().unwrap().process_event_imgui(&self.vulkan.window, &event);
-        self.cam.process_keyboard(&self.controls, delta_time.as_secs_f64());
-    }
+        let size = self.window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            // minimized, there is nothing to present to
+            return;
+        }
 
-    fn on_mouse_motion(&mut self, delta: &(f64, f64)) {
-        if self.focus {
-            self.cam.process_mouse(*delta);
+        let cmd = self.scheduler.begin(&mut self.gpu);
+        let slot = self.scheduler.frame_index();
+        if self.resize {
+            self.recreate_swapchain();
+        }
+        let Some(image) = self.swapchain.acquire(slot) else {
+            self.resize = true;
+            // the slot only comes around again once its frame is submitted
+            self.scheduler.submit(&self.gpu, &[], &[]);
+            return;
+        };
+
+        self.update_shadows();
+        let camera = self.camera.data(self.targets.color.extent);
+        self.update_chunks(&camera);
+        let seconds = self.started.elapsed().as_secs_f32();
+        update_buffer(&self.gpu.device, cmd, &self.camera_buffer, slice_as_u8(&[camera]));
+        let occlusion = self.visible.is_none() && self.settings.occlusion_culling;
+        if self.visible.is_none() {
+            self.cull.face_culling = self.settings.face_culling;
+            // the last frame's depth, chunks it hid can pop in for a frame when the camera moves
+            let hiz = self.targets.hiz.as_ref().filter(|_| occlusion && self.targets.hiz_built);
+            self.cull.record(&self.gpu, cmd, &self.chunks, self.cam_index, hiz);
+        }
+        let mut state = SkyState::at(&self.settings.world_time);
+        self.atmosphere.settings = self.settings.atmosphere;
+        if self.atmosphere.settings.enabled {
+            state.apply_atmosphere(&self.atmosphere.settings);
+            self.atmosphere.update(&self.gpu, cmd, state.sun, &camera);
+            self.atmosphere.record(&self.gpu, cmd);
+        }
+        self.sky.update(&self.gpu, cmd, &SkyData::new(&state, &camera));
+        let block = self.camera.block();
+        self.fog.settings = self.settings.fog;
+        self.fog.blend(self.world.biome(block[0], block[2]), delta.as_secs_f32());
+        self.fog.update(&self.gpu, cmd, seconds);
+        if self.shadows.settings.enabled {
+            self.shadows.record(&self.gpu, cmd, &self.chunks, &camera, state.light);
+        }
+        self.record_world(cmd);
+        self.targets.hiz_built = match &self.targets.hiz {
+            Some(hiz) if occlusion => {
+                hiz.build(&self.gpu, cmd, &self.targets.depth);
+                true
+            }
+            _ => false,
+        };
+
+        let settings = &mut self.settings;
+        self.gui.record(&mut self.gpu, cmd, slot, &self.window, &self.targets.color, |ui| settings.build(ui));
+        image_barrier(
+            &self.gpu.device,
+            cmd,
+            &self.targets.color,
+            (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE),
+            (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::PipelineStageFlags2::BLIT, vk::AccessFlags2::TRANSFER_READ),
+        );
+        self.swapchain.record_blit(&self.gpu, cmd, &image, &self.targets.color);
+
+        self.scheduler.submit(&self.gpu, &[image.wait], &[image.signal]);
+        if self.swapchain.present(&self.gpu, &image) {
+            self.resize = true;
         }
     }
 
-    fn on_key_press(&mut self, key_event: &RawKeyEvent, keycode: KeyCode) {
-        self.controls.update_key(keycode, key_event.state == ElementState::Pressed);
+    /// Loads the columns around the camera and meshes the sections that changed since the last frame.
+    /// With cpu culling it also walks the sections the camera can see. When a mesh or the visible sections
+    /// changed the chunks are uploaded again, the old ones are retired with the frame being recorded.
+    fn update_chunks(&mut self, camera: &CameraData) {
+        let res = CHUNK_RESOLUTION as i32;
+        let block = self.camera.block();
+        let radius = self.settings.fog.render_distance as i32;
+        self.world.load_around([block[0].div_euclid(res), block[2].div_euclid(res)], radius);
+        let seconds = self.started.elapsed().as_secs_f32();
+
+        // dropped columns aren't remeshed, their sections just go
+        let loaded = self.meshes.len();
+        let world = &self.world;
+        self.meshes.retain(|[x, _, z], _| world.column(*x, *z).is_some());
+        let mut changed = self.meshes.len() != loaded;
+        for [x, y, z] in self.world.take_remesh() {
+            changed |= match self.world.mesh_section(x, y, z) {
+                Some(tiles) => {
+                    let origin = [x * res, y * res, z * res];
+                    let tiles = tiles.into_iter().map(|(offset, quads)| ([origin[0] + offset[0], origin[1] + offset[1], origin[2] + offset[2]], quads)).collect();
+                    let loaded_at = self.meshes.get(&[x, y, z]).map_or(seconds, |mesh| mesh.loaded_at);
+                    self.meshes.insert([x, y, z], SectionMesh { tiles, loaded_at });
+                    true
+                }
+                None => self.meshes.remove(&[x, y, z]).is_some(),
+            };
+        }
 
-        if key_event.state == ElementState::Pressed && keycode == KeyCode::Escape {
-            unsafe {
-                self.focus = !self.focus;
-                let cursor_grab: CursorGrabMode = transmute(self.focus);
-                self.vulkan.window.set_cursor_grab(cursor_grab).unwrap();
-                self.vulkan.window.set_cursor_visible(!self.focus);
+        let visible = self.settings.cpu_culling.then(|| {
+            let frustum = Frustum::from_viewproj(&camera.viewproj);
+            let shadow_distance = if self.shadows.settings.enabled { self.shadows.settings.distance } else { 0.0 };
+            let position = self.camera.position;
+            let in_view = |[x, y, z]: [i32; 3]| {
+                let min = glm::Vec3::new((x * res) as f32, (y * res) as f32, (z * res) as f32) * RENDER_VOXEL_SCALE;
+                let max = min + glm::Vec3::broadcast(res as f32 * RENDER_VOXEL_SCALE);
+                // casters behind the camera still shadow what it sees
+                frustum.intersects_aabb(min, max) || (position.clamped(min, max) - position).mag() < shadow_distance
+            };
+            self.world.visible_sections(block, radius, in_view)
+        });
+        if !changed && visible == self.visible {
+            return;
+        }
+        self.visible = visible;
+
+        let mut batch = ChunkBatch::new();
+        let meshes: Vec<&SectionMesh> = match &self.visible {
+            Some(visible) => visible.iter().filter_map(|section| self.meshes.get(section)).collect(),
+            None => self.meshes.values().collect(),
+        };
+        for mesh in meshes {
+            for (offset, quads) in &mesh.tiles {
+                batch.add_loaded_at(*offset, quads.clone(), mesh.loaded_at);
             }
         }
+        let old = std::mem::replace(&mut self.chunks, GpuChunks::upload(&mut self.gpu, &batch));
+        self.scheduler.retire(move |gpu| old.free(gpu));
     }
 
-    fn on_destroy(&mut self) {
-        unsafe {
-            self.vulkan.device.device_wait_idle().unwrap();
-
-            // for i in 0..self.frame_data.len() {
-            //     let frame = &mut self.frame_data[i];
-            //     self.vulkan.allocator.destroy_buffer(frame.cam_buffer.buffer, &mut frame.cam_buffer.alloc);
-            //     self.vulkan.allocator.destroy_buffer(frame.objects.buffer, &mut frame.objects.alloc);
-            // }
-
-            // for pipeline in self.pipeline.clone() {
-            //     self.vulkan.device.destroy_pipeline(pipeline, None);
-            // }
-
-            // self.pipeline.clear();
-
-            // self.vulkan
-            //     .allocator
-            //     .destroy_buffer(self.vertex_buffer.buffer, &mut self.vertex_buffer.alloc);
+    /// Takes the settings of the shadows section, a new cascade count or resolution needs a new map
+    fn update_shadows(&mut self) {
+        if self.shadows.fits(&self.settings.shadows) {
+            self.shadows.settings = self.settings.shadows;
+            return;
+        }
+        match ShadowMap::new(&mut self.gpu, self.settings.shadows) {
+            Ok(shadows) => {
+                let old = std::mem::replace(&mut self.shadows, shadows);
+                self.scheduler.retire(move |gpu| old.free(gpu));
+            }
+            Err(e) => {
+                log::warn!("{}, keeping the old shadow map", e);
+                self.settings.shadows = self.shadows.settings;
+            }
         }
-        self.vulkan.destroy();
     }
 
-    fn set_imgui_draw(&mut self, imgui_func: fn(ui: &mut imgui::Ui)) {
-        todo!()
+    fn update(&mut self, delta: Duration) {
+        self.gui.update_delta_time(delta);
+        self.settings.world_time.advance(delta);
+        self.camera.fly(&self.keys, delta.as_secs_f32());
     }
-}
 
-extern crate ultraviolet as glm;
-
-impl TestApplication {
-    const HZ_MAX: i64 = (1000.0 / 60.0) as i64;
-    fn new(event_loop: &EventLoop<()>) -> Self {
-        ApplicationTrait::on_new(event_loop)
+    /// Fills the color with the sky and draws the chunks over it, leaves the color in `COLOR_ATTACHMENT_OPTIMAL`
+    fn record_world(&self, cmd: vk::CommandBuffer) {
+        let device = &self.gpu.device;
+        let FrameTargets { color, depth, color_index, .. } = &self.targets;
+        // the last frame's blit read the color, its depth test wrote the depth and the Hi-Z build may have read it
+        image_barrier(
+            device,
+            cmd,
+            color,
+            (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::BLIT, vk::AccessFlags2::NONE),
+            (vk::ImageLayout::GENERAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE),
+        );
+        let atmosphere = self.atmosphere.settings.enabled.then_some(&self.atmosphere);
+        self.sky.record(&self.gpu, cmd, *color_index, color.extent, atmosphere);
+        image_barrier(
+            device,
+            cmd,
+            color,
+            (vk::ImageLayout::GENERAL, vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_STORAGE_WRITE),
+            (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE),
+        );
+        image_barrier(
+            device,
+            cmd,
+            depth,
+            (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS, vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        );
+
+        let color_attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(color.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let depth_attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(depth.view)
+            .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } });
+        let extent = color.extent;
+        let area = vk::Rect2D { offset: vk::Offset2D::default(), extent };
+        let mut flags = CHUNK_DAYLIGHT;
+        for (enabled, flag) in [(self.shadows.settings.enabled, CHUNK_SHADOWS), (atmosphere.is_some(), CHUNK_ATMOSPHERE), (self.fog.settings.enabled, CHUNK_FOG)] {
+            if enabled {
+                flags |= flag;
+            }
+        }
+        let push = ChunkPushConstant {
+            cam_index: self.cam_index,
+            texture_index: self.textures.texture_index,
+            material_index: self.textures.material_index,
+            shadow_index: self.shadows.data_index,
+            sky_index: self.sky.data_index,
+            atmosphere_index: self.atmosphere.data_index,
+            fog_index: self.fog.data_index,
+            flags,
+            ..Default::default()
+        };
+        unsafe {
+            device.cmd_begin_rendering(cmd, &vk::RenderingInfo::default().render_area(area).layer_count(1).color_attachments(std::slice::from_ref(&color_attachment)).depth_attachment(&depth_attachment));
+            device.cmd_set_viewport(cmd, 0, &[vk::Viewport { x: 0.0, y: 0.0, width: extent.width as f32, height: extent.height as f32, min_depth: 0.0, max_depth: 1.0 }]);
+            device.cmd_set_scissor(cmd, 0, &[area]);
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipelines[self.settings.pipeline_index as usize]);
+            self.gpu.bind_bindless(cmd, vk::PipelineBindPoint::GRAPHICS);
+            match self.visible {
+                Some(_) => self.chunks.draw_all(&self.gpu, cmd, push),
+                None => self.chunks.draw(&self.gpu, cmd, push),
+            }
+            if self.visible.is_none() && self.settings.show_culled {
+                device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.bounds_pipeline);
+                self.chunks.draw_culled(&self.gpu, cmd, self.cam_index);
+            }
+            device.cmd_end_rendering(cmd);
+        }
     }
 
-    // need to rebuild the swapchain and any resources that depend on the window extent
-    fn recreate_swapchain(&mut self) {
-        log::info!("Recreating swapchain");
+    fn destroy(&mut self) {
+        self.scheduler.wait_idle(&mut self.gpu);
+        self.gui.destroy(&mut self.gpu);
+        self.chunks.free(&mut self.gpu);
+        self.cull.destroy(&self.gpu.device);
+        self.shadows.free(&mut self.gpu);
+        self.sky.destroy(&self.gpu.device);
+        self.atmosphere.destroy(&self.gpu.device);
+        self.fog.destroy(&self.gpu.device);
+        self.textures.destroy(&self.gpu.device);
+        self.camera_buffer.destroy(&self.gpu.device);
+        self.targets.free(&mut self.gpu);
         unsafe {
-            self.vulkan.device.device_wait_idle().unwrap();
+            for pipeline in self.pipelines.into_iter().chain([self.bounds_pipeline]) {
+                self.gpu.device.destroy_pipeline(pipeline, None);
+            }
         }
-
-        self.vulkan.recreate_swapchain();
-
-        log::info!("recreate computation images");
-
-        // TODO, recreate my general image
-
-        self.cam.resize_window(self.vulkan.window_extent);
-        self.resize = false;
+        self.scheduler.destroy(&mut self.gpu);
+        self.swapchain.destroy(&self.gpu.device);
     }
 }
+
+/// Opens the window and runs the app until it is closed
+pub fn run() {
+    Builder::new().filter_level(log::LevelFilter::Info).init();
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+    let mut app = match TestApplication::new(&event_loop) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    event_loop.run(move |event, target| app.handle_event(event, target)).unwrap();
+}
@@ -0,0 +1,670 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use super::*;
+use biome::Biome;
+use block::BlockId;
+use column::{ChunkColumn, Section, WorldHeight};
+use loot::{Container, ItemStack};
+use nbt::Tag;
+use region::RegionStorage;
+use world::World;
+
+const USAGE: &str = "usage: anvil <import <minecraft world> <world>|export <world> <minecraft world>> <min_x> <min_z> <max_x> <max_z>";
+
+/// Minecraft 1.20.1, what exported chunks and level.dat claim to be
+const DATA_VERSION: i32 = 3465;
+const VERSION_NAME: &str = "1.20.1";
+/// Overworld height of 1.18 and later, exported sections outside are dropped
+const MINECRAFT_MIN_SECTION: i32 = -4;
+const MINECRAFT_MAX_SECTION: i32 = 19;
+
+/// Minecraft chunks per column side
+const CHUNKS_PER_COLUMN: i32 = CHUNK_RESOLUTION as i32 / 16;
+const SECTION_VOLUME: usize = 16 * 16 * 16;
+
+/// Exact names, checked before the suffix rules in `minecraft_block`
+const BLOCK_NAMES: [(&str, BlockId); 32] = [
+    ("air", block::AIR),
+    ("cave_air", block::AIR),
+    ("void_air", block::AIR),
+    ("stone", block::STONE),
+    ("deepslate", block::STONE),
+    ("granite", block::STONE),
+    ("diorite", block::STONE),
+    ("andesite", block::STONE),
+    ("tuff", block::STONE),
+    ("calcite", block::STONE),
+    ("bedrock", block::STONE),
+    ("dirt", block::DIRT),
+    ("coarse_dirt", block::DIRT),
+    ("rooted_dirt", block::DIRT),
+    ("podzol", block::DIRT),
+    ("mud", block::DIRT),
+    ("grass_block", block::GRASS),
+    ("moss_block", block::GRASS),
+    ("sand", block::SAND),
+    ("red_sand", block::SAND),
+    ("sandstone", block::SAND),
+    ("water", block::WATER),
+    ("snow", block::SNOW),
+    ("snow_block", block::SNOW),
+    ("powder_snow", block::SNOW),
+    ("ice", block::SNOW),
+    ("gravel", block::GRAVEL),
+    ("cobblestone", block::COBBLESTONE),
+    ("mossy_cobblestone", block::COBBLESTONE),
+    ("chest", block::CHEST),
+    ("trapped_chest", block::CHEST),
+    ("barrel", block::CHEST),
+];
+
+/// Our block for a minecraft name, `None` for blocks without a close match
+pub fn minecraft_block(name: &str) -> Option<BlockId> {
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    if let Some((_, id)) = BLOCK_NAMES.iter().find(|(known, _)| *known == name) {
+        return Some(*id);
+    }
+    if name.ends_with("_planks") || name.ends_with("_stairs") || name.ends_with("_slab") {
+        return Some(block::PLANKS);
+    }
+    if name.ends_with("_log") || name.ends_with("_wood") || name.ends_with("_stem") {
+        return Some(block::LOG);
+    }
+    if name.ends_with("_leaves") {
+        return Some(block::GRASS);
+    }
+    if name.contains("ore") || name.ends_with("stone_bricks") || name.ends_with("terracotta") {
+        return Some(block::STONE);
+    }
+    None
+}
+
+pub fn minecraft_name(id: BlockId) -> &'static str {
+    match id {
+        block::STONE => "minecraft:stone",
+        block::DIRT => "minecraft:dirt",
+        block::GRASS => "minecraft:grass_block",
+        block::SAND => "minecraft:sand",
+        block::WATER => "minecraft:water",
+        block::SNOW => "minecraft:snow_block",
+        block::GRAVEL => "minecraft:gravel",
+        block::PLANKS => "minecraft:oak_planks",
+        block::LOG => "minecraft:oak_log",
+        block::COBBLESTONE => "minecraft:cobblestone",
+        block::CHEST => "minecraft:chest",
+        _ => "minecraft:air",
+    }
+}
+
+pub fn minecraft_biome(name: &str) -> Biome {
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    match name {
+        "desert" | "badlands" | "eroded_badlands" | "wooded_badlands" => Biome::Desert,
+        "beach" | "snowy_beach" | "stony_shore" => Biome::Beach,
+        name if name.contains("ocean") || name == "river" || name == "frozen_river" => Biome::Ocean,
+        name if name.contains("forest") || name.contains("jungle") || name.contains("taiga") || name.contains("swamp") || name == "dark_forest" => Biome::Forest,
+        name if name.contains("snowy") || name.contains("frozen") || name == "ice_spikes" => Biome::Tundra,
+        name if name.contains("peaks") || name.contains("windswept") || name.contains("slopes") || name == "stony_peaks" => Biome::Mountains,
+        _ => Biome::Plains,
+    }
+}
+
+pub fn minecraft_biome_name(biome: Biome) -> &'static str {
+    match biome {
+        Biome::Plains => "minecraft:plains",
+        Biome::Forest => "minecraft:forest",
+        Biome::Desert => "minecraft:desert",
+        Biome::Mountains => "minecraft:windswept_hills",
+        Biome::Tundra => "minecraft:snowy_plains",
+        Biome::Ocean => "minecraft:ocean",
+        Biome::Beach => "minecraft:beach",
+    }
+}
+
+/// Palette indices packed into longs, entries don't span two longs (1.16 and later)
+fn unpack(data: &[i64], bits: usize, count: usize) -> Vec<usize> {
+    let per_long = 64 / bits;
+    let mask = (1u64 << bits) - 1;
+    (0..count).map(|i| data.get(i / per_long).map_or(0, |long| ((*long as u64 >> ((i % per_long) * bits)) & mask) as usize)).collect()
+}
+
+fn pack(indices: &[usize], bits: usize) -> Vec<i64> {
+    let per_long = 64 / bits;
+    indices.chunks(per_long).map(|chunk| chunk.iter().enumerate().fold(0u64, |long, (i, index)| long | ((*index as u64) << (i * bits))) as i64).collect()
+}
+
+fn bits_for(len: usize) -> usize {
+    (usize::BITS - (len.max(1) - 1).leading_zeros()) as usize
+}
+
+/// One `.mca` file, read whole since imports touch most of its chunks
+struct McaRegion {
+    bytes: Vec<u8>,
+}
+
+impl McaRegion {
+    fn chunk(&self, local_x: i32, local_z: i32) -> Result<Option<Tag>, String> {
+        let entry = 4 * (local_x + local_z * 32) as usize;
+        let location = u32::from_be_bytes(self.bytes[entry..entry + 4].try_into().unwrap());
+        if location == 0 {
+            return Ok(None);
+        }
+
+        let offset = (location >> 8) as usize * 4096;
+        let header = self.bytes.get(offset..offset + 5).ok_or("chunk location past the end of the file")?;
+        let len = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let data = self.bytes.get(offset + 5..offset + 4 + len).ok_or("chunk data past the end of the file")?;
+
+        let mut nbt = vec![];
+        match header[4] {
+            1 => GzDecoder::new(data).read_to_end(&mut nbt),
+            2 => ZlibDecoder::new(data).read_to_end(&mut nbt),
+            3 => {
+                nbt = data.to_vec();
+                Ok(0)
+            }
+            compression => return Err(format!("unsupported chunk compression {}", compression)),
+        }
+        .map_err(|e| format!("invalid chunk data: {}", e))?;
+        nbt::read(&nbt).map(|(_, tag)| Some(tag))
+    }
+}
+
+/// Reads columns out of a minecraft world, one of our columns covers 4x4 minecraft chunks
+pub struct AnvilImport {
+    region_dir: PathBuf,
+    regions: HashMap<[i32; 2], Option<McaRegion>>,
+    /// Names without a match, with how often they were replaced by stone
+    pub unknown: HashMap<String, usize>,
+}
+
+/// Block palette and indices of a section, one format per era
+fn section_blocks(section: &Tag) -> Option<(Vec<String>, Vec<usize>)> {
+    // 1.18 and later nest the palette, 1.16 and 1.17 have it directly on the section
+    let (palette, data) = match section.get("block_states") {
+        Some(states) => (states.get("palette")?.as_list()?, states.get("data")),
+        None => (section.get("Palette")?.as_list()?, section.get("BlockStates")),
+    };
+    let names: Vec<String> = palette.iter().map(|entry| entry.get("Name").and_then(Tag::as_str).unwrap_or("minecraft:air").to_owned()).collect();
+    let indices = match data.and_then(Tag::as_long_array) {
+        Some(data) => unpack(data, bits_for(names.len()).max(4), SECTION_VOLUME),
+        None => vec![0; SECTION_VOLUME],
+    };
+    Some((names, indices))
+}
+
+/// 4x4x4 cells of 4 blocks, `None` before 1.18 where biomes are numeric ids
+fn section_biomes(section: &Tag) -> Option<Vec<Biome>> {
+    let biomes = section.get("biomes")?;
+    let palette: Vec<Biome> = biomes.get("palette")?.as_list()?.iter().map(|name| minecraft_biome(name.as_str().unwrap_or(""))).collect();
+    let indices = match biomes.get("data").and_then(Tag::as_long_array) {
+        Some(data) => unpack(data, bits_for(palette.len()), 64),
+        None => vec![0; 64],
+    };
+    Some(indices.iter().map(|index| palette.get(*index).copied().unwrap_or_default()).collect())
+}
+
+impl AnvilImport {
+    pub fn new(world: impl AsRef<Path>) -> Self {
+        Self { region_dir: world.as_ref().join("region"), regions: HashMap::new(), unknown: HashMap::new() }
+    }
+
+    fn chunk(&mut self, chunk_x: i32, chunk_z: i32) -> Result<Option<Tag>, String> {
+        let key = [chunk_x.div_euclid(32), chunk_z.div_euclid(32)];
+        if !self.regions.contains_key(&key) {
+            let path = self.region_dir.join(format!("r.{}.{}.mca", key[0], key[1]));
+            let region = match std::fs::read(&path) {
+                Ok(bytes) if bytes.len() >= 8192 => Some(McaRegion { bytes }),
+                Ok(_) => return Err(format!("{}: shorter than the region header", path.display())),
+                Err(_) => None,
+            };
+            self.regions.insert(key, region);
+        }
+        match &self.regions[&key] {
+            Some(region) => region.chunk(chunk_x.rem_euclid(32), chunk_z.rem_euclid(32)).map_err(|e| format!("chunk ({}, {}): {}", chunk_x, chunk_z, e)),
+            None => Ok(None),
+        }
+    }
+
+    /// `None` if none of the minecraft chunks of the column exist, blocks outside `height` are dropped
+    pub fn column(&mut self, x: i32, z: i32, height: WorldHeight) -> Result<Option<ChunkColumn>, String> {
+        let res = CHUNK_RESOLUTION as i32;
+        let mut column = ChunkColumn::from_sections(x, z, height.min_section().unwrap_or(0), vec![], vec![Biome::default(); CHUNK_RESOLUTION * CHUNK_RESOLUTION], height);
+        let mut found = false;
+        // biome cells of every section, to pick the one at the surface afterwards
+        let mut biome_cells: HashMap<[i32; 3], Vec<Biome>> = HashMap::new();
+        let mut containers = vec![];
+
+        for chunk_z in 0..CHUNKS_PER_COLUMN {
+            for chunk_x in 0..CHUNKS_PER_COLUMN {
+                let Some(root) = self.chunk(x * CHUNKS_PER_COLUMN + chunk_x, z * CHUNKS_PER_COLUMN + chunk_z)? else {
+                    continue;
+                };
+                found = true;
+                let level = root.get("Level").unwrap_or(&root);
+                let sections = level.get("sections").or_else(|| level.get("Sections")).and_then(Tag::as_list).unwrap_or(&[]);
+
+                for section in sections {
+                    let Some(section_y) = section.get("Y").and_then(Tag::as_i64) else {
+                        continue;
+                    };
+                    let section_y = section_y as i32;
+                    if let Some(biomes) = section_biomes(section) {
+                        biome_cells.insert([chunk_x, section_y, chunk_z], biomes);
+                    }
+                    let Some((names, indices)) = section_blocks(section) else {
+                        continue;
+                    };
+
+                    let blocks: Vec<BlockId> = names
+                        .iter()
+                        .map(|name| {
+                            minecraft_block(name).unwrap_or_else(|| {
+                                *self.unknown.entry(name.clone()).or_insert(0) += 1;
+                                block::STONE
+                            })
+                        })
+                        .collect();
+                    if blocks.iter().all(|block| *block == block::AIR) {
+                        continue;
+                    }
+
+                    for (i, index) in indices.iter().enumerate() {
+                        let block = blocks.get(*index).copied().unwrap_or(block::AIR);
+                        if block != block::AIR {
+                            let [lx, ly, lz] = [i % 16, i / 256, (i / 16) % 16];
+                            column.set_block(chunk_x as usize * 16 + lx, section_y * 16 + ly as i32, chunk_z as usize * 16 + lz, block);
+                        }
+                    }
+                }
+
+                let entities = level.get("block_entities").or_else(|| level.get("TileEntities")).and_then(Tag::as_list).unwrap_or(&[]);
+                containers.extend(entities.iter().filter_map(container));
+            }
+        }
+        if !found {
+            return Ok(None);
+        }
+
+        for container in containers {
+            let section_y = container.pos[1].div_euclid(res);
+            if let Some(Section::Blocks(chunk)) = column.section_mut(section_y) {
+                chunk.containers.push(container);
+            }
+        }
+
+        // the biome of a column is the one at its surface
+        for lz in 0..CHUNK_RESOLUTION {
+            for lx in 0..CHUNK_RESOLUTION {
+                let top = (column.bottom * res..column.top() * res).rev().find(|y| column.block(lx, *y, lz).is_some_and(|block| block != block::AIR));
+                let y = top.unwrap_or(0);
+                let cell = [lx as i32 / 16, y.div_euclid(16), lz as i32 / 16];
+                if let Some(cells) = biome_cells.get(&cell) {
+                    column.biomes[lx + lz * CHUNK_RESOLUTION] = cells[(lx % 16) / 4 + ((lz % 16) / 4) * 4 + (y.rem_euclid(16) as usize / 4) * 16];
+                }
+            }
+        }
+        column.compact();
+        Ok(Some(column))
+    }
+}
+
+/// Chests and barrels, other block entities have nothing we keep
+fn container(entity: &Tag) -> Option<Container> {
+    let id = entity.get("id")?.as_str()?;
+    if minecraft_block(id) != Some(block::CHEST) {
+        return None;
+    }
+    let pos = [entity.get("x")?.as_i64()? as i32, entity.get("y")?.as_i64()? as i32, entity.get("z")?.as_i64()? as i32];
+    Some(Container { pos, items: items(entity) })
+}
+
+/// `Items` of a container block entity, numeric item ids from before 1.8 are skipped
+pub fn items(entity: &Tag) -> Vec<ItemStack> {
+    entity
+        .get("Items")
+        .and_then(Tag::as_list)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|item| {
+            let name = item.get("id")?.as_str()?;
+            // 1.20.5 renamed the byte `Count` to an int `count`
+            let count = item.get("Count").or_else(|| item.get("count")).and_then(Tag::as_i64).unwrap_or(1);
+            Some(ItemStack { item: name.strip_prefix("minecraft:").unwrap_or(name).to_owned(), count: count as u32 })
+        })
+        .collect()
+}
+
+/// Chunk nbt of one minecraft chunk of `column`, `chunk_x`/`chunk_z` are inside the column
+fn export_chunk(column: &ChunkColumn, chunk_x: i32, chunk_z: i32) -> Tag {
+    let res = CHUNK_RESOLUTION as i32;
+    let [ox, oz] = [chunk_x as usize * 16, chunk_z as usize * 16];
+    let first = (column.bottom * res / 16).max(MINECRAFT_MIN_SECTION);
+    let last = (column.top() * res / 16 - 1).min(MINECRAFT_MAX_SECTION);
+
+    let mut sections = vec![];
+    let mut entities = vec![];
+    for section_y in first..=last {
+        let mut palette: Vec<BlockId> = vec![];
+        let mut indices = Vec::with_capacity(SECTION_VOLUME);
+        for i in 0..SECTION_VOLUME {
+            let [lx, ly, lz] = [i % 16, i / 256, (i / 16) % 16];
+            let block = column.block(ox + lx, section_y * 16 + ly as i32, oz + lz).unwrap_or(block::AIR);
+            let index = palette.iter().position(|known| *known == block).unwrap_or_else(|| {
+                palette.push(block);
+                palette.len() - 1
+            });
+            indices.push(index);
+        }
+        if palette == [block::AIR] {
+            continue;
+        }
+
+        let mut block_states = vec![("palette", Tag::List(palette.iter().map(|block| Tag::compound(vec![("Name", Tag::String(minecraft_name(*block).to_owned()))])).collect()))];
+        if palette.len() > 1 {
+            block_states.push(("data", Tag::LongArray(pack(&indices, bits_for(palette.len()).max(4)))));
+        }
+
+        let mut biome_palette: Vec<Biome> = vec![];
+        let mut biome_indices = vec![];
+        for cell in 0..64 {
+            let biome = column.biomes[ox + (cell % 4) * 4 + (oz + ((cell / 4) % 4) * 4) * CHUNK_RESOLUTION];
+            let index = biome_palette.iter().position(|known| *known == biome).unwrap_or_else(|| {
+                biome_palette.push(biome);
+                biome_palette.len() - 1
+            });
+            biome_indices.push(index);
+        }
+        let mut biomes = vec![("palette", Tag::List(biome_palette.iter().map(|biome| Tag::String(minecraft_biome_name(*biome).to_owned())).collect()))];
+        if biome_palette.len() > 1 {
+            biomes.push(("data", Tag::LongArray(pack(&biome_indices, bits_for(biome_palette.len())))));
+        }
+
+        sections.push(Tag::compound(vec![("Y", Tag::Byte(section_y as i8)), ("block_states", Tag::compound(block_states)), ("biomes", Tag::compound(biomes))]));
+    }
+
+    for section in &column.sections {
+        let Section::Blocks(chunk) = section else {
+            continue;
+        };
+        for container in &chunk.containers {
+            let local = [container.pos[0] - column.x * res - ox as i32, container.pos[2] - column.z * res - oz as i32];
+            if !(0..16).contains(&local[0]) || !(0..16).contains(&local[1]) {
+                continue;
+            }
+            let items = container
+                .items
+                .iter()
+                .enumerate()
+                .map(|(slot, stack)| Tag::compound(vec![("Slot", Tag::Byte(slot as i8)), ("id", Tag::String(format!("minecraft:{}", stack.item))), ("Count", Tag::Byte(stack.count.min(64) as i8))]))
+                .collect();
+            entities.push(Tag::compound(vec![
+                ("id", Tag::String("minecraft:chest".to_owned())),
+                ("x", Tag::Int(container.pos[0])),
+                ("y", Tag::Int(container.pos[1])),
+                ("z", Tag::Int(container.pos[2])),
+                ("keepPacked", Tag::Byte(0)),
+                ("Items", Tag::List(items)),
+            ]));
+        }
+    }
+
+    // no heightmaps or light, minecraft computes both when `isLightOn` is false
+    Tag::compound(vec![
+        ("DataVersion", Tag::Int(DATA_VERSION)),
+        ("xPos", Tag::Int(column.x * CHUNKS_PER_COLUMN + chunk_x)),
+        ("zPos", Tag::Int(column.z * CHUNKS_PER_COLUMN + chunk_z)),
+        ("yPos", Tag::Int(MINECRAFT_MIN_SECTION)),
+        ("Status", Tag::String("minecraft:full".to_owned())),
+        ("LastUpdate", Tag::Long(0)),
+        ("InhabitedTime", Tag::Long(0)),
+        ("isLightOn", Tag::Byte(0)),
+        ("sections", Tag::List(sections)),
+        ("block_entities", Tag::List(entities)),
+        ("structures", Tag::compound(vec![("starts", Tag::Compound(vec![])), ("References", Tag::Compound(vec![]))])),
+    ])
+}
+
+/// Writes whole `.mca` files, chunks are zlib compressed
+struct McaWriter {
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl McaWriter {
+    fn new() -> Self {
+        Self { chunks: vec![None; 1024] }
+    }
+
+    fn set(&mut self, local_x: i32, local_z: i32, chunk: &Tag) {
+        let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+        zlib.write_all(&nbt::write("", chunk)).unwrap();
+        self.chunks[(local_x + local_z * 32) as usize] = Some(zlib.finish().unwrap());
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; 8192];
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let Some(data) = chunk else {
+                continue;
+            };
+            let sector = bytes.len() / 4096;
+            bytes.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+            bytes.push(2);
+            bytes.extend_from_slice(data);
+            bytes.resize(bytes.len().div_ceil(4096) * 4096, 0);
+
+            let sectors = bytes.len() / 4096 - sector;
+            bytes[index * 4..index * 4 + 4].copy_from_slice(&(((sector as u32) << 8) | sectors.min(255) as u32).to_be_bytes());
+        }
+        bytes
+    }
+}
+
+/// level.dat of a creative void world, only the exported chunks have terrain
+fn level_dat(name: &str, seed: i64, spawn: [i32; 3]) -> Vec<u8> {
+    let last_played = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|time| time.as_millis() as i64).unwrap_or(0);
+    let flat = Tag::compound(vec![
+        ("type", Tag::String("minecraft:flat".to_owned())),
+        ("settings", Tag::compound(vec![("layers", Tag::List(vec![])), ("biome", Tag::String("minecraft:plains".to_owned())), ("features", Tag::Byte(0)), ("lakes", Tag::Byte(0)), ("structure_overrides", Tag::List(vec![]))])),
+    ]);
+    let overworld = Tag::compound(vec![("type", Tag::String("minecraft:overworld".to_owned())), ("generator", flat)]);
+
+    let data = Tag::compound(vec![
+        ("DataVersion", Tag::Int(DATA_VERSION)),
+        ("Version", Tag::compound(vec![("Id", Tag::Int(DATA_VERSION)), ("Name", Tag::String(VERSION_NAME.to_owned())), ("Series", Tag::String("main".to_owned())), ("Snapshot", Tag::Byte(0))])),
+        ("version", Tag::Int(19133)),
+        ("LevelName", Tag::String(name.to_owned())),
+        ("GameType", Tag::Int(1)),
+        ("allowCommands", Tag::Byte(1)),
+        ("initialized", Tag::Byte(1)),
+        ("LastPlayed", Tag::Long(last_played)),
+        ("Time", Tag::Long(0)),
+        ("DayTime", Tag::Long(6000)),
+        ("SpawnX", Tag::Int(spawn[0])),
+        ("SpawnY", Tag::Int(spawn[1])),
+        ("SpawnZ", Tag::Int(spawn[2])),
+        ("WorldGenSettings", Tag::compound(vec![("seed", Tag::Long(seed)), ("generate_features", Tag::Byte(0)), ("bonus_chest", Tag::Byte(0)), ("dimensions", Tag::compound(vec![("minecraft:overworld", overworld)]))])),
+    ]);
+    nbt::write_gzip("", &Tag::compound(vec![("Data", data)]))
+}
+
+/// Writes the columns as a minecraft world, the region files are replaced whole
+pub fn export(columns: &[&ChunkColumn], world: impl AsRef<Path>, seed: i64) -> Result<(), String> {
+    let world = world.as_ref();
+    let region_dir = world.join("region");
+    std::fs::create_dir_all(&region_dir).map_err(|e| format!("{}: {}", region_dir.display(), e))?;
+
+    let mut regions: HashMap<[i32; 2], McaWriter> = HashMap::new();
+    for column in columns {
+        for chunk_z in 0..CHUNKS_PER_COLUMN {
+            for chunk_x in 0..CHUNKS_PER_COLUMN {
+                let [global_x, global_z] = [column.x * CHUNKS_PER_COLUMN + chunk_x, column.z * CHUNKS_PER_COLUMN + chunk_z];
+                let region = regions.entry([global_x.div_euclid(32), global_z.div_euclid(32)]).or_insert_with(McaWriter::new);
+                region.set(global_x.rem_euclid(32), global_z.rem_euclid(32), &export_chunk(column, chunk_x, chunk_z));
+            }
+        }
+    }
+    for ([x, z], region) in &regions {
+        let path = region_dir.join(format!("r.{}.{}.mca", x, z));
+        std::fs::write(&path, region.bytes()).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    let spawn = match columns.first() {
+        Some(column) => {
+            let res = CHUNK_RESOLUTION as i32;
+            let (x, z) = (CHUNK_RESOLUTION / 2, CHUNK_RESOLUTION / 2);
+            let top = (column.bottom * res..column.top() * res).rev().find(|y| column.block(x, *y, z).is_some_and(|block| block != block::AIR)).unwrap_or(0);
+            [column.x * res + x as i32, top + 1, column.z * res + z as i32]
+        }
+        None => [0, 64, 0],
+    };
+    let name = world.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| "export".to_owned());
+    let path = world.join("level.dat");
+    std::fs::write(&path, level_dat(&name, seed, spawn)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn parse_area(args: &[String]) -> Result<([i32; 2], [i32; 2]), String> {
+    let numbers: Vec<i32> = args.iter().map(|n| n.parse().map_err(|_| format!("invalid column coordinate '{}'", n))).collect::<Result<_, _>>()?;
+    if numbers.len() != 4 {
+        return Err(USAGE.to_owned());
+    }
+    Ok(([numbers[0].min(numbers[2]), numbers[1].min(numbers[3])], [numbers[0].max(numbers[2]), numbers[1].max(numbers[3])]))
+}
+
+fn run_command(args: &[String]) -> Result<(), String> {
+    if args.len() != 7 {
+        return Err(USAGE.to_owned());
+    }
+    let (min, max) = parse_area(&args[3..7])?;
+
+    match args[0].as_str() {
+        "import" => {
+            let mut import = AnvilImport::new(&args[1]);
+            let world = World::open_or_create(&args[2])?;
+            let terrain = world.terrain();
+            let mut storage = RegionStorage::new(world.region_dir(), terrain.world_height)?;
+            let mut imported = 0;
+            for z in min[1]..=max[1] {
+                for x in min[0]..=max[0] {
+                    if let Some(column) = import.column(x, z, terrain.world_height)? {
+                        storage.save_column(&column)?;
+                        imported += 1;
+                    }
+                }
+            }
+            let mut unknown: Vec<_> = import.unknown.iter().collect();
+            unknown.sort_by(|a, b| b.1.cmp(a.1));
+            for (name, count) in unknown {
+                log::warn!("{} is unknown, {} sections used stone instead", name, count);
+            }
+            println!("imported {} columns into {}", imported, args[2]);
+        }
+        "export" => {
+            let world = World::open(&args[1])?;
+            let seed = world.info.seed as i64;
+            let mut manager = world.chunk_manager()?;
+            for z in min[1]..=max[1] {
+                for x in min[0]..=max[0] {
+                    manager.load_column(x, z);
+                }
+            }
+            let mut columns: Vec<&ChunkColumn> = manager.columns().collect();
+            columns.sort_by_key(|column| (column.z, column.x));
+            export(&columns, &args[2], seed)?;
+            println!("exported {} columns to {}", columns.len(), args[2]);
+        }
+        _ => return Err(USAGE.to_owned()),
+    }
+    Ok(())
+}
+
+/// Entry point for `vulkanrust anvil ...`, coordinates are in columns of `CHUNK_RESOLUTION` blocks
+pub fn run(args: &[String]) {
+    if let Err(e) = run_command(args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_round_trip() {
+        for bits in [1, 4, 5, 7, 12] {
+            let indices: Vec<usize> = (0..SECTION_VOLUME).map(|i| (i * 7 + i / 5) % (1 << bits)).collect();
+            assert_eq!(unpack(&pack(&indices, bits), bits, indices.len()), indices, "{} bits", bits);
+        }
+    }
+
+    #[test]
+    fn indices_dont_span_longs() {
+        // 5 bits fit 12 entries per long, the top 4 bits stay unused
+        let data = [1 | (2 << 5) | (3 << 10) | (31 << 55), 7];
+        let indices = unpack(&data, 5, 13);
+        assert_eq!(indices[..3], [1, 2, 3]);
+        assert_eq!(indices[11], 31);
+        assert_eq!(indices[12], 7);
+        assert_eq!(pack(&indices, 5), data);
+        // missing longs read as 0
+        assert_eq!(unpack(&data, 5, 26)[25], 0);
+    }
+
+    #[test]
+    fn sections_of_both_eras() {
+        let palette = || Tag::List(["minecraft:air", "minecraft:stone"].iter().map(|name| Tag::compound(vec![("Name", Tag::String(name.to_string()))])).collect());
+        // 4 bits at least, the first block of the section is stone
+        let mut data = vec![0i64; SECTION_VOLUME / 16];
+        data[0] = 1;
+
+        let modern = Tag::compound(vec![("Y", Tag::Byte(0)), ("block_states", Tag::compound(vec![("palette", palette()), ("data", Tag::LongArray(data.clone()))]))]);
+        let legacy = Tag::compound(vec![("Y", Tag::Byte(0)), ("Palette", palette()), ("BlockStates", Tag::LongArray(data))]);
+        for section in [modern, legacy] {
+            let (names, indices) = section_blocks(&section).unwrap();
+            assert_eq!(names, ["minecraft:air", "minecraft:stone"]);
+            assert_eq!(indices.len(), SECTION_VOLUME);
+            assert_eq!(indices.iter().sum::<usize>(), 1);
+            assert_eq!(indices[0], 1);
+        }
+    }
+
+    #[test]
+    fn bits_for_palettes() {
+        assert_eq!([1, 2, 3, 4, 16, 17, 256].map(bits_for), [0, 1, 2, 2, 4, 5, 8]);
+    }
+
+    #[test]
+    fn exported_column_imports_back() {
+        let dir = std::env::temp_dir().join(format!("vulkanrust-anvil-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let height = WorldHeight::default();
+        let biomes = vec![Biome::Desert; CHUNK_RESOLUTION * CHUNK_RESOLUTION];
+        let mut column = ChunkColumn::from_sections(-1, 2, 0, vec![Section::Uniform(block::STONE), Section::Empty], biomes, height);
+        let res = CHUNK_RESOLUTION as i32;
+        let blocks = [block::DIRT, block::GRASS, block::SAND, block::WATER, block::PLANKS, block::LOG, block::COBBLESTONE];
+        for (i, block) in blocks.iter().enumerate() {
+            column.set_block(i * 9, res + i as i32 * 3, 63 - i * 5, *block);
+        }
+        column.set_block(17, res - 1, 40, block::AIR);
+
+        export(&[&column], &dir, 7).unwrap();
+        let imported = AnvilImport::new(&dir).column(-1, 2, height).unwrap().unwrap();
+        assert!(AnvilImport::new(&dir).column(0, 2, height).unwrap().is_none());
+        for y in 0..2 * res {
+            for z in 0..CHUNK_RESOLUTION {
+                for x in 0..CHUNK_RESOLUTION {
+                    assert_eq!(imported.block(x, y, z), column.block(x, y, z), "block ({}, {}, {})", x, y, z);
+                }
+            }
+        }
+        assert!(imported.biomes.iter().all(|biome| *biome == Biome::Desert));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::*;
+use column::ChunkColumn;
+use region::{encode_column, RegionStorage};
+
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+pub const BACKUP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// How often the world directory is backed up while auto-saving, see `backup::backup`
+#[derive(Clone, Debug)]
+pub struct BackupPolicy {
+    pub world: PathBuf,
+    pub interval: Duration,
+    pub keep: usize,
+}
+
+enum Job {
+    Save(ChunkColumn),
+    Backup(PathBuf, usize),
+    Flush(Sender<()>),
+}
+
+/// Failed saves come back with the column so it isn't lost
+pub enum SaveResult {
+    Saved([i32; 2]),
+    Failed(ChunkColumn, String),
+}
+
+/// Encodes and writes columns on a background thread so the frame loop never waits on the disk.
+/// Jobs run in the order they were queued, a later save of the same column always wins.
+pub struct AutoSave {
+    pub interval: Duration,
+    pub backup: Option<BackupPolicy>,
+    last_save: Instant,
+    last_backup: Instant,
+    jobs: Option<Sender<Job>>,
+    results: Receiver<SaveResult>,
+    thread: Option<JoinHandle<()>>,
+    /// Saves in the queue per column, loading one of them has to wait for the queue
+    queued: HashMap<[i32; 2], usize>,
+}
+
+fn run_jobs(storage: Arc<Mutex<RegionStorage>>, jobs: Receiver<Job>, results: Sender<SaveResult>) {
+    for job in jobs {
+        match job {
+            Job::Save(column) => {
+                let data = encode_column(&column);
+                let result = storage.lock().unwrap().save_encoded(column.x, column.z, &data);
+                let _ = results.send(match result {
+                    Ok(()) => SaveResult::Saved([column.x, column.z]),
+                    Err(e) => SaveResult::Failed(column, e),
+                });
+            }
+            Job::Backup(world, keep) => {
+                // no column is written while the region files are copied
+                let _storage = storage.lock().unwrap();
+                match backup::backup(&world, keep) {
+                    Ok(path) => log::info!("backed up the world to {}", path.display()),
+                    Err(e) => log::warn!("backup failed: {}", e),
+                }
+            }
+            Job::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+impl AutoSave {
+    pub fn new(storage: Arc<Mutex<RegionStorage>>, interval: Duration, backup: Option<BackupPolicy>) -> Self {
+        let (jobs, job_receiver) = channel();
+        let (result_sender, results) = channel();
+        let thread = std::thread::Builder::new().name("autosave".to_owned()).spawn(move || run_jobs(storage, job_receiver, result_sender)).unwrap();
+        Self { interval, backup, last_save: Instant::now(), last_backup: Instant::now(), jobs: Some(jobs), results, thread: Some(thread), queued: HashMap::new() }
+    }
+
+    fn send(&self, job: Job) {
+        self.jobs.as_ref().unwrap().send(job).expect("autosave thread stopped");
+    }
+
+    /// Takes a snapshot, the column can keep changing while it's written
+    pub fn queue(&mut self, column: ChunkColumn) {
+        *self.queued.entry([column.x, column.z]).or_default() += 1;
+        self.send(Job::Save(column));
+    }
+
+    pub fn is_queued(&self, key: [i32; 2]) -> bool {
+        self.queued.contains_key(&key)
+    }
+
+    /// True once per `interval`
+    pub fn save_due(&mut self) -> bool {
+        let due = self.last_save.elapsed() >= self.interval;
+        if due {
+            self.last_save = Instant::now();
+        }
+        due
+    }
+
+    /// Queues a backup behind the pending saves once per backup interval
+    pub fn backup_if_due(&mut self) {
+        let Some(policy) = &self.backup else {
+            return;
+        };
+        if self.last_backup.elapsed() >= policy.interval {
+            self.last_backup = Instant::now();
+            self.send(Job::Backup(policy.world.clone(), policy.keep));
+        }
+    }
+
+    /// Results of finished saves, doesn't block
+    pub fn finished(&mut self) -> Vec<SaveResult> {
+        let results: Vec<SaveResult> = self.results.try_iter().collect();
+        for result in &results {
+            let key = match result {
+                SaveResult::Saved(key) => *key,
+                SaveResult::Failed(column, _) => [column.x, column.z],
+            };
+            if let Some(count) = self.queued.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    self.queued.remove(&key);
+                }
+            }
+        }
+        results
+    }
+
+    /// Blocks until every queued job ran
+    pub fn flush(&mut self) -> Vec<SaveResult> {
+        let (done, wait) = channel();
+        self.send(Job::Flush(done));
+        let _ = wait.recv();
+        self.finished()
+    }
+}
+
+impl Drop for AutoSave {
+    /// Finishes the queued jobs
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use biome::Biome;
+    use column::{Section, WorldHeight};
+    use world::World;
+
+    fn column(x: i32, z: i32, block: block::BlockId) -> ChunkColumn {
+        let biomes = vec![Biome::Plains; CHUNK_RESOLUTION * CHUNK_RESOLUTION];
+        ChunkColumn::from_sections(x, z, 0, vec![Section::Uniform(block)], biomes, WorldHeight::default())
+    }
+
+    #[test]
+    fn queued_saves_and_backups_run_in_order() {
+        let dir = std::env::temp_dir().join(format!("vulkanrust-autosave-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let world = World::create(&dir, 7).unwrap();
+        let storage = Arc::new(Mutex::new(RegionStorage::new(world.region_dir(), WorldHeight::default()).unwrap()));
+        let policy = BackupPolicy { world: dir.clone(), interval: Duration::ZERO, keep: 1 };
+        let mut autosave = AutoSave::new(storage.clone(), Duration::ZERO, Some(policy));
+
+        autosave.queue(column(0, 0, block::DIRT));
+        autosave.queue(column(0, 0, block::STONE));
+        autosave.backup_if_due();
+        assert!(autosave.is_queued([0, 0]));
+        let results = autosave.flush();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| matches!(result, SaveResult::Saved([0, 0]))));
+        assert!(!autosave.is_queued([0, 0]));
+
+        // the later save wins, and the backup ran after both
+        let saved = storage.lock().unwrap().load_column(0, 0).unwrap().unwrap();
+        assert_eq!(saved.block(0, 0, 0), Some(block::STONE));
+        let backups = backup::backups(&dir);
+        assert_eq!(backups.len(), 1);
+        let mut backed_up = RegionStorage::new(backups[0].join(world::REGION_DIR), WorldHeight::default()).unwrap();
+        assert_eq!(backed_up.load_column(0, 0).unwrap().unwrap().block(0, 0, 0), Some(block::STONE));
+        drop(autosave);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::*;
+use column::{ChunkColumn, WorldHeight};
+use region::{decode_column, encode_column, region_file_name, region_files, RegionFile, REGION_SIZE};
+use world::{copy_dir, World, WorldInfo, PRESET_FILE, REGION_DIR, WORLD_INFO_FILE};
+
+/// Inside the world directory, one directory per backup named by its creation time in milliseconds
+pub const BACKUP_DIR: &str = "backups";
+/// Backups kept by default, older ones are deleted after each new backup
+pub const BACKUP_KEEP: usize = 5;
+
+/// Finished backups of the world in `dir`, newest first
+pub fn backups(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir.join(BACKUP_DIR)) else {
+        return vec![];
+    };
+    let mut backups: Vec<(u128, PathBuf)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            Some((path.file_name()?.to_str()?.parse().ok()?, path))
+        })
+        .collect();
+    backups.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Copies `world.txt`, the preset and the region files into a new backup and deletes all but the newest `keep`.
+/// The copy is made under a temporary name, an interrupted backup is never used for recovery.
+pub fn backup(dir: &Path, keep: usize) -> Result<PathBuf, String> {
+    let backup_dir = dir.join(BACKUP_DIR);
+    std::fs::create_dir_all(&backup_dir).map_err(|e| format!("{}: {}", backup_dir.display(), e))?;
+    // left behind by a crash during an earlier backup
+    for entry in std::fs::read_dir(&backup_dir).map_err(|e| format!("{}: {}", backup_dir.display(), e))?.flatten() {
+        if entry.path().extension().is_some_and(|extension| extension == "tmp") {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0);
+    let tmp = backup_dir.join(format!("{}.tmp", millis));
+    let target = backup_dir.join(millis.to_string());
+    copy_dir(&dir.join(REGION_DIR), &tmp.join(REGION_DIR))?;
+    for name in [WORLD_INFO_FILE, PRESET_FILE] {
+        if dir.join(name).exists() {
+            std::fs::copy(dir.join(name), tmp.join(name)).map_err(|e| format!("{}: {}", dir.join(name).display(), e))?;
+        }
+    }
+    std::fs::rename(&tmp, &target).map_err(|e| format!("{}: {}", target.display(), e))?;
+
+    for old in backups(dir).into_iter().skip(keep) {
+        match std::fs::remove_dir_all(&old) {
+            Ok(()) => log::info!("removed old backup {}", old.display()),
+            Err(e) => log::warn!("{}: {}", old.display(), e),
+        }
+    }
+    Ok(target)
+}
+
+/// Column from the region file of a backup, `None` if the backup doesn't hold a readable copy
+fn read_backup(backup: &Path, region: [i32; 2], local_x: i32, local_z: i32, height: WorldHeight) -> Option<ChunkColumn> {
+    let path = backup.join(REGION_DIR).join(region_file_name(region));
+    if !path.exists() {
+        return None;
+    }
+    let result = RegionFile::open(&path).and_then(|mut file| file.read(local_x, local_z)).and_then(|data| data.map(|data| decode_column(&data, height)).transpose());
+    result.unwrap_or_else(|e| {
+        log::warn!("{}: {}", backup.display(), e);
+        None
+    })
+}
+
+/// Local positions of the columns any backup holds for `region`
+fn backed_up_columns(backups: &[PathBuf], region: [i32; 2]) -> Vec<(i32, i32)> {
+    let mut columns: Vec<(i32, i32)> = backups.iter().map(|backup| backup.join(REGION_DIR).join(region_file_name(region))).filter(|path| path.exists()).filter_map(|path| RegionFile::open(path).ok()).flat_map(|file| file.columns()).collect();
+    columns.sort();
+    columns.dedup();
+    columns
+}
+
+/// Restores `world.txt` from the newest backup if it can't be read
+fn recover_info(dir: &Path, backups: &[PathBuf]) -> Result<Option<String>, String> {
+    let path = dir.join(WORLD_INFO_FILE);
+    let readable = |path: &Path| std::fs::read_to_string(path).is_ok_and(|text| WorldInfo::parse(&text).is_ok());
+    if !path.exists() || readable(&path) {
+        return Ok(None);
+    }
+    let Some(backup) = backups.iter().find(|backup| readable(&backup.join(WORLD_INFO_FILE))) else {
+        return Err(format!("{} is damaged and no backup has a readable copy", path.display()));
+    };
+    std::fs::copy(backup.join(WORLD_INFO_FILE), &path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Some(format!("{} restored from {}", WORLD_INFO_FILE, backup.display())))
+}
+
+/// Reads every saved column of the world in `dir`. Damaged ones are restored from the newest backup
+/// holding a readable copy, or generated again if there is none. Returns what happened, which is also logged.
+pub fn recover(dir: &Path) -> Result<Vec<String>, String> {
+    let backups = backups(dir);
+    let mut report: Vec<String> = recover_info(dir, &backups)?.into_iter().collect();
+    let world = World::open(dir)?;
+    let terrain = world.terrain();
+    let height = terrain.world_height;
+
+    for region in region_files(&world.region_dir())? {
+        let path = world.region_dir().join(region_file_name(region));
+        let (mut file, mut damaged) = match RegionFile::open(&path) {
+            Ok(file) => {
+                let dropped = file.dropped();
+                (file, dropped)
+            }
+            // nothing in the file can be trusted, keep it aside and rebuild it from the backups
+            Err(e) => {
+                let aside = path.with_extension("region.damaged");
+                std::fs::rename(&path, &aside).map_err(|e| format!("{}: {}", path.display(), e))?;
+                report.push(format!("{}, moved it to {}", e, aside.display()));
+                (RegionFile::open(&path)?, backed_up_columns(&backups, region))
+            }
+        };
+        for (local_x, local_z) in file.columns() {
+            if let Err(e) = file.read(local_x, local_z).and_then(|data| decode_column(&data.unwrap_or_default(), height)) {
+                log::warn!("{}", e);
+                damaged.push((local_x, local_z));
+            }
+        }
+
+        for (local_x, local_z) in damaged {
+            let [x, z] = [region[0] * REGION_SIZE + local_x, region[1] * REGION_SIZE + local_z];
+            let restored = backups.iter().find_map(|backup| read_backup(backup, region, local_x, local_z, height).map(|column| (backup, column)));
+            let (column, message) = match restored {
+                Some((backup, column)) => (column, format!("column ({}, {}) restored from {}", x, z, backup.display())),
+                None => (ChunkColumn::generate(x, z, &terrain), format!("column ({}, {}) has no readable backup, generated it again", x, z)),
+            };
+            file.write(local_x, local_z, &encode_column(&column))?;
+            report.push(message);
+        }
+    }
+
+    for message in &report {
+        log::warn!("{}", message);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use biome::Biome;
+    use column::Section;
+    use region::RegionStorage;
+
+    const PLANKS_Y: i32 = CHUNK_RESOLUTION as i32 + 4;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vulkanrust-backup-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// World with column (2, 3) saved, a planks block marks it as the saved one and not a generated one
+    fn world(dir: &Path) -> World {
+        let world = World::create(dir, 7).unwrap();
+        let height = world.terrain().world_height;
+        let biomes = vec![Biome::Plains; CHUNK_RESOLUTION * CHUNK_RESOLUTION];
+        let mut column = ChunkColumn::from_sections(2, 3, 0, vec![Section::Uniform(block::STONE), Section::Empty], biomes, height);
+        column.set_block(0, PLANKS_Y, 0, block::PLANKS);
+        RegionStorage::new(world.region_dir(), height).unwrap().save_column(&column).unwrap();
+        world
+    }
+
+    fn region_path(world: &World) -> PathBuf {
+        world.region_dir().join(region_file_name([0, 0]))
+    }
+
+    /// Overwrites part of the payload, the only column of the region is in its last sector
+    fn damage_payload(path: &Path) {
+        let mut data = std::fs::read(path).unwrap();
+        let offset = data.len() - 4096 + 12;
+        data[offset..offset + 4].copy_from_slice(&[0xff; 4]);
+        std::fs::write(path, data).unwrap();
+    }
+
+    fn load(world: &World) -> ChunkColumn {
+        RegionStorage::new(world.region_dir(), world.terrain().world_height).unwrap().load_column(2, 3).unwrap().unwrap()
+    }
+
+    #[test]
+    fn undamaged_world_needs_nothing() {
+        let dir = scratch("undamaged");
+        world(&dir);
+        assert!(recover(&dir).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn damaged_payload_is_restored_from_backup() {
+        let dir = scratch("restore");
+        let world = world(&dir);
+        let backup = backup(&dir, BACKUP_KEEP).unwrap();
+        damage_payload(&region_path(&world));
+
+        let report = recover(&dir).unwrap();
+        assert_eq!(report, vec![format!("column (2, 3) restored from {}", backup.display())]);
+        assert_eq!(load(&world).block(0, PLANKS_Y, 0), Some(block::PLANKS));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn damaged_payload_without_backup_is_generated() {
+        let dir = scratch("generate");
+        let world = world(&dir);
+        damage_payload(&region_path(&world));
+
+        let report = recover(&dir).unwrap();
+        assert_eq!(report, vec!["column (2, 3) has no readable backup, generated it again".to_owned()]);
+        let generated = ChunkColumn::generate(2, 3, &world.terrain());
+        let loaded = load(&world);
+        assert_eq!(loaded.bottom, generated.bottom);
+        assert_eq!(loaded.block(0, PLANKS_Y, 0), generated.block(0, PLANKS_Y, 0));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unreadable_region_is_moved_aside_and_rebuilt() {
+        let dir = scratch("region");
+        let world = world(&dir);
+        backup(&dir, BACKUP_KEEP).unwrap();
+        let path = region_path(&world);
+        std::fs::write(&path, b"not a region").unwrap();
+
+        let report = recover(&dir).unwrap();
+        assert_eq!(report.len(), 2, "{:?}", report);
+        assert!(path.with_extension("region.damaged").exists());
+        assert_eq!(load(&world).block(0, PLANKS_Y, 0), Some(block::PLANKS));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn damaged_info_is_restored_from_backup() {
+        let dir = scratch("info");
+        world(&dir);
+        backup(&dir, BACKUP_KEEP).unwrap();
+        std::fs::write(dir.join(WORLD_INFO_FILE), "seed = ").unwrap();
+
+        assert_eq!(recover(&dir).unwrap().len(), 1);
+        assert_eq!(World::open(&dir).unwrap().info.seed, 7);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn old_backups_are_removed() {
+        let dir = scratch("keep");
+        world(&dir);
+        let made: Vec<PathBuf> = (0..3)
+            .map(|_| {
+                // backups are named by the millisecond they were made in
+                std::thread::sleep(std::time::Duration::from_millis(2));
+                backup(&dir, 2).unwrap()
+            })
+            .collect();
+        assert_eq!(backups(&dir), vec![made[2].clone(), made[1].clone()]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,59 @@
+use super::block::{self, BlockId};
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Biome {
+    #[default]
+    Plains,
+    Forest,
+    Desert,
+    Mountains,
+    Tundra,
+    Ocean,
+    Beach,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 7] = [Biome::Plains, Biome::Forest, Biome::Desert, Biome::Mountains, Biome::Tundra, Biome::Ocean, Biome::Beach];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::Plains => "plains",
+            Biome::Forest => "forest",
+            Biome::Desert => "desert",
+            Biome::Mountains => "mountains",
+            Biome::Tundra => "tundra",
+            Biome::Ocean => "ocean",
+            Biome::Beach => "beach",
+        }
+    }
+
+    /// Used for previews and for matching colors in biome mask images
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            Biome::Plains => [141, 179, 96],
+            Biome::Forest => [5, 102, 33],
+            Biome::Desert => [250, 148, 24],
+            Biome::Mountains => [96, 96, 96],
+            Biome::Tundra => [255, 255, 255],
+            Biome::Ocean => [0, 0, 112],
+            Biome::Beach => [250, 222, 85],
+        }
+    }
+
+    pub fn surface_block(&self) -> BlockId {
+        match self {
+            Biome::Plains | Biome::Forest => block::GRASS,
+            Biome::Desert | Biome::Beach => block::SAND,
+            Biome::Mountains => block::STONE,
+            Biome::Tundra => block::SNOW,
+            Biome::Ocean => block::GRAVEL,
+        }
+    }
+}
+
+impl From<u8> for Biome {
+    fn from(id: u8) -> Self {
+        Self::ALL.get(id as usize).copied().unwrap_or_default()
+    }
+}
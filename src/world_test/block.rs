@@ -0,0 +1,53 @@
+use super::*;
+
+pub type BlockId = Gridbits;
+
+pub const AIR: BlockId = 0;
+pub const STONE: BlockId = 1;
+pub const DIRT: BlockId = 2;
+pub const GRASS: BlockId = 3;
+pub const SAND: BlockId = 4;
+pub const WATER: BlockId = 5;
+pub const SNOW: BlockId = 6;
+pub const GRAVEL: BlockId = 7;
+pub const PLANKS: BlockId = 8;
+pub const LOG: BlockId = 9;
+pub const COBBLESTONE: BlockId = 10;
+pub const CHEST: BlockId = 11;
+
+pub struct BlockInfo {
+    pub name: &'static str,
+    /// Used for previews and for matching colors in mask images
+    pub color: [u8; 3],
+    pub solid: bool,
+}
+
+impl BlockInfo {
+    const fn new(name: &'static str, color: [u8; 3], solid: bool) -> Self {
+        Self { name, color, solid }
+    }
+}
+
+/// Indexed by `BlockId`
+pub const BLOCKS: [BlockInfo; 12] = [
+    BlockInfo::new("air", [0, 0, 0], false),
+    BlockInfo::new("stone", [125, 125, 125], true),
+    BlockInfo::new("dirt", [134, 96, 67], true),
+    BlockInfo::new("grass", [95, 159, 53], true),
+    BlockInfo::new("sand", [219, 207, 163], true),
+    BlockInfo::new("water", [47, 67, 244], false),
+    BlockInfo::new("snow", [249, 254, 254], true),
+    BlockInfo::new("gravel", [131, 127, 126], true),
+    BlockInfo::new("planks", [162, 130, 78], true),
+    BlockInfo::new("log", [102, 81, 49], true),
+    BlockInfo::new("cobblestone", [110, 110, 110], true),
+    BlockInfo::new("chest", [164, 116, 48], true),
+];
+
+pub fn info(id: BlockId) -> &'static BlockInfo {
+    &BLOCKS[id as usize]
+}
+
+pub fn by_name(name: &str) -> Option<BlockId> {
+    BLOCKS.iter().position(|block| block.name == name).map(|id| id as BlockId)
+}
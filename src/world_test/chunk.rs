@@ -1,6 +1,9 @@
 use super::*;
 use ash::vk::{self, PFN_vkAllocateCommandBuffers, PFN_vkCmdDrawMultiEXT};
-use generation::NoiseParameters;
+use biome::Biome;
+use block::BlockId;
+use generation::{Column, NoiseParameters, TerrainSource};
+use loot::Container;
 use glm::Vec3;
 use voxelengine::{
     terrain::block::GPUBlock,
@@ -11,31 +14,51 @@ use voxelengine::{
     },
 };
 
-fn generate_chunk(global_x: i32, global_y: i32, global_z: i32, chunk_resolution: usize) -> Chunk {
-    let x_start = global_x;
-    let y_start = global_y;
-    let z_start = global_z;
-    let chunk_length = chunk_resolution;
+/// How many blocks of a column use the surface material
+pub const SURFACE_DEPTH: usize = 3;
 
-    let grid = generation::generate_height_map(global_x, global_z, chunk_resolution, &NoiseParameters::default());
+pub fn generate_chunk(global_x: i32, global_y: i32, global_z: i32, chunk_resolution: usize, terrain: &TerrainSource) -> Chunk {
+    let columns = terrain.generate_columns(global_x, global_z, chunk_resolution);
+    fill_chunk(&columns, [global_x, global_y, global_z], chunk_resolution, terrain)
+}
 
-    let mut chunk = Chunk::new();
+/// Terrain, water and structures of one section, every section of a column shares the same `columns`
+pub fn fill_chunk(columns: &[Column], global_pos: [i32; 3], chunk_resolution: usize, terrain: &TerrainSource) -> Chunk {
+    let chunk_length = chunk_resolution;
+    let y_start = global_pos[1];
+
+    let mut chunk = Chunk::empty();
 
     for z in 0..chunk_length {
         let z_offset = chunk_resolution * z;
         for x in 0..chunk_length {
-            let height = grid[x + z_offset] as usize;
-            for y in 0..height {
+            let column = &columns[x + z_offset];
+            let height = column.height as i32;
+            chunk.biomes[x + z_offset] = column.biome;
+
+            for y in 0..chunk_length {
+                let world_y = y_start + y as i32;
+                let material = if world_y < height - SURFACE_DEPTH as i32 {
+                    block::STONE
+                } else if world_y < height {
+                    column.surface
+                } else if world_y < column.water_level as i32 {
+                    block::WATER
+                } else {
+                    break;
+                };
                 let y_offset = chunk_resolution * chunk_resolution * y;
-                chunk.mats.mats[y_offset + z_offset + x] = 1;
+                chunk.mats.mats[y_offset + z_offset + x] = material;
             }
         }
     }
 
+    terrain.structures.place(&mut chunk, global_pos, &|x, z| terrain.finished_column(x, z));
+
     chunk
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MatArray {
     pub mats: Vec<Gridbits>,
 }
@@ -66,81 +89,39 @@ impl MatArray {
         todo!();
     }
 }
-#[repr(C, align(16))]
-#[derive(Default)]
-struct ChunkConstant {
-    pos: Vec3,
-    cam_index: u32,
-    quad_index: u32,
-}
-
+/// The lod chunk of an octree node on the cpu, uploaded through `render::chunks::ChunkBatch` like every other chunk
 pub struct ChunkMesh {
     chunk: Chunk,
     center: Vec3,
     scale: f32,
-    quad_len: usize,
-    draw_commands: Option<Vec<BufferIndex>>,
-    chunk_constant: [ChunkConstant; 1],
 }
 
 impl ChunkMesh {
-    pub fn new_test(res: &mut BufferStorage, graphic_queue: TKQueue, cmd: vk::CommandBuffer) -> Self {
-        let chunk = Chunk::new();
-
-        let quads = mesh::mesh(&chunk.mats.mats);
-
-        let buffers = BufferBuilder::new()
-            .set_name("ChunkData-1")
-            .set_data(slice_as_u8(&quads))
-            .set_is_descriptor(true)
-            .set_queue_family(graphic_queue)
-            .set_size((quads.len() * size_of::<GPUBlock>()) as u64)
-            .set_memory(Memory::Local)
-            .set_type(BufferType::Storage)
-            .build_resource(res, cmd);
-
-        let chunk_constant = [ChunkConstant { pos: Vec3::zero(), cam_index: 0, quad_index: 0 }];
-        Self {
-            chunk,
-            center: Vec3::zero(),
-            scale: 1.0,
-            draw_commands: Some(buffers),
-            quad_len: quads.len(),
-            chunk_constant: chunk_constant,
-        }
-    }
-
-    pub unsafe fn draw(&self, device: &ash::Device, res: &BufferStorage, cmd: vk::CommandBuffer, layout: vk::PipelineLayout, cam_index: u32) {
-        let shader_index = res.get_buffer_ref(self.draw_commands.as_ref().unwrap().clone()[0]).index;
-
-        device.cmd_push_constants(
-            cmd,
-            layout,
-            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE,
-            0,
-            slice_as_u8(&self.chunk_constant),
-        );
-        device.cmd_draw(cmd, 6, self.quad_len as u32, 0, 0);
-    }
-
     pub fn new(center: Vec3, lod: usize) -> Self {
         let size = 2usize.pow(lod as u32 - 1) as f32 * CHUNK_RESOLUTION as f32 * VOXEL_SCALE;
-        let chunks = Self::generate_chunks(center - Vec3::new(size, 0.0, size), lod);
+        let terrain = TerrainSource::load(NoiseParameters::default());
+        let chunks = Self::generate_chunks(center - Vec3::new(size, size, size), lod, &terrain);
         let chunk = Self::generate_lod_chunk(lod, chunks);
 
         let target_size = CHUNK_RESOLUTION >> lod;
         let scale = target_size as f32 / CHUNK_RESOLUTION as f32;
-        Self { center, scale, chunk, draw_commands: None, quad_len: 0, chunk_constant: [ChunkConstant::default()] }
+        Self { center, scale, chunk }
     }
 
-    fn generate_chunks(bot_left: glm::Vec3, lod: usize) -> Vec<Chunk> {
+    /// The sections of the `2^(lod - 1)` cube starting at `bot_left`, sections outside the world height stay air
+    fn generate_chunks(bot_left: glm::Vec3, lod: usize, terrain: &TerrainSource) -> Vec<Chunk> {
         let chunk_amount = 2usize.pow(lod as u32 - 1);
-        let size = 2usize.pow(lod as u32 - 1) as f32 * CHUNK_RESOLUTION as f32 * VOXEL_SCALE;
         let mut chunks = vec![];
         for y in 0..chunk_amount {
             for z in 0..chunk_amount {
                 for x in 0..chunk_amount {
-                    chunks.push(generate_chunk(bot_left.x as i32, (bot_left.y - size) as i32, bot_left.z as i32, CHUNK_RESOLUTION));
+                    let offset = |i: usize| (i * CHUNK_RESOLUTION) as i32;
+                    let global_y = bot_left.y as i32 + offset(y);
+                    if !terrain.world_height.contains_section(global_y.div_euclid(CHUNK_RESOLUTION as i32)) {
+                        chunks.push(Chunk::empty());
+                        continue;
+                    }
+                    chunks.push(generate_chunk(bot_left.x as i32 + offset(x), global_y, bot_left.z as i32 + offset(z), CHUNK_RESOLUTION, terrain));
                 }
             }
         }
@@ -176,15 +157,30 @@ impl ChunkMesh {
         chunk
     }
 }
-#[derive(Debug)]
-struct Chunk {
-    mats: MatArray,
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub mats: MatArray,
+    /// One per x/z column
+    pub biomes: Vec<Biome>,
+    pub containers: Vec<Container>,
 }
 impl Chunk {
     fn new() -> Self {
         let mats = MatArray::new(CHUNK_RESOLUTION);
 
-        Self { mats }
+        Self { mats, biomes: vec![Biome::default(); CHUNK_RESOLUTION * CHUNK_RESOLUTION], containers: vec![] }
+    }
+
+    /// All air, used as the starting point for generation
+    pub fn empty() -> Self {
+        Self::filled(block::AIR, vec![Biome::default(); CHUNK_RESOLUTION * CHUNK_RESOLUTION])
+    }
+
+    /// Every block is `block`, used to expand uniform sections
+    pub fn filled(block: BlockId, biomes: Vec<Biome>) -> Self {
+        let mats = MatArray { mats: vec![block; CHUNK_RESOLUTION * CHUNK_RESOLUTION * CHUNK_RESOLUTION] };
+
+        Self { mats, biomes, containers: vec![] }
     }
 
     fn generate_lod(lod_chunk: &mut Chunk, lod_chunk_offset: usize, lod: usize, mats: &Vec<u64>) {
@@ -0,0 +1,260 @@
+use super::*;
+use biome::Biome;
+use block::BlockId;
+use chunk::{fill_chunk, Chunk, SURFACE_DEPTH};
+use generation::{preset_value, Column, TerrainSource};
+
+/// Vertical limits of the world in blocks, `None` generates as far as the terrain goes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldHeight {
+    pub min_y: Option<i32>,
+    pub max_y: Option<i32>,
+}
+
+impl WorldHeight {
+    pub const fn default() -> Self {
+        Self { min_y: Some(-64), max_y: None }
+    }
+
+    /// `min_height = <y>|unbounded` and `max_height = <y>|unbounded` from a preset, missing keys keep the default
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let limit = |key: &str, default: Option<i32>| match preset_value(text, key) {
+            None => Ok(default),
+            Some("unbounded") => Ok(None),
+            Some(value) => value.parse().map(Some).map_err(|_| format!("invalid {} '{}'", key, value)),
+        };
+
+        let height = Self { min_y: limit("min_height", Self::default().min_y)?, max_y: limit("max_height", Self::default().max_y)? };
+        if let (Some(min), Some(max)) = (height.min_y, height.max_y) {
+            if min >= max {
+                return Err(format!("min_height {} is not below max_height {}", min, max));
+            }
+        }
+        Ok(height)
+    }
+
+    /// Lowest section, rounded down so `min_y` is inside it
+    pub fn min_section(&self) -> Option<i32> {
+        self.min_y.map(|y| y.div_euclid(CHUNK_RESOLUTION as i32))
+    }
+
+    /// Highest section, rounded up so `max_y - 1` is inside it
+    pub fn max_section(&self) -> Option<i32> {
+        self.max_y.map(|y| (y - 1).div_euclid(CHUNK_RESOLUTION as i32))
+    }
+
+    pub fn contains_section(&self, section_y: i32) -> bool {
+        self.min_section().is_none_or(|min| section_y >= min) && self.max_section().is_none_or(|max| section_y <= max)
+    }
+}
+
+/// One `CHUNK_RESOLUTION` cube of a column, uniform sections store a single block
+#[derive(Clone, Debug)]
+pub enum Section {
+    Empty,
+    Uniform(BlockId),
+    Blocks(Box<Chunk>),
+}
+
+impl Section {
+    /// Collapses chunks that only contain one block and no containers
+    pub fn from_chunk(chunk: Chunk) -> Self {
+        let first = chunk.mats.mats[0];
+        if !chunk.containers.is_empty() || chunk.mats.mats.iter().any(|block| *block != first) {
+            return Section::Blocks(Box::new(chunk));
+        }
+        if first == block::AIR {
+            Section::Empty
+        } else {
+            Section::Uniform(first)
+        }
+    }
+
+    /// Block at `x + z * res + y * res * res`
+    pub fn block(&self, index: usize) -> BlockId {
+        match self {
+            Section::Empty => block::AIR,
+            Section::Uniform(block) => *block,
+            Section::Blocks(chunk) => chunk.mats.mats[index],
+        }
+    }
+
+    pub fn set_block(&mut self, index: usize, block: BlockId, biomes: &[Biome]) {
+        if self.block(index) == block {
+            return;
+        }
+        if let Section::Empty | Section::Uniform(_) = self {
+            let fill = self.block(0);
+            *self = Section::Blocks(Box::new(Chunk::filled(fill, biomes.to_vec())));
+        }
+        if let Section::Blocks(chunk) = self {
+            chunk.mats.mats[index] = block;
+        }
+    }
+
+    /// Hides everything behind it, neighbours of opaque sections don't need faces towards it
+    pub fn is_opaque(&self) -> bool {
+        matches!(self, Section::Uniform(block) if block::info(*block).solid)
+    }
+
+    /// Materials for meshing, `None` when there is nothing to mesh
+    pub fn mats(&self) -> Option<Vec<BlockId>> {
+        match self {
+            Section::Empty => None,
+            Section::Uniform(block) => Some(vec![*block; CHUNK_RESOLUTION * CHUNK_RESOLUTION * CHUNK_RESOLUTION]),
+            Section::Blocks(chunk) => Some(chunk.mats.mats.clone()),
+        }
+    }
+}
+
+/// Everything below the lowest generated section of a column without `min_y`
+const BEDROCK_SECTION: Section = Section::Uniform(block::STONE);
+const EMPTY_SECTION: Section = Section::Empty;
+
+/// A vertical stack of sections sharing the same x/z chunk position
+#[derive(Clone)]
+pub struct ChunkColumn {
+    /// Chunk coordinates, multiply by `CHUNK_RESOLUTION` for blocks
+    pub x: i32,
+    pub z: i32,
+    /// Section y of `sections[0]`
+    pub bottom: i32,
+    pub sections: Vec<Section>,
+    /// One per x/z column
+    pub biomes: Vec<Biome>,
+    /// Finished terrain columns, kept to generate sections added later
+    columns: Vec<Column>,
+    height: WorldHeight,
+}
+
+impl ChunkColumn {
+    pub fn generate(x: i32, z: i32, terrain: &TerrainSource) -> Self {
+        let res = CHUNK_RESOLUTION as i32;
+        let columns = terrain.generate_columns(x * res, z * res, CHUNK_RESOLUTION);
+        let biomes = columns.iter().map(|column| column.biome).collect();
+
+        let lowest = columns.iter().map(|column| column.height as i32).min().unwrap_or(0);
+        let highest = columns.iter().map(|column| column.height.max(column.water_level) as i32).max().unwrap_or(0);
+
+        let height = terrain.world_height;
+        let mut column = Self { x, z, bottom: 0, sections: vec![], biomes, columns, height };
+
+        // unbounded ends grow until the first section that is all stone below or all air above the terrain
+        let bottom = match height.min_section() {
+            Some(min) => min,
+            None => {
+                let mut bottom = (lowest - SURFACE_DEPTH as i32).div_euclid(res);
+                while !matches!(column.generate_section(bottom - 1, terrain), Section::Uniform(block::STONE)) {
+                    bottom -= 1;
+                }
+                bottom
+            }
+        };
+        column.bottom = bottom;
+
+        let mut section_y = bottom;
+        loop {
+            let section = column.generate_section(section_y, terrain);
+            let done = match height.max_section() {
+                Some(max) => section_y >= max,
+                None => section_y * res >= highest && matches!(section, Section::Empty),
+            };
+            // the trailing empty section of an unbounded column is implied
+            if done && height.max_y.is_none() {
+                break;
+            }
+            column.sections.push(section);
+            if done {
+                break;
+            }
+            section_y += 1;
+        }
+        column
+    }
+
+    /// Column read back from storage, sections added later are filled with stone or air instead of generated
+    pub fn from_sections(x: i32, z: i32, bottom: i32, sections: Vec<Section>, biomes: Vec<Biome>, height: WorldHeight) -> Self {
+        Self { x, z, bottom, sections, biomes, columns: vec![], height }
+    }
+
+    /// Skips filling sections that are entirely below the surface or above the terrain and water
+    fn generate_section(&self, section_y: i32, terrain: &TerrainSource) -> Section {
+        let res = CHUNK_RESOLUTION as i32;
+        let global_pos = [self.x * res, section_y * res, self.z * res];
+        let column_at = |x: i32, z: i32| terrain.finished_column(x, z);
+
+        if !terrain.structures.touches(global_pos, &column_at) {
+            let top = global_pos[1] + res;
+            if self.columns.iter().all(|column| top + SURFACE_DEPTH as i32 <= column.height as i32) {
+                return Section::Uniform(block::STONE);
+            }
+            if self.columns.iter().all(|column| global_pos[1] >= column.height.max(column.water_level) as i32) {
+                return Section::Empty;
+            }
+        }
+        Section::from_chunk(fill_chunk(&self.columns, global_pos, CHUNK_RESOLUTION, terrain))
+    }
+
+    /// Turns sections that became a single block back into empty or uniform ones
+    pub fn compact(&mut self) {
+        for section in &mut self.sections {
+            if let Section::Blocks(chunk) = section {
+                let chunk = std::mem::replace(chunk, Box::new(Chunk::empty()));
+                *section = Section::from_chunk(*chunk);
+            }
+        }
+    }
+
+    pub fn top(&self) -> i32 {
+        self.bottom + self.sections.len() as i32
+    }
+
+    /// Section at `section_y`, past the generated sections of unbounded columns it is stone below and air above
+    pub fn section(&self, section_y: i32) -> Option<&Section> {
+        if !self.height.contains_section(section_y) {
+            return None;
+        }
+        if section_y < self.bottom {
+            return Some(&BEDROCK_SECTION);
+        }
+        Some(self.sections.get((section_y - self.bottom) as usize).unwrap_or(&EMPTY_SECTION))
+    }
+
+    /// Grows unbounded columns so the section exists, `None` outside the world height
+    fn section_index(&mut self, section_y: i32) -> Option<usize> {
+        if !self.height.contains_section(section_y) {
+            return None;
+        }
+        while section_y < self.bottom {
+            self.sections.insert(0, Section::Uniform(block::STONE));
+            self.bottom -= 1;
+        }
+        while section_y >= self.top() {
+            self.sections.push(Section::Empty);
+        }
+        Some((section_y - self.bottom) as usize)
+    }
+
+    pub fn section_mut(&mut self, section_y: i32) -> Option<&mut Section> {
+        let index = self.section_index(section_y)?;
+        Some(&mut self.sections[index])
+    }
+
+    /// `x` and `z` are local to the column, `y` is global
+    pub fn block(&self, x: usize, y: i32, z: usize) -> Option<BlockId> {
+        let res = CHUNK_RESOLUTION as i32;
+        let section = self.section(y.div_euclid(res))?;
+        Some(section.block(x + z * CHUNK_RESOLUTION + y.rem_euclid(res) as usize * CHUNK_RESOLUTION * CHUNK_RESOLUTION))
+    }
+
+    /// Returns false outside the world height
+    pub fn set_block(&mut self, x: usize, y: i32, z: usize, block: BlockId) -> bool {
+        let res = CHUNK_RESOLUTION as i32;
+        let Some(section) = self.section_index(y.div_euclid(res)) else {
+            return false;
+        };
+        let index = x + z * CHUNK_RESOLUTION + y.rem_euclid(res) as usize * CHUNK_RESOLUTION * CHUNK_RESOLUTION;
+        self.sections[section].set_block(index, block, &self.biomes);
+        true
+    }
+}
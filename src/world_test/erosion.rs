@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::*;
+use rng::{hash2, Rng};
+
+const EROSION_SEED: u64 = 0x45_524F_4445;
+
+pub struct ErosionParameters {
+    /// Blocks per side of a region that gets eroded in one go
+    pub region_size: i32,
+    /// Extra blocks around a region that take part in the simulation, neighbouring regions are blended across
+    /// them so the borders stay seamless. Has to be smaller than `region_size`
+    pub overlap: i32,
+    pub hydraulic: bool,
+    /// Droplets per block
+    pub droplet_density: f32,
+    pub droplet_lifetime: u32,
+    pub inertia: f32,
+    pub sediment_capacity: f32,
+    pub min_capacity: f32,
+    pub erode_speed: f32,
+    pub deposit_speed: f32,
+    pub evaporate_speed: f32,
+    pub gravity: f32,
+    pub thermal: bool,
+    pub thermal_iterations: u32,
+    /// Max height difference between neighbours before material slides down
+    pub talus: f32,
+    pub thermal_rate: f32,
+    /// Regions kept in memory
+    pub cache_size: usize,
+}
+
+impl ErosionParameters {
+    pub const fn default() -> Self {
+        Self {
+            region_size: 256,
+            overlap: 32,
+            hydraulic: true,
+            droplet_density: 0.5,
+            droplet_lifetime: 30,
+            inertia: 0.05,
+            sediment_capacity: 4.0,
+            min_capacity: 0.01,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporate_speed: 0.01,
+            gravity: 4.0,
+            thermal: true,
+            thermal_iterations: 20,
+            talus: 1.2,
+            thermal_rate: 0.5,
+            cache_size: 64,
+        }
+    }
+}
+
+struct HeightGrid {
+    size: usize,
+    heights: Vec<f32>,
+}
+
+impl HeightGrid {
+    fn get(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.size + x]
+    }
+
+    /// Bilinear height and gradient at a point inside the grid
+    fn height_and_gradient(&self, x: f32, z: f32) -> (f32, [f32; 2]) {
+        let cx = x.floor() as usize;
+        let cz = z.floor() as usize;
+        let u = x - cx as f32;
+        let v = z - cz as f32;
+
+        let nw = self.get(cx, cz);
+        let ne = self.get(cx + 1, cz);
+        let sw = self.get(cx, cz + 1);
+        let se = self.get(cx + 1, cz + 1);
+
+        let gradient = [(ne - nw) * (1.0 - v) + (se - sw) * v, (sw - nw) * (1.0 - u) + (se - ne) * u];
+        let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+        (height, gradient)
+    }
+
+    /// Spreads `amount` over the 4 nodes around the point, negative removes material
+    fn add(&mut self, x: f32, z: f32, amount: f32) {
+        let cx = x.floor() as usize;
+        let cz = z.floor() as usize;
+        let u = x - cx as f32;
+        let v = z - cz as f32;
+        let size = self.size;
+
+        self.heights[cz * size + cx] += amount * (1.0 - u) * (1.0 - v);
+        self.heights[cz * size + cx + 1] += amount * u * (1.0 - v);
+        self.heights[(cz + 1) * size + cx] += amount * (1.0 - u) * v;
+        self.heights[(cz + 1) * size + cx + 1] += amount * u * v;
+    }
+}
+
+fn hydraulic(grid: &mut HeightGrid, parameters: &ErosionParameters, rng: &mut Rng) {
+    let size = grid.size as f32;
+    let droplets = (size * size * parameters.droplet_density) as usize;
+
+    for _ in 0..droplets {
+        let mut pos = [rng.next_f64() as f32 * (size - 2.0), rng.next_f64() as f32 * (size - 2.0)];
+        let mut dir = [0.0f32; 2];
+        let mut speed = 1.0f32;
+        let mut water = 1.0f32;
+        let mut sediment = 0.0f32;
+
+        for _ in 0..parameters.droplet_lifetime {
+            let (height, gradient) = grid.height_and_gradient(pos[0], pos[1]);
+
+            dir[0] = dir[0] * parameters.inertia - gradient[0] * (1.0 - parameters.inertia);
+            dir[1] = dir[1] * parameters.inertia - gradient[1] * (1.0 - parameters.inertia);
+            let length = (dir[0] * dir[0] + dir[1] * dir[1]).sqrt();
+            if length == 0.0 {
+                break;
+            }
+            dir = [dir[0] / length, dir[1] / length];
+
+            let old_pos = pos;
+            pos = [pos[0] + dir[0], pos[1] + dir[1]];
+            if pos[0] < 0.0 || pos[1] < 0.0 || pos[0] >= size - 1.0 || pos[1] >= size - 1.0 {
+                break;
+            }
+
+            let delta = grid.height_and_gradient(pos[0], pos[1]).0 - height;
+            let capacity = (-delta * speed * water * parameters.sediment_capacity).max(parameters.min_capacity);
+
+            if sediment > capacity || delta > 0.0 {
+                // fill the pit it just left, or drop what it can't carry
+                let deposit = if delta > 0.0 { delta.min(sediment) } else { (sediment - capacity) * parameters.deposit_speed };
+                sediment -= deposit;
+                grid.add(old_pos[0], old_pos[1], deposit);
+            } else {
+                let erode = ((capacity - sediment) * parameters.erode_speed).min(-delta);
+                sediment += erode;
+                grid.add(old_pos[0], old_pos[1], -erode);
+            }
+
+            speed = (speed * speed + delta * parameters.gravity).max(0.0).sqrt();
+            water *= 1.0 - parameters.evaporate_speed;
+        }
+    }
+}
+
+fn thermal(grid: &mut HeightGrid, parameters: &ErosionParameters) {
+    let size = grid.size;
+    let neighbours: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+    for _ in 0..parameters.thermal_iterations {
+        let mut next = grid.heights.clone();
+        for z in 1..size - 1 {
+            for x in 1..size - 1 {
+                let height = grid.get(x, z);
+                for (dx, dz) in neighbours {
+                    let nx = (x as isize + dx) as usize;
+                    let nz = (z as isize + dz) as usize;
+                    let diff = height - grid.get(nx, nz);
+                    if diff > parameters.talus {
+                        // a quarter since material can slide to all four sides
+                        let moved = (diff - parameters.talus) * parameters.thermal_rate * 0.25;
+                        next[z * size + x] -= moved;
+                        next[nz * size + nx] += moved;
+                    }
+                }
+            }
+        }
+        grid.heights = next;
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Eroded heights of one region including the overlap, `size` per side
+struct ErodedRegion {
+    size: i32,
+    heights: Vec<f32>,
+}
+
+pub struct Erosion {
+    parameters: ErosionParameters,
+    seed: u64,
+    cache: Mutex<HashMap<(i32, i32), Arc<ErodedRegion>>>,
+}
+
+impl Erosion {
+    pub fn new(seed: u64, parameters: ErosionParameters) -> Self {
+        Self { parameters, seed, cache: Mutex::new(HashMap::new()) }
+    }
+
+    fn erode_region(&self, region_x: i32, region_z: i32, raw_height: &dyn Fn(i32, i32) -> f64) -> ErodedRegion {
+        let parameters = &self.parameters;
+        let region_size = parameters.region_size;
+        let overlap = parameters.overlap;
+        let size = (region_size + overlap * 2) as usize;
+
+        let start_x = region_x * region_size - overlap;
+        let start_z = region_z * region_size - overlap;
+
+        let mut heights = Vec::with_capacity(size * size);
+        for z in 0..size as i32 {
+            for x in 0..size as i32 {
+                heights.push(raw_height(start_x + x, start_z + z) as f32);
+            }
+        }
+
+        let mut grid = HeightGrid { size, heights };
+        let mut rng = Rng::new(hash2(self.seed ^ EROSION_SEED, region_x, region_z));
+        if parameters.hydraulic {
+            hydraulic(&mut grid, parameters, &mut rng);
+        }
+        if parameters.thermal {
+            thermal(&mut grid, parameters);
+        }
+        ErodedRegion { size: size as i32, heights: grid.heights }
+    }
+
+    /// Weight of a region's result `distance` blocks inside the edge of its simulated area. Zero at the edge where
+    /// droplets leave the grid, full from twice the overlap on, so two neighbours cross-fade over their overlap.
+    fn blend_weight(&self, distance: i32) -> f32 {
+        smoothstep(0.0, (self.parameters.overlap * 2).max(1) as f32, distance as f32 + 0.5)
+    }
+
+    fn region(&self, region_x: i32, region_z: i32, raw_height: &dyn Fn(i32, i32) -> f64) -> Arc<ErodedRegion> {
+        if let Some(region) = self.cache.lock().unwrap().get(&(region_x, region_z)) {
+            return region.clone();
+        }
+
+        // erosion is deterministic, so two threads racing on the same region get the same result
+        let region = Arc::new(self.erode_region(region_x, region_z, raw_height));
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.parameters.cache_size {
+            cache.clear();
+        }
+        cache.insert((region_x, region_z), region.clone());
+        region
+    }
+
+    /// `raw_height` is the continuous terrain height before erosion. Near region borders the eroded regions whose
+    /// overlap covers the column are blended
+    pub fn height(&self, global_x: i32, global_z: i32, raw_height: &dyn Fn(i32, i32) -> f64) -> f64 {
+        let region_size = self.parameters.region_size;
+        let overlap = self.parameters.overlap;
+        // regions whose simulated area, the region plus the overlap, contains the coordinate
+        let covering = |v: i32| (v - overlap).div_euclid(region_size)..=(v + overlap).div_euclid(region_size);
+
+        let mut height = 0.0;
+        let mut total = 0.0;
+        for region_z in covering(global_z) {
+            for region_x in covering(global_x) {
+                let region = self.region(region_x, region_z, raw_height);
+                let x = global_x - (region_x * region_size - overlap);
+                let z = global_z - (region_z * region_size - overlap);
+                let weight = self.blend_weight(x.min(region.size - 1 - x)) * self.blend_weight(z.min(region.size - 1 - z));
+
+                height += region.heights[(z * region.size + x) as usize] * weight;
+                total += weight;
+            }
+        }
+        (height / total) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_parameters() -> ErosionParameters {
+        ErosionParameters { region_size: 32, overlap: 8, thermal_iterations: 5, ..ErosionParameters::default() }
+    }
+
+    fn hills(x: i32, z: i32) -> f64 {
+        (x as f64 * 0.15).sin() * 6.0 + (z as f64 * 0.1).cos() * 4.0 + x as f64 * 0.05
+    }
+
+    #[test]
+    fn blending_keeps_uneroded_heights() {
+        let parameters = ErosionParameters { hydraulic: false, thermal: false, ..small_parameters() };
+        let erosion = Erosion::new(1, parameters);
+        for z in -40..40 {
+            for x in -40..40 {
+                assert!((erosion.height(x, z, &hills) - hills(x, z)).abs() < 1e-4, "{} {}", x, z);
+            }
+        }
+    }
+
+    #[test]
+    fn borders_are_eroded_and_seamless() {
+        let erosion = Erosion::new(1, small_parameters());
+        let eroded = |x: i32, z: i32| erosion.height(x, z, &hills);
+
+        // the border column is eroded like any other instead of falling back to the raw height
+        let changed = (-64..64).filter(|&z| (eroded(32, z) - hills(32, z)).abs() > 1e-3).count();
+        assert!(changed > 64, "{} border columns eroded", changed);
+
+        let step = |x: i32, z: i32| ((eroded(x + 1, z) - hills(x + 1, z)) - (eroded(x, z) - hills(x, z))).abs();
+        let inside = (-64..64).map(|z| step(16, z)).fold(0.0, f64::max);
+        let across = (-64..64).map(|z| step(31, z)).fold(0.0, f64::max);
+        assert!(across <= inside * 2.0, "step across the border {} vs inside {}", across, inside);
+    }
+}
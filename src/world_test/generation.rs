@@ -1,41 +1,271 @@
-use libnoise::{Generator, Source};
+use libnoise::Generator;
 use voxelengine::terrain::Chunk;
 
 use super::*;
+use biome::Biome;
+use block::BlockId;
+use column::WorldHeight;
+use erosion::{Erosion, ErosionParameters};
+use heightmap::{HeightmapImport, HeightmapSettings};
+use noise_graph::{CompiledNoise, NoiseGraph};
+use structure::{Structures, STRUCTURES_PATH};
+use water::{Water, WaterParameters};
+
+pub const TERRAIN_GRAPH_PATH: &str = "assets/terrain.noise";
+/// Optional, `mode = heightmap` switches generation to an imported image, `erosion = true` enables erosion,
+/// `min_height`/`max_height` limit the world vertically
+pub const GENERATOR_PRESET_PATH: &str = "assets/generator.preset";
+
+const CLIMATE_GRAPH: &str = "climate = simplex\n";
+const TEMPERATURE_SEED: u64 = 0x5445_4D50;
+const HUMIDITY_SEED: u64 = 0x4855_4D49;
+/// Blocks per unit of climate noise, a biome spans a few chunks
+const CLIMATE_SCALE: f64 = 256.0;
+/// Blocks above sea level where the noise terrain turns into mountains
+const MOUNTAIN_HEIGHT: u32 = 12;
+/// Blocks above sea level that are still beach
+const BEACH_HEIGHT: u32 = 1;
+
+/// Value of a `key = value` line in a preset file
+pub fn preset_value<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines().filter_map(|line| line.split('#').next().unwrap().split_once('=')).find(|(k, _)| k.trim() == key).map(|(_, value)| value.trim())
+}
 
 pub struct NoiseParameters {
     amplitude: f64,
     seed: u32,
     scale: [f64; 2],
     hill_effect: f64,
+    sea_level: u32,
 }
 
 impl NoiseParameters {
     pub const fn default() -> Self {
-        Self { amplitude: 10.0, seed: 51251351, scale: [0.2, 0.2], hill_effect: 15.0 }
+        Self::with_seed(51251351)
+    }
+
+    pub const fn with_seed(seed: u32) -> Self {
+        Self { amplitude: 10.0, seed, scale: [0.2, 0.2], hill_effect: 15.0, sea_level: 6 }
+    }
+
+    pub const fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// The old hardcoded `simplex(seed).add(1.0).scale(scale)` chain as a graph. Terrain still differs from before
+    /// the graph for the same seed, columns are now sampled at their global position (see `noise_height`).
+    pub fn default_graph(&self) -> NoiseGraph {
+        let text = format!("terrain = simplex\nshifted = add source=terrain value=1.0\nscaled = scale source=shifted x={} z={}\n", self.scale[0], self.scale[1]);
+        NoiseGraph::parse(&text).unwrap()
+    }
+
+    /// Compiles the terrain graph from `TERRAIN_GRAPH_PATH`, falls back to the default graph
+    pub fn load_generator(&self) -> CompiledNoise {
+        let graph = if std::path::Path::new(TERRAIN_GRAPH_PATH).exists() {
+            match NoiseGraph::load(TERRAIN_GRAPH_PATH) {
+                Ok(graph) => graph,
+                Err(e) => {
+                    log::warn!("{}, using the default terrain graph", e);
+                    self.default_graph()
+                }
+            }
+        } else {
+            self.default_graph()
+        };
+        graph.compile(self.seed as u64)
     }
 }
 
-pub fn generate_height_map(global_x: i32, global_z: i32, chunk_resolution: usize, parameters: &NoiseParameters) -> Vec<u32> {
+pub fn generate_height_map(global_x: i32, global_z: i32, chunk_resolution: usize, parameters: &NoiseParameters, generator: &impl Generator<2>) -> Vec<u32> {
     let x_start = global_x;
     let z_start = global_z;
     let chunk_length = CHUNK_RESOLUTION;
 
     let mut grid = vec![0u32; chunk_length * chunk_length];
-    let amplitude = parameters.amplitude;
-    let seed = parameters.seed;
-    let hill_effect = parameters.hill_effect;
-    let scale = parameters.scale;
-
-    let generator = Source::simplex(seed as u64).add(1.0).scale(scale);
 
     for z in 0..chunk_length {
         let z_offset = chunk_length * z;
         for x in 0..chunk_length {
-            let nx = (x as f64 + x_start as f64) / chunk_length as f64;
-            let nz = (z_offset as f64 + z_start as f64) / chunk_length as f64;
-            grid[(z_offset + x as usize) as usize] = (((generator.sample([nx as f64, nz as f64]) * hill_effect).round() / hill_effect) * amplitude).round() as u32;
+            grid[(z_offset + x as usize) as usize] = noise_height(x_start + x as i32, z_start + z as i32, parameters, generator);
         }
     }
     grid
 }
+
+/// Height of a single column, only depends on the global position so chunk borders line up
+pub fn noise_height(global_x: i32, global_z: i32, parameters: &NoiseParameters, generator: &impl Generator<2>) -> u32 {
+    let amplitude = parameters.amplitude;
+    let hill_effect = parameters.hill_effect;
+
+    let nx = global_x as f64 / CHUNK_RESOLUTION as f64;
+    let nz = global_z as f64 / CHUNK_RESOLUTION as f64;
+    (((generator.sample([nx, nz]) * hill_effect).round() / hill_effect) * amplitude).round() as u32
+}
+
+/// Height without the `hill_effect` steps, input for erosion
+pub fn noise_raw_height(global_x: i32, global_z: i32, parameters: &NoiseParameters, generator: &impl Generator<2>) -> f64 {
+    let nx = global_x as f64 / CHUNK_RESOLUTION as f64;
+    let nz = global_z as f64 / CHUNK_RESOLUTION as f64;
+    generator.sample([nx, nz]) * parameters.amplitude
+}
+
+/// Generated data of one x/z column in a chunk
+#[derive(Clone, Copy, Debug)]
+pub struct Column {
+    pub height: u32,
+    pub surface: BlockId,
+    pub biome: Biome,
+    /// Water fills `height..water_level`
+    pub water_level: u32,
+}
+
+/// Temperature and humidity noise, picks the biomes of the noise generator
+pub struct Climate {
+    temperature: CompiledNoise,
+    humidity: CompiledNoise,
+}
+
+impl Climate {
+    pub fn new(seed: u64) -> Self {
+        let graph = NoiseGraph::parse(CLIMATE_GRAPH).unwrap();
+        Self { temperature: graph.compile(seed ^ TEMPERATURE_SEED), humidity: graph.compile(seed ^ HUMIDITY_SEED) }
+    }
+
+    /// Biome of a column from its height before water is applied, the climate only decides on land
+    pub fn biome(&self, global_x: i32, global_z: i32, height: u32, sea_level: u32) -> Biome {
+        if height < sea_level {
+            return Biome::Ocean;
+        }
+        if height <= sea_level + BEACH_HEIGHT {
+            return Biome::Beach;
+        }
+        if height > sea_level + MOUNTAIN_HEIGHT {
+            return Biome::Mountains;
+        }
+        let position = [global_x as f64 / CLIMATE_SCALE, global_z as f64 / CLIMATE_SCALE];
+        let (temperature, humidity) = (self.temperature.sample(position), self.humidity.sample(position));
+        if temperature < -0.35 {
+            Biome::Tundra
+        } else if temperature > 0.35 && humidity < 0.0 {
+            Biome::Desert
+        } else if humidity > 0.15 {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+}
+
+pub enum HeightSource {
+    Noise(CompiledNoise, Climate),
+    Heightmap(HeightmapImport),
+}
+
+impl HeightSource {
+    pub fn column(&self, global_x: i32, global_z: i32, parameters: &NoiseParameters) -> Column {
+        match self {
+            HeightSource::Noise(generator, climate) => {
+                let height = noise_height(global_x, global_z, parameters, generator);
+                let biome = climate.biome(global_x, global_z, height, parameters.sea_level);
+                Column { height, surface: biome.surface_block(), biome, water_level: 0 }
+            }
+            HeightSource::Heightmap(heightmap) => heightmap.column(global_x, global_z),
+        }
+    }
+
+    pub fn raw_height(&self, global_x: i32, global_z: i32, parameters: &NoiseParameters) -> f64 {
+        match self {
+            HeightSource::Noise(generator, _) => noise_raw_height(global_x, global_z, parameters, generator),
+            HeightSource::Heightmap(heightmap) => heightmap.raw_height(global_x, global_z),
+        }
+    }
+}
+
+pub struct TerrainSource {
+    pub parameters: NoiseParameters,
+    pub height: HeightSource,
+    pub erosion: Option<Erosion>,
+    pub water: Water,
+    pub structures: Structures,
+    pub world_height: WorldHeight,
+}
+
+impl TerrainSource {
+    /// Uses the preset at `GENERATOR_PRESET_PATH` if there is one
+    pub fn load(parameters: NoiseParameters) -> Self {
+        let text = std::fs::read_to_string(GENERATOR_PRESET_PATH).unwrap_or_default();
+        Self::from_preset(parameters, &text)
+    }
+
+    /// An empty preset generates from the noise graph without erosion
+    pub fn from_preset(parameters: NoiseParameters, text: &str) -> Self {
+        let mut water_parameters = WaterParameters::default();
+        water_parameters.sea_level = parameters.sea_level;
+
+        let path = std::path::Path::new(GENERATOR_PRESET_PATH);
+
+        let erosion = match preset_value(text, "erosion") {
+            Some("true") => Some(Erosion::new(parameters.seed as u64, ErosionParameters::default())),
+            _ => None,
+        };
+
+        let mut height = None;
+        if preset_value(text, "mode") == Some("heightmap") {
+            let base = path.parent().unwrap();
+            match HeightmapSettings::parse(text, base).and_then(|settings| HeightmapImport::load(settings).map_err(|e| e.to_string())) {
+                Ok(heightmap) => {
+                    water_parameters.sea_level = heightmap.sea_level();
+                    height = Some(HeightSource::Heightmap(heightmap));
+                }
+                Err(e) => log::warn!("{}: {}, using the noise generator", GENERATOR_PRESET_PATH, e),
+            }
+        }
+        let height = height.unwrap_or_else(|| HeightSource::Noise(parameters.load_generator(), Climate::new(parameters.seed as u64)));
+
+        let structures = if std::path::Path::new(STRUCTURES_PATH).exists() {
+            Structures::load(STRUCTURES_PATH, parameters.seed as u64).unwrap_or_else(|e| {
+                log::warn!("{}, generating without structures", e);
+                Structures::empty(parameters.seed as u64)
+            })
+        } else {
+            Structures::empty(parameters.seed as u64)
+        };
+
+        let world_height = WorldHeight::parse(text).unwrap_or_else(|e| {
+            log::warn!("{}: {}, using the default world height", GENERATOR_PRESET_PATH, e);
+            WorldHeight::default()
+        });
+
+        let water = Water::new(parameters.seed as u64, water_parameters);
+        Self { parameters, height, erosion, water, structures, world_height }
+    }
+
+    /// Column before water is applied
+    pub fn column(&self, global_x: i32, global_z: i32) -> Column {
+        let mut column = self.height.column(global_x, global_z, &self.parameters);
+        if let Some(erosion) = &self.erosion {
+            let raw_height = |x: i32, z: i32| self.height.raw_height(x, z, &self.parameters);
+            column.height = erosion.height(global_x, global_z, &raw_height).round().max(0.0) as u32;
+        }
+        column
+    }
+
+    /// Column with rivers, lakes and sea applied
+    pub fn finished_column(&self, global_x: i32, global_z: i32) -> Column {
+        let height_at = |x: i32, z: i32| self.column(x, z).height;
+        let mut column = self.column(global_x, global_z);
+        self.water.apply(global_x, global_z, &mut column, &height_at);
+        column
+    }
+
+    /// Columns of a chunk, indexed by `x + z * chunk_resolution`
+    pub fn generate_columns(&self, global_x: i32, global_z: i32, chunk_resolution: usize) -> Vec<Column> {
+        let mut columns = Vec::with_capacity(chunk_resolution * chunk_resolution);
+        for z in 0..chunk_resolution {
+            for x in 0..chunk_resolution {
+                columns.push(self.finished_column(global_x + x as i32, global_z + z as i32));
+            }
+        }
+        columns
+    }
+}
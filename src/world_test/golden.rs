@@ -0,0 +1,167 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use super::*;
+use chunk::Chunk;
+use column::{ChunkColumn, Section};
+use generation::{NoiseParameters, TerrainSource};
+
+pub const GOLDEN_PATH: &str = "golden/worldgen.txt";
+const USAGE: &str = "usage: golden <check|refresh>";
+
+const SEEDS: [u32; 3] = [51251351, 1, 987654321];
+/// Presets on top of the committed assets, a local `generator.preset` is ignored
+const PRESETS: [(&str, &str); 2] = [("default", ""), ("erosion", "erosion = true")];
+const CHUNKS: [[i32; 2]; 6] = [[0, 0], [1, 0], [0, 1], [-1, -1], [5, -3], [-12, 9]];
+
+/// FNV-1a, unlike `DefaultHasher` it is the same on every rust version and platform
+struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    fn new() -> Self {
+        Self { state: 0xcbf2_9ce4_8422_2325 }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u64;
+            self.state = self.state.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write_i32(value.len() as i32);
+        self.write(value.as_bytes());
+    }
+}
+
+/// Hash of the materials plus the metadata, stable across runs and machines
+pub fn chunk_hash(chunk: &Chunk) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(&chunk.mats.mats);
+    hasher.write(&chunk.biomes.iter().map(|biome| *biome as u8).collect::<Vec<u8>>());
+
+    hasher.write_i32(chunk.containers.len() as i32);
+    for container in &chunk.containers {
+        container.pos.iter().for_each(|v| hasher.write_i32(*v));
+        hasher.write_i32(container.items.len() as i32);
+        for stack in &container.items {
+            hasher.write_str(&stack.item);
+            hasher.write_i32(stack.count as i32);
+        }
+    }
+    hasher.state
+}
+
+/// Every section of the column, uniform sections hash like the chunk they stand for
+pub fn column_hash(column: &ChunkColumn) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write_i32(column.bottom);
+    for section in &column.sections {
+        let hash = match section {
+            Section::Blocks(chunk) => chunk_hash(chunk),
+            _ => chunk_hash(&Chunk::filled(section.block(0), column.biomes.clone())),
+        };
+        hasher.write(&hash.to_le_bytes());
+    }
+    hasher.state
+}
+
+/// `"<seed> <preset> <chunk x> <chunk z>"` to hash
+fn generate_hashes() -> BTreeMap<String, u64> {
+    let mut hashes = BTreeMap::new();
+    for seed in SEEDS {
+        for (preset_name, preset) in PRESETS {
+            let terrain = TerrainSource::from_preset(NoiseParameters::with_seed(seed), preset);
+            for [chunk_x, chunk_z] in CHUNKS {
+                let column = ChunkColumn::generate(chunk_x, chunk_z, &terrain);
+                hashes.insert(format!("{} {} {} {}", seed, preset_name, chunk_x, chunk_z), column_hash(&column));
+            }
+        }
+    }
+    hashes
+}
+
+fn read_golden() -> Result<BTreeMap<String, u64>, String> {
+    let text = std::fs::read_to_string(GOLDEN_PATH).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("{} is missing, run `golden refresh` and commit it", GOLDEN_PATH),
+        _ => format!("{}: {}", GOLDEN_PATH, e),
+    })?;
+    let mut hashes = BTreeMap::new();
+    for line in text.lines().filter(|line| !line.starts_with('#') && !line.trim().is_empty()) {
+        let (key, hash) = line.rsplit_once(' ').ok_or_else(|| format!("invalid line '{}'", line))?;
+        let hash = u64::from_str_radix(hash, 16).map_err(|_| format!("invalid hash in '{}'", line))?;
+        hashes.insert(key.to_owned(), hash);
+    }
+    Ok(hashes)
+}
+
+fn write_golden(hashes: &BTreeMap<String, u64>) {
+    let mut text = String::from("# <seed> <preset> <chunk x> <chunk z> <hash>, written by `golden refresh`\n");
+    for (key, hash) in hashes {
+        writeln!(text, "{} {:016x}", key, hash).unwrap();
+    }
+    std::fs::create_dir_all(std::path::Path::new(GOLDEN_PATH).parent().unwrap()).unwrap();
+    std::fs::write(GOLDEN_PATH, text).unwrap();
+}
+
+/// Compares freshly generated chunks against the golden file, returns the mismatching keys
+pub fn check() -> Result<Vec<String>, String> {
+    let golden = read_golden()?;
+    let hashes = generate_hashes();
+
+    let mut mismatches = vec![];
+    for (key, hash) in &hashes {
+        match golden.get(key) {
+            Some(expected) if expected == hash => {}
+            Some(expected) => mismatches.push(format!("{}: expected {:016x}, got {:016x}", key, expected, hash)),
+            None => mismatches.push(format!("{}: missing from {}", key, GOLDEN_PATH)),
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Entry point for `vulkanrust golden ...`, exits with 1 when generation changed
+pub fn run(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("check") => match check() {
+            Ok(mismatches) if mismatches.is_empty() => println!("worldgen matches {}", GOLDEN_PATH),
+            Ok(mismatches) => {
+                for mismatch in &mismatches {
+                    eprintln!("{}", mismatch);
+                }
+                eprintln!("{} columns changed, run `golden refresh` if that is intended", mismatches.len());
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Some("refresh") => {
+            let hashes = generate_hashes();
+            write_golden(&hashes);
+            println!("wrote {} hashes to {}", hashes.len(), GOLDEN_PATH);
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worldgen_matches_golden() {
+        let mismatches = check().unwrap_or_else(|e| panic!("{}", e));
+        assert!(mismatches.is_empty(), "{} columns changed, run `golden refresh` if that is intended:\n{}", mismatches.len(), mismatches.join("\n"));
+    }
+}
@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    ImageError, ImageResult,
+};
+
+use super::{
+    biome::Biome,
+    block::{self, BlockId, BLOCKS},
+    generation::Column,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeMode {
+    /// Repeat the image
+    Tile,
+    /// Keep using the border pixels
+    Clamp,
+}
+
+#[derive(Clone, Debug)]
+pub struct HeightmapSettings {
+    pub image: PathBuf,
+    /// Rgb image, each pixel is matched to the closest block color
+    pub materials: Option<PathBuf>,
+    /// Rgb image, each pixel is matched to the closest biome color
+    pub biomes: Option<PathBuf>,
+    /// Blocks between a black and a white pixel
+    pub height_scale: f64,
+    /// Block height of `sea_level_value`
+    pub sea_level: i32,
+    /// Pixel value in [0, 1] that lies at sea level
+    pub sea_level_value: f64,
+    /// Blocks per pixel, horizontally
+    pub blocks_per_pixel: f64,
+    pub edge: EdgeMode,
+}
+
+impl HeightmapSettings {
+    pub fn new(image: PathBuf) -> Self {
+        Self { image, materials: None, biomes: None, height_scale: 64.0, sea_level: 16, sea_level_value: 0.0, blocks_per_pixel: 1.0, edge: EdgeMode::Clamp }
+    }
+
+    /// Reads `key = value` lines, paths are relative to `base`. The preset is shared with the rest of generation,
+    /// keys this doesn't know belong to them and are skipped.
+    pub fn parse(text: &str, base: &Path) -> Result<Self, String> {
+        let mut settings = Self::new(PathBuf::new());
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| format!("expected key = value, got '{}'", line))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            let float = || value.parse::<f64>().map_err(|_| format!("invalid number {} = {}", key, value));
+            match key {
+                "image" => settings.image = base.join(value),
+                "materials" => settings.materials = Some(base.join(value)),
+                "biomes" => settings.biomes = Some(base.join(value)),
+                "height_scale" => settings.height_scale = float()?,
+                "sea_level" => settings.sea_level = float()? as i32,
+                "sea_level_value" => settings.sea_level_value = float()?,
+                "blocks_per_pixel" => settings.blocks_per_pixel = float()?,
+                "edge" => {
+                    settings.edge = match value {
+                        "tile" => EdgeMode::Tile,
+                        "clamp" => EdgeMode::Clamp,
+                        _ => return Err(format!("unknown edge mode '{}'", value)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if settings.image.as_os_str().is_empty() {
+            return Err("heightmap preset needs an image".to_owned());
+        }
+        Ok(settings)
+    }
+}
+
+/// Sampling clamps to the last pixel, an image without pixels has none
+fn non_empty(path: &Path, width: u32, height: u32) -> ImageResult<()> {
+    if width == 0 || height == 0 {
+        let message = format!("{} has no pixels", path.display());
+        return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(message))));
+    }
+    Ok(())
+}
+
+struct Mask {
+    width: u32,
+    height: u32,
+    /// Already mapped to block or biome ids
+    values: Vec<u8>,
+}
+
+impl Mask {
+    fn load(path: &Path, colors: &[Option<[u8; 3]>]) -> ImageResult<Self> {
+        let image = image::open(path)?.to_rgb8();
+        non_empty(path, image.width(), image.height())?;
+        let values = image.pixels().map(|pixel| closest_color(pixel.0, colors) as u8).collect();
+        Ok(Self { width: image.width(), height: image.height(), values })
+    }
+}
+
+/// `None` entries are never picked
+pub fn closest_color(color: [u8; 3], colors: &[Option<[u8; 3]>]) -> usize {
+    let distance = |other: &Option<[u8; 3]>| match other {
+        Some(other) => (0..3).map(|i| (color[i] as i32 - other[i] as i32).pow(2)).sum::<i32>(),
+        None => i32::MAX,
+    };
+    (0..colors.len()).min_by_key(|i| distance(&colors[*i])).unwrap_or(0)
+}
+
+pub struct HeightmapImport {
+    settings: HeightmapSettings,
+    width: u32,
+    height: u32,
+    /// Normalized to [0, 1]
+    heights: Vec<f32>,
+    materials: Option<Mask>,
+    biomes: Option<Mask>,
+}
+
+impl HeightmapImport {
+    /// 8 and 16 bit grayscale images are both read as 16 bit
+    pub fn load(settings: HeightmapSettings) -> ImageResult<Self> {
+        let image = image::open(&settings.image)?.to_luma16();
+        non_empty(&settings.image, image.width(), image.height())?;
+        let heights = image.pixels().map(|pixel| pixel.0[0] as f32 / u16::MAX as f32).collect();
+
+        // air and water can't be painted
+        let block_colors: Vec<Option<[u8; 3]>> = BLOCKS.iter().map(|block| block.solid.then_some(block.color)).collect();
+        let biome_colors: Vec<Option<[u8; 3]>> = Biome::ALL.iter().map(|biome| Some(biome.color())).collect();
+
+        let materials = match &settings.materials {
+            Some(path) => Some(Mask::load(path, &block_colors)?),
+            None => None,
+        };
+        let biomes = match &settings.biomes {
+            Some(path) => Some(Mask::load(path, &biome_colors)?),
+            None => None,
+        };
+
+        Ok(Self { width: image.width(), height: image.height(), heights, materials, biomes, settings })
+    }
+
+    fn wrap(&self, coord: i64, size: u32) -> u32 {
+        match self.settings.edge {
+            EdgeMode::Tile => coord.rem_euclid(size as i64) as u32,
+            EdgeMode::Clamp => coord.clamp(0, size as i64 - 1) as u32,
+        }
+    }
+
+    /// `x` and `z` are in heightmap pixels, masks can have another resolution than the heightmap
+    fn sample_mask(&self, mask: &Mask, x: f64, z: f64) -> u8 {
+        let mx = self.wrap((x * mask.width as f64 / self.width as f64).floor() as i64, mask.width);
+        let mz = self.wrap((z * mask.height as f64 / self.height as f64).floor() as i64, mask.height);
+        mask.values[(mz * mask.width + mx) as usize]
+    }
+
+    /// Bilinear, so zoomed in heightmaps don't turn into steps
+    fn sample_height(&self, x: f64, z: f64) -> f64 {
+        let x0 = x.floor();
+        let z0 = z.floor();
+        let tx = x - x0;
+        let tz = z - z0;
+
+        let get = |x: i64, z: i64| {
+            let x = self.wrap(x, self.width);
+            let z = self.wrap(z, self.height);
+            self.heights[(z * self.width + x) as usize] as f64
+        };
+
+        let (x0, z0) = (x0 as i64, z0 as i64);
+        let top = get(x0, z0) * (1.0 - tx) + get(x0 + 1, z0) * tx;
+        let bottom = get(x0, z0 + 1) * (1.0 - tx) + get(x0 + 1, z0 + 1) * tx;
+        top * (1.0 - tz) + bottom * tz
+    }
+
+    pub fn sea_level(&self) -> u32 {
+        self.settings.sea_level.max(0) as u32
+    }
+
+    pub fn raw_height(&self, global_x: i32, global_z: i32) -> f64 {
+        let value = self.sample_height(global_x as f64 / self.settings.blocks_per_pixel, global_z as f64 / self.settings.blocks_per_pixel);
+        self.settings.sea_level as f64 + (value - self.settings.sea_level_value) * self.settings.height_scale
+    }
+
+    pub fn column(&self, global_x: i32, global_z: i32) -> Column {
+        let x = global_x as f64 / self.settings.blocks_per_pixel;
+        let z = global_z as f64 / self.settings.blocks_per_pixel;
+        let height = self.raw_height(global_x, global_z);
+
+        let biome = match &self.biomes {
+            Some(mask) => Biome::from(self.sample_mask(mask, x, z)),
+            None => Biome::default(),
+        };
+        let surface: BlockId = match &self.materials {
+            Some(mask) => self.sample_mask(mask, x, z),
+            None if self.biomes.is_some() => biome.surface_block(),
+            None => block::STONE,
+        };
+
+        Column { height: height.round().max(0.0) as u32, surface, biome, water_level: 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_test::column::WorldHeight;
+
+    const EXAMPLE_PRESET: &str = "assets/generator.preset.example";
+
+    #[test]
+    fn example_preset_parses() {
+        let text = std::fs::read_to_string(EXAMPLE_PRESET).unwrap();
+        let base = Path::new(EXAMPLE_PRESET).parent().unwrap();
+        let settings = HeightmapSettings::parse(&text, base).unwrap();
+        assert_eq!(settings.image, base.join("heightmaps/terrain.png"));
+        assert_eq!(settings.edge, EdgeMode::Clamp);
+        let height = WorldHeight::parse(&text).unwrap();
+        assert_eq!((height.min_y, height.max_y), (Some(-64), None));
+    }
+
+    #[test]
+    fn skips_keys_of_other_consumers() {
+        let settings = HeightmapSettings::parse("mode = heightmap\nimage = a.png\nerosion = true\nmin_height = 0\nsomething_new = 1\nheight_scale = 32\n", Path::new("")).unwrap();
+        assert_eq!(settings.height_scale, 32.0);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let error = |text: &str| HeightmapSettings::parse(text, Path::new("")).unwrap_err();
+        assert_eq!(error("mode = heightmap\n"), "heightmap preset needs an image");
+        assert_eq!(error("image = a.png\nheight_scale = tall\n"), "invalid number height_scale = tall");
+        assert_eq!(error("image = a.png\nedge = wrap\n"), "unknown edge mode 'wrap'");
+        assert_eq!(error("image = a.png\nheightmap\n"), "expected key = value, got 'heightmap'");
+    }
+}
@@ -0,0 +1,95 @@
+use super::*;
+use rng::Rng;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+/// A container placed by generation, `pos` is in global block coordinates
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Container {
+    pub pos: [i32; 3],
+    pub items: Vec<ItemStack>,
+}
+
+#[derive(Clone, Debug)]
+struct LootEntry {
+    item: String,
+    weight: u32,
+    count: [u32; 2],
+}
+
+#[derive(Clone, Debug)]
+pub struct LootTable {
+    rolls: [u32; 2],
+    entries: Vec<LootEntry>,
+}
+
+fn parse_range(text: &str) -> Result<[u32; 2], String> {
+    let parse = |n: &str| n.parse::<u32>().map_err(|_| format!("invalid number '{}'", n));
+    match text.split_once('-') {
+        Some((min, max)) => Ok([parse(min)?, parse(max)?]),
+        None => {
+            let n = parse(text)?;
+            Ok([n, n])
+        }
+    }
+}
+
+impl LootTable {
+    /// `rolls=<min>-<max> <item>:<weight>:<min>-<max> ...`
+    pub fn parse(words: &[&str]) -> Result<Self, String> {
+        let mut rolls = [1, 1];
+        let mut entries = vec![];
+
+        for word in words {
+            if let Some(range) = word.strip_prefix("rolls=") {
+                rolls = parse_range(range)?;
+                continue;
+            }
+
+            let parts: Vec<&str> = word.split(':').collect();
+            if parts.len() != 3 {
+                return Err(format!("expected <item>:<weight>:<count>, got '{}'", word));
+            }
+            let weight = parts[1].parse().map_err(|_| format!("invalid weight '{}'", parts[1]))?;
+            entries.push(LootEntry { item: parts[0].to_owned(), weight, count: parse_range(parts[2])? });
+        }
+
+        Ok(Self { rolls, entries })
+    }
+
+    pub fn fill(&self, rng: &mut Rng) -> Vec<ItemStack> {
+        let total_weight: u32 = self.entries.iter().map(|entry| entry.weight).sum();
+        if total_weight == 0 {
+            return vec![];
+        }
+
+        let rolls = rng.range(self.rolls[0] as i32, self.rolls[1] as i32 + 1);
+        let mut items: Vec<ItemStack> = vec![];
+
+        for _ in 0..rolls {
+            let mut pick = (rng.next_u64() % total_weight as u64) as u32;
+            let entry = self
+                .entries
+                .iter()
+                .find(|entry| {
+                    if pick < entry.weight {
+                        return true;
+                    }
+                    pick -= entry.weight;
+                    false
+                })
+                .unwrap();
+
+            let count = rng.range(entry.count[0] as i32, entry.count[1] as i32 + 1) as u32;
+            match items.iter_mut().find(|stack| stack.item == entry.item) {
+                Some(stack) => stack.count += count,
+                None => items.push(ItemStack { item: entry.item.clone(), count }),
+            }
+        }
+        items
+    }
+}
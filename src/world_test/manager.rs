@@ -0,0 +1,313 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::*;
+use autosave::{AutoSave, BackupPolicy, SaveResult};
+use biome::Biome;
+use block::BlockId;
+use column::{ChunkColumn, Section};
+use loot::Container;
+use generation::TerrainSource;
+use mesh::GPUQuad;
+use region::RegionStorage;
+use visibility::FaceConnectivity;
+
+/// Loaded columns of the world, addressed in chunk coordinates
+pub struct ChunkManager {
+    pub terrain: TerrainSource,
+    columns: HashMap<[i32; 2], ChunkColumn>,
+    /// Sections whose mesh is out of date, `[x, section y, z]`
+    remesh: HashSet<[i32; 3]>,
+    /// Saved columns are loaded instead of generated, `None` keeps everything in memory
+    storage: Option<Arc<Mutex<RegionStorage>>>,
+    /// Columns changed since they were generated or loaded, unchanged ones are generated again
+    modified: HashSet<[i32; 2]>,
+    /// Saves in the background once started, otherwise saving blocks
+    autosave: Option<AutoSave>,
+    /// Face connectivity of meshed sections, see `visible_sections`
+    connectivity: HashMap<[i32; 3], FaceConnectivity>,
+}
+
+impl ChunkManager {
+    pub fn new(terrain: TerrainSource) -> Self {
+        Self { terrain, columns: HashMap::new(), remesh: HashSet::new(), storage: None, modified: HashSet::new(), autosave: None, connectivity: HashMap::new() }
+    }
+
+    pub fn with_storage(terrain: TerrainSource, storage: RegionStorage) -> Self {
+        Self { storage: Some(Arc::new(Mutex::new(storage))), ..Self::new(terrain) }
+    }
+
+    /// Saves modified columns every `interval` on a background thread from then on, see `autosave`
+    pub fn start_autosave(&mut self, interval: Duration, backup: Option<BackupPolicy>) {
+        match &self.storage {
+            Some(storage) => self.autosave = Some(AutoSave::new(storage.clone(), interval, backup)),
+            None => log::warn!("auto-save needs storage, keeping everything in memory"),
+        }
+    }
+
+    /// Call once per frame, queues the modified columns when the interval passed and never waits for the disk
+    pub fn autosave(&mut self) {
+        let Some(autosave) = self.autosave.as_mut() else {
+            return;
+        };
+        let results = autosave.finished();
+        if autosave.save_due() {
+            self.queue_modified();
+        }
+        self.autosave.as_mut().unwrap().backup_if_due();
+        self.handle_save_results(results);
+    }
+
+    /// Snapshots every modified column into the auto-save queue
+    fn queue_modified(&mut self) {
+        let Some(autosave) = self.autosave.as_mut() else {
+            return;
+        };
+        let mut modified: Vec<[i32; 2]> = self.modified.drain().collect();
+        modified.sort();
+        for key in modified {
+            if let Some(column) = self.columns.get(&key) {
+                autosave.queue(column.clone());
+            }
+        }
+    }
+
+    /// Failed columns are loaded again if they were dropped meanwhile and stay modified
+    fn handle_save_results(&mut self, results: Vec<SaveResult>) {
+        for result in results {
+            if let SaveResult::Failed(column, e) = result {
+                let key = [column.x, column.z];
+                log::warn!("{}, saving column ({}, {}) again later", e, key[0], key[1]);
+                self.columns.entry(key).or_insert(column);
+                self.modified.insert(key);
+            }
+        }
+    }
+
+    /// Generates missing columns within `radius` chunks and drops the ones further away
+    pub fn load_around(&mut self, center: [i32; 2], radius: i32) {
+        let outside: Vec<[i32; 2]> = self.columns.keys().filter(|[x, z]| (x - center[0]).abs() > radius || (z - center[1]).abs() > radius).copied().collect();
+        for key in outside {
+            self.unload_column(key);
+        }
+
+        for z in center[1] - radius..=center[1] + radius {
+            for x in center[0] - radius..=center[0] + radius {
+                self.load_column(x, z);
+            }
+        }
+    }
+
+    pub fn load_column(&mut self, x: i32, z: i32) -> &mut ChunkColumn {
+        // the disk doesn't have the column's latest state until its queued saves ran
+        let loaded = self.columns.contains_key(&[x, z]);
+        if let Some(autosave) = self.autosave.as_mut().filter(|autosave| !loaded && autosave.is_queued([x, z])) {
+            let results = autosave.flush();
+            self.handle_save_results(results);
+        }
+        if !self.columns.contains_key(&[x, z]) {
+            let saved = self.storage.as_ref().map(|storage| storage.lock().unwrap().load_column(x, z)).transpose().unwrap_or_else(|e| {
+                log::warn!("{}, generating it again, `world recover` restores it from a backup", e);
+                None
+            });
+            let column = saved.flatten().unwrap_or_else(|| ChunkColumn::generate(x, z, &self.terrain));
+            let sections = column.bottom..column.top();
+            self.columns.insert([x, z], column);
+
+            // neighbours may have kept faces towards the unloaded column
+            for [dx, dz] in [[0, 0], [1, 0], [-1, 0], [0, 1], [0, -1]] {
+                for section_y in sections.clone() {
+                    self.mark_remesh(x + dx, section_y, z + dz);
+                }
+            }
+        }
+        self.columns.get_mut(&[x, z]).unwrap()
+    }
+
+    pub fn column(&self, x: i32, z: i32) -> Option<&ChunkColumn> {
+        self.columns.get(&[x, z])
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = &ChunkColumn> {
+        self.columns.values()
+    }
+
+    pub fn section(&self, x: i32, section_y: i32, z: i32) -> Option<&Section> {
+        self.column(x, z)?.section(section_y)
+    }
+
+    /// `None` if the column isn't loaded or `pos` is outside the world height
+    pub fn block(&self, pos: [i32; 3]) -> Option<BlockId> {
+        let res = CHUNK_RESOLUTION as i32;
+        let column = self.column(pos[0].div_euclid(res), pos[2].div_euclid(res))?;
+        column.block(pos[0].rem_euclid(res) as usize, pos[1], pos[2].rem_euclid(res) as usize)
+    }
+
+    /// Biome of the block column at `x`, `z`, `None` if its chunk column isn't loaded
+    pub fn biome(&self, x: i32, z: i32) -> Option<Biome> {
+        let res = CHUNK_RESOLUTION as i32;
+        let column = self.column(x.div_euclid(res), z.div_euclid(res))?;
+        column.biomes.get(x.rem_euclid(res) as usize + z.rem_euclid(res) as usize * CHUNK_RESOLUTION).copied()
+    }
+
+    /// Generates the column if needed, returns false outside the world height
+    pub fn set_block(&mut self, pos: [i32; 3], block: BlockId) -> bool {
+        let res = CHUNK_RESOLUTION as i32;
+        let [x, y, z] = [pos[0].div_euclid(res), pos[1].div_euclid(res), pos[2].div_euclid(res)];
+        if !self.load_column(x, z).set_block(pos[0].rem_euclid(res) as usize, pos[1], pos[2].rem_euclid(res) as usize, block) {
+            return false;
+        }
+
+        self.modified.insert([x, z]);
+        self.connectivity.remove(&[x, y, z]);
+
+        // blocks on the border change which faces the neighbour needs
+        let local = [pos[0].rem_euclid(res), pos[1].rem_euclid(res), pos[2].rem_euclid(res)];
+        self.mark_remesh(x, y, z);
+        for (axis, offset) in [(0, [1, 0, 0]), (1, [0, 1, 0]), (2, [0, 0, 1])] {
+            if local[axis] == 0 {
+                self.mark_remesh(x - offset[0], y - offset[1], z - offset[2]);
+            }
+            if local[axis] == res - 1 {
+                self.mark_remesh(x + offset[0], y + offset[1], z + offset[2]);
+            }
+        }
+        true
+    }
+
+    /// Replaces the container at the same position, ignored unless the section holding it has blocks
+    pub fn add_container(&mut self, container: Container) {
+        let res = CHUNK_RESOLUTION as i32;
+        let [x, y, z] = [container.pos[0].div_euclid(res), container.pos[1].div_euclid(res), container.pos[2].div_euclid(res)];
+        if let Some(Section::Blocks(chunk)) = self.load_column(x, z).section_mut(y) {
+            chunk.containers.retain(|existing| existing.pos != container.pos);
+            chunk.containers.push(container);
+            self.modified.insert([x, z]);
+        }
+    }
+
+    /// Writes the column if it was modified, failures are logged and the column stays modified
+    fn save_column(&mut self, key: [i32; 2]) {
+        let (Some(storage), Some(column)) = (self.storage.as_ref(), self.columns.get(&key)) else {
+            return;
+        };
+        if !self.modified.contains(&key) {
+            return;
+        }
+        match storage.lock().unwrap().save_column(column) {
+            Ok(()) => {
+                self.modified.remove(&key);
+            }
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+
+    /// Drops the column after saving it if it was modified, it stays loaded if saving fails
+    fn unload_column(&mut self, key: [i32; 2]) {
+        if self.modified.contains(&key) {
+            if let Some(autosave) = self.autosave.as_mut() {
+                self.modified.remove(&key);
+                autosave.queue(self.columns.remove(&key).unwrap());
+                self.connectivity.retain(|[x, _, z], _| [*x, *z] != key);
+                return;
+            }
+            self.save_column(key);
+            if self.storage.is_some() && self.modified.contains(&key) {
+                return;
+            }
+        }
+        self.columns.remove(&key);
+        self.connectivity.retain(|[x, _, z], _| [*x, *z] != key);
+    }
+
+    /// Writes every modified column, waits for the auto-save queue when auto-saving
+    pub fn save(&mut self) {
+        if self.autosave.is_some() {
+            self.queue_modified();
+            let results = self.autosave.as_mut().unwrap().flush();
+            self.handle_save_results(results);
+            return;
+        }
+        let mut modified: Vec<[i32; 2]> = self.modified.iter().copied().collect();
+        modified.sort();
+        for key in modified {
+            self.save_column(key);
+        }
+    }
+
+    fn mark_remesh(&mut self, x: i32, section_y: i32, z: i32) {
+        self.remesh.insert([x, section_y, z]);
+    }
+
+    /// Sections that changed since the last call, sorted so meshing order is stable
+    pub fn take_remesh(&mut self) -> Vec<[i32; 3]> {
+        let mut sections: Vec<[i32; 3]> = self.remesh.drain().collect();
+        sections.sort();
+        sections
+    }
+
+    /// Empty sections and opaque sections surrounded by opaque sections have no visible faces,
+    /// unloaded neighbours and the space above the world count as visible, below the world floor doesn't
+    pub fn needs_mesh(&self, x: i32, section_y: i32, z: i32) -> bool {
+        match self.section(x, section_y, z) {
+            None | Some(Section::Empty) => false,
+            Some(section) if section.is_opaque() => {
+                let neighbours = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+                !neighbours.iter().all(|[dx, dy, dz]| self.section(x + dx, section_y + dy, z + dz).map_or(*dy < 0, Section::is_opaque))
+            }
+            Some(_) => true,
+        }
+    }
+
+    /// Quads of one section per `CHUNK_SIZE` tile with the tile offset in blocks, `None` when `needs_mesh` skips it.
+    /// Updates the section's face connectivity too.
+    pub fn mesh_section(&mut self, x: i32, section_y: i32, z: i32) -> Option<Vec<([i32; 3], Vec<GPUQuad>)>> {
+        if let Some(section) = self.section(x, section_y, z) {
+            let connectivity = FaceConnectivity::of_section(section);
+            self.connectivity.insert([x, section_y, z], connectivity);
+        }
+        if !self.needs_mesh(x, section_y, z) {
+            return None;
+        }
+        let res = CHUNK_RESOLUTION as i32;
+        let section = self.section(x, section_y, z)?;
+        let origin = [x * res, section_y * res, z * res];
+        // blocks of neighbouring sections only decide whether a solid tile on the border can be skipped
+        let block = |[bx, by, bz]: [i32; 3]| {
+            if [bx, by, bz].iter().all(|v| (0..res).contains(v)) {
+                section.block((bx + bz * res + by * res * res) as usize)
+            } else {
+                self.block([origin[0] + bx, origin[1] + by, origin[2] + bz]).unwrap_or(block::AIR)
+            }
+        };
+        Some(mesh::mesh_tiles([res; 3], block))
+    }
+
+    /// Cached by `mesh_section`, sections that weren't meshed yet are flood filled here. `None` if not loaded.
+    pub fn section_connectivity(&self, x: i32, section_y: i32, z: i32) -> Option<FaceConnectivity> {
+        let section = self.section(x, section_y, z)?;
+        Some(self.connectivity.get(&[x, section_y, z]).copied().unwrap_or_else(|| FaceConnectivity::of_section(section)))
+    }
+
+    /// Sections the camera at block position `camera` can see through air and other non-solid blocks,
+    /// within `radius` columns and accepted by `in_view`. Sorted from the camera outwards.
+    /// Outside the world height or loaded columns every loaded section in range counts as visible.
+    /// The walk stays between the lowest and highest generated section.
+    pub fn visible_sections(&self, camera: [i32; 3], radius: i32, in_view: impl Fn([i32; 3]) -> bool) -> Vec<[i32; 3]> {
+        let res = CHUNK_RESOLUTION as i32;
+        let start = camera.map(|v| v.div_euclid(res));
+        // unbounded columns continue as stone and air forever, nothing past the generated sections needs a walk
+        let bottom = self.columns.values().map(|column| column.bottom).min().unwrap_or(start[1]).min(start[1]);
+        let top = self.columns.values().map(ChunkColumn::top).max().unwrap_or(start[1]).max(start[1] + 1);
+        let in_range = |[x, y, z]: [i32; 3]| (x - start[0]).abs() <= radius && (z - start[2]).abs() <= radius && y >= bottom && y < top;
+        if self.section(start[0], start[1], start[2]).is_none() {
+            let mut sections: Vec<[i32; 3]> = self.columns.values().flat_map(|column| (column.bottom..column.top()).map(|y| [column.x, y, column.z])).filter(|section| in_range(*section) && in_view(*section)).collect();
+            sections.sort_by_key(|[x, y, z]| ((x - start[0]).abs() + (y - start[1]).abs() + (z - start[2]).abs(), [*x, *y, *z]));
+            return sections;
+        }
+        visibility::reachable_sections(start, |[x, y, z]| self.section_connectivity(x, y, z), |section| in_range(section) && in_view(section))
+    }
+}
@@ -1,12 +1,77 @@
 use std::{slice::Windows, thread::yield_now, u8};
 
 use super::*;
+use block::BlockId;
 
 use std::fmt::Debug;
 
 pub fn mesh(y_axis: &[Gridbits]) -> Vec<GPUQuad> {
-    let size = size_of::<Gridbits>() * 8;
+    mesh_faces(y_axis, y_axis, block::AIR)
+}
+
+/// Meshes every block id separately so greedy merging never joins two materials, the id ends up in each quad.
+/// Faces are hidden by solid blocks and by the same block, water next to stone keeps its face but not next to water.
+pub fn mesh_materials(blocks: &[BlockId]) -> Vec<GPUQuad> {
+    let mut ids: Vec<BlockId> = blocks.iter().copied().filter(|id| *id != block::AIR).collect();
+    ids.sort();
+    ids.dedup();
+
+    let solid: Vec<Gridbits> = blocks.iter().map(|id| block::info(*id).solid as Gridbits).collect();
+    let mut quads = vec![];
+    for id in ids {
+        let faces: Vec<Gridbits> = blocks.iter().map(|block| (*block == id) as Gridbits).collect();
+        let occluders: Vec<Gridbits> = faces.iter().zip(&solid).map(|(face, solid)| face | solid).collect();
+        quads.extend(mesh_faces(&occluders, &faces, id));
+    }
+    quads
+}
+
+/// Meshes a volume of `size` blocks in the `CHUNK_SIZE` tiles `mesh_materials` handles, `block` gets positions
+/// relative to the min corner and may be asked for the blocks around the volume too.
+/// Quads per tile with the tile offset, empty tiles and solid tiles enclosed by solid blocks are skipped.
+pub fn mesh_tiles(size: [i32; 3], block: impl Fn([i32; 3]) -> BlockId) -> Vec<([i32; 3], Vec<GPUQuad>)> {
+    let tile = CHUNK_SIZE as i32;
+    let tiles = size.map(|size| (size + tile - 1) / tile);
+    let solid = |pos: [i32; 3]| block::info(block(pos)).solid;
+    let mut meshes = vec![];
+
+    for tile_y in 0..tiles[1] {
+        for tile_z in 0..tiles[2] {
+            for tile_x in 0..tiles[0] {
+                let offset = [tile_x * tile, tile_y * tile, tile_z * tile];
+                let at = |[x, y, z]: [i32; 3]| [offset[0] + x, offset[1] + y, offset[2] + z];
+                // indexed `x + z * 8 + y * 64`, blocks past `size` stay air
+                let mut blocks = vec![block::AIR; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+                for y in 0..tile {
+                    for z in 0..tile {
+                        for x in 0..tile {
+                            let pos = at([x, y, z]);
+                            if (0..3).all(|i| pos[i] < size[i]) {
+                                blocks[(x + z * tile + y * tile * tile) as usize] = block(pos);
+                            }
+                        }
+                    }
+                }
+
+                if blocks.iter().all(|id| *id == block::AIR) {
+                    continue;
+                }
+                let mut shell = (0..tile).flat_map(|a| (0..tile).flat_map(move |b| [[-1, a, b], [tile, a, b], [a, -1, b], [a, tile, b], [a, b, -1], [a, b, tile]]));
+                if blocks.iter().all(|id| block::info(*id).solid) && shell.all(|pos| solid(at(pos))) {
+                    continue;
+                }
+                let quads = mesh_materials(&blocks);
+                if !quads.is_empty() {
+                    meshes.push((offset, quads));
+                }
+            }
+        }
+    }
+    meshes
+}
 
+/// Solid bits of `y_axis` as columns along each axis, only the even entries are filled
+fn axis_columns(y_axis: &[Gridbits]) -> [[[Gridbits; CHUNK_SIZE]; CHUNK_SIZE]; 6] {
     #[inline]
     fn insert_voxel_to_axis(x: usize, y: usize, z: usize, block: Gridbits, axis_cols: &mut [[Gridbits; CHUNK_SIZE]; CHUNK_SIZE]) {
         axis_cols[z][x] |= block << y as Gridbits;
@@ -48,6 +113,14 @@ pub fn mesh(y_axis: &[Gridbits]) -> Vec<GPUQuad> {
             }
         }
     }
+    axis_cols
+}
+
+/// Binary greedy mesh of `faces` where the neighbour in `occluders` is empty, both hold 0 or 1 per block
+fn mesh_faces(occluders: &[Gridbits], faces: &[Gridbits], material: BlockId) -> Vec<GPUQuad> {
+    let size = Gridbits::BITS as usize;
+    let occluder_cols = axis_columns(occluders);
+    let mut axis_cols = axis_columns(faces);
 
     // CULL FACES
     // ORDER don't matter as long as everything get culled
@@ -55,9 +128,10 @@ pub fn mesh(y_axis: &[Gridbits]) -> Vec<GPUQuad> {
         for z in 0..size {
             for x in 0..size {
                 let col = axis_cols[axis * 2][z][x];
+                let occluder = occluder_cols[axis * 2][z][x];
 
-                axis_cols[axis * 2][z][x] = col & !(col << 1);
-                axis_cols[axis * 2 + 1][z][x] = col & !(col >> 1);
+                axis_cols[axis * 2][z][x] = col & !(occluder << 1);
+                axis_cols[axis * 2 + 1][z][x] = col & !(occluder >> 1);
             }
         }
     }
@@ -134,7 +208,7 @@ pub fn mesh(y_axis: &[Gridbits]) -> Vec<GPUQuad> {
 
                     let pos = axis.get_position(x as u32, y as u32 + add, z as u32);
 
-                    quads.push(GPUQuad::new(pos.0 as u64, pos.1 as u64, pos.2 as u64, width as u64, height as u64, face as u64));
+                    quads.push(GPUQuad::new(pos.0 as u64, pos.1 as u64, pos.2 as u64, width as u64, height as u64, face as u64).with_material(material));
                     let x = 1;
                 }
             }
@@ -181,6 +255,7 @@ impl From<u32> for Axis {
 }
 
 #[repr(C, align(8))]
+#[derive(Clone, Copy)]
 pub struct GPUQuad {
     data: u64,
 }
@@ -194,10 +269,39 @@ impl GPUQuad {
         Self { data }
     }
 
+    /// Block id in bits 38 to 45, the shaders pick the texture layers of the face from it
+    pub fn with_material(self, material: BlockId) -> Self {
+        Self { data: self.data | ((material as u64) << 38) }
+    }
+
+    pub fn material(&self) -> BlockId {
+        (self.data >> 38) as BlockId
+    }
+
+    /// 0 and 1 face -x and +x, 2 and 3 -z and +z, 4 and 5 -y and +y
+    pub fn face(&self) -> usize {
+        ((self.data >> 35) & 7) as usize
+    }
+
+    /// Min and max corner in blocks, width and height run along the axes `chunk.vert` uses for the face
+    pub fn bounds(&self) -> ([u32; 3], [u32; 3]) {
+        let field = |shift: u64| ((self.data >> shift) & 127) as u32;
+        let min = [field(0), field(7), field(14)];
+        let (w_axis, h_axis) = match self.face() / 2 {
+            0 => (2, 1),
+            1 => (0, 1),
+            _ => (0, 2),
+        };
+        let mut max = min;
+        max[w_axis] += field(21);
+        max[h_axis] += field(28);
+        (min, max)
+    }
+
     pub fn println(&self) {
         let mask_6 = ((1 as u64) << 7) - 1;
         println!("bits: {:b}", mask_6);
-        let mask_3 = ((1 as u64) << 4) - 1;
+        let mask_3 = ((1 as u64) << 3) - 1;
 
         let x = self.data & mask_6;
         let y = (self.data >> 7) & mask_6;
@@ -206,7 +310,7 @@ impl GPUQuad {
         let h = (self.data >> 28) & mask_6;
         let f = (self.data >> 35) & mask_3;
 
-        println!("x: {}\ny: {}\nz: {}\nw: {}\nh: {}\nf: {}", x, y, z, w, h, f);
+        println!("x: {}\ny: {}\nz: {}\nw: {}\nh: {}\nf: {}\nm: {}", x, y, z, w, h, f, self.material());
     }
 }
 
@@ -216,3 +320,26 @@ impl GPUQuad {
 // w 6 bits
 // h 6 bits
 // face: 3 bits
+// material: 8 bits
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_hold_their_blocks() {
+        let meshes = mesh_tiles([64; 3], |pos| if pos == [20, 30, 40] { block::STONE } else { block::AIR });
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].0, [16, 24, 40]);
+        assert_eq!(meshes[0].1.len(), 6);
+    }
+
+    #[test]
+    fn enclosed_solid_tiles_are_skipped() {
+        let inside = |pos: [i32; 3]| pos.iter().all(|v| (0..64).contains(v));
+        assert!(mesh_tiles([64; 3], |_| block::STONE).is_empty());
+        // only the tiles on the surface of the cube can be seen
+        let meshes = mesh_tiles([64; 3], |pos| if inside(pos) { block::STONE } else { block::AIR });
+        assert_eq!(meshes.len(), 8 * 8 * 8 - 6 * 6 * 6);
+    }
+}
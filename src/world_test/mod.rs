@@ -1,13 +1,37 @@
+pub mod anvil;
+mod autosave;
+mod backup;
+pub mod biome;
+pub mod block;
 pub mod chunk;
-mod generation;
-mod mesh;
+mod column;
+mod erosion;
+pub mod generation;
+pub mod golden;
+mod heightmap;
+mod loot;
+pub mod manager;
+pub mod mesh;
+mod nbt;
 mod node;
+mod noise_graph;
+pub mod preview;
+mod region;
+mod rng;
+pub mod schematic;
+mod structure;
+pub mod time;
+mod visibility;
+mod volume;
+pub mod vox;
+mod water;
+pub mod world;
 
-const CHUNK_RESOLUTION: usize = 64;
+pub const CHUNK_RESOLUTION: usize = 64;
 const DEPTH: usize = 2;
 
 const VOXEL_SCALE: f32 = 1.0;
-const CHUNK_SIZE: usize = size_of::<Gridbits>() * 8;
+const CHUNK_SIZE: usize = Gridbits::BITS as usize;
 
 pub type Gridbits = u8;
 type TextureID = u8;
@@ -0,0 +1,267 @@
+use std::io::{Read, Write};
+
+use flate2::{read::GzDecoder, read::ZlibDecoder, write::GzEncoder, Compression};
+
+/// Named binary tag, big endian as written by Minecraft
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    /// Every element has the same type, an empty list is written as a list of `End`
+    List(Vec<Tag>),
+    /// Keeps the order it was read or built in
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const END: u8 = 0;
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    pub fn compound(entries: Vec<(&str, Tag)>) -> Self {
+        Tag::Compound(entries.into_iter().map(|(name, tag)| (name.to_owned(), tag)).collect())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter().find(|(key, _)| key == name).map(|(_, tag)| tag),
+            _ => None,
+        }
+    }
+
+    /// Any integer tag widened, Minecraft isn't consistent about which size it writes
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(v) => Some(*v as i64),
+            Tag::Short(v) => Some(*v as i64),
+            Tag::Int(v) => Some(*v as i64),
+            Tag::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Tag::ByteArray(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.offset..self.offset + len).ok_or("nbt data ends early")?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Err(format!("negative nbt length {}", len));
+        }
+        Ok(len as usize)
+    }
+
+    /// Java writes modified utf8, only the encoding of nul and surrogates differs so lossy is good enough for names
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.i16()? as u16 as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn payload(&mut self, id: u8) -> Result<Tag, String> {
+        Ok(match id {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(self.i16()?),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(self.i64()?),
+            5 => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            6 => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            7 => {
+                let len = self.len()?;
+                Tag::ByteArray(self.take(len)?.iter().map(|b| *b as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element = self.u8()?;
+                let len = self.len()?;
+                if element == END && len > 0 {
+                    return Err("nbt list of end tags".to_owned());
+                }
+                Tag::List((0..len).map(|_| self.payload(element)).collect::<Result<_, _>>()?)
+            }
+            10 => {
+                let mut entries = vec![];
+                loop {
+                    let id = self.u8()?;
+                    if id == END {
+                        break;
+                    }
+                    let name = self.string()?;
+                    entries.push((name, self.payload(id)?));
+                }
+                Tag::Compound(entries)
+            }
+            11 => {
+                let len = self.len()?;
+                Tag::IntArray((0..len).map(|_| self.i32()).collect::<Result<_, _>>()?)
+            }
+            12 => {
+                let len = self.len()?;
+                Tag::LongArray((0..len).map(|_| self.i64()).collect::<Result<_, _>>()?)
+            }
+            id => return Err(format!("unknown nbt tag id {}", id)),
+        })
+    }
+}
+
+/// Uncompressed root compound, returns its name and the tag
+pub fn read(bytes: &[u8]) -> Result<(String, Tag), String> {
+    let mut reader = Reader { bytes, offset: 0 };
+    let id = reader.u8()?;
+    if id != 10 {
+        return Err(format!("nbt root is tag {}, not a compound", id));
+    }
+    let name = reader.string()?;
+    Ok((name, reader.payload(id)?))
+}
+
+/// Gzip (level.dat and schematics) or zlib, detected from the header
+pub fn read_compressed(bytes: &[u8]) -> Result<(String, Tag), String> {
+    let mut data = vec![];
+    let result = match bytes {
+        [0x1f, 0x8b, ..] => GzDecoder::new(bytes).read_to_end(&mut data),
+        [0x78, ..] => ZlibDecoder::new(bytes).read_to_end(&mut data),
+        _ => return read(bytes),
+    };
+    result.map_err(|e| format!("invalid compressed nbt: {}", e))?;
+    read(&data)
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(v) => out.push(*v as u8),
+        Tag::Short(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::ByteArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+            out.extend(v.iter().map(|b| *b as u8));
+        }
+        Tag::String(v) => write_string(out, v),
+        Tag::List(v) => {
+            out.push(v.first().map_or(END, Tag::id));
+            out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+            v.iter().for_each(|tag| write_payload(out, tag));
+        }
+        Tag::Compound(entries) => {
+            for (name, tag) in entries {
+                out.push(tag.id());
+                write_string(out, name);
+                write_payload(out, tag);
+            }
+            out.push(END);
+        }
+        Tag::IntArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+            v.iter().for_each(|i| out.extend_from_slice(&i.to_be_bytes()));
+        }
+        Tag::LongArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+            v.iter().for_each(|i| out.extend_from_slice(&i.to_be_bytes()));
+        }
+    }
+}
+
+/// Uncompressed, `tag` has to be a compound
+pub fn write(name: &str, tag: &Tag) -> Vec<u8> {
+    let mut out = vec![tag.id()];
+    write_string(&mut out, name);
+    write_payload(&mut out, tag);
+    out
+}
+
+pub fn write_gzip(name: &str, tag: &Tag) -> Vec<u8> {
+    let mut gzip = GzEncoder::new(vec![], Compression::default());
+    gzip.write_all(&write(name, tag)).unwrap();
+    gzip.finish().unwrap()
+}
@@ -0,0 +1,487 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use libnoise::{Generator, Source};
+
+// Text format, one node per line, nodes can only reference nodes defined above them:
+//
+//   # comment
+//   base   = simplex seed=12
+//   hills  = fbm source=base octaves=5 frequency=1.0 lacunarity=2.0 persistence=0.5
+//   warped = warp source=hills x=base z=base strength=0.25
+//   shaped = spline source=warped points=-1:0,0:0.4,1:1
+//   output shaped
+//
+// Without an `output` line the last node is the output.
+
+#[derive(Debug)]
+pub struct NoiseGraphError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for NoiseGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "noise graph line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SourceKind {
+    Simplex,
+    Perlin,
+    Value,
+    Worley,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum OctaveKind {
+    Fbm,
+    Billow,
+    Ridged,
+}
+
+type NodeIndex = usize;
+
+#[derive(Clone, Debug)]
+pub enum NoiseNode {
+    Constant(f64),
+    Source { kind: SourceKind, seed: Option<u64> },
+    Octaves { kind: OctaveKind, source: NodeIndex, octaves: u32, frequency: f64, lacunarity: f64, persistence: f64 },
+    Scale { source: NodeIndex, scale: [f64; 2] },
+    Translate { source: NodeIndex, offset: [f64; 2] },
+    Add { source: NodeIndex, value: f64 },
+    Mul { source: NodeIndex, value: f64 },
+    Abs { source: NodeIndex },
+    Clamp { source: NodeIndex, min: f64, max: f64 },
+    /// Offsets the sample point of `source` by the output of `x` and `z`
+    Warp { source: NodeIndex, x: NodeIndex, z: NodeIndex, strength: f64 },
+    /// Piecewise linear curve, points are sorted by input
+    Spline { source: NodeIndex, points: Vec<(f64, f64)> },
+    Sum { a: NodeIndex, b: NodeIndex },
+    Product { a: NodeIndex, b: NodeIndex },
+    Min { a: NodeIndex, b: NodeIndex },
+    Max { a: NodeIndex, b: NodeIndex },
+    /// Lerp between `a` and `b`, `control` in [-1, 1]
+    Blend { a: NodeIndex, b: NodeIndex, control: NodeIndex },
+    /// Samples `source` once per point while the graph is sampled, for nodes that are read by several others
+    Cache { source: NodeIndex },
+}
+
+#[derive(Clone, Debug)]
+pub struct NoiseGraph {
+    nodes: Vec<NoiseNode>,
+    output: NodeIndex,
+}
+
+impl NoiseGraph {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, NoiseGraphError> {
+        let text = std::fs::read_to_string(path.as_ref()).map_err(|e| NoiseGraphError { line: 0, message: format!("{}: {}", path.as_ref().display(), e) })?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, NoiseGraphError> {
+        let mut nodes = vec![];
+        let mut names = HashMap::new();
+        let mut output = None;
+
+        for (i, raw) in text.lines().enumerate() {
+            let line_nr = i + 1;
+            let err = |message: String| NoiseGraphError { line: line_nr, message };
+
+            let line = raw.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix("output ") {
+                output = Some(*names.get(name.trim()).ok_or_else(|| err(format!("unknown node '{}'", name.trim())))?);
+                continue;
+            }
+
+            let (name, def) = line.split_once('=').ok_or_else(|| err("expected '<name> = <kind> ...'".to_owned()))?;
+            let name = name.trim();
+            let mut words = def.split_whitespace();
+            let kind = words.next().ok_or_else(|| err("missing node kind".to_owned()))?;
+
+            let args = Args::parse(words).map_err(err)?;
+            let node = Self::parse_node(kind, &args, &names).map_err(err)?;
+
+            if names.insert(name.to_owned(), nodes.len()).is_some() {
+                return Err(err(format!("node '{}' is defined twice", name)));
+            }
+            nodes.push(node);
+        }
+
+        if nodes.is_empty() {
+            return Err(NoiseGraphError { line: 0, message: "graph has no nodes".to_owned() });
+        }
+        let output = output.unwrap_or(nodes.len() - 1);
+        Ok(Self { nodes, output })
+    }
+
+    fn parse_node(kind: &str, args: &Args, names: &HashMap<String, NodeIndex>) -> Result<NoiseNode, String> {
+        let node = |key: &str| -> Result<NodeIndex, String> {
+            let name = args.get(key).ok_or_else(|| format!("'{}' needs {}=<node>", kind, key))?;
+            names.get(name).copied().ok_or_else(|| format!("unknown node '{}'", name))
+        };
+        let source = || node("source");
+
+        let node = match kind {
+            "constant" => NoiseNode::Constant(args.float("value", 0.0)?),
+            "simplex" | "perlin" | "value" | "worley" => {
+                let kind = match kind {
+                    "simplex" => SourceKind::Simplex,
+                    "perlin" => SourceKind::Perlin,
+                    "value" => SourceKind::Value,
+                    _ => SourceKind::Worley,
+                };
+                let seed = match args.get("seed") {
+                    Some(seed) => Some(seed.parse().map_err(|_| format!("invalid seed '{}'", seed))?),
+                    None => None,
+                };
+                NoiseNode::Source { kind, seed }
+            }
+            "fbm" | "billow" | "ridged" => NoiseNode::Octaves {
+                kind: match kind {
+                    "fbm" => OctaveKind::Fbm,
+                    "billow" => OctaveKind::Billow,
+                    _ => OctaveKind::Ridged,
+                },
+                source: source()?,
+                octaves: args.float("octaves", 4.0)? as u32,
+                frequency: args.float("frequency", 1.0)?,
+                lacunarity: args.float("lacunarity", 2.0)?,
+                persistence: args.float("persistence", 0.5)?,
+            },
+            "scale" => NoiseNode::Scale { source: source()?, scale: [args.float("x", 1.0)?, args.float("z", 1.0)?] },
+            "translate" => NoiseNode::Translate { source: source()?, offset: [args.float("x", 0.0)?, args.float("z", 0.0)?] },
+            "add" => NoiseNode::Add { source: source()?, value: args.float("value", 0.0)? },
+            "mul" => NoiseNode::Mul { source: source()?, value: args.float("value", 1.0)? },
+            "abs" => NoiseNode::Abs { source: source()? },
+            "clamp" => NoiseNode::Clamp { source: source()?, min: args.float("min", -1.0)?, max: args.float("max", 1.0)? },
+            "warp" => NoiseNode::Warp { source: source()?, x: node("x")?, z: node("z")?, strength: args.float("strength", 1.0)? },
+            "spline" => {
+                let text = args.get("points").ok_or("'spline' needs points=<in>:<out>,...")?;
+                let mut points = vec![];
+                for point in text.split(',') {
+                    let (x, y) = point.split_once(':').ok_or_else(|| format!("invalid spline point '{}'", point))?;
+                    let x: f64 = x.parse().map_err(|_| format!("invalid spline point '{}'", point))?;
+                    let y: f64 = y.parse().map_err(|_| format!("invalid spline point '{}'", point))?;
+                    points.push((x, y));
+                }
+                points.sort_by(|a, b| a.0.total_cmp(&b.0));
+                NoiseNode::Spline { source: source()?, points }
+            }
+            "sum" => NoiseNode::Sum { a: node("a")?, b: node("b")? },
+            "product" => NoiseNode::Product { a: node("a")?, b: node("b")? },
+            "min" => NoiseNode::Min { a: node("a")?, b: node("b")? },
+            "max" => NoiseNode::Max { a: node("a")?, b: node("b")? },
+            "blend" => NoiseNode::Blend { a: node("a")?, b: node("b")?, control: node("control")? },
+            "cache" => NoiseNode::Cache { source: source()? },
+            _ => return Err(format!("unknown node kind '{}'", kind)),
+        };
+        Ok(node)
+    }
+
+    /// Builds the libnoise sources, `seed` is used by every source node without its own seed
+    pub fn compile(&self, seed: u64) -> CompiledNoise {
+        let mut cache_slots = 0;
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| match node {
+                NoiseNode::Source { kind, seed: node_seed } => {
+                    let seed = node_seed.unwrap_or(seed);
+                    let sample: Box<dyn Fn([f64; 2]) -> f64 + Send + Sync> = match kind {
+                        SourceKind::Simplex => {
+                            let generator = Source::simplex(seed);
+                            Box::new(move |point| generator.sample(point))
+                        }
+                        SourceKind::Perlin => {
+                            let generator = Source::perlin(seed);
+                            Box::new(move |point| generator.sample(point))
+                        }
+                        SourceKind::Value => {
+                            let generator = Source::value(seed);
+                            Box::new(move |point| generator.sample(point))
+                        }
+                        SourceKind::Worley => {
+                            let generator = Source::worley(seed);
+                            Box::new(move |point| generator.sample(point))
+                        }
+                    };
+                    CompiledNode::Source(sample)
+                }
+                NoiseNode::Cache { source } => {
+                    cache_slots += 1;
+                    CompiledNode::Cache { source: *source, slot: cache_slots - 1 }
+                }
+                node => CompiledNode::Node(node.clone()),
+            })
+            .collect();
+
+        CompiledNoise { nodes, output: self.output, cache_slots }
+    }
+}
+
+struct Args<'a> {
+    values: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Args<'a> {
+    fn parse(words: impl Iterator<Item = &'a str>) -> Result<Self, String> {
+        let mut values = vec![];
+        for word in words {
+            let (key, value) = word.split_once('=').ok_or_else(|| format!("expected key=value, got '{}'", word))?;
+            values.push((key, value));
+        }
+        Ok(Self { values })
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.values.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn float(&self, key: &str, default: f64) -> Result<f64, String> {
+        match self.get(key) {
+            Some(value) => value.parse().map_err(|_| format!("invalid number {}={}", key, value)),
+            None => Ok(default),
+        }
+    }
+}
+
+/// Last point and value of a cache node, kept for one `sample` call
+type CacheSlot = Option<([f64; 2], f64)>;
+
+enum CompiledNode {
+    Source(Box<dyn Fn([f64; 2]) -> f64 + Send + Sync>),
+    /// `slot` indexes the cache slots of a `sample` call
+    Cache { source: NodeIndex, slot: usize },
+    Node(NoiseNode),
+}
+
+/// A noise graph ready for sampling, it is a regular libnoise generator so it can be chained further.
+/// Sampling doesn't mutate it, so one graph can be shared by threads generating chunks in parallel.
+pub struct CompiledNoise {
+    nodes: Vec<CompiledNode>,
+    output: NodeIndex,
+    cache_slots: usize,
+}
+
+impl CompiledNoise {
+    fn sample_node(&self, index: NodeIndex, point: [f64; 2], cache: &mut [CacheSlot]) -> f64 {
+        let node = match &self.nodes[index] {
+            CompiledNode::Source(sample) => return sample(point),
+            CompiledNode::Cache { source, slot } => {
+                if let Some((last_point, value)) = cache[*slot] {
+                    if last_point == point {
+                        return value;
+                    }
+                }
+                let value = self.sample_node(*source, point, cache);
+                cache[*slot] = Some((point, value));
+                return value;
+            }
+            CompiledNode::Node(node) => node,
+        };
+
+        match node {
+            NoiseNode::Constant(value) => *value,
+            NoiseNode::Octaves { kind, source, octaves, frequency, lacunarity, persistence } => {
+                let mut sum = 0.0;
+                let mut amplitude = 1.0;
+                let mut total_amplitude = 0.0;
+                let mut frequency = *frequency;
+
+                for _ in 0..*octaves {
+                    let value = self.sample_node(*source, [point[0] * frequency, point[1] * frequency], cache);
+                    let value = match kind {
+                        OctaveKind::Fbm => value,
+                        OctaveKind::Billow => value.abs() * 2.0 - 1.0,
+                        OctaveKind::Ridged => 1.0 - value.abs() * 2.0,
+                    };
+                    sum += value * amplitude;
+                    total_amplitude += amplitude;
+                    amplitude *= persistence;
+                    frequency *= lacunarity;
+                }
+
+                if total_amplitude == 0.0 {
+                    0.0
+                } else {
+                    sum / total_amplitude
+                }
+            }
+            NoiseNode::Scale { source, scale } => self.sample_node(*source, [point[0] * scale[0], point[1] * scale[1]], cache),
+            NoiseNode::Translate { source, offset } => self.sample_node(*source, [point[0] + offset[0], point[1] + offset[1]], cache),
+            NoiseNode::Add { source, value } => self.sample_node(*source, point, cache) + value,
+            NoiseNode::Mul { source, value } => self.sample_node(*source, point, cache) * value,
+            NoiseNode::Abs { source } => self.sample_node(*source, point, cache).abs(),
+            NoiseNode::Clamp { source, min, max } => self.sample_node(*source, point, cache).clamp(*min, *max),
+            NoiseNode::Warp { source, x, z, strength } => {
+                let dx = self.sample_node(*x, point, cache) * strength;
+                // offset the second lookup so both axes don't get the same displacement
+                let dz = self.sample_node(*z, [point[0] + 5.2, point[1] + 1.3], cache) * strength;
+                self.sample_node(*source, [point[0] + dx, point[1] + dz], cache)
+            }
+            NoiseNode::Spline { source, points } => spline(points, self.sample_node(*source, point, cache)),
+            NoiseNode::Sum { a, b } => self.sample_node(*a, point, cache) + self.sample_node(*b, point, cache),
+            NoiseNode::Product { a, b } => self.sample_node(*a, point, cache) * self.sample_node(*b, point, cache),
+            NoiseNode::Min { a, b } => self.sample_node(*a, point, cache).min(self.sample_node(*b, point, cache)),
+            NoiseNode::Max { a, b } => self.sample_node(*a, point, cache).max(self.sample_node(*b, point, cache)),
+            NoiseNode::Blend { a, b, control } => {
+                let t = ((self.sample_node(*control, point, cache) + 1.0) * 0.5).clamp(0.0, 1.0);
+                let a = self.sample_node(*a, point, cache);
+                let b = self.sample_node(*b, point, cache);
+                a + (b - a) * t
+            }
+            NoiseNode::Source { .. } | NoiseNode::Cache { .. } => unreachable!(),
+        }
+    }
+}
+
+impl Generator<2> for CompiledNoise {
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        let mut cache = vec![None; self.cache_slots];
+        self.sample_node(self.output, point, &mut cache)
+    }
+}
+
+fn spline(points: &[(f64, f64)], x: f64) -> f64 {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return x,
+    };
+    if x <= first.0 {
+        return first.1;
+    }
+    if x >= last.0 {
+        return last.1;
+    }
+
+    for window in points.windows(2) {
+        let (x0, y0) = window[0];
+        let (x1, y1) = window[1];
+        if x <= x1 {
+            let t = if x1 == x0 { 0.0 } else { (x - x0) / (x1 - x0) };
+            return y0 + (y1 - y0) * t;
+        }
+    }
+    last.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> NoiseGraphError {
+        NoiseGraph::parse(text).expect_err("graph should not parse")
+    }
+
+    fn sample(text: &str, point: [f64; 2]) -> f64 {
+        NoiseGraph::parse(text).unwrap().compile(1).sample(point)
+    }
+
+    #[test]
+    fn parses_the_documented_example() {
+        let graph = NoiseGraph::parse(
+            "# comment\n\
+             base   = simplex seed=12\n\
+             hills  = fbm source=base octaves=5 frequency=1.0 lacunarity=2.0 persistence=0.5\n\
+             \n\
+             warped = warp source=hills x=base z=base strength=0.25\n\
+             shaped = spline source=warped points=1:1,-1:0,0:0.4 # sorted by input\n\
+             output hills\n",
+        )
+        .unwrap();
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.output, 1);
+        assert!(matches!(graph.nodes[0], NoiseNode::Source { kind: SourceKind::Simplex, seed: Some(12) }));
+        assert!(matches!(graph.nodes[1], NoiseNode::Octaves { kind: OctaveKind::Fbm, source: 0, octaves: 5, .. }));
+        assert!(matches!(graph.nodes[2], NoiseNode::Warp { source: 1, x: 0, z: 0, .. }));
+        match &graph.nodes[3] {
+            NoiseNode::Spline { source: 2, points } => assert_eq!(points, &[(-1.0, 0.0), (0.0, 0.4), (1.0, 1.0)]),
+            node => panic!("expected a spline, got {:?}", node),
+        }
+    }
+
+    #[test]
+    fn output_defaults_to_the_last_node() {
+        let graph = NoiseGraph::parse("a = constant value=1\nb = constant value=2\n").unwrap();
+        assert_eq!(graph.output, 1);
+    }
+
+    #[test]
+    fn defaults_apply_to_missing_arguments() {
+        let graph = NoiseGraph::parse("a = perlin\nb = ridged source=a\nc = clamp source=b\n").unwrap();
+        assert!(matches!(graph.nodes[0], NoiseNode::Source { kind: SourceKind::Perlin, seed: None }));
+        assert!(matches!(graph.nodes[1], NoiseNode::Octaves { kind: OctaveKind::Ridged, octaves: 4, frequency, lacunarity, persistence, .. } if frequency == 1.0 && lacunarity == 2.0 && persistence == 0.5));
+        assert!(matches!(graph.nodes[2], NoiseNode::Clamp { min, max, .. } if min == -1.0 && max == 1.0));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = parse_error("a = constant value=1\n\n# comment\nb = wobble source=a\n");
+        assert_eq!(error.line, 4);
+        assert_eq!(error.message, "unknown node kind 'wobble'");
+        assert_eq!(error.to_string(), "noise graph line 4: unknown node kind 'wobble'");
+    }
+
+    #[test]
+    fn rejects_invalid_graphs() {
+        let cases = [
+            ("", 0, "graph has no nodes"),
+            ("# only a comment\n", 0, "graph has no nodes"),
+            ("a constant\n", 1, "expected '<name> = <kind> ...'"),
+            ("a =\n", 1, "missing node kind"),
+            ("a = constant 1\n", 1, "expected key=value, got '1'"),
+            ("a = constant value=high\n", 1, "invalid number value=high"),
+            ("a = simplex seed=-1\n", 1, "invalid seed '-1'"),
+            ("a = add value=1\n", 1, "'add' needs source=<node>"),
+            ("a = add source=b\n", 1, "unknown node 'b'"),
+            ("a = constant\nb = add source=c value=1\nc = constant\n", 2, "unknown node 'c'"),
+            ("a = constant\na = constant\n", 2, "node 'a' is defined twice"),
+            ("a = constant\nb = spline source=a\n", 2, "'spline' needs points=<in>:<out>,..."),
+            ("a = constant\nb = spline source=a points=0:1,2\n", 2, "invalid spline point '2'"),
+            ("a = constant\nb = spline source=a points=0:x\n", 2, "invalid spline point '0:x'"),
+            ("a = constant\nb = sum a=a\n", 2, "'sum' needs b=<node>"),
+            ("a = constant\noutput b\n", 2, "unknown node 'b'"),
+        ];
+        for (text, line, message) in cases {
+            let error = parse_error(text);
+            assert_eq!((error.line, error.message.as_str()), (line, message), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn samples_arithmetic_nodes() {
+        assert_eq!(sample("a = constant value=2\nb = add source=a value=1\nc = mul source=b value=4\n", [0.0, 0.0]), 12.0);
+        assert_eq!(sample("a = constant value=-3\nb = abs source=a\nc = clamp source=b min=0 max=2\n", [0.0, 0.0]), 2.0);
+        assert_eq!(sample("a = constant value=2\nb = constant value=5\nc = min a=a b=b\nd = max a=a b=b\ne = product a=c b=d\nf = sum a=e b=a\n", [0.0, 0.0]), 12.0);
+        // control -1 is all of a, 1 all of b
+        assert_eq!(sample("a = constant value=2\nb = constant value=4\nc = constant value=0\nd = blend a=a b=b control=c\n", [0.0, 0.0]), 3.0);
+    }
+
+    #[test]
+    fn spline_interpolates_and_clamps() {
+        let graph = "a = constant value=0.5\nb = spline source=a points=0:0,1:10\n";
+        assert_eq!(sample(graph, [0.0, 0.0]), 5.0);
+        assert_eq!(sample(&graph.replace("value=0.5", "value=-2"), [0.0, 0.0]), 0.0);
+        assert_eq!(sample(&graph.replace("value=0.5", "value=3"), [0.0, 0.0]), 10.0);
+    }
+
+    #[test]
+    fn cache_matches_the_uncached_graph() {
+        let uncached = NoiseGraph::parse("a = simplex\nb = fbm source=a\nc = warp source=b x=b z=b strength=0.5\nd = sum a=b b=c\n").unwrap().compile(7);
+        let cached = NoiseGraph::parse("a = simplex\nb = fbm source=a\nk = cache source=b\nc = warp source=k x=k z=k strength=0.5\nd = sum a=k b=c\n").unwrap().compile(7);
+        for point in [[0.0, 0.0], [0.3, -1.7], [12.5, 4.25], [0.3, -1.7]] {
+            assert_eq!(cached.sample(point), uncached.sample(point));
+        }
+    }
+
+    #[test]
+    fn compiled_graphs_can_be_shared_between_threads() {
+        fn shareable<T: Send + Sync>() {}
+        shareable::<CompiledNoise>();
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+
+use image::{Rgb, RgbImage};
+
+use super::*;
+use block::AIR;
+use column::ChunkColumn;
+use generation::{NoiseParameters, TerrainSource};
+
+const USAGE: &str = "usage: preview <min_chunk_x> <min_chunk_z> <max_chunk_x> <max_chunk_z> [--out <dir>] [--slices <y>,<y>,...]";
+
+struct PreviewArgs {
+    min: [i32; 2],
+    max: [i32; 2],
+    out: PathBuf,
+    slices: Vec<i32>,
+}
+
+impl PreviewArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut numbers = vec![];
+        let mut out = PathBuf::from("preview");
+        let mut slices = vec![CHUNK_RESOLUTION as i32 / 4, CHUNK_RESOLUTION as i32 / 2];
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => out = PathBuf::from(args.next().ok_or("--out needs a directory")?),
+                "--slices" => {
+                    let list = args.next().ok_or("--slices needs a list of heights")?;
+                    slices = list.split(',').map(|y| y.parse().map_err(|_| format!("invalid slice height '{}'", y))).collect::<Result<_, _>>()?;
+                }
+                number => numbers.push(number.parse::<i32>().map_err(|_| format!("invalid chunk coordinate '{}'", number))?),
+            }
+        }
+
+        if numbers.len() != 4 {
+            return Err(USAGE.to_owned());
+        }
+        let min = [numbers[0].min(numbers[2]), numbers[1].min(numbers[3])];
+        let max = [numbers[0].max(numbers[2]), numbers[1].max(numbers[3])];
+        Ok(Self { min, max, out, slices })
+    }
+}
+
+/// Top down view of generated chunks, one pixel per column
+struct PreviewMap {
+    width: usize,
+    depth: usize,
+    heights: Vec<i32>,
+    top_blocks: Vec<block::BlockId>,
+    biomes: Vec<biome::Biome>,
+    /// Solid or not, per slice
+    slices: Vec<Vec<bool>>,
+}
+
+impl PreviewMap {
+    fn new(width: usize, depth: usize, slice_count: usize) -> Self {
+        let size = width * depth;
+        Self { width, depth, heights: vec![0; size], top_blocks: vec![AIR; size], biomes: vec![Default::default(); size], slices: vec![vec![false; size]; slice_count] }
+    }
+
+    fn insert_column(&mut self, column: &ChunkColumn, pixel_x: usize, pixel_z: usize, slice_heights: &[i32]) {
+        let res = CHUNK_RESOLUTION;
+        for z in 0..res {
+            for x in 0..res {
+                let pixel = (pixel_z + z) * self.width + pixel_x + x;
+                let block = |y: i32| column.block(x, y, z).unwrap_or(AIR);
+
+                let top = (column.bottom * res as i32..column.top() * res as i32).rev().find(|y| block(*y) != AIR);
+                if let Some(y) = top {
+                    self.heights[pixel] = y + 1;
+                    self.top_blocks[pixel] = block(y);
+                }
+                self.biomes[pixel] = column.biomes[x + z * res];
+
+                for (slice, y) in slice_heights.iter().enumerate() {
+                    self.slices[slice][pixel] = block(*y) != AIR;
+                }
+            }
+        }
+    }
+
+    /// Light coming from the top left, so slopes are visible in flat colored maps
+    fn shade(&self, x: usize, z: usize) -> f32 {
+        let height = |x: usize, z: usize| self.heights[z * self.width + x] as f32;
+        let left = height(x.saturating_sub(1), z);
+        let up = height(x, z.saturating_sub(1));
+        let slope = (height(x, z) - left) + (height(x, z) - up);
+        (1.0 + slope * 0.15).clamp(0.6, 1.3)
+    }
+
+    fn render(&self, color: impl Fn(usize, usize, usize) -> [u8; 3]) -> RgbImage {
+        RgbImage::from_fn(self.width as u32, self.depth as u32, |x, z| {
+            let (x, z) = (x as usize, z as usize);
+            Rgb(color(x, z, z * self.width + x))
+        })
+    }
+
+    fn height_image(&self) -> RgbImage {
+        let min = *self.heights.iter().min().unwrap();
+        let range = (*self.heights.iter().max().unwrap() - min).max(1) as f32;
+        self.render(|x, z, pixel| {
+            let value = (self.heights[pixel] - min) as f32 / range * 255.0 * self.shade(x, z);
+            let value = value.clamp(0.0, 255.0) as u8;
+            [value, value, value]
+        })
+    }
+
+    fn biome_image(&self) -> RgbImage {
+        self.render(|_, _, pixel| self.biomes[pixel].color())
+    }
+
+    fn material_image(&self) -> RgbImage {
+        self.render(|x, z, pixel| {
+            let shade = self.shade(x, z);
+            block::info(self.top_blocks[pixel]).color.map(|c| (c as f32 * shade).clamp(0.0, 255.0) as u8)
+        })
+    }
+
+    fn slice_image(&self, slice: usize) -> RgbImage {
+        self.render(|_, _, pixel| if self.slices[slice][pixel] { [200, 200, 200] } else { [20, 20, 20] })
+    }
+}
+
+/// Entry point for `vulkanrust preview ...`, runs generation on the cpu and writes png maps
+pub fn run(args: &[String]) {
+    let args = match PreviewArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let chunks_x = (args.max[0] - args.min[0] + 1) as usize;
+    let chunks_z = (args.max[1] - args.min[1] + 1) as usize;
+    let mut map = PreviewMap::new(chunks_x * CHUNK_RESOLUTION, chunks_z * CHUNK_RESOLUTION, args.slices.len());
+
+    let terrain = TerrainSource::load(NoiseParameters::default());
+
+    for chunk_z in 0..chunks_z {
+        for chunk_x in 0..chunks_x {
+            let column = ChunkColumn::generate(args.min[0] + chunk_x as i32, args.min[1] + chunk_z as i32, &terrain);
+            map.insert_column(&column, chunk_x * CHUNK_RESOLUTION, chunk_z * CHUNK_RESOLUTION, &args.slices);
+        }
+    }
+
+    if let Err(e) = std::fs::create_dir_all(&args.out) {
+        eprintln!("{}: {}", args.out.display(), e);
+        std::process::exit(1);
+    }
+
+    let mut images = vec![("height.png".to_owned(), map.height_image()), ("biomes.png".to_owned(), map.biome_image()), ("materials.png".to_owned(), map.material_image())];
+    for (slice, y) in args.slices.iter().enumerate() {
+        images.push((format!("slice_y{}.png", y), map.slice_image(slice)));
+    }
+
+    for (name, image) in images {
+        let path = args.out.join(name);
+        if let Err(e) = image.save(&path) {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        println!("wrote {}", path.display());
+    }
+}
@@ -0,0 +1,647 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression, Crc};
+
+use super::*;
+use biome::Biome;
+use chunk::Chunk;
+use column::{ChunkColumn, Section, WorldHeight};
+use loot::{Container, ItemStack};
+use world::{block_remap, REGISTRY_V1};
+
+/// Columns per region side, a region file holds `REGION_SIZE * REGION_SIZE` columns
+pub const REGION_SIZE: i32 = 32;
+const SECTOR_SIZE: u64 = 4096;
+const ENTRY_COUNT: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"VXRG";
+/// Version 2 adds a crc32 of every payload, version 1 files can be read and upgraded with `upgrade`
+const REGION_VERSION: u32 = 2;
+/// Bumped whenever the column encoding changes, older versions are upgraded in `decode_column`
+pub const COLUMN_VERSION: u32 = 2;
+
+/// Sector 0 holds the magic and version, then the location table and the timestamps
+const LOCATION_OFFSET: u64 = SECTOR_SIZE;
+const TIMESTAMP_OFFSET: u64 = LOCATION_OFFSET + (ENTRY_COUNT * 8) as u64;
+const HEADER_SECTORS: u32 = ((TIMESTAMP_OFFSET + (ENTRY_COUNT * 8) as u64) / SECTOR_SIZE) as u32;
+
+/// Where a column is stored, `sectors == 0` means it was never written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Location {
+    sector: u32,
+    sectors: u32,
+}
+
+impl Location {
+    fn range(&self) -> std::ops::Range<usize> {
+        self.sector as usize..(self.sector + self.sectors) as usize
+    }
+}
+
+/// One file of `REGION_SIZE * REGION_SIZE` columns in 4096 byte sectors.
+///
+/// Writes never touch sectors in use, the payload goes to free sectors and is synced
+/// before the location entry points at it, so a crash leaves either the old or the new column.
+/// Every payload is stored as `[len u32][crc32 u32][payload]`, damage is reported by `read`.
+pub struct RegionFile {
+    path: PathBuf,
+    file: File,
+    version: u32,
+    locations: Vec<Location>,
+    timestamps: Vec<u64>,
+    /// Per sector, false can be reused
+    used: Vec<bool>,
+    /// Entries with an invalid location, their columns can only come back from a backup
+    dropped: Vec<usize>,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn checksum(payload: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(payload);
+    crc.sum()
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+impl RegionFile {
+    /// Creates the file with an empty header if it doesn't exist or is empty
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path).map_err(error)?;
+
+        let header_len = HEADER_SECTORS as u64 * SECTOR_SIZE;
+        let len = file.metadata().map_err(error)?.len();
+        // only a new file gets a header, a shorter one is damaged and has to be reported
+        if len > 0 && len < header_len {
+            return Err(format!("{}: truncated region header", path.display()));
+        }
+        if len == 0 {
+            let mut header = vec![0u8; header_len as usize];
+            header[0..4].copy_from_slice(MAGIC);
+            header[4..8].copy_from_slice(&REGION_VERSION.to_le_bytes());
+            file.seek(SeekFrom::Start(0)).map_err(error)?;
+            file.write_all(&header).map_err(error)?;
+            file.sync_all().map_err(error)?;
+        }
+
+        let mut header = vec![0u8; header_len as usize];
+        file.seek(SeekFrom::Start(0)).map_err(error)?;
+        file.read_exact(&mut header).map_err(error)?;
+        if &header[0..4] != MAGIC {
+            return Err(format!("{}: not a region file", path.display()));
+        }
+        let version = read_u32(&header, 4);
+        if version == 0 || version > REGION_VERSION {
+            return Err(format!("{}: unsupported region version {}", path.display(), version));
+        }
+
+        let file_sectors = file.metadata().map_err(error)?.len().div_ceil(SECTOR_SIZE) as usize;
+        let mut used = vec![false; file_sectors];
+        used[..HEADER_SECTORS as usize].fill(true);
+
+        let mut locations = vec![Location::default(); ENTRY_COUNT];
+        let mut timestamps = vec![0; ENTRY_COUNT];
+        let mut dropped = vec![];
+        for index in 0..ENTRY_COUNT {
+            let offset = LOCATION_OFFSET as usize + index * 8;
+            let location = Location { sector: read_u32(&header, offset), sectors: read_u32(&header, offset + 4) };
+            timestamps[index] = read_u64(&header, TIMESTAMP_OFFSET as usize + index * 8);
+            if location.sectors == 0 {
+                continue;
+            }
+
+            // a location past the end or over another column can only come from a damaged file, that column is lost
+            let valid = location.sector >= HEADER_SECTORS && location.range().end <= file_sectors && !used[location.range()].iter().any(|used| *used);
+            if !valid {
+                log::warn!("{}: column {} has an invalid location {:?}, dropping it", path.display(), index, location);
+                dropped.push(index);
+                continue;
+            }
+            used[location.range()].fill(true);
+            locations[index] = location;
+        }
+
+        Ok(Self { path, file, version, locations, timestamps, used, dropped })
+    }
+
+    fn index(local_x: i32, local_z: i32) -> usize {
+        (local_x + local_z * REGION_SIZE) as usize
+    }
+
+    fn local(index: usize) -> (i32, i32) {
+        (index as i32 % REGION_SIZE, index as i32 / REGION_SIZE)
+    }
+
+    /// Local positions of every written column
+    pub fn columns(&self) -> Vec<(i32, i32)> {
+        (0..ENTRY_COUNT).filter(|index| self.locations[*index].sectors != 0).map(Self::local).collect()
+    }
+
+    /// Local positions of the columns `open` dropped because their location was damaged
+    pub fn dropped(&self) -> Vec<(i32, i32)> {
+        self.dropped.iter().map(|index| Self::local(*index)).collect()
+    }
+
+    pub fn contains(&self, local_x: i32, local_z: i32) -> bool {
+        self.locations[Self::index(local_x, local_z)].sectors != 0
+    }
+
+    /// Seconds since the unix epoch of the last write, 0 if never written
+    pub fn timestamp(&self, local_x: i32, local_z: i32) -> u64 {
+        self.timestamps[Self::index(local_x, local_z)]
+    }
+
+    /// Payload as passed to `write`, `None` if the column was never written, an error if its checksum doesn't match
+    pub fn read(&mut self, local_x: i32, local_z: i32) -> Result<Option<Vec<u8>>, String> {
+        let location = self.locations[Self::index(local_x, local_z)];
+        if location.sectors == 0 {
+            return Ok(None);
+        }
+        let error = |e: std::io::Error| format!("{}: {}", self.path.display(), e);
+
+        let mut sectors = vec![0u8; (location.sectors as u64 * SECTOR_SIZE) as usize];
+        self.file.seek(SeekFrom::Start(location.sector as u64 * SECTOR_SIZE)).map_err(error)?;
+        self.file.read_exact(&mut sectors).map_err(error)?;
+
+        let len = read_u32(&sectors, 0) as usize;
+        let start = if self.version == 1 { 4 } else { 8 };
+        if len + start > sectors.len() {
+            return Err(format!("{}: column ({}, {}) has a length past its sectors", self.path.display(), local_x, local_z));
+        }
+        let payload = &sectors[start..start + len];
+        if self.version > 1 && checksum(payload) != read_u32(&sectors, 4) {
+            return Err(format!("{}: column ({}, {}) doesn't match its checksum", self.path.display(), local_x, local_z));
+        }
+        Ok(Some(payload.to_vec()))
+    }
+
+    /// First run of `count` free sectors, appends to the file when there is none
+    fn allocate(&mut self, count: usize) -> usize {
+        let mut run = 0;
+        for sector in 0..self.used.len() {
+            run = if self.used[sector] { 0 } else { run + 1 };
+            if run == count {
+                return sector + 1 - count;
+            }
+        }
+        // a free run at the end of the file can be extended
+        let start = self.used.len() - run;
+        self.used.resize(start + count, false);
+        start
+    }
+
+    pub fn write(&mut self, local_x: i32, local_z: i32, payload: &[u8]) -> Result<(), String> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        self.write_entry(Self::index(local_x, local_z), payload, timestamp)
+    }
+
+    fn write_entry(&mut self, index: usize, payload: &[u8], timestamp: u64) -> Result<(), String> {
+        if self.version != REGION_VERSION {
+            return Err(format!("{}: region version {} is read only, open the world to upgrade it", self.path.display(), self.version));
+        }
+        let mut data = (payload.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(&checksum(payload).to_le_bytes());
+        data.extend_from_slice(payload);
+        let count = (data.len() as u64).div_ceil(SECTOR_SIZE) as usize;
+        data.resize(count * SECTOR_SIZE as usize, 0);
+
+        // the old sectors stay reserved until the new location is on disk
+        let start = self.allocate(count);
+        let error = |e: std::io::Error| format!("{}: {}", self.path.display(), e);
+        self.file.seek(SeekFrom::Start(start as u64 * SECTOR_SIZE)).map_err(error)?;
+        self.file.write_all(&data).map_err(error)?;
+        self.file.sync_data().map_err(error)?;
+
+        let location = Location { sector: start as u32, sectors: count as u32 };
+
+        let mut entry = location.sector.to_le_bytes().to_vec();
+        entry.extend_from_slice(&location.sectors.to_le_bytes());
+        self.file.seek(SeekFrom::Start(LOCATION_OFFSET + index as u64 * 8)).map_err(error)?;
+        self.file.write_all(&entry).map_err(error)?;
+        self.file.seek(SeekFrom::Start(TIMESTAMP_OFFSET + index as u64 * 8)).map_err(error)?;
+        self.file.write_all(&timestamp.to_le_bytes()).map_err(error)?;
+        self.file.sync_data().map_err(error)?;
+
+        let old = std::mem::replace(&mut self.locations[index], location);
+        self.used[old.range()].fill(false);
+        self.used[location.range()].fill(true);
+        self.timestamps[index] = timestamp;
+        self.dropped.retain(|dropped| *dropped != index);
+        Ok(())
+    }
+
+    /// Rewrites an older region file in the current version next to it and replaces it, returns false if it was current.
+    /// Columns that can't be read are left out with a warning.
+    pub fn upgrade(path: impl AsRef<Path>) -> Result<bool, String> {
+        let path = path.as_ref();
+        let mut old = Self::open(path)?;
+        if old.version == REGION_VERSION {
+            return Ok(false);
+        }
+
+        let tmp = path.with_extension("region.tmp");
+        let _ = std::fs::remove_file(&tmp);
+        let mut new = Self::open(&tmp)?;
+        for index in 0..ENTRY_COUNT {
+            let (local_x, local_z) = Self::local(index);
+            match old.read(local_x, local_z) {
+                Ok(Some(payload)) => new.write_entry(index, &payload, old.timestamps[index])?,
+                Ok(None) => {}
+                Err(e) => log::warn!("{}, leaving it out of the upgraded file", e),
+            }
+        }
+        drop((old, new));
+        std::fs::rename(&tmp, path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(true)
+    }
+}
+
+/// Little endian writer for the column encoding
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.offset..self.offset + len).ok_or("column data ends early")?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "invalid utf8 in column data".to_owned())
+    }
+}
+
+const SECTION_EMPTY: u8 = 0;
+const SECTION_UNIFORM: u8 = 1;
+const SECTION_BLOCKS: u8 = 2;
+
+/// Version, block names, position, biomes and every section with its containers, zlib compressed
+pub fn encode_column(column: &ChunkColumn) -> Vec<u8> {
+    let mut encoder = Encoder { bytes: vec![] };
+    encoder.u32(COLUMN_VERSION);
+    // ids are only meaningful with the registry they were written with
+    encoder.u32(block::BLOCKS.len() as u32);
+    block::BLOCKS.iter().for_each(|block| encoder.str(block.name));
+    encoder.i32(column.x);
+    encoder.i32(column.z);
+    encoder.i32(column.bottom);
+    encoder.bytes.extend(column.biomes.iter().map(|biome| *biome as u8));
+
+    encoder.u32(column.sections.len() as u32);
+    for section in &column.sections {
+        match section {
+            Section::Empty => encoder.u8(SECTION_EMPTY),
+            Section::Uniform(block) => {
+                encoder.u8(SECTION_UNIFORM);
+                encoder.u8(*block);
+            }
+            Section::Blocks(chunk) => {
+                encoder.u8(SECTION_BLOCKS);
+                encoder.bytes.extend_from_slice(&chunk.mats.mats);
+                encoder.u32(chunk.containers.len() as u32);
+                for container in &chunk.containers {
+                    container.pos.iter().for_each(|v| encoder.i32(*v));
+                    encoder.u32(container.items.len() as u32);
+                    for stack in &container.items {
+                        encoder.str(&stack.item);
+                        encoder.u32(stack.count);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+    zlib.write_all(&encoder.bytes).unwrap();
+    zlib.finish().unwrap()
+}
+
+pub fn decode_column(data: &[u8], height: WorldHeight) -> Result<ChunkColumn, String> {
+    let mut bytes = vec![];
+    ZlibDecoder::new(data).read_to_end(&mut bytes).map_err(|e| format!("invalid column data: {}", e))?;
+    let mut decoder = Decoder { bytes: &bytes, offset: 0 };
+
+    let version = decoder.u32()?;
+    let registry: Vec<String> = match version {
+        1 => REGISTRY_V1.iter().map(|name| name.to_string()).collect(),
+        2 => (0..decoder.u32()?).map(|_| decoder.str()).collect::<Result<_, _>>()?,
+        _ => return Err(format!("column version {} is newer than {}", version, COLUMN_VERSION)),
+    };
+    let remap = block_remap(&registry);
+    let [x, z, bottom] = [decoder.i32()?, decoder.i32()?, decoder.i32()?];
+    let biomes: Vec<Biome> = decoder.take(CHUNK_RESOLUTION * CHUNK_RESOLUTION)?.iter().map(|biome| Biome::from(*biome)).collect();
+
+    // the highest stored id past the registry, for one warning per column
+    let mut unknown = None;
+    let mut remap_id = |stored: u8| {
+        if stored as usize >= registry.len() {
+            unknown = unknown.max(Some(stored));
+        }
+        remap[stored as usize]
+    };
+
+    let section_count = decoder.u32()?;
+    let mut sections = Vec::with_capacity(section_count as usize);
+    for _ in 0..section_count {
+        let section = match decoder.u8()? {
+            SECTION_EMPTY => Section::Empty,
+            SECTION_UNIFORM => Section::Uniform(remap_id(decoder.u8()?)),
+            SECTION_BLOCKS => {
+                let mut chunk = Chunk::filled(block::AIR, biomes.clone());
+                let mats = decoder.take(CHUNK_RESOLUTION * CHUNK_RESOLUTION * CHUNK_RESOLUTION)?;
+                chunk.mats.mats.iter_mut().zip(mats).for_each(|(block, stored)| *block = remap_id(*stored));
+                for _ in 0..decoder.u32()? {
+                    let pos = [decoder.i32()?, decoder.i32()?, decoder.i32()?];
+                    let mut items = vec![];
+                    for _ in 0..decoder.u32()? {
+                        items.push(ItemStack { item: decoder.str()?, count: decoder.u32()? });
+                    }
+                    chunk.containers.push(Container { pos, items });
+                }
+                Section::Blocks(Box::new(chunk))
+            }
+            tag => return Err(format!("unknown section tag {}", tag)),
+        };
+        sections.push(section);
+    }
+    if let Some(id) = unknown {
+        log::warn!("column ({}, {}) has block ids up to {} but a block table of {}, loading them as stone", x, z, id, registry.len());
+    }
+
+    Ok(ChunkColumn::from_sections(x, z, bottom, sections, biomes, height))
+}
+
+/// File name of the region holding region coordinates `region`
+pub fn region_file_name(region: [i32; 2]) -> String {
+    format!("r.{}.{}.region", region[0], region[1])
+}
+
+/// Region coordinates of every region file in `dir`, sorted
+pub fn region_files(dir: &Path) -> Result<Vec<[i32; 2]>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut regions: Vec<[i32; 2]> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let (x, z) = name.strip_prefix("r.")?.strip_suffix(".region")?.split_once('.')?;
+            Some([x.parse().ok()?, z.parse().ok()?])
+        })
+        .collect();
+    regions.sort();
+    Ok(regions)
+}
+
+/// Region files of one world, `r.<x>.<z>.region` in `dir`
+pub struct RegionStorage {
+    dir: PathBuf,
+    height: WorldHeight,
+    regions: HashMap<[i32; 2], RegionFile>,
+}
+
+impl RegionStorage {
+    pub fn new(dir: impl AsRef<Path>, height: WorldHeight) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(Self { dir, height, regions: HashMap::new() })
+    }
+
+    /// Region file and the position inside it of a column
+    fn region(&mut self, x: i32, z: i32) -> Result<(&mut RegionFile, i32, i32), String> {
+        let region = [x.div_euclid(REGION_SIZE), z.div_euclid(REGION_SIZE)];
+        if !self.regions.contains_key(&region) {
+            let file = RegionFile::open(self.dir.join(region_file_name(region)))?;
+            self.regions.insert(region, file);
+        }
+        Ok((self.regions.get_mut(&region).unwrap(), x.rem_euclid(REGION_SIZE), z.rem_euclid(REGION_SIZE)))
+    }
+
+    /// `None` if the column was never saved
+    pub fn load_column(&mut self, x: i32, z: i32) -> Result<Option<ChunkColumn>, String> {
+        let height = self.height;
+        let (region, local_x, local_z) = self.region(x, z)?;
+        match region.read(local_x, local_z)? {
+            Some(data) => decode_column(&data, height).map(Some).map_err(|e| format!("column ({}, {}): {}", x, z, e)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_column(&mut self, column: &ChunkColumn) -> Result<(), String> {
+        self.save_encoded(column.x, column.z, &encode_column(column))
+    }
+
+    /// Output of `encode_column`, lets the encoding happen without holding the storage
+    pub fn save_encoded(&mut self, x: i32, z: i32, data: &[u8]) -> Result<(), String> {
+        let (region, local_x, local_z) = self.region(x, z)?;
+        region.write(local_x, local_z, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vulkanrust-region-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Stone section under a section of air with one planks block at local (x, `PLANKS_Y`, z)
+    const PLANKS_Y: i32 = CHUNK_RESOLUTION as i32 + 4;
+
+    fn column(x: i32, z: i32) -> ChunkColumn {
+        let biomes = vec![Biome::Plains; CHUNK_RESOLUTION * CHUNK_RESOLUTION];
+        let mut column = ChunkColumn::from_sections(x, z, 0, vec![Section::Uniform(block::STONE), Section::Empty], biomes, WorldHeight::default());
+        column.set_block(x as usize, PLANKS_Y, z as usize, block::PLANKS);
+        column
+    }
+
+    fn patch(path: &Path, offset: u64, bytes: &[u8]) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(bytes).unwrap();
+    }
+
+    fn location(path: &Path, local_x: i32, local_z: i32) -> Location {
+        let header = std::fs::read(path).unwrap();
+        let offset = LOCATION_OFFSET as usize + RegionFile::index(local_x, local_z) * 8;
+        Location { sector: read_u32(&header, offset), sectors: read_u32(&header, offset + 4) }
+    }
+
+    /// Two columns in region [0, 0], returns the path of the region file
+    fn write_region(dir: &Path) -> PathBuf {
+        let mut storage = RegionStorage::new(dir, WorldHeight::default()).unwrap();
+        storage.save_column(&column(0, 0)).unwrap();
+        storage.save_column(&column(1, 0)).unwrap();
+        dir.join(region_file_name([0, 0]))
+    }
+
+    #[test]
+    fn columns_round_trip() {
+        let dir = scratch("round-trip");
+        write_region(&dir);
+
+        let mut storage = RegionStorage::new(&dir, WorldHeight::default()).unwrap();
+        let loaded = storage.load_column(1, 0).unwrap().unwrap();
+        assert_eq!((loaded.x, loaded.z, loaded.bottom), (1, 0, 0));
+        assert_eq!(loaded.block(1, PLANKS_Y, 0), Some(block::PLANKS));
+        assert_eq!(loaded.block(0, PLANKS_Y, 0), Some(block::AIR));
+        assert_eq!(loaded.block(5, 3, 5), Some(block::STONE));
+        assert!(storage.load_column(2, 0).unwrap().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn damaged_payload_is_reported() {
+        let dir = scratch("payload");
+        let path = write_region(&dir);
+        let damaged = location(&path, 0, 0);
+        patch(&path, damaged.sector as u64 * SECTOR_SIZE + 12, &[0xff; 4]);
+
+        let mut file = RegionFile::open(&path).unwrap();
+        assert!(file.dropped().is_empty());
+        let error = file.read(0, 0).unwrap_err();
+        assert!(error.contains("checksum"), "{}", error);
+        assert!(file.read(1, 0).unwrap().is_some());
+
+        let mut storage = RegionStorage::new(&dir, WorldHeight::default()).unwrap();
+        assert!(storage.load_column(0, 0).is_err());
+        assert!(storage.load_column(1, 0).unwrap().is_some());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn damaged_location_is_dropped() {
+        let dir = scratch("location");
+        let path = write_region(&dir);
+        // past the end of the file
+        patch(&path, LOCATION_OFFSET, &1000u32.to_le_bytes());
+
+        let mut file = RegionFile::open(&path).unwrap();
+        assert_eq!(file.dropped(), vec![(0, 0)]);
+        assert_eq!(file.columns(), vec![(1, 0)]);
+        assert!(file.read(0, 0).unwrap().is_none());
+
+        // writing the column again clears it from the dropped ones
+        file.write(0, 0, &encode_column(&column(0, 0))).unwrap();
+        assert!(file.dropped().is_empty());
+        assert_eq!(decode_column(&file.read(0, 0).unwrap().unwrap(), WorldHeight::default()).unwrap().block(0, PLANKS_Y, 0), Some(block::PLANKS));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn overlapping_locations_are_dropped() {
+        let dir = scratch("overlap");
+        let path = write_region(&dir);
+        let first = location(&path, 0, 0);
+        patch(&path, LOCATION_OFFSET + 8, &first.sector.to_le_bytes());
+
+        let file = RegionFile::open(&path).unwrap();
+        assert_eq!(file.dropped(), vec![(1, 0)]);
+        assert_eq!(file.columns(), vec![(0, 0)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn damaged_magic_is_rejected() {
+        let dir = scratch("magic");
+        let path = write_region(&dir);
+        patch(&path, 0, b"XXXX");
+        let error = RegionFile::open(&path).err().unwrap();
+        assert!(error.contains("not a region file"), "{}", error);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        let dir = scratch("truncated");
+        let path = write_region(&dir);
+        std::fs::write(&path, b"VXRG").unwrap();
+        let error = RegionFile::open(&path).err().unwrap();
+        assert!(error.contains("truncated"), "{}", error);
+        assert_eq!(std::fs::read(&path).unwrap(), b"VXRG");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn version_1_is_upgraded() {
+        let dir = scratch("upgrade");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(region_file_name([0, 0]));
+
+        // version 1 stores `[len u32][payload]` without a checksum
+        let payload = encode_column(&column(3, 4));
+        let mut data = vec![0u8; HEADER_SECTORS as usize * SECTOR_SIZE as usize];
+        data[0..4].copy_from_slice(MAGIC);
+        data[4..8].copy_from_slice(&1u32.to_le_bytes());
+        let offset = LOCATION_OFFSET as usize + RegionFile::index(3, 4) * 8;
+        data[offset..offset + 4].copy_from_slice(&HEADER_SECTORS.to_le_bytes());
+        data[offset + 4..offset + 8].copy_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(&payload);
+        data.resize((HEADER_SECTORS as usize + 1) * SECTOR_SIZE as usize, 0);
+        std::fs::write(&path, data).unwrap();
+
+        let mut old = RegionFile::open(&path).unwrap();
+        assert_eq!(old.read(3, 4).unwrap(), Some(payload.clone()));
+        assert!(old.write(3, 4, &payload).unwrap_err().contains("read only"));
+        drop(old);
+
+        assert!(RegionFile::upgrade(&path).unwrap());
+        assert!(!RegionFile::upgrade(&path).unwrap());
+        let mut file = RegionFile::open(&path).unwrap();
+        assert_eq!(file.read(3, 4).unwrap(), Some(payload));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,385 @@
+use std::{collections::HashMap, path::Path};
+
+use super::*;
+use anvil::{items, minecraft_block};
+use block::BlockId;
+use loot::Container;
+use nbt::Tag;
+use volume::BlockVolume;
+use world::World;
+
+const USAGE: &str = "usage: schematic paste <file> <world> <x> <y> <z> [quarter turns] [--air]";
+/// Largest volume a schematic may have, a damaged size would otherwise allocate gigabytes
+const MAX_VOLUME: usize = 1 << 28;
+
+/// Blocks by name, unknown names become stone and are counted for a warning
+struct BlockNames {
+    unknown: HashMap<String, usize>,
+}
+
+impl BlockNames {
+    /// `minecraft:oak_log[axis=y]`, the block state properties are dropped
+    fn block(&mut self, name: &str) -> Option<BlockId> {
+        let name = name.split('[').next().unwrap();
+        if name == "minecraft:structure_void" {
+            return None;
+        }
+        Some(minecraft_block(name).unwrap_or_else(|| {
+            *self.unknown.entry(name.to_owned()).or_insert(0) += 1;
+            block::STONE
+        }))
+    }
+
+    fn warn(&self, path: &Path) {
+        let mut unknown: Vec<_> = self.unknown.iter().collect();
+        unknown.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (name, count) in unknown {
+            log::warn!("{}: {} is unknown, {} palette entries used stone instead", path.display(), name, count);
+        }
+    }
+}
+
+/// Pre 1.13 block ids of MCEdit schematics, data values are ignored
+fn legacy_block(id: u16) -> Option<BlockId> {
+    Some(match id {
+        0 | 6 | 31 | 32 | 37 | 38 | 39 | 40 | 50 | 59 | 63 | 65 | 68 | 69 | 75 | 76 | 77 | 83 | 106 | 175 => block::AIR,
+        1 | 7 | 14 | 15 | 16 | 21 | 56 | 73 | 74 | 97 | 98 | 129 | 159 | 172 => block::STONE,
+        3 | 60 | 88 => block::DIRT,
+        2 | 18 | 110 | 161 => block::GRASS,
+        12 | 24 | 128 | 179 => block::SAND,
+        8 | 9 => block::WATER,
+        78 | 79 | 80 | 174 => block::SNOW,
+        13 => block::GRAVEL,
+        5 | 53 | 85 | 125 | 126 | 134 | 135 | 136 | 163 | 164 => block::PLANKS,
+        17 | 162 => block::LOG,
+        4 | 48 | 67 | 139 => block::COBBLESTONE,
+        54 | 146 => block::CHEST,
+        _ => return None,
+    })
+}
+
+/// Sponge block data, one varint palette index per block
+fn varints(bytes: &[i8], count: usize) -> Result<Vec<usize>, String> {
+    let mut values = Vec::with_capacity(count);
+    let mut bytes = bytes.iter().map(|b| *b as u8);
+    while values.len() < count {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = bytes.next().ok_or("block data ends early")?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift > 28 {
+                return Err("varint in block data is too long".to_owned());
+            }
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Litematica packs indices without padding, an entry can span two longs
+fn unpack_tight(data: &[i64], bits: usize, count: usize) -> Vec<usize> {
+    let mask = (1u64 << bits) - 1;
+    (0..count)
+        .map(|i| {
+            let start = i * bits;
+            let (long, offset) = (start / 64, start % 64);
+            let low = data.get(long).map_or(0, |v| *v as u64) >> offset;
+            let high = if offset + bits > 64 { data.get(long + 1).map_or(0, |v| *v as u64) << (64 - offset) } else { 0 };
+            ((low | high) & mask) as usize
+        })
+        .collect()
+}
+
+fn size_field(tag: &Tag, name: &str) -> Result<i32, String> {
+    tag.get(name).and_then(Tag::as_i64).map(|v| v as i32).ok_or_else(|| format!("missing {}", name))
+}
+
+/// Blocks in a volume of `size`, an error past `MAX_VOLUME`
+fn volume_len(size: [i32; 3]) -> Result<usize, String> {
+    let too_large = || format!("a {}x{}x{} schematic is too large", size[0], size[1], size[2]);
+    let [x, y, z] = size.map(|v| v.max(0) as usize);
+    x.checked_mul(y).and_then(|xy| xy.checked_mul(z)).filter(|len| *len <= MAX_VOLUME).ok_or_else(too_large)
+}
+
+/// Sponge version 1 to 3, version 3 nests everything under `Blocks`
+fn read_sponge(root: &Tag, names: &mut BlockNames) -> Result<BlockVolume, String> {
+    let root = root.get("Schematic").unwrap_or(root);
+    let size = [size_field(root, "Width")? as u16 as i32, size_field(root, "Height")? as u16 as i32, size_field(root, "Length")? as u16 as i32];
+    let blocks = root.get("Blocks").unwrap_or(root);
+
+    let Some(Tag::Compound(palette)) = blocks.get("Palette") else {
+        return Err("missing block palette".to_owned());
+    };
+    let mut by_index = HashMap::new();
+    for (name, index) in palette {
+        by_index.insert(index.as_i64().ok_or("palette index is not a number")? as usize, names.block(name));
+    }
+
+    let data = blocks.get("BlockData").or_else(|| blocks.get("Data")).and_then(Tag::as_byte_array).ok_or("missing block data")?;
+    let count = volume_len(size)?;
+    let mut volume = BlockVolume::new(size);
+    // sponge orders blocks x, then z, then y like our volumes
+    for (i, index) in varints(data, count)?.into_iter().enumerate() {
+        volume.blocks[i] = *by_index.get(&index).ok_or_else(|| format!("block data uses palette index {} which doesn't exist", index))?;
+    }
+
+    let entities = blocks.get("BlockEntities").or_else(|| root.get("TileEntities")).and_then(Tag::as_list).unwrap_or(&[]);
+    for entity in entities {
+        let (Some(id), Some(pos)) = (entity.get("Id").and_then(Tag::as_str), entity.get("Pos").and_then(Tag::as_int_array)) else {
+            continue;
+        };
+        if minecraft_block(id) == Some(block::CHEST) && pos.len() == 3 {
+            let data = entity.get("Data").unwrap_or(entity);
+            volume.containers.push(Container { pos: [pos[0], pos[1], pos[2]], items: items(data) });
+        }
+    }
+    Ok(volume)
+}
+
+/// Every region of a litematic in one volume, regions with negative sizes grow from their position towards negative
+fn read_litematic(root: &Tag, names: &mut BlockNames) -> Result<BlockVolume, String> {
+    let Some(Tag::Compound(regions)) = root.get("Regions") else {
+        return Err("missing regions".to_owned());
+    };
+
+    let mut boxes = vec![];
+    for (name, region) in regions {
+        let error = |e: String| format!("region {}: {}", name, e);
+        let position = region.get("Position").ok_or_else(|| error("missing Position".to_owned()))?;
+        let size = region.get("Size").ok_or_else(|| error("missing Size".to_owned()))?;
+        let position = [size_field(position, "x").map_err(error)?, size_field(position, "y").map_err(error)?, size_field(position, "z").map_err(error)?];
+        let size = [size_field(size, "x").map_err(error)?, size_field(size, "y").map_err(error)?, size_field(size, "z").map_err(error)?];
+        let min: [i32; 3] = std::array::from_fn(|i| if size[i] < 0 { position[i] + size[i] + 1 } else { position[i] });
+        boxes.push((name, region, min, size.map(i32::abs)));
+    }
+
+    let min: [i32; 3] = std::array::from_fn(|i| boxes.iter().map(|(_, _, min, _)| min[i]).min().unwrap_or(0));
+    let max: [i32; 3] = std::array::from_fn(|i| boxes.iter().map(|(_, _, min, size)| min[i] + size[i]).max().unwrap_or(0));
+    let size: [i32; 3] = std::array::from_fn(|i| max[i] - min[i]);
+    volume_len(size)?;
+    let mut volume = BlockVolume::new(size);
+
+    for (name, region, region_min, size) in boxes {
+        let palette: Vec<Option<BlockId>> = region
+            .get("BlockStatePalette")
+            .and_then(Tag::as_list)
+            .ok_or_else(|| format!("region {}: missing palette", name))?
+            .iter()
+            .map(|entry| names.block(entry.get("Name").and_then(Tag::as_str).unwrap_or("minecraft:air")))
+            .collect();
+        let data = region.get("BlockStates").and_then(Tag::as_long_array).ok_or_else(|| format!("region {}: missing block states", name))?;
+
+        let count = volume_len(size).map_err(|e| format!("region {}: {}", name, e))?;
+        let bits = (usize::BITS - (palette.len().max(1) - 1).leading_zeros()).max(2) as usize;
+        let offset: [i32; 3] = std::array::from_fn(|i| region_min[i] - min[i]);
+        for (i, index) in unpack_tight(data, bits, count).into_iter().enumerate() {
+            let i = i as i32;
+            let pos = [i % size[0], i / (size[0] * size[2]), (i / size[0]) % size[2]];
+            volume.set([pos[0] + offset[0], pos[1] + offset[1], pos[2] + offset[2]], palette.get(index).copied().flatten());
+        }
+
+        for entity in region.get("TileEntities").and_then(Tag::as_list).unwrap_or(&[]) {
+            let pos = [size_field(entity, "x"), size_field(entity, "y"), size_field(entity, "z")];
+            if let [Ok(x), Ok(y), Ok(z)] = pos {
+                let pos = [x + offset[0], y + offset[1], z + offset[2]];
+                if volume.get(pos) == Some(block::CHEST) {
+                    volume.containers.push(Container { pos, items: items(entity) });
+                }
+            }
+        }
+    }
+    Ok(volume)
+}
+
+/// MCEdit schematics store numeric ids, `AddBlocks` holds the high nibble of ids above 255
+fn read_legacy(root: &Tag, names: &mut BlockNames) -> Result<BlockVolume, String> {
+    let size = [size_field(root, "Width")? as u16 as i32, size_field(root, "Height")? as u16 as i32, size_field(root, "Length")? as u16 as i32];
+    let ids = root.get("Blocks").and_then(Tag::as_byte_array).ok_or("missing Blocks")?;
+    let add = root.get("AddBlocks").and_then(Tag::as_byte_array).unwrap_or(&[]);
+
+    let count = volume_len(size)?;
+    if ids.len() < count {
+        return Err(format!("{} blocks for a {}x{}x{} schematic", ids.len(), size[0], size[1], size[2]));
+    }
+    let mut volume = BlockVolume::new(size);
+    for (i, low) in ids.iter().take(count).enumerate() {
+        let high = add.get(i / 2).map_or(0, |nibbles| if i % 2 == 0 { (*nibbles as u8) >> 4 } else { *nibbles as u8 & 0xf });
+        let id = *low as u8 as u16 | (high as u16) << 8;
+        volume.blocks[i] = Some(legacy_block(id).unwrap_or_else(|| {
+            *names.unknown.entry(format!("legacy id {}", id)).or_insert(0) += 1;
+            block::STONE
+        }));
+    }
+
+    for entity in root.get("TileEntities").and_then(Tag::as_list).unwrap_or(&[]) {
+        let pos = [size_field(entity, "x"), size_field(entity, "y"), size_field(entity, "z")];
+        if let [Ok(x), Ok(y), Ok(z)] = pos {
+            if volume.get([x, y, z]) == Some(block::CHEST) {
+                volume.containers.push(Container { pos: [x, y, z], items: items(entity) });
+            }
+        }
+    }
+    Ok(volume)
+}
+
+/// `.schem`, `.litematic` or `.schematic`, picked by extension
+pub fn load(path: impl AsRef<Path>) -> Result<BlockVolume, String> {
+    let path = path.as_ref();
+    let error = |e: String| format!("{}: {}", path.display(), e);
+    let bytes = std::fs::read(path).map_err(|e| error(e.to_string()))?;
+    let (_, root) = nbt::read_compressed(&bytes).map_err(error)?;
+
+    let mut names = BlockNames { unknown: HashMap::new() };
+    let volume = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("schem") => read_sponge(&root, &mut names),
+        Some("litematic") => read_litematic(&root, &mut names),
+        Some("schematic") => read_legacy(&root, &mut names),
+        _ => Err("unknown schematic extension, expected .schem, .litematic or .schematic".to_owned()),
+    }
+    .map_err(error)?;
+    names.warn(path);
+    Ok(volume)
+}
+
+fn run_command(args: &[String]) -> Result<(), String> {
+    let include_air = args.iter().any(|arg| arg == "--air");
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "--air").collect();
+    if args.first().map(|arg| arg.as_str()) != Some("paste") || !(6..=7).contains(&args.len()) {
+        return Err(USAGE.to_owned());
+    }
+
+    let number = |arg: &String| arg.parse::<i32>().map_err(|_| format!("invalid number '{}'", arg));
+    let origin = [number(args[3])?, number(args[4])?, number(args[5])?];
+    let rotation = args.get(6).map(|arg| number(arg)).transpose()?.unwrap_or(0).rem_euclid(4) as u8;
+
+    let volume = load(args[1])?;
+    let mut manager = World::open_or_create(args[2])?.chunk_manager()?;
+
+    let placed = volume.place(&mut manager, origin, rotation, include_air);
+    manager.save();
+    println!("placed {} blocks of a {}x{}x{} volume into {}", placed, volume.size[0], volume.size[1], volume.size[2], args[2]);
+    Ok(())
+}
+
+/// Entry point for `vulkanrust schematic ...`
+pub fn run(args: &[String]) {
+    if let Err(e) = run_command(args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> BlockNames {
+        BlockNames { unknown: HashMap::new() }
+    }
+
+    /// Litematica layout, the inverse of `unpack_tight`
+    fn pack_tight(indices: &[usize], bits: usize) -> Vec<i64> {
+        let mut data = vec![0u64; (indices.len() * bits).div_ceil(64)];
+        for (i, index) in indices.iter().enumerate() {
+            let (long, offset) = (i * bits / 64, i * bits % 64);
+            data[long] |= (*index as u64) << offset;
+            if offset + bits > 64 {
+                data[long + 1] |= (*index as u64) >> (64 - offset);
+            }
+        }
+        data.into_iter().map(|long| long as i64).collect()
+    }
+
+    fn palette(names: &[&str]) -> Tag {
+        Tag::Compound(names.iter().enumerate().map(|(i, name)| (name.to_string(), Tag::Int(i as i32))).collect())
+    }
+
+    #[test]
+    fn varints_decode() {
+        // 300 takes two bytes
+        let bytes = [0x01, 0xac, 0x02, 0x7f].map(|b: u8| b as i8);
+        assert_eq!(varints(&bytes, 3).unwrap(), [1, 300, 127]);
+        assert_eq!(varints(&bytes, 4).unwrap_err(), "block data ends early");
+        assert_eq!(varints(&[0x80u8 as i8; 6], 1).unwrap_err(), "varint in block data is too long");
+    }
+
+    #[test]
+    fn tight_indices_span_longs() {
+        // 5 bits, the 13th entry starts at bit 60 of the first long and ends in the second
+        let data = [(0b0110 << 60) | 3, 0b1];
+        let indices = unpack_tight(&data, 5, 13);
+        assert_eq!(indices[0], 3);
+        assert_eq!(indices[12], 0b10110);
+        assert_eq!(pack_tight(&indices, 5), data);
+    }
+
+    #[test]
+    fn tight_indices_round_trip() {
+        for bits in [2, 3, 5, 7, 13] {
+            let indices: Vec<usize> = (0..1000).map(|i| (i * 31 + i / 3) % (1 << bits)).collect();
+            assert_eq!(unpack_tight(&pack_tight(&indices, bits), bits, indices.len()), indices, "{} bits", bits);
+        }
+    }
+
+    #[test]
+    fn sponge_blocks() {
+        // 2x1x2, x then z: stone, air, dirt, unknown
+        let data = [1, 0, 2, 3].map(|i: i8| i);
+        let blocks = Tag::compound(vec![("Palette", palette(&["minecraft:air", "minecraft:stone", "minecraft:dirt", "minecraft:magma_block"])), ("Data", Tag::ByteArray(data.to_vec()))]);
+        let root = Tag::compound(vec![("Schematic", Tag::compound(vec![("Width", Tag::Short(2)), ("Height", Tag::Short(1)), ("Length", Tag::Short(2)), ("Blocks", blocks)]))]);
+
+        let mut names = names();
+        let volume = read_sponge(&root, &mut names).unwrap();
+        assert_eq!(volume.size, [2, 1, 2]);
+        assert_eq!(volume.blocks, [Some(block::STONE), Some(block::AIR), Some(block::DIRT), Some(block::STONE)]);
+        assert_eq!(names.unknown["minecraft:magma_block"], 1);
+    }
+
+    #[test]
+    fn legacy_blocks() {
+        // ids 1, 3 and 54, then 256 + 1 from `AddBlocks` which is unknown
+        let root = Tag::compound(vec![
+            ("Width", Tag::Short(4)),
+            ("Height", Tag::Short(1)),
+            ("Length", Tag::Short(1)),
+            ("Blocks", Tag::ByteArray(vec![1, 3, 54, 1])),
+            ("AddBlocks", Tag::ByteArray(vec![0x00, 0x01])),
+        ]);
+        let mut names = names();
+        let volume = read_legacy(&root, &mut names).unwrap();
+        assert_eq!(volume.blocks, [Some(block::STONE), Some(block::DIRT), Some(block::CHEST), Some(block::STONE)]);
+        assert_eq!(names.unknown["legacy id 257"], 1);
+    }
+
+    #[test]
+    fn litematic_regions() {
+        // 3x1x1 growing towards negative x, structure void keeps the world block
+        let indices = [1, 2, 0];
+        let region = Tag::compound(vec![
+            ("Position", Tag::compound(vec![("x", Tag::Int(2)), ("y", Tag::Int(0)), ("z", Tag::Int(0))])),
+            ("Size", Tag::compound(vec![("x", Tag::Int(-3)), ("y", Tag::Int(1)), ("z", Tag::Int(1))])),
+            ("BlockStatePalette", Tag::List(["minecraft:air", "minecraft:oak_log[axis=y]", "minecraft:structure_void"].iter().map(|name| Tag::compound(vec![("Name", Tag::String(name.to_string()))])).collect())),
+            ("BlockStates", Tag::LongArray(pack_tight(&indices, 2))),
+        ]);
+        let root = Tag::compound(vec![("Regions", Tag::compound(vec![("main", region)]))]);
+        let volume = read_litematic(&root, &mut names()).unwrap();
+        assert_eq!(volume.size, [3, 1, 1]);
+        assert_eq!(volume.blocks, [Some(block::LOG), None, Some(block::AIR)]);
+    }
+
+    #[test]
+    fn oversized_volumes_are_rejected() {
+        let size = [("Width", Tag::Short(-1)), ("Height", Tag::Short(-1)), ("Length", Tag::Short(-1))];
+        let legacy = Tag::compound(size.iter().cloned().chain([("Blocks", Tag::ByteArray(vec![]))]).collect());
+        let error = read_legacy(&legacy, &mut names()).err().unwrap();
+        assert!(error.contains("65535x65535x65535 schematic is too large"), "{}", error);
+
+        let sponge = Tag::compound(size.iter().cloned().chain([("Palette", palette(&["minecraft:air"])), ("BlockData", Tag::ByteArray(vec![]))]).collect());
+        assert!(read_sponge(&sponge, &mut names()).err().unwrap().contains("too large"));
+    }
+}
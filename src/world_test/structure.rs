@@ -0,0 +1,677 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use super::*;
+use biome::Biome;
+use block::BlockId;
+use chunk::Chunk;
+use generation::Column;
+use loot::{Container, LootTable};
+use rng::{hash2, Rng};
+
+pub const STRUCTURES_PATH: &str = "assets/structures";
+const STRUCTURE_SETS_FILE: &str = "structures.cfg";
+const TEMPLATE_EXT: &str = "structure";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facing {
+    North, // -z
+    East,  // +x
+    South, // +z
+    West,  // -x
+}
+
+impl Facing {
+    const ALL: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+
+    fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "north" => Ok(Facing::North),
+            "east" => Ok(Facing::East),
+            "south" => Ok(Facing::South),
+            "west" => Ok(Facing::West),
+            _ => Err(format!("unknown facing '{}'", text)),
+        }
+    }
+
+    fn offset(&self) -> [i32; 2] {
+        match self {
+            Facing::North => [0, -1],
+            Facing::East => [1, 0],
+            Facing::South => [0, 1],
+            Facing::West => [-1, 0],
+        }
+    }
+
+    fn opposite(&self) -> Self {
+        self.rotate(2)
+    }
+
+    /// Clockwise quarter turns seen from above
+    fn rotate(&self, turns: u8) -> Self {
+        Self::ALL[(*self as usize + turns as usize) % 4]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Connector {
+    pub pos: [i32; 3],
+    pub facing: Facing,
+    /// Pool new pieces are taken from, `None` only accepts connections
+    pub pool: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    pub name: String,
+    pub size: [i32; 3],
+    /// `None` keeps whatever generation put there, indexed by `x + z * size.x + y * size.x * size.z`
+    pub blocks: Vec<Option<BlockId>>,
+    pub connectors: Vec<Connector>,
+    /// Containers and the loot table that fills them
+    pub loot: Vec<([i32; 3], String)>,
+}
+
+impl Template {
+    /// ```text
+    /// size = <x> <y> <z>
+    /// palette = <char>:<block>[@<loot table>] ...
+    /// connector = <x> <y> <z> <facing> [<pool>]
+    /// layer
+    /// <z rows of x chars>, starting at y = 0, a space keeps the terrain
+    /// ```
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut palette: HashMap<char, (BlockId, Option<String>)> = HashMap::new();
+        let mut connectors = vec![];
+        let mut layers: Vec<Vec<&str>> = vec![];
+
+        for line in text.lines() {
+            if let Some(layer) = layers.last_mut() {
+                if line.trim() != "layer" {
+                    layer.push(line);
+                    continue;
+                }
+            }
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if trimmed == "layer" {
+                layers.push(vec![]);
+                continue;
+            }
+
+            let (key, value) = trimmed.split_once('=').ok_or_else(|| format!("expected key = value, got '{}'", trimmed))?;
+            let words: Vec<&str> = value.split_whitespace().collect();
+            let int = |text: &str| text.parse::<i32>().map_err(|_| format!("invalid number '{}'", text));
+
+            match key.trim() {
+                "size" if words.len() == 3 => size = Some([int(words[0])?, int(words[1])?, int(words[2])?]),
+                "palette" => {
+                    for entry in words {
+                        let mut chars = entry.chars();
+                        let (symbol, rest) = match (chars.next(), chars.next()) {
+                            (Some(symbol), Some(':')) => (symbol, chars.as_str()),
+                            _ => return Err(format!("expected <char>:<block>, got '{}'", entry)),
+                        };
+                        let (block_name, loot) = match rest.split_once('@') {
+                            Some((block_name, loot)) => (block_name, Some(loot.to_owned())),
+                            None => (rest, None),
+                        };
+                        let block = block::by_name(block_name).ok_or_else(|| format!("unknown block '{}'", block_name))?;
+                        palette.insert(symbol, (block, loot));
+                    }
+                }
+                "connector" if words.len() == 4 || words.len() == 5 => connectors.push(Connector {
+                    pos: [int(words[0])?, int(words[1])?, int(words[2])?],
+                    facing: Facing::parse(words[3])?,
+                    pool: words.get(4).map(|pool| pool.to_string()),
+                }),
+                key => return Err(format!("invalid line '{}' for key '{}'", trimmed, key)),
+            }
+        }
+
+        let size = size.ok_or("template needs a size")?;
+        if size.iter().any(|v| *v <= 0) {
+            return Err(format!("invalid size {:?}", size));
+        }
+        if layers.len() != size[1] as usize {
+            return Err(format!("expected {} layers, got {}", size[1], layers.len()));
+        }
+
+        let mut blocks = vec![None; (size[0] * size[1] * size[2]) as usize];
+        let mut loot = vec![];
+        for (y, layer) in layers.iter().enumerate() {
+            let rows: Vec<&&str> = layer.iter().filter(|row| !row.is_empty()).collect();
+            if rows.len() != size[2] as usize {
+                return Err(format!("layer {} has {} rows, expected {}", y, rows.len(), size[2]));
+            }
+            for (z, row) in rows.iter().enumerate() {
+                for (x, symbol) in row.chars().take(size[0] as usize).enumerate() {
+                    if symbol == ' ' {
+                        continue;
+                    }
+                    let (block, table) = palette.get(&symbol).ok_or_else(|| format!("'{}' is not in the palette", symbol))?;
+                    let pos = [x as i32, y as i32, z as i32];
+                    blocks[x + z * size[0] as usize + y * (size[0] * size[2]) as usize] = Some(*block);
+                    if let Some(table) = table {
+                        loot.push((pos, table.clone()));
+                    }
+                }
+            }
+        }
+
+        Ok(Self { name: name.to_owned(), size, blocks, connectors, loot })
+    }
+
+    fn rotated_size(&self, rotation: u8) -> [i32; 3] {
+        if rotation.is_multiple_of(2) {
+            self.size
+        } else {
+            [self.size[2], self.size[1], self.size[0]]
+        }
+    }
+
+    /// Template position to position inside the rotated bounding box
+    fn rotate(&self, pos: [i32; 3], rotation: u8) -> [i32; 3] {
+        let [sx, _, sz] = self.size;
+        let [x, y, z] = pos;
+        match rotation % 4 {
+            0 => [x, y, z],
+            1 => [sz - 1 - z, y, x],
+            2 => [sx - 1 - x, y, sz - 1 - z],
+            _ => [z, y, sx - 1 - x],
+        }
+    }
+}
+
+struct Pool {
+    entries: Vec<(usize, u32)>,
+}
+
+impl Pool {
+    /// Templates in a random order, heavier ones tend to come first
+    fn shuffled(&self, rng: &mut Rng) -> Vec<usize> {
+        let mut entries = self.entries.clone();
+        let mut order = vec![];
+        while !entries.is_empty() {
+            let total: u32 = entries.iter().map(|(_, weight)| weight).sum();
+            let mut pick = (rng.next_u64() % total.max(1) as u64) as u32;
+            let index = entries
+                .iter()
+                .position(|(_, weight)| {
+                    if pick < *weight {
+                        return true;
+                    }
+                    pick -= weight;
+                    false
+                })
+                .unwrap_or(0);
+            order.push(entries.remove(index).0);
+        }
+        order
+    }
+}
+
+pub struct StructureSet {
+    pub name: String,
+    start_pool: String,
+    /// Connector steps away from the start piece
+    depth: u32,
+    /// Blocks from the start that pieces may reach
+    radius: i32,
+    /// Region size in chunks, at most one structure per region
+    spacing: i32,
+    /// Minimum chunks between structures of neighbouring regions
+    separation: i32,
+    biomes: Vec<Biome>,
+    /// Offset from the surface, negative puts it underground
+    y_offset: i32,
+    salt: u64,
+}
+
+impl StructureSet {
+    /// `<name> start=<pool> depth= radius= spacing= separation= biomes=a,b y_offset= salt=`
+    fn parse(name: &str, words: &[&str]) -> Result<Self, String> {
+        let mut set = Self { name: name.to_owned(), start_pool: String::new(), depth: 0, radius: 64, spacing: 8, separation: 2, biomes: Biome::ALL.to_vec(), y_offset: 0, salt: 0 };
+
+        for word in words {
+            let (key, value) = word.split_once('=').ok_or_else(|| format!("expected key=value, got '{}'", word))?;
+            let int = || value.parse::<i64>().map_err(|_| format!("invalid number {}={}", key, value));
+            match key {
+                "start" => set.start_pool = value.to_owned(),
+                "depth" => set.depth = int()? as u32,
+                "radius" => set.radius = int()? as i32,
+                "spacing" => set.spacing = int()? as i32,
+                "separation" => set.separation = int()? as i32,
+                "y_offset" => set.y_offset = int()? as i32,
+                "salt" => set.salt = int()? as u64,
+                "biomes" => {
+                    set.biomes = value
+                        .split(',')
+                        .map(|name| Biome::ALL.iter().copied().find(|biome| biome.name() == name).ok_or_else(|| format!("unknown biome '{}'", name)))
+                        .collect::<Result<_, _>>()?
+                }
+                _ => return Err(format!("unknown key '{}'", key)),
+            }
+        }
+
+        if set.start_pool.is_empty() {
+            return Err(format!("structure '{}' needs a start pool", name));
+        }
+        if set.spacing <= 0 {
+            return Err(format!("structure '{}' needs a positive spacing", name));
+        }
+        if set.separation < 0 || set.separation >= set.spacing {
+            return Err(format!("structure '{}' needs 0 <= separation < spacing", name));
+        }
+        Ok(set)
+    }
+
+    /// Chunk the structure of this region starts in
+    fn start_chunk(&self, seed: u64, region_x: i32, region_z: i32) -> [i32; 2] {
+        let mut rng = Rng::new(hash2(seed ^ self.salt, region_x, region_z));
+        let range = self.spacing - self.separation;
+        [region_x * self.spacing + rng.range(0, range), region_z * self.spacing + rng.range(0, range)]
+    }
+}
+
+struct Piece {
+    template: usize,
+    rotation: u8,
+    /// Min corner in global block coordinates
+    origin: [i32; 3],
+    size: [i32; 3],
+}
+
+impl Piece {
+    fn intersects(&self, other: &Piece) -> bool {
+        (0..3).all(|i| self.origin[i] < other.origin[i] + other.size[i] && other.origin[i] < self.origin[i] + self.size[i])
+    }
+}
+
+/// All pieces of one structure
+struct Assembled {
+    pieces: Vec<Piece>,
+    seed: u64,
+}
+
+/// Assembled structure per set index and region, `None` where the region has none
+type StructureCache = HashMap<(usize, i32, i32), Option<Arc<Assembled>>>;
+
+pub struct Structures {
+    templates: Vec<Template>,
+    pools: HashMap<String, Pool>,
+    loot_tables: HashMap<String, LootTable>,
+    sets: Vec<StructureSet>,
+    seed: u64,
+    cache: Mutex<StructureCache>,
+}
+
+impl Structures {
+    pub fn empty(seed: u64) -> Self {
+        Self { templates: vec![], pools: HashMap::new(), loot_tables: HashMap::new(), sets: vec![], seed, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Reads every `.structure` template in `dir` plus the pools, structure sets and loot tables from `structures.cfg`
+    pub fn load(dir: impl AsRef<Path>, seed: u64) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let mut structures = Self::empty(seed);
+
+        let mut paths: Vec<_> = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        // directory order is not stable, template indices end up in the generation
+        paths.sort();
+
+        for path in paths {
+            if path.extension().and_then(|ext| ext.to_str()) != Some(TEMPLATE_EXT) {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let template = Template::parse(&name, &text).map_err(|e| format!("{}: {}", path.display(), e))?;
+            structures.templates.push(template);
+        }
+
+        let path = dir.join(STRUCTURE_SETS_FILE);
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        for (i, line) in text.lines().enumerate() {
+            let err = |e: String| format!("{}:{}: {}", path.display(), i + 1, e);
+            let line = line.split('#').next().unwrap().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() < 2 {
+                continue;
+            }
+
+            match words[0] {
+                "pool" => {
+                    let mut entries = vec![];
+                    for entry in &words[2..] {
+                        let (name, weight) = entry.split_once(':').unwrap_or((entry, "1"));
+                        let template = structures.templates.iter().position(|t| t.name == name).ok_or_else(|| err(format!("unknown template '{}'", name)))?;
+                        let weight = weight.parse().map_err(|_| err(format!("invalid weight '{}'", weight)))?;
+                        entries.push((template, weight));
+                    }
+                    structures.pools.insert(words[1].to_owned(), Pool { entries });
+                }
+                "structure" => structures.sets.push(StructureSet::parse(words[1], &words[2..]).map_err(err)?),
+                "loot" => {
+                    structures.loot_tables.insert(words[1].to_owned(), LootTable::parse(&words[2..]).map_err(err)?);
+                }
+                other => return Err(err(format!("unknown entry '{}'", other))),
+            }
+        }
+
+        Ok(structures)
+    }
+
+    /// Jigsaw assembly, grows pieces from the start piece through their connectors
+    fn assemble(&self, set: &StructureSet, start: [i32; 3], seed: u64) -> Option<Assembled> {
+        let mut rng = Rng::new(seed);
+        let start_pool = self.pools.get(&set.start_pool)?;
+        let start_template = *start_pool.shuffled(&mut rng).first()?;
+        let rotation = rng.range(0, 4) as u8;
+
+        let template = &self.templates[start_template];
+        let size = template.rotated_size(rotation);
+        let origin = [start[0] - size[0] / 2, start[1], start[2] - size[2] / 2];
+        let mut pieces = vec![Piece { template: start_template, rotation, origin, size }];
+
+        // piece, connector, depth
+        let mut open: VecDeque<(usize, usize, u32)> = (0..template.connectors.len()).map(|c| (0, c, 0)).collect();
+
+        while let Some((piece_index, connector_index, depth)) = open.pop_front() {
+            if depth >= set.depth {
+                continue;
+            }
+            let piece = &pieces[piece_index];
+            let parent_template = &self.templates[piece.template];
+            let connector = &parent_template.connectors[connector_index];
+            let pool = match connector.pool.as_ref().and_then(|pool| self.pools.get(pool)) {
+                Some(pool) => pool,
+                None => continue,
+            };
+
+            let facing = connector.facing.rotate(piece.rotation);
+            let local = parent_template.rotate(connector.pos, piece.rotation);
+            let offset = facing.offset();
+            let target = [piece.origin[0] + local[0] + offset[0], piece.origin[1] + local[1], piece.origin[2] + local[2] + offset[1]];
+
+            'candidates: for candidate in pool.shuffled(&mut rng) {
+                let template = &self.templates[candidate];
+                let first_rotation = rng.range(0, 4) as u8;
+
+                for turn in 0..4 {
+                    let rotation = (first_rotation + turn) % 4;
+                    for (index, other) in template.connectors.iter().enumerate() {
+                        if other.facing.rotate(rotation) != facing.opposite() {
+                            continue;
+                        }
+
+                        let local = template.rotate(other.pos, rotation);
+                        let origin = [target[0] - local[0], target[1] - local[1], target[2] - local[2]];
+                        let new_piece = Piece { template: candidate, rotation, origin, size: template.rotated_size(rotation) };
+
+                        let outside = (0..3).step_by(2).any(|i| (new_piece.origin[i] - start[i]).abs().max((new_piece.origin[i] + new_piece.size[i] - start[i]).abs()) > set.radius);
+                        if outside || pieces.iter().any(|piece| piece.intersects(&new_piece)) {
+                            continue;
+                        }
+
+                        let new_index = pieces.len();
+                        pieces.push(new_piece);
+                        for c in 0..template.connectors.len() {
+                            if c != index {
+                                open.push_back((new_index, c, depth + 1));
+                            }
+                        }
+                        break 'candidates;
+                    }
+                }
+            }
+        }
+
+        Some(Assembled { pieces, seed })
+    }
+
+    fn structure(&self, set_index: usize, region_x: i32, region_z: i32, column_at: &dyn Fn(i32, i32) -> Column) -> Option<Arc<Assembled>> {
+        let key = (set_index, region_x, region_z);
+        if let Some(assembled) = self.cache.lock().unwrap().get(&key) {
+            return assembled.clone();
+        }
+
+        let set = &self.sets[set_index];
+        let chunk = set.start_chunk(self.seed, region_x, region_z);
+        let x = chunk[0] * CHUNK_RESOLUTION as i32 + CHUNK_RESOLUTION as i32 / 2;
+        let z = chunk[1] * CHUNK_RESOLUTION as i32 + CHUNK_RESOLUTION as i32 / 2;
+        let column = column_at(x, z);
+
+        let assembled = if set.biomes.contains(&column.biome) && column.water_level <= column.height {
+            // layer 0 replaces the top block of the terrain
+            let start = [x, column.height as i32 - 1 + set.y_offset, z];
+            let seed = hash2(self.seed ^ set.salt, x, z);
+            self.assemble(set, start, seed).map(Arc::new)
+        } else {
+            None
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() > 4096 {
+            cache.clear();
+        }
+        cache.insert(key, assembled.clone());
+        assembled
+    }
+
+    /// Structures that could reach the chunk at `global_pos`, `column_at` gives the finished terrain columns
+    fn nearby(&self, global_pos: [i32; 3], column_at: &dyn Fn(i32, i32) -> Column) -> Vec<Arc<Assembled>> {
+        let res = CHUNK_RESOLUTION as i32;
+        let chunk_x = global_pos[0].div_euclid(res);
+        let chunk_z = global_pos[2].div_euclid(res);
+        let mut nearby = vec![];
+
+        for (set_index, set) in self.sets.iter().enumerate() {
+            let reach = (set.radius + res - 1) / res;
+            let min_region = [(chunk_x - reach).div_euclid(set.spacing), (chunk_z - reach).div_euclid(set.spacing)];
+            let max_region = [(chunk_x + reach).div_euclid(set.spacing), (chunk_z + reach).div_euclid(set.spacing)];
+
+            for region_z in min_region[1]..=max_region[1] {
+                for region_x in min_region[0]..=max_region[0] {
+                    let start = set.start_chunk(self.seed, region_x, region_z);
+                    if (start[0] - chunk_x).abs() > reach || (start[1] - chunk_z).abs() > reach {
+                        continue;
+                    }
+                    nearby.extend(self.structure(set_index, region_x, region_z, column_at));
+                }
+            }
+        }
+        nearby
+    }
+
+    /// Writes every structure piece overlapping the chunk
+    pub fn place(&self, chunk: &mut Chunk, global_pos: [i32; 3], column_at: &dyn Fn(i32, i32) -> Column) {
+        for assembled in self.nearby(global_pos, column_at) {
+            self.write(chunk, global_pos, &assembled);
+        }
+    }
+
+    /// True if `place` would write anything into the chunk, lets generation skip sections
+    pub fn touches(&self, global_pos: [i32; 3], column_at: &dyn Fn(i32, i32) -> Column) -> bool {
+        let res = CHUNK_RESOLUTION as i32;
+        let chunk_piece = Piece { template: 0, rotation: 0, origin: global_pos, size: [res, res, res] };
+        self.nearby(global_pos, column_at).iter().any(|assembled| assembled.pieces.iter().any(|piece| piece.intersects(&chunk_piece)))
+    }
+
+    fn write(&self, chunk: &mut Chunk, global_pos: [i32; 3], assembled: &Assembled) {
+        let res = CHUNK_RESOLUTION as i32;
+        let chunk_piece = Piece { template: 0, rotation: 0, origin: global_pos, size: [res, res, res] };
+
+        for piece in assembled.pieces.iter().filter(|piece| piece.intersects(&chunk_piece)) {
+            let template = &self.templates[piece.template];
+            let [sx, sy, sz] = template.size;
+
+            for y in 0..sy {
+                for z in 0..sz {
+                    for x in 0..sx {
+                        let block = match template.blocks[(x + z * sx + y * sx * sz) as usize] {
+                            Some(block) => block,
+                            None => continue,
+                        };
+                        let local = template.rotate([x, y, z], piece.rotation);
+                        let global = [piece.origin[0] + local[0], piece.origin[1] + local[1], piece.origin[2] + local[2]];
+                        let [cx, cy, cz] = [global[0] - global_pos[0], global[1] - global_pos[1], global[2] - global_pos[2]];
+                        if cx < 0 || cy < 0 || cz < 0 || cx >= res || cy >= res || cz >= res {
+                            continue;
+                        }
+                        chunk.mats.mats[(cx + cz * res + cy * res * res) as usize] = block;
+                    }
+                }
+            }
+
+            for (pos, table) in &template.loot {
+                let local = template.rotate(*pos, piece.rotation);
+                let global = [piece.origin[0] + local[0], piece.origin[1] + local[1], piece.origin[2] + local[2]];
+                let inside = (0..3).all(|i| global[i] >= global_pos[i] && global[i] < global_pos[i] + res);
+                if let (true, Some(table)) = (inside, self.loot_tables.get(table)) {
+                    let mut rng = Rng::new(hash2(assembled.seed ^ global[1] as u64, global[0], global[2]));
+                    chunk.containers.push(Container { pos: global, items: table.fill(&mut rng) });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(words: &str) -> Result<StructureSet, String> {
+        StructureSet::parse("test", &words.split_whitespace().collect::<Vec<_>>())
+    }
+
+    /// Templates by name and text, pools of equally weighted templates
+    fn build(templates: &[(&str, &str)], pools: &[(&str, &[&str])]) -> Structures {
+        let mut structures = Structures::empty(1);
+        for (name, text) in templates {
+            structures.templates.push(Template::parse(name, text).unwrap());
+        }
+        for (pool, names) in pools {
+            let entries = names.iter().map(|name| (structures.templates.iter().position(|t| t.name == *name).unwrap(), 1)).collect();
+            structures.pools.insert(pool.to_string(), Pool { entries });
+        }
+        structures
+    }
+
+    #[test]
+    fn parses_a_template() {
+        let template = Template::parse(
+            "hut",
+            "size = 2 2 1\n\
+             palette = s:stone c:planks@chest\n\
+             connector = 0 0 0 west streets\n\
+             connector = 1 1 0 north\n\
+             layer\n\
+             sc\n\
+             layer\n\
+             \x20s\n",
+        )
+        .unwrap();
+        let stone = block::by_name("stone").unwrap();
+        let planks = block::by_name("planks").unwrap();
+        assert_eq!(template.size, [2, 2, 1]);
+        assert_eq!(template.blocks, [Some(stone), Some(planks), None, Some(stone)]);
+        assert_eq!(template.loot, [([1, 0, 0], "chest".to_owned())]);
+        assert_eq!(template.connectors.len(), 2);
+        assert_eq!(template.connectors[0].facing, Facing::West);
+        assert_eq!(template.connectors[0].pool.as_deref(), Some("streets"));
+        assert_eq!(template.connectors[1].pos, [1, 1, 0]);
+        assert_eq!(template.connectors[1].pool, None);
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        let cases = [
+            ("palette = s:stone\nlayer\ns\n", "template needs a size"),
+            ("size = 0 1 1\nlayer\n", "invalid size [0, 1, 1]"),
+            ("size = 1 2 1\npalette = s:stone\nlayer\ns\n", "expected 2 layers, got 1"),
+            ("size = 1 1 2\npalette = s:stone\nlayer\ns\n", "layer 0 has 1 rows, expected 2"),
+            ("size = 1 1 1\npalette = s:marble\n", "unknown block 'marble'"),
+            ("size = 1 1 1\nlayer\nx\n", "'x' is not in the palette"),
+            ("size = 1 1 1\nconnector = 0 0 0 up\n", "unknown facing 'up'"),
+        ];
+        for (text, message) in cases {
+            assert_eq!(Template::parse("test", text).unwrap_err(), message, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn parses_a_structure_set() {
+        let set = set("start=centers depth=3 radius=40 spacing=6 separation=2 biomes=plains,desert y_offset=-4 salt=7").unwrap();
+        assert_eq!(set.start_pool, "centers");
+        assert_eq!((set.depth, set.radius, set.spacing, set.separation, set.y_offset, set.salt), (3, 40, 6, 2, -4, 7));
+        assert_eq!(set.biomes, [Biome::Plains, Biome::Desert]);
+    }
+
+    #[test]
+    fn rejects_invalid_structure_sets() {
+        let cases = [
+            ("depth=1", "structure 'test' needs a start pool"),
+            ("start=a spacing=0 separation=0", "structure 'test' needs a positive spacing"),
+            ("start=a spacing=-4 separation=-8", "structure 'test' needs a positive spacing"),
+            ("start=a spacing=4 separation=4", "structure 'test' needs 0 <= separation < spacing"),
+            ("start=a spacing=4 separation=-1", "structure 'test' needs 0 <= separation < spacing"),
+            ("start=a biomes=swamp", "unknown biome 'swamp'"),
+            ("start=a depth=deep", "invalid number depth=deep"),
+            ("start=a width=3", "unknown key 'width'"),
+        ];
+        for (words, message) in cases {
+            assert_eq!(set(words).err().as_deref(), Some(message), "{}", words);
+        }
+    }
+
+    #[test]
+    fn jigsaw_matches_connectors_and_stops_at_the_depth() {
+        // arms attach to the hub's east and west and keep growing outwards
+        let hub = "size = 1 1 1\npalette = s:stone\nconnector = 0 0 0 east arms\nconnector = 0 0 0 west arms\nlayer\ns\n";
+        let arm = "size = 1 1 1\npalette = s:stone\nconnector = 0 0 0 east arms\nconnector = 0 0 0 west arms\nlayer\ns\n";
+        let structures = build(&[("hub", hub), ("arm", arm)], &[("hubs", &["hub"]), ("arms", &["arm"])]);
+
+        for (depth, expected) in [(0, vec![0]), (1, vec![-1, 0, 1]), (2, vec![-2, -1, 0, 1, 2])] {
+            let set = set(&format!("start=hubs depth={}", depth)).unwrap();
+            let assembled = structures.assemble(&set, [0, 10, 0], 3).unwrap();
+            // the start piece turns randomly, the line runs along x or z
+            let axis = if assembled.pieces.iter().any(|piece| piece.origin[0] != 0) { 0 } else { 2 };
+            let mut line: Vec<i32> = assembled.pieces.iter().map(|piece| piece.origin[axis]).collect();
+            line.sort();
+            assert_eq!(line, expected, "depth {}", depth);
+            assert!(assembled.pieces.iter().all(|piece| piece.origin[1] == 10 && piece.origin[2 - axis] == 0));
+        }
+
+        // the only connector of the end faces north, it has to turn to face the hub's east connector
+        let hub = "size = 1 1 1\npalette = s:stone\nconnector = 0 0 0 east ends\nlayer\ns\n";
+        let end = "size = 1 1 1\npalette = s:stone\nconnector = 0 0 0 north\nlayer\ns\n";
+        let structures = build(&[("hub", hub), ("end", end)], &[("hubs", &["hub"]), ("ends", &["end"])]);
+        let assembled = structures.assemble(&set("start=hubs depth=1").unwrap(), [0, 0, 0], 3).unwrap();
+        assert_eq!(assembled.pieces.len(), 2);
+        let end = &assembled.pieces[1];
+        let facing = structures.templates[end.template].connectors[0].facing.rotate(end.rotation);
+        let hub_facing = structures.templates[assembled.pieces[0].template].connectors[0].facing.rotate(assembled.pieces[0].rotation);
+        assert_eq!(facing, hub_facing.opposite());
+        let offset = hub_facing.offset();
+        assert_eq!(end.origin, [offset[0], 0, offset[1]]);
+    }
+
+    #[test]
+    fn jigsaw_rejects_overlapping_pieces() {
+        // both connectors want a piece in the same spot, the second one doesn't fit
+        let hub = "size = 1 1 1\npalette = s:stone\nconnector = 0 0 0 east ends\nconnector = 0 0 0 east ends\nlayer\ns\n";
+        let end = "size = 1 1 1\npalette = s:stone\nconnector = 0 0 0 west\nlayer\ns\n";
+        let structures = build(&[("hub", hub), ("end", end)], &[("hubs", &["hub"]), ("ends", &["end"])]);
+        let assembled = structures.assemble(&set("start=hubs depth=1").unwrap(), [0, 0, 0], 3).unwrap();
+        assert_eq!(assembled.pieces.len(), 2);
+        assert!(!assembled.pieces[0].intersects(&assembled.pieces[1]));
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+/// Ticks in one day, tick 0 of a day is sunrise, a quarter day later is noon
+pub const TICKS_PER_DAY: u64 = 24000;
+/// Ticks per real second at speed 1, a day lasts 20 minutes
+pub const TICKS_PER_SECOND: f64 = 20.0;
+
+/// Time of the world in ticks, saved as `time` in `world.txt`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldTime {
+    pub ticks: u64,
+    /// Multiplier of `TICKS_PER_SECOND`, the debug UI speeds it up to watch a day pass
+    pub speed: f32,
+    /// Stops `advance`, setting `ticks` still works
+    pub frozen: bool,
+    /// Fraction of a tick left over from the last `advance`
+    carry: f64,
+}
+
+impl WorldTime {
+    pub const fn new(ticks: u64) -> Self {
+        Self { ticks, speed: 1.0, frozen: false, carry: 0.0 }
+    }
+
+    /// Call once per frame with the frame time
+    pub fn advance(&mut self, delta: Duration) {
+        if self.frozen {
+            return;
+        }
+        self.carry += delta.as_secs_f64() * TICKS_PER_SECOND * self.speed.max(0.0) as f64;
+        let whole = self.carry.floor();
+        self.ticks += whole as u64;
+        self.carry -= whole;
+    }
+
+    pub fn day(&self) -> u64 {
+        self.ticks / TICKS_PER_DAY
+    }
+
+    /// 0 at sunrise, 0.25 at noon, 0.5 at sunset and 0.75 at midnight, includes the fraction of the current tick
+    pub fn time_of_day(&self) -> f32 {
+        (((self.ticks % TICKS_PER_DAY) as f64 + self.carry) / TICKS_PER_DAY as f64) as f32
+    }
+
+    /// Jumps to `time_of_day` within the current day, for the debug UI
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.ticks = self.day() * TICKS_PER_DAY + (time_of_day.rem_euclid(1.0) as f64 * TICKS_PER_DAY as f64) as u64;
+        self.carry = 0.0;
+    }
+
+    /// Clock time with sunrise at 6:00, as hours and minutes
+    pub fn clock(&self) -> (u32, u32) {
+        let minutes = ((self.time_of_day() * 24.0 * 60.0) as u32 + 6 * 60) % (24 * 60);
+        (minutes / 60, minutes % 60)
+    }
+}
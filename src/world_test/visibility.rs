@@ -0,0 +1,170 @@
+use std::collections::{HashSet, VecDeque};
+
+use super::*;
+use block::BlockId;
+use column::Section;
+
+/// Section faces in the order of `GPUQuad::face`: -x, +x, -z, +z, -y, +y
+pub const FACES: usize = 6;
+pub const FACE_OFFSETS: [[i32; 3]; FACES] = [[-1, 0, 0], [1, 0, 0], [0, 0, -1], [0, 0, 1], [0, -1, 0], [0, 1, 0]];
+
+pub fn opposite(face: usize) -> usize {
+    face ^ 1
+}
+
+/// Which faces of a section can see each other through non-solid blocks, one bit per face pair
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaceConnectivity(u64);
+
+impl FaceConnectivity {
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self((1 << (FACES * FACES)) - 1);
+
+    fn connect(&mut self, a: usize, b: usize) {
+        self.0 |= 1 << (a * FACES + b) | 1 << (b * FACES + a);
+    }
+
+    pub fn connects(&self, a: usize, b: usize) -> bool {
+        self.0 & (1 << (a * FACES + b)) != 0
+    }
+
+    /// Flood fills the non-solid blocks of a `res` cube indexed `x + z * res + y * res * res`,
+    /// every faces a region touches can see each other
+    pub fn from_blocks(blocks: &[BlockId], res: usize) -> Self {
+        let mut connectivity = Self::NONE;
+        let mut visited = vec![false; blocks.len()];
+        let mut stack = vec![];
+        for start in 0..blocks.len() {
+            if visited[start] || block::info(blocks[start]).solid {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start);
+            let mut touched = 0u8;
+            while let Some(index) = stack.pop() {
+                let pos = [index % res, index / (res * res), index / res % res];
+                for (face, [dx, dy, dz]) in FACE_OFFSETS.iter().enumerate() {
+                    let [x, y, z] = [pos[0] as i32 + dx, pos[1] as i32 + dy, pos[2] as i32 + dz];
+                    if [x, y, z].iter().any(|v| *v < 0 || *v >= res as i32) {
+                        touched |= 1 << face;
+                        continue;
+                    }
+                    let next = x as usize + z as usize * res + y as usize * res * res;
+                    if !visited[next] && !block::info(blocks[next]).solid {
+                        visited[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+            for a in 0..FACES {
+                for b in a..FACES {
+                    if touched & (1 << a) != 0 && touched & (1 << b) != 0 {
+                        connectivity.connect(a, b);
+                    }
+                }
+            }
+            if connectivity == Self::ALL {
+                break;
+            }
+        }
+        connectivity
+    }
+
+    pub fn of_section(section: &Section) -> Self {
+        match section {
+            Section::Empty => Self::ALL,
+            Section::Uniform(block) if block::info(*block).solid => Self::NONE,
+            Section::Uniform(_) => Self::ALL,
+            Section::Blocks(chunk) => Self::from_blocks(&chunk.mats.mats, CHUNK_RESOLUTION),
+        }
+    }
+}
+
+/// Breadth first walk from `start` through faces that see each other, like Minecraft's cave culling.
+/// A section is entered once, and never in a direction opposite to one taken on the way there,
+/// so the walk only moves away from the camera. `connectivity` is `None` for sections that can't be entered,
+/// `in_view` rejects sections outside the frustum or render distance.
+pub fn reachable_sections(start: [i32; 3], connectivity: impl Fn([i32; 3]) -> Option<FaceConnectivity>, in_view: impl Fn([i32; 3]) -> bool) -> Vec<[i32; 3]> {
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, None::<usize>, 0u8)]);
+    let mut reachable = vec![];
+    while let Some((section, entered_from, directions)) = queue.pop_front() {
+        let Some(faces) = connectivity(section) else {
+            continue;
+        };
+        reachable.push(section);
+        for (face, [dx, dy, dz]) in FACE_OFFSETS.iter().enumerate() {
+            if directions & (1 << opposite(face)) != 0 || entered_from.is_some_and(|from| !faces.connects(from, face)) {
+                continue;
+            }
+            let next = [section[0] + dx, section[1] + dy, section[2] + dz];
+            if !in_view(next) || !visited.insert(next) {
+                continue;
+            }
+            queue.push_back((next, Some(opposite(face)), directions | 1 << face));
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES: usize = 4;
+
+    fn blocks(solid: impl Fn(usize, usize, usize) -> bool) -> Vec<BlockId> {
+        (0..RES * RES * RES).map(|index| if solid(index % RES, index / (RES * RES), index / RES % RES) { block::STONE } else { block::AIR }).collect()
+    }
+
+    #[test]
+    fn uniform_blocks_connect_all_or_nothing() {
+        assert_eq!(FaceConnectivity::from_blocks(&blocks(|_, _, _| false), RES), FaceConnectivity::ALL);
+        assert_eq!(FaceConnectivity::from_blocks(&blocks(|_, _, _| true), RES), FaceConnectivity::NONE);
+        // water doesn't block the view
+        assert_eq!(FaceConnectivity::from_blocks(&[block::WATER; RES * RES * RES], RES), FaceConnectivity::ALL);
+    }
+
+    #[test]
+    fn floor_splits_top_from_bottom() {
+        let faces = FaceConnectivity::from_blocks(&blocks(|_, y, _| y == 2), RES);
+        let [nx, px, nz, pz, ny, py] = [0, 1, 2, 3, 4, 5];
+        assert!(!faces.connects(ny, py));
+        assert!(faces.connects(ny, nx) && faces.connects(py, nx));
+        assert!(faces.connects(nx, px) && faces.connects(nz, pz));
+        assert_eq!(faces.connects(py, ny), faces.connects(ny, py));
+    }
+
+    #[test]
+    fn tunnel_connects_its_ends_only() {
+        let faces = FaceConnectivity::from_blocks(&blocks(|_, y, z| !(y == 1 && z == 1)), RES);
+        for a in 0..FACES {
+            for b in 0..FACES {
+                assert_eq!(faces.connects(a, b), a < 2 && b < 2, "faces {} and {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn walk_stops_behind_solid_sections() {
+        let connectivity = |[x, y, z]: [i32; 3]| match [x, y, z] {
+            [2, 0, 0] => Some(FaceConnectivity::NONE),
+            [-3..=3, 0, 0] => Some(FaceConnectivity::ALL),
+            _ => None,
+        };
+        let reachable = reachable_sections([0, 0, 0], connectivity, |_| true);
+        assert_eq!(reachable, vec![[0, 0, 0], [-1, 0, 0], [1, 0, 0], [-2, 0, 0], [2, 0, 0], [-3, 0, 0]]);
+
+        let reachable = reachable_sections([0, 0, 0], connectivity, |[x, _, _]| x >= 0);
+        assert_eq!(reachable, vec![[0, 0, 0], [1, 0, 0], [2, 0, 0]]);
+    }
+
+    #[test]
+    fn walk_only_moves_away_from_the_camera() {
+        // a cave bending back towards -x after going +x, the last section would need both directions
+        let cave = [[0, 0, 0], [1, 0, 0], [1, 0, 1], [1, 0, 2], [0, 0, 2]];
+        let connectivity = |section: [i32; 3]| cave.contains(&section).then_some(FaceConnectivity::ALL);
+        let reachable = reachable_sections([0, 0, 0], connectivity, |_| true);
+        assert_eq!(reachable, &cave[..4]);
+    }
+}
@@ -0,0 +1,87 @@
+use super::*;
+use block::BlockId;
+use loot::Container;
+use manager::ChunkManager;
+
+/// A box of blocks that can be pasted into the world, filled by the schematic and model importers
+#[derive(Clone, Debug)]
+pub struct BlockVolume {
+    pub size: [i32; 3],
+    /// `None` keeps whatever the world has there, indexed by `x + z * size.x + y * size.x * size.z`
+    pub blocks: Vec<Option<BlockId>>,
+    /// Positions are relative to the volume
+    pub containers: Vec<Container>,
+}
+
+impl BlockVolume {
+    pub fn new(size: [i32; 3]) -> Self {
+        Self { size, blocks: vec![None; (size[0] * size[1] * size[2]).max(0) as usize], containers: vec![] }
+    }
+
+    fn index(&self, pos: [i32; 3]) -> Option<usize> {
+        if (0..3).any(|i| pos[i] < 0 || pos[i] >= self.size[i]) {
+            return None;
+        }
+        Some((pos[0] + pos[2] * self.size[0] + pos[1] * self.size[0] * self.size[2]) as usize)
+    }
+
+    pub fn get(&self, pos: [i32; 3]) -> Option<BlockId> {
+        self.index(pos).and_then(|index| self.blocks[index])
+    }
+
+    /// Positions outside the volume are ignored
+    pub fn set(&mut self, pos: [i32; 3], block: Option<BlockId>) {
+        if let Some(index) = self.index(pos) {
+            self.blocks[index] = block;
+        }
+    }
+
+    pub fn rotated_size(&self, rotation: u8) -> [i32; 3] {
+        if rotation.is_multiple_of(2) {
+            self.size
+        } else {
+            [self.size[2], self.size[1], self.size[0]]
+        }
+    }
+
+    /// Clockwise quarter turns seen from above, same as structure templates
+    pub fn rotate(&self, pos: [i32; 3], rotation: u8) -> [i32; 3] {
+        let [sx, _, sz] = self.size;
+        let [x, y, z] = pos;
+        match rotation % 4 {
+            0 => [x, y, z],
+            1 => [sz - 1 - z, y, x],
+            2 => [sx - 1 - x, y, sz - 1 - z],
+            _ => [z, y, sx - 1 - x],
+        }
+    }
+
+    /// Writes the volume with its min corner at `origin`, air only replaces blocks with `include_air`.
+    /// Returns how many blocks were written, blocks outside the world height are skipped
+    pub fn place(&self, manager: &mut ChunkManager, origin: [i32; 3], rotation: u8, include_air: bool) -> usize {
+        let [sx, sy, sz] = self.size;
+        let mut placed = 0;
+        for y in 0..sy {
+            for z in 0..sz {
+                for x in 0..sx {
+                    let block = match self.blocks[(x + z * sx + y * sx * sz) as usize] {
+                        Some(block::AIR) if !include_air => continue,
+                        Some(block) => block,
+                        None => continue,
+                    };
+                    let local = self.rotate([x, y, z], rotation);
+                    if manager.set_block([origin[0] + local[0], origin[1] + local[1], origin[2] + local[2]], block) {
+                        placed += 1;
+                    }
+                }
+            }
+        }
+
+        for container in &self.containers {
+            let local = self.rotate(container.pos, rotation);
+            let pos = [origin[0] + local[0], origin[1] + local[1], origin[2] + local[2]];
+            manager.add_container(Container { pos, items: container.items.clone() });
+        }
+        placed
+    }
+}
@@ -0,0 +1,563 @@
+use std::{collections::HashMap, path::Path};
+
+use super::*;
+use block::{BlockId, BLOCKS};
+use heightmap::closest_color;
+use manager::ChunkManager;
+use mesh::GPUQuad;
+use volume::BlockVolume;
+use world::World;
+
+const USAGE: &str = "usage: vox <import <file> <world> <x> <y> <z> [quarter turns]|export <world> <file> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z>>";
+
+/// MagicaVoxel refuses models larger than this on any axis
+const MAX_MODEL_SIZE: i32 = 256;
+const VOX_VERSION: i32 = 200;
+
+/// One `SIZE` + `XYZI` pair, positions are in vox space where z is up
+#[derive(Clone, Debug)]
+pub struct VoxModel {
+    pub size: [i32; 3],
+    /// x, y, z, palette index
+    pub voxels: Vec<[u8; 4]>,
+}
+
+/// Rotation and translation of one node, `rotation` maps vox axes like the `_r` byte of the file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transform {
+    /// Row major, each row has a single 1 or -1
+    pub rotation: [[i32; 3]; 3],
+    pub translation: [i32; 3],
+}
+
+impl Transform {
+    const IDENTITY: Self = Self { rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]], translation: [0, 0, 0] };
+
+    /// `_r` packs the column of the non zero entry of row 0 and 1 in bits 0..4 and the signs of the rows in bits 4..7
+    fn parse(rotation: Option<&str>, translation: Option<&str>) -> Result<Self, String> {
+        let mut transform = Self::IDENTITY;
+        if let Some(r) = rotation {
+            let r: u8 = r.trim().parse().map_err(|_| format!("invalid rotation '{}'", r))?;
+            let first = (r & 3) as usize;
+            let second = ((r >> 2) & 3) as usize;
+            if first > 2 || second > 2 || first == second {
+                return Err(format!("invalid rotation {}", r));
+            }
+            let third = 3 - first - second;
+            transform.rotation = [[0; 3]; 3];
+            for (row, column) in [first, second, third].into_iter().enumerate() {
+                transform.rotation[row][column] = if r & (1 << (4 + row)) != 0 { -1 } else { 1 };
+            }
+        }
+        if let Some(t) = translation {
+            let values: Vec<i32> = t.split_whitespace().map(|v| v.parse().map_err(|_| format!("invalid translation '{}'", t))).collect::<Result<_, _>>()?;
+            if values.len() != 3 {
+                return Err(format!("invalid translation '{}'", t));
+            }
+            transform.translation = [values[0], values[1], values[2]];
+        }
+        Ok(transform)
+    }
+
+    fn apply(&self, pos: [i32; 3]) -> [i32; 3] {
+        std::array::from_fn(|row| (0..3).map(|column| self.rotation[row][column] * pos[column]).sum::<i32>() + self.translation[row])
+    }
+
+    /// `self` applied after `child`
+    fn then(&self, child: &Transform) -> Transform {
+        let rotation = std::array::from_fn(|row| std::array::from_fn(|column| (0..3).map(|k| self.rotation[row][k] * child.rotation[k][column]).sum()));
+        Transform { rotation, translation: self.apply(child.translation) }
+    }
+}
+
+/// A model placed by the scene graph
+#[derive(Clone, Copy, Debug)]
+pub struct VoxInstance {
+    pub model: usize,
+    pub transform: Transform,
+}
+
+#[derive(Clone, Debug)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// Index 1..=255 of a voxel color, index 0 is unused
+    pub palette: [[u8; 4]; 256],
+    /// Every shape of the scene graph, or each model once when the file has none
+    pub instances: Vec<VoxInstance>,
+}
+
+enum Node {
+    Transform { child: i32, transform: Transform },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<usize> },
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.offset..self.offset + len).ok_or("vox data ends early")?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.i32()?.max(0) as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>, String> {
+        let count = self.i32()?.max(0);
+        (0..count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+}
+
+/// MagicaVoxel's palette when a file has no `RGBA` chunk, only used as a fallback so a plain gray ramp is enough
+fn default_palette() -> [[u8; 4]; 256] {
+    std::array::from_fn(|i| {
+        let value = 255 - i as u8;
+        [value, value, value, 255]
+    })
+}
+
+impl VoxFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(4)? != b"VOX " {
+            return Err("not a vox file".to_owned());
+        }
+        let _version = reader.i32()?;
+
+        let mut models = vec![];
+        let mut size = None;
+        let mut palette = default_palette();
+        let mut nodes: HashMap<i32, Node> = HashMap::new();
+
+        // MAIN only has children, so the chunks are read flat
+        while reader.offset < bytes.len() {
+            let id = reader.take(4)?;
+            let content_len = reader.i32()?.max(0) as usize;
+            let _children_len = reader.i32()?;
+            if id == b"MAIN" {
+                continue;
+            }
+            let mut content = Reader { bytes: reader.take(content_len)?, offset: 0 };
+
+            match id {
+                b"SIZE" => size = Some([content.i32()?, content.i32()?, content.i32()?]),
+                b"XYZI" => {
+                    let size = size.take().ok_or("XYZI without a SIZE before it")?;
+                    let count = content.i32()?.max(0) as usize;
+                    let voxels = (0..count).map(|_| content.take(4).map(|v| [v[0], v[1], v[2], v[3]])).collect::<Result<_, _>>()?;
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    // the chunk stores colors 1..=255 followed by an unused one
+                    for entry in palette.iter_mut().skip(1) {
+                        let color = content.take(4)?;
+                        *entry = [color[0], color[1], color[2], color[3]];
+                    }
+                }
+                b"nTRN" => {
+                    let node = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let child = content.i32()?;
+                    let _reserved = content.i32()?;
+                    let _layer = content.i32()?;
+                    let frames = content.i32()?;
+                    // animations aren't supported, the first frame is the pose
+                    let frame = if frames > 0 { content.dict()? } else { HashMap::new() };
+                    let transform = Transform::parse(frame.get("_r").map(String::as_str), frame.get("_t").map(String::as_str))?;
+                    nodes.insert(node, Node::Transform { child, transform });
+                }
+                b"nGRP" => {
+                    let node = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let count = content.i32()?.max(0);
+                    let children = (0..count).map(|_| content.i32()).collect::<Result<_, _>>()?;
+                    nodes.insert(node, Node::Group { children });
+                }
+                b"nSHP" => {
+                    let node = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let count = content.i32()?.max(0);
+                    let mut shape_models = vec![];
+                    for _ in 0..count {
+                        shape_models.push(content.i32()?.max(0) as usize);
+                        let _model_attributes = content.dict()?;
+                    }
+                    nodes.insert(node, Node::Shape { models: shape_models });
+                }
+                // materials, layers, cameras and notes don't affect the blocks
+                _ => {}
+            }
+        }
+
+        let mut instances = vec![];
+        if nodes.contains_key(&0) {
+            collect_instances(&nodes, 0, Transform::IDENTITY, &mut instances, 0)?;
+        } else {
+            instances = (0..models.len()).map(|model| VoxInstance { model, transform: Transform::IDENTITY }).collect();
+        }
+        if let Some(instance) = instances.iter().find(|instance| instance.model >= models.len()) {
+            return Err(format!("shape uses model {} but the file has {}", instance.model, models.len()));
+        }
+        Ok(Self { models, palette, instances })
+    }
+
+    /// Closest solid block for every palette index
+    pub fn block_palette(&self) -> [BlockId; 256] {
+        let colors: Vec<Option<[u8; 3]>> = BLOCKS.iter().map(|block| block.solid.then_some(block.color)).collect();
+        std::array::from_fn(|i| closest_color([self.palette[i][0], self.palette[i][1], self.palette[i][2]], &colors) as BlockId)
+    }
+
+    /// Voxels of an instance in vox space, the model center is at the translation like in MagicaVoxel
+    fn instance_voxels(&self, instance: &VoxInstance) -> impl Iterator<Item = ([i32; 3], u8)> + '_ {
+        let model = &self.models[instance.model];
+        let half = model.size.map(|size| size / 2);
+        let transform = instance.transform;
+        model.voxels.iter().map(move |[x, y, z, color]| (transform.apply([*x as i32 - half[0], *y as i32 - half[1], *z as i32 - half[2]]), *color))
+    }
+
+    /// Vox is z up, ours is y up
+    fn to_block_space(pos: [i32; 3]) -> [i32; 3] {
+        [pos[0], pos[2], pos[1]]
+    }
+
+    fn volume_of(&self, instances: &[VoxInstance]) -> BlockVolume {
+        let palette = self.block_palette();
+        let voxels: Vec<([i32; 3], u8)> = instances.iter().flat_map(|instance| self.instance_voxels(instance)).map(|(pos, color)| (Self::to_block_space(pos), color)).collect();
+        if voxels.is_empty() {
+            return BlockVolume::new([0, 0, 0]);
+        }
+
+        let min: [i32; 3] = std::array::from_fn(|i| voxels.iter().map(|(pos, _)| pos[i]).min().unwrap());
+        let max: [i32; 3] = std::array::from_fn(|i| voxels.iter().map(|(pos, _)| pos[i]).max().unwrap());
+        let mut volume = BlockVolume::new(std::array::from_fn(|i| max[i] - min[i] + 1));
+        for (pos, color) in voxels {
+            volume.set([pos[0] - min[0], pos[1] - min[1], pos[2] - min[2]], Some(palette[color as usize]));
+        }
+        volume
+    }
+
+    /// The whole scene in one volume, empty space keeps the world
+    pub fn to_volume(&self) -> BlockVolume {
+        self.volume_of(&self.instances)
+    }
+
+    /// Each instance as its own prop
+    pub fn props(&self) -> Vec<VoxProp> {
+        self.instances
+            .iter()
+            .map(|instance| {
+                let origin = self.instance_voxels(instance).map(|(pos, _)| Self::to_block_space(pos)).fold([i32::MAX; 3], |min, pos| std::array::from_fn(|i| min[i].min(pos[i])));
+                VoxProp { origin, volume: self.volume_of(std::slice::from_ref(instance)) }
+            })
+            .filter(|prop| !prop.volume.blocks.is_empty())
+            .collect()
+    }
+}
+
+fn collect_instances(nodes: &HashMap<i32, Node>, node: i32, parent: Transform, instances: &mut Vec<VoxInstance>, depth: u32) -> Result<(), String> {
+    if depth > 64 {
+        return Err("scene graph is cyclic or too deep".to_owned());
+    }
+    match nodes.get(&node) {
+        Some(Node::Transform { child, transform }) => collect_instances(nodes, *child, parent.then(transform), instances, depth + 1),
+        Some(Node::Group { children }) => children.iter().try_for_each(|child| collect_instances(nodes, *child, parent, instances, depth + 1)),
+        Some(Node::Shape { models }) => {
+            instances.extend(models.iter().map(|model| VoxInstance { model: *model, transform: parent }));
+            Ok(())
+        }
+        None => Err(format!("scene graph references missing node {}", node)),
+    }
+}
+
+/// A model kept out of the world, meshed in `CHUNK_SIZE` tiles like chunk sections
+pub struct VoxProp {
+    /// Min corner in vox scene space converted to y up
+    pub origin: [i32; 3],
+    pub volume: BlockVolume,
+}
+
+impl VoxProp {
+    /// Quads per tile with the tile offset inside the prop, empty tiles are skipped
+    pub fn mesh(&self) -> Vec<([i32; 3], Vec<GPUQuad>)> {
+        mesh::mesh_tiles(self.volume.size, |pos| self.volume.get(pos).unwrap_or(block::AIR))
+    }
+}
+
+fn chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    out.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    out.extend_from_slice(content);
+    out.extend_from_slice(children);
+}
+
+fn dict(out: &mut Vec<u8>, entries: &[(&str, String)]) {
+    out.extend_from_slice(&(entries.len() as i32).to_le_bytes());
+    for (key, value) in entries {
+        for text in [key.as_bytes(), value.as_bytes()] {
+            out.extend_from_slice(&(text.len() as i32).to_le_bytes());
+            out.extend_from_slice(text);
+        }
+    }
+}
+
+/// Blocks in `min..=max` as a vox file, split into models of at most `MAX_MODEL_SIZE`.
+/// Palette index `i` is block id `i`, so the colors are the preview colors
+pub fn export(manager: &ChunkManager, min: [i32; 3], max: [i32; 3]) -> Vec<u8> {
+    let size: [i32; 3] = std::array::from_fn(|i| max[i] - min[i] + 1);
+    let tiles: [i32; 3] = size.map(|size| (size + MAX_MODEL_SIZE - 1) / MAX_MODEL_SIZE);
+
+    let mut children = vec![];
+    let mut shapes = vec![];
+    for tile_y in 0..tiles[1] {
+        for tile_z in 0..tiles[2] {
+            for tile_x in 0..tiles[0] {
+                let start = [min[0] + tile_x * MAX_MODEL_SIZE, min[1] + tile_y * MAX_MODEL_SIZE, min[2] + tile_z * MAX_MODEL_SIZE];
+                let end: [i32; 3] = std::array::from_fn(|i| (start[i] + MAX_MODEL_SIZE - 1).min(max[i]));
+                // vox space, z is up
+                let model_size = [end[0] - start[0] + 1, end[2] - start[2] + 1, end[1] - start[1] + 1];
+
+                let mut voxels = vec![];
+                for y in start[1]..=end[1] {
+                    for z in start[2]..=end[2] {
+                        for x in start[0]..=end[0] {
+                            match manager.block([x, y, z]) {
+                                Some(block::AIR) | None => {}
+                                Some(block) => voxels.extend_from_slice(&[(x - start[0]) as u8, (z - start[2]) as u8, (y - start[1]) as u8, block]),
+                            }
+                        }
+                    }
+                }
+                if voxels.is_empty() {
+                    continue;
+                }
+
+                let mut content = vec![];
+                model_size.iter().for_each(|v| content.extend_from_slice(&v.to_le_bytes()));
+                chunk(&mut children, b"SIZE", &content, &[]);
+                let mut content = ((voxels.len() / 4) as i32).to_le_bytes().to_vec();
+                content.extend_from_slice(&voxels);
+                chunk(&mut children, b"XYZI", &content, &[]);
+
+                // translations point at the model center, relative to the min corner of the export
+                let local = [start[0] - min[0], start[2] - min[2], start[1] - min[1]];
+                shapes.push(std::array::from_fn::<i32, 3, _>(|i| local[i] + model_size[i] / 2));
+            }
+        }
+    }
+
+    let mut palette = vec![];
+    for i in 1..=256 {
+        let color = BLOCKS.get(i).map_or([0, 0, 0], |block| block.color);
+        palette.extend_from_slice(&[color[0], color[1], color[2], 255]);
+    }
+    chunk(&mut children, b"RGBA", &palette, &[]);
+
+    // root transform -> group -> a transform and a shape per model
+    let mut content = vec![];
+    content.extend_from_slice(&0i32.to_le_bytes());
+    dict(&mut content, &[]);
+    content.extend_from_slice(&1i32.to_le_bytes());
+    content.extend_from_slice(&(-1i32).to_le_bytes());
+    content.extend_from_slice(&(-1i32).to_le_bytes());
+    content.extend_from_slice(&1i32.to_le_bytes());
+    dict(&mut content, &[]);
+    chunk(&mut children, b"nTRN", &content, &[]);
+
+    let mut content = vec![];
+    content.extend_from_slice(&1i32.to_le_bytes());
+    dict(&mut content, &[]);
+    content.extend_from_slice(&(shapes.len() as i32).to_le_bytes());
+    (0..shapes.len()).for_each(|i| content.extend_from_slice(&(2 + 2 * i as i32).to_le_bytes()));
+    chunk(&mut children, b"nGRP", &content, &[]);
+
+    for (model, translation) in shapes.iter().enumerate() {
+        let node = 2 + 2 * model as i32;
+        let mut content = vec![];
+        content.extend_from_slice(&node.to_le_bytes());
+        dict(&mut content, &[]);
+        content.extend_from_slice(&(node + 1).to_le_bytes());
+        content.extend_from_slice(&(-1i32).to_le_bytes());
+        content.extend_from_slice(&0i32.to_le_bytes());
+        content.extend_from_slice(&1i32.to_le_bytes());
+        dict(&mut content, &[("_t", format!("{} {} {}", translation[0], translation[1], translation[2]))]);
+        chunk(&mut children, b"nTRN", &content, &[]);
+
+        let mut content = vec![];
+        content.extend_from_slice(&(node + 1).to_le_bytes());
+        dict(&mut content, &[]);
+        content.extend_from_slice(&1i32.to_le_bytes());
+        content.extend_from_slice(&(model as i32).to_le_bytes());
+        dict(&mut content, &[]);
+        chunk(&mut children, b"nSHP", &content, &[]);
+    }
+
+    let mut out = b"VOX ".to_vec();
+    out.extend_from_slice(&VOX_VERSION.to_le_bytes());
+    chunk(&mut out, b"MAIN", &[], &children);
+    out
+}
+
+fn run_command(args: &[String]) -> Result<(), String> {
+    let number = |arg: &String| arg.parse::<i32>().map_err(|_| format!("invalid number '{}'", arg));
+
+    match args.first().map(String::as_str) {
+        Some("import") if (6..=7).contains(&args.len()) => {
+            let volume = VoxFile::load(&args[1])?.to_volume();
+            let origin = [number(&args[3])?, number(&args[4])?, number(&args[5])?];
+            let rotation = args.get(6).map(number).transpose()?.unwrap_or(0).rem_euclid(4) as u8;
+
+            let mut manager = World::open_or_create(&args[2])?.chunk_manager()?;
+            let placed = volume.place(&mut manager, origin, rotation, false);
+            manager.save();
+            println!("placed {} blocks of a {}x{}x{} model into {}", placed, volume.size[0], volume.size[1], volume.size[2], args[2]);
+        }
+        Some("export") if args.len() == 9 => {
+            let a: Vec<i32> = args[3..9].iter().map(number).collect::<Result<_, _>>()?;
+            let (min, max) = ([a[0].min(a[3]), a[1].min(a[4]), a[2].min(a[5])], [a[0].max(a[3]), a[1].max(a[4]), a[2].max(a[5])]);
+
+            let mut manager = World::open(&args[1])?.chunk_manager()?;
+            let res = CHUNK_RESOLUTION as i32;
+            for z in min[2].div_euclid(res)..=max[2].div_euclid(res) {
+                for x in min[0].div_euclid(res)..=max[0].div_euclid(res) {
+                    manager.load_column(x, z);
+                }
+            }
+            std::fs::write(&args[2], export(&manager, min, max)).map_err(|e| format!("{}: {}", args[2], e))?;
+            println!("wrote {}", args[2]);
+        }
+        _ => return Err(USAGE.to_owned()),
+    }
+    Ok(())
+}
+
+/// Entry point for `vulkanrust vox ...`
+pub fn run(args: &[String]) {
+    if let Err(e) = run_command(args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generation::{NoiseParameters, TerrainSource};
+
+    fn i32s(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// `VOX ` header and a `MAIN` chunk holding `children`
+    fn file(children: &[u8]) -> Vec<u8> {
+        let mut out = b"VOX ".to_vec();
+        out.extend_from_slice(&VOX_VERSION.to_le_bytes());
+        chunk(&mut out, b"MAIN", &[], children);
+        out
+    }
+
+    #[test]
+    fn model_without_scene_graph() {
+        // a 3x2x4 model with two voxels, the default palette is a gray ramp
+        let mut children = vec![];
+        chunk(&mut children, b"SIZE", &i32s(&[3, 2, 4]), &[]);
+        let mut voxels = i32s(&[2]);
+        voxels.extend_from_slice(&[0, 0, 0, 1, 2, 1, 3, 1]);
+        chunk(&mut children, b"XYZI", &voxels, &[]);
+
+        let vox = VoxFile::parse(&file(&children)).unwrap();
+        assert_eq!(vox.models.len(), 1);
+        assert_eq!(vox.models[0].size, [3, 2, 4]);
+        assert_eq!(vox.models[0].voxels, [[0, 0, 0, 1], [2, 1, 3, 1]]);
+        assert_eq!(vox.instances.len(), 1);
+        assert_eq!(vox.instances[0].transform, Transform::IDENTITY);
+
+        // z up becomes y up
+        let volume = vox.to_volume();
+        assert_eq!(volume.size, [3, 4, 2]);
+        let block = vox.block_palette()[1];
+        assert_eq!(volume.get([0, 0, 0]), Some(block));
+        assert_eq!(volume.get([2, 3, 1]), Some(block));
+        assert_eq!(volume.blocks.iter().filter(|block| block.is_some()).count(), 2);
+    }
+
+    #[test]
+    fn broken_files_are_rejected() {
+        assert_eq!(VoxFile::parse(b"RIFF").unwrap_err(), "not a vox file");
+        let mut children = vec![];
+        chunk(&mut children, b"XYZI", &i32s(&[0]), &[]);
+        assert_eq!(VoxFile::parse(&file(&children)).unwrap_err(), "XYZI without a SIZE before it");
+
+        // the voxel count claims more than the chunk holds
+        let mut children = vec![];
+        chunk(&mut children, b"SIZE", &i32s(&[1, 1, 1]), &[]);
+        chunk(&mut children, b"XYZI", &i32s(&[2, 0]), &[]);
+        assert_eq!(VoxFile::parse(&file(&children)).unwrap_err(), "vox data ends early");
+
+        // a transform that is its own grandchild
+        let mut children = vec![];
+        let mut content = i32s(&[0]);
+        dict(&mut content, &[]);
+        content.extend(i32s(&[1, -1, 0, 0]));
+        chunk(&mut children, b"nTRN", &content, &[]);
+        let mut content = i32s(&[1]);
+        dict(&mut content, &[]);
+        content.extend(i32s(&[1, 0]));
+        chunk(&mut children, b"nGRP", &content, &[]);
+        assert_eq!(VoxFile::parse(&file(&children)).unwrap_err(), "scene graph is cyclic or too deep");
+    }
+
+    #[test]
+    fn transforms() {
+        let rotated = Transform::parse(Some("1"), Some("10 -2 3")).unwrap();
+        assert_eq!(rotated.rotation, [[0, 1, 0], [1, 0, 0], [0, 0, 1]]);
+        assert_eq!(rotated.apply([1, 2, 3]), [12, -1, 6]);
+        // the sign of row 0 is bit 4
+        assert_eq!(Transform::parse(Some("20"), None).unwrap().rotation, [[-1, 0, 0], [0, 1, 0], [0, 0, 1]]);
+        assert!(Transform::parse(Some("0"), None).is_err());
+        assert!(Transform::parse(None, Some("1 2")).is_err());
+        assert_eq!(rotated.then(&Transform::IDENTITY), rotated);
+    }
+
+    #[test]
+    fn exported_blocks_import_back() {
+        let mut manager = ChunkManager::new(TerrainSource::from_preset(NoiseParameters::default(), ""));
+        let (min, max) = ([-3, 500, 5], [4, 505, 9]);
+        let solid: Vec<BlockId> = (0..BLOCKS.len() as BlockId).filter(|id| block::info(*id).solid).collect();
+        let positions: Vec<[i32; 3]> = (0..solid.len() as i32).map(|i| [min[0] + i % 8, min[1] + i / 3, min[2] + i % 5]).collect();
+        for (pos, block) in positions.iter().zip(&solid) {
+            manager.set_block(*pos, *block);
+        }
+
+        let vox = VoxFile::parse(&export(&manager, min, max)).unwrap();
+        let volume = vox.to_volume();
+        assert_eq!(volume.blocks.iter().filter(|block| block.is_some()).count(), solid.len());
+        // the volume starts at the lowest placed block on each axis
+        let start: [i32; 3] = std::array::from_fn(|axis| positions.iter().map(|pos| pos[axis]).min().unwrap());
+        for y in 0..volume.size[1] {
+            for z in 0..volume.size[2] {
+                for x in 0..volume.size[0] {
+                    let world = manager.block([start[0] + x, start[1] + y, start[2] + z]).filter(|block| *block != block::AIR);
+                    assert_eq!(volume.get([x, y, z]), world, "block ({}, {}, {})", x, y, z);
+                }
+            }
+        }
+    }
+}
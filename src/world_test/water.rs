@@ -0,0 +1,146 @@
+use libnoise::Generator;
+
+use super::*;
+use block::{GRAVEL, SAND};
+use generation::Column;
+use noise_graph::{CompiledNoise, NoiseGraph};
+use rng::{hash2, Rng};
+
+/// Meandering ridge lines of the warped noise become river valleys
+const RIVER_GRAPH: &str = "base = simplex\nriver = warp source=base x=base z=base strength=0.4\n";
+const RIVER_SEED: u64 = 0x52_4956_4552;
+const LAKE_SEED: u64 = 0x4C41_4B45;
+
+pub struct WaterParameters {
+    pub sea_level: u32,
+    /// Blocks per unit of river noise, bigger means fewer and longer rivers
+    pub river_scale: f64,
+    /// Noise distance from the river center that still gets lowered
+    pub valley_width: f64,
+    /// Noise distance from the river center that reaches the full depth
+    pub channel_width: f64,
+    pub river_depth: u32,
+    /// Every cell can have at most one lake
+    pub lake_cell_size: i32,
+    pub lake_chance: f64,
+    pub lake_radius: [i32; 2],
+    pub lake_depth: u32,
+    /// Blocks above sea level that still turn into sand next to water
+    pub beach_height: u32,
+}
+
+impl WaterParameters {
+    pub const fn default() -> Self {
+        Self {
+            sea_level: 6,
+            river_scale: 256.0,
+            valley_width: 0.08,
+            channel_width: 0.03,
+            river_depth: 2,
+            lake_cell_size: 96,
+            lake_chance: 0.3,
+            lake_radius: [6, 16],
+            lake_depth: 3,
+            beach_height: 1,
+        }
+    }
+}
+
+struct Lake {
+    center: [i32; 2],
+    radius: i32,
+    level: u32,
+}
+
+pub struct Water {
+    parameters: WaterParameters,
+    rivers: CompiledNoise,
+    seed: u64,
+}
+
+impl Water {
+    pub fn new(seed: u64, parameters: WaterParameters) -> Self {
+        let rivers = NoiseGraph::parse(RIVER_GRAPH).unwrap().compile(seed ^ RIVER_SEED);
+        Self { parameters, rivers, seed }
+    }
+
+    pub fn sea_level(&self) -> u32 {
+        self.parameters.sea_level
+    }
+
+    fn lake(&self, cell_x: i32, cell_z: i32, height_at: &dyn Fn(i32, i32) -> u32) -> Option<Lake> {
+        let parameters = &self.parameters;
+        let mut rng = Rng::new(hash2(self.seed ^ LAKE_SEED, cell_x, cell_z));
+        if rng.next_f64() >= parameters.lake_chance {
+            return None;
+        }
+
+        let radius = rng.range(parameters.lake_radius[0], parameters.lake_radius[1] + 1);
+        // keep the whole lake inside its cell
+        let margin = radius.min(parameters.lake_cell_size / 2);
+        let center = [
+            cell_x * parameters.lake_cell_size + rng.range(margin, parameters.lake_cell_size - margin),
+            cell_z * parameters.lake_cell_size + rng.range(margin, parameters.lake_cell_size - margin),
+        ];
+
+        let level = height_at(center[0], center[1]).saturating_sub(2);
+        if level <= parameters.sea_level {
+            return None;
+        }
+        Some(Lake { center, radius, level })
+    }
+
+    /// Carves rivers and lakes, fills everything below sea level and puts sand on the shores.
+    /// `height_at` is the terrain height before any water was applied
+    pub fn apply(&self, global_x: i32, global_z: i32, column: &mut Column, height_at: &dyn Fn(i32, i32) -> u32) {
+        let parameters = &self.parameters;
+        let original_height = column.height;
+        let mut height = column.height as f64;
+        let sea_level = parameters.sea_level as f64;
+
+        // Rivers
+        let distance = self.rivers.sample([global_x as f64 / parameters.river_scale, global_z as f64 / parameters.river_scale]).abs();
+        let near_river = distance < parameters.valley_width;
+        if near_river {
+            let valley = smoothstep(parameters.valley_width, parameters.channel_width, distance);
+            let bed = sea_level - parameters.river_depth as f64 * smoothstep(parameters.channel_width, 0.0, distance).max(0.5);
+            height = height.min(height + (bed - height) * valley);
+        }
+
+        // Lakes
+        let cell_x = global_x.div_euclid(parameters.lake_cell_size);
+        let cell_z = global_z.div_euclid(parameters.lake_cell_size);
+        if let Some(lake) = self.lake(cell_x, cell_z, height_at) {
+            let dx = (global_x - lake.center[0]) as f64;
+            let dz = (global_z - lake.center[1]) as f64;
+            let distance = (dx * dx + dz * dz).sqrt() / lake.radius as f64;
+
+            // columns lower than the lake would leave the water floating
+            if distance < 1.0 && original_height >= lake.level {
+                let bed = lake.level as f64 - 1.0 - parameters.lake_depth as f64 * (1.0 - distance);
+                height = height.min(bed);
+                column.water_level = column.water_level.max(lake.level);
+            }
+        }
+
+        let height = height.round().max(0.0) as u32;
+        column.height = height;
+
+        if height < parameters.sea_level {
+            column.water_level = column.water_level.max(parameters.sea_level);
+        }
+
+        // Shores
+        let is_shore = height + 1 >= parameters.sea_level && height <= parameters.sea_level + parameters.beach_height;
+        if column.water_level > height {
+            column.surface = if column.water_level - height > 3 { GRAVEL } else { SAND };
+        } else if is_shore || (near_river && height <= parameters.sea_level + parameters.beach_height + 1) {
+            column.surface = SAND;
+        }
+    }
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
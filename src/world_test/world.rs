@@ -0,0 +1,366 @@
+use std::{
+    fmt::Write as _,
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use super::*;
+use autosave::{BackupPolicy, AUTOSAVE_INTERVAL, BACKUP_INTERVAL};
+use backup::BACKUP_KEEP;
+use block::BlockId;
+use generation::{preset_value, NoiseParameters, TerrainSource, GENERATOR_PRESET_PATH};
+use manager::ChunkManager;
+use region::{region_file_name, region_files, RegionFile, RegionStorage};
+
+const USAGE: &str = "usage: world <info <world>|backup <world> [keep]|recover <world>|visibility <world> <x> <y> <z> [radius]|check-fixtures>";
+
+/// Bumped with every change to the world layout or the column encoding, see `MIGRATIONS`
+pub const DATA_VERSION: u32 = 3;
+pub const WORLD_INFO_FILE: &str = "world.txt";
+pub const PRESET_FILE: &str = "generator.preset";
+pub const REGION_DIR: &str = "region";
+pub const FIXTURES_PATH: &str = "fixtures/worlds";
+const EXPECTED_FILE: &str = "expected.txt";
+
+/// Block names in id order of column version 1, later columns carry their own table
+pub const REGISTRY_V1: [&str; 12] = ["air", "stone", "dirt", "grass", "sand", "water", "snow", "gravel", "planks", "log", "cobblestone", "chest"];
+
+/// Ids of a column written with `registry` to current ids, blocks that no longer exist become stone.
+/// Ids past the registry can only come from damaged data and become stone too, `decode_column` warns about them
+pub fn block_remap(registry: &[String]) -> [BlockId; 256] {
+    std::array::from_fn(|id| match registry.get(id) {
+        Some(name) => block::by_name(name).unwrap_or_else(|| {
+            log::warn!("block '{}' no longer exists, loading it as stone", name);
+            block::STONE
+        }),
+        None => block::STONE,
+    })
+}
+
+/// Upgrades a world directory from `to - 1` to `to`, runs before the world info is read
+struct Migration {
+    to: u32,
+    description: &'static str,
+    apply: fn(&Path) -> Result<(), String>,
+}
+
+const MIGRATIONS: [Migration; 2] = [
+    Migration { to: 2, description: "add world.txt, version 1 worlds only had region files", apply: migrate_to_v2 },
+    Migration { to: 3, description: "add checksums to region files", apply: migrate_to_v3 },
+];
+
+/// Version 1 worlds were generated with the default seed and whatever preset the assets had
+fn migrate_to_v2(dir: &Path) -> Result<(), String> {
+    let preset = std::fs::read_to_string(GENERATOR_PRESET_PATH).unwrap_or_default();
+    let mut info = WorldInfo::new(NoiseParameters::default().seed());
+    info.data_version = 2;
+    write_atomic(&dir.join(PRESET_FILE), preset.as_bytes())?;
+    write_atomic(&dir.join(WORLD_INFO_FILE), info.to_text().as_bytes())
+}
+
+fn migrate_to_v3(dir: &Path) -> Result<(), String> {
+    let region_dir = dir.join(REGION_DIR);
+    for region in region_files(&region_dir)? {
+        RegionFile::upgrade(region_dir.join(region_file_name(region)))?;
+    }
+    let mut info = WorldInfo::parse(&std::fs::read_to_string(dir.join(WORLD_INFO_FILE)).map_err(|e| e.to_string())?)?;
+    info.data_version = 3;
+    write_atomic(&dir.join(WORLD_INFO_FILE), info.to_text().as_bytes())
+}
+
+/// Writes to a temporary file first, a crash leaves the old file or the new one
+fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let tmp = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&tmp).map_err(error)?;
+    file.write_all(data).map_err(error)?;
+    file.sync_all().map_err(error)?;
+    std::fs::rename(&tmp, path).map_err(error)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerState {
+    pub position: [f64; 3],
+    /// Degrees
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl PlayerState {
+    pub const fn default() -> Self {
+        Self { position: [0.0, 128.0, 0.0], yaw: 0.0, pitch: 0.0 }
+    }
+}
+
+/// Contents of `world.txt`
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldInfo {
+    pub data_version: u32,
+    pub seed: u32,
+    pub player: PlayerState,
+    /// Ticks since the world was created
+    pub time: u64,
+}
+
+fn parse_numbers<T: std::str::FromStr, const N: usize>(text: &str, key: &str) -> Result<[T; N], String> {
+    let values: Vec<T> = text.split_whitespace().map(|v| v.parse().map_err(|_| format!("invalid {} '{}'", key, text))).collect::<Result<_, _>>()?;
+    values.try_into().map_err(|_| format!("{} needs {} values, got '{}'", key, N, text))
+}
+
+impl WorldInfo {
+    pub const fn new(seed: u32) -> Self {
+        Self { data_version: DATA_VERSION, seed, player: PlayerState::default(), time: 0 }
+    }
+
+    /// `key = value` lines, missing keys other than `data_version` and `seed` keep their defaults
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let required = |key: &str| preset_value(text, key).ok_or_else(|| format!("missing {}", key));
+        let data_version = required("data_version")?.parse().map_err(|_| "invalid data_version".to_owned())?;
+        let seed = required("seed")?.parse().map_err(|_| "invalid seed".to_owned())?;
+
+        let mut info = Self { data_version, ..Self::new(seed) };
+        if let Some(value) = preset_value(text, "player_position") {
+            info.player.position = parse_numbers(value, "player_position")?;
+        }
+        if let Some(value) = preset_value(text, "player_rotation") {
+            [info.player.yaw, info.player.pitch] = parse_numbers(value, "player_rotation")?;
+        }
+        if let Some(value) = preset_value(text, "time") {
+            info.time = value.parse().map_err(|_| format!("invalid time '{}'", value))?;
+        }
+        Ok(info)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# written by vulkanrust, the generator preset is in generator.preset\n");
+        writeln!(text, "data_version = {}", self.data_version).unwrap();
+        writeln!(text, "seed = {}", self.seed).unwrap();
+        let [x, y, z] = self.player.position;
+        writeln!(text, "player_position = {} {} {}", x, y, z).unwrap();
+        writeln!(text, "player_rotation = {} {}", self.player.yaw, self.player.pitch).unwrap();
+        writeln!(text, "time = {}", self.time).unwrap();
+        text
+    }
+}
+
+/// A world directory: `world.txt`, the generator preset it was created with and the region files
+pub struct World {
+    pub dir: PathBuf,
+    pub info: WorldInfo,
+    pub preset: String,
+}
+
+impl World {
+    /// Uses the current `assets/generator.preset`, later changes to it don't affect the world
+    pub fn create(dir: impl AsRef<Path>, seed: u32) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(dir.join(REGION_DIR)).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let preset = std::fs::read_to_string(GENERATOR_PRESET_PATH).unwrap_or_default();
+        write_atomic(&dir.join(PRESET_FILE), preset.as_bytes())?;
+
+        let world = Self { dir, info: WorldInfo::new(seed), preset };
+        world.save_info()?;
+        Ok(world)
+    }
+
+    /// Runs the migrations the world needs before reading it
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        let info_path = dir.join(WORLD_INFO_FILE);
+
+        loop {
+            let version = match std::fs::read_to_string(&info_path) {
+                Ok(text) => WorldInfo::parse(&text).map_err(|e| format!("{}: {}", info_path.display(), e))?.data_version,
+                Err(_) if dir.join(REGION_DIR).is_dir() => 1,
+                Err(e) => return Err(format!("{}: {}", info_path.display(), e)),
+            };
+            if version > DATA_VERSION {
+                return Err(format!("{}: data version {} is newer than {}", dir.display(), version, DATA_VERSION));
+            }
+            let Some(migration) = MIGRATIONS.iter().find(|migration| migration.to == version + 1) else {
+                break;
+            };
+            log::info!("{}: migrating from version {} to {}, {}", dir.display(), version, migration.to, migration.description);
+            (migration.apply)(&dir).map_err(|e| format!("migration to version {} failed: {}", migration.to, e))?;
+        }
+
+        let info = WorldInfo::parse(&std::fs::read_to_string(&info_path).map_err(|e| format!("{}: {}", info_path.display(), e))?)?;
+        let preset = std::fs::read_to_string(dir.join(PRESET_FILE)).unwrap_or_default();
+        Ok(Self { dir, info, preset })
+    }
+
+    /// New worlds use the default seed
+    pub fn open_or_create(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        if dir.join(WORLD_INFO_FILE).exists() || dir.join(REGION_DIR).is_dir() {
+            Self::open(dir)
+        } else {
+            Self::create(dir, NoiseParameters::default().seed())
+        }
+    }
+
+    pub fn save_info(&self) -> Result<(), String> {
+        write_atomic(&self.dir.join(WORLD_INFO_FILE), self.info.to_text().as_bytes())
+    }
+
+    pub fn terrain(&self) -> TerrainSource {
+        TerrainSource::from_preset(NoiseParameters::with_seed(self.info.seed), &self.preset)
+    }
+
+    pub fn region_dir(&self) -> PathBuf {
+        self.dir.join(REGION_DIR)
+    }
+
+    pub fn chunk_manager(&self) -> Result<ChunkManager, String> {
+        let terrain = self.terrain();
+        let storage = RegionStorage::new(self.region_dir(), terrain.world_height)?;
+        Ok(ChunkManager::with_storage(terrain, storage))
+    }
+
+    /// Saves in the background every `AUTOSAVE_INTERVAL` and backs the world up every `BACKUP_INTERVAL`
+    pub fn autosaving_chunk_manager(&self) -> Result<ChunkManager, String> {
+        let mut manager = self.chunk_manager()?;
+        let backup = BackupPolicy { world: self.dir.clone(), interval: BACKUP_INTERVAL, keep: BACKUP_KEEP };
+        manager.start_autosave(AUTOSAVE_INTERVAL, Some(backup));
+        Ok(manager)
+    }
+}
+
+pub fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(to).map_err(|e| format!("{}: {}", to.display(), e))?;
+    for entry in std::fs::read_dir(from).map_err(|e| format!("{}: {}", from.display(), e))? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            std::fs::copy(&path, &target).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Opens a copy of the fixture and compares it against `expected.txt`:
+/// `seed = <seed>` and `block <x> <y> <z> <name>` lines
+fn check_fixture(fixture: &Path) -> Result<Vec<String>, String> {
+    let expected = std::fs::read_to_string(fixture.join(EXPECTED_FILE)).map_err(|e| format!("{}: {}", fixture.join(EXPECTED_FILE).display(), e))?;
+    let copy = std::env::temp_dir().join(format!("vulkanrust-fixture-{}-{}", std::process::id(), fixture.file_name().unwrap().to_string_lossy()));
+    let _ = std::fs::remove_dir_all(&copy);
+    copy_dir(fixture, &copy)?;
+    std::fs::remove_file(copy.join(EXPECTED_FILE)).map_err(|e| e.to_string())?;
+
+    let result = (|| {
+        let world = World::open(&copy)?;
+        let mut failures = vec![];
+        if world.info.data_version != DATA_VERSION {
+            failures.push(format!("data version is {} after migrating", world.info.data_version));
+        }
+        if let Some(seed) = preset_value(&expected, "seed") {
+            if seed != world.info.seed.to_string() {
+                failures.push(format!("seed is {}, expected {}", world.info.seed, seed));
+            }
+        }
+
+        let mut manager = world.chunk_manager()?;
+        for line in expected.lines().filter_map(|line| line.strip_prefix("block ")) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let [x, y, z, name] = words[..] else {
+                return Err(format!("invalid line 'block {}'", line));
+            };
+            let pos: [i32; 3] = parse_numbers(&format!("{} {} {}", x, y, z), "block position")?;
+            let res = CHUNK_RESOLUTION as i32;
+            manager.load_column(pos[0].div_euclid(res), pos[2].div_euclid(res));
+            let found = manager.block(pos).map_or("outside the world", |id| block::info(id).name);
+            if found != name {
+                failures.push(format!("block at {:?} is {}, expected {}", pos, found, name));
+            }
+        }
+        Ok(failures)
+    })();
+    let _ = std::fs::remove_dir_all(&copy);
+    result
+}
+
+/// Every fixture in `FIXTURES_PATH`, returns the failures prefixed with the fixture name
+pub fn check_fixtures() -> Result<Vec<String>, String> {
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(FIXTURES_PATH).map_err(|e| format!("{}: {}", FIXTURES_PATH, e))?.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_dir()).collect();
+    fixtures.sort();
+
+    let mut failures = vec![];
+    for fixture in fixtures {
+        let name = fixture.file_name().unwrap().to_string_lossy().to_string();
+        match check_fixture(&fixture) {
+            Ok(fixture_failures) => failures.extend(fixture_failures.into_iter().map(|failure| format!("{}: {}", name, failure))),
+            Err(e) => failures.push(format!("{}: {}", name, e)),
+        }
+        println!("checked {}", name);
+    }
+    Ok(failures)
+}
+
+/// Loads the columns around a block position and prints which sections the cave culling walk reaches from it
+fn print_visibility(dir: &str, args: &[String]) -> Result<(), String> {
+    let numbers: Vec<i32> = args.iter().map(|arg| arg.parse().map_err(|_| format!("invalid number '{}'", arg))).collect::<Result<_, _>>()?;
+    let (camera, radius) = match numbers[..] {
+        [x, y, z] => ([x, y, z], 2),
+        [x, y, z, radius] => ([x, y, z], radius),
+        _ => return Err(USAGE.to_owned()),
+    };
+    let mut manager = World::open(dir)?.chunk_manager()?;
+    let res = CHUNK_RESOLUTION as i32;
+    manager.load_around([camera[0].div_euclid(res), camera[2].div_euclid(res)], radius);
+
+    let visible = manager.visible_sections(camera, radius, |_| true);
+    let meshed = manager.columns().flat_map(|column| (column.bottom..column.top()).map(|y| [column.x, y, column.z])).filter(|[x, y, z]| manager.needs_mesh(*x, *y, *z)).count();
+    for [x, y, z] in &visible {
+        println!("{} {} {}", x, y, z);
+    }
+    let drawn = visible.iter().filter(|[x, y, z]| manager.needs_mesh(*x, *y, *z)).count();
+    println!("{} sections visible, {} of the {} loaded sections with faces would be drawn", visible.len(), drawn, meshed);
+    Ok(())
+}
+
+/// Entry point for `vulkanrust world ...`
+pub fn run(args: &[String]) {
+    let result = match (args.first().map(String::as_str), args.get(1)) {
+        (Some("info"), Some(dir)) => World::open(dir).map(|world| print!("{}", world.info.to_text())),
+        (Some("backup"), Some(dir)) => {
+            let keep = args.get(2).map(|keep| keep.parse().map_err(|_| format!("invalid count '{}'", keep))).transpose();
+            keep.and_then(|keep| backup::backup(Path::new(dir), keep.unwrap_or(BACKUP_KEEP))).map(|backup| println!("wrote {}", backup.display()))
+        }
+        (Some("recover"), Some(dir)) => backup::recover(Path::new(dir)).map(|report| match report.len() {
+            0 => println!("no damage found"),
+            count => {
+                report.iter().for_each(|message| println!("{}", message));
+                println!("recovered {} problems", count);
+            }
+        }),
+        (Some("visibility"), Some(dir)) => print_visibility(dir, &args[2..]),
+        (Some("check-fixtures"), None) => check_fixtures().and_then(|failures| {
+            for failure in &failures {
+                eprintln!("{}", failure);
+            }
+            if !failures.is_empty() {
+                return Err(format!("{} fixture checks failed", failures.len()));
+            }
+            println!("all fixtures load");
+            Ok(())
+        }),
+        _ => Err(USAGE.to_owned()),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_load() {
+        let failures = check_fixtures().unwrap();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}